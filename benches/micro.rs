@@ -0,0 +1,9 @@
+//! `cargo bench --features bench`, the benchmarks of `microbench`
+
+use yet_another_rchip8::microbench::{self, Bencher};
+
+fn main() {
+    for measurement in microbench::all(&Bencher::default()) {
+        println!("{}", measurement);
+    }
+}
@@ -0,0 +1,254 @@
+use std::collections::HashMap;
+
+use crate::opcodes::{OpcodeSpec, Operand, OPCODES};
+use crate::symbols::Symbols;
+use crate::{err, Result};
+
+/// One line of source after the label was taken off
+enum Statement {
+    Instruction(String, Vec<String>),
+    /// DB: bytes
+    Bytes(Vec<String>),
+    /// DW: big endian words
+    Words(Vec<String>),
+}
+
+impl Statement {
+    fn size(&self) -> usize {
+        match self {
+            Statement::Instruction(..) => 2,
+            Statement::Bytes(values) => values.len(),
+            Statement::Words(values) => values.len() * 2,
+        }
+    }
+}
+
+/// Assemble a program loaded at `start` into a ROM binary.
+///
+/// The dialect is the one the disassembler prints: one statement per line,
+/// `;` starts a comment, `name:` defines a label for the address of what
+/// follows, `DB`/`DW` emit bytes/words and the mnemonics are those of
+/// `opcodes::OPCODES`. Numbers are decimal, `0x`/`$` hex or `0b` binary,
+/// labels can be used wherever a number is expected.
+pub fn assemble(source: &str, start: u16) -> Result<Vec<u8>> {
+    assemble_with_symbols(source, start).map(|(rom, _)| rom)
+}
+
+/// `assemble` and the addresses of the labels
+pub fn assemble_with_symbols(source: &str, start: u16) -> Result<(Vec<u8>, Symbols)> {
+    // first pass: addresses of the labels
+    let mut labels = HashMap::new();
+    let mut statements = vec![];
+    let mut addr = start as usize;
+    for (n, line) in source.lines().enumerate() {
+        let line_no = n + 1;
+        let mut code = line.split(';').next().unwrap().trim();
+        while let Some((label, rest)) = split_label(code) {
+            if !is_label(label) {
+                return err!("line {}: invalid label name: {}", line_no, label);
+            }
+            if labels.insert(label.to_string(), addr).is_some() {
+                return err!("line {}: duplicate label: {}", line_no, label);
+            }
+            code = rest.trim();
+        }
+        if code.is_empty() {
+            continue;
+        }
+        let statement = parse_statement(code);
+        addr += statement.size();
+        statements.push((line_no, statement));
+    }
+
+    // second pass: encode with all labels known
+    let mut rom = vec![];
+    for (line_no, statement) in statements {
+        let at = |e: Box<dyn std::error::Error>| format!("line {}: {}", line_no, e);
+        match statement {
+            Statement::Bytes(values) => {
+                for value in values {
+                    rom.push(value_of(&value, &labels, 0xFF).map_err(at)? as u8);
+                }
+            }
+            Statement::Words(values) => {
+                for value in values {
+                    let word = value_of(&value, &labels, 0xFFFF).map_err(at)?;
+                    rom.extend_from_slice(&word.to_be_bytes());
+                }
+            }
+            Statement::Instruction(mnemonic, operands) => {
+                let opcode = encode(&mnemonic, &operands, &labels).map_err(at)?;
+                rom.extend_from_slice(&opcode.to_be_bytes());
+            }
+        }
+    }
+    let symbols = Symbols::from_labels(labels.iter().map(|(name, &addr)| (name, addr as u16)));
+    Ok((rom, symbols))
+}
+
+/// `label: rest`, a colon inside the operands is not a label
+fn split_label(code: &str) -> Option<(&str, &str)> {
+    let (label, rest) = code.split_once(':')?;
+    let label = label.trim();
+    if label.contains(char::is_whitespace) || label.contains(',') {
+        None
+    } else {
+        Some((label, rest))
+    }
+}
+
+fn is_label(name: &str) -> bool {
+    name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+        && register(name).is_none()
+}
+
+fn parse_statement(code: &str) -> Statement {
+    let (mnemonic, rest) = match code.split_once(char::is_whitespace) {
+        Some((mnemonic, rest)) => (mnemonic, rest.trim()),
+        None => (code, ""),
+    };
+    let operands: Vec<String> = if rest.is_empty() {
+        vec![]
+    } else {
+        rest.split(',').map(|op| op.trim().to_string()).collect()
+    };
+    match mnemonic.to_ascii_uppercase().as_str() {
+        "DB" => Statement::Bytes(operands),
+        "DW" => Statement::Words(operands),
+        mnemonic => Statement::Instruction(mnemonic.to_string(), operands),
+    }
+}
+
+/// the first table row with this mnemonic whose operands fit
+fn encode(mnemonic: &str, operands: &[String], labels: &HashMap<String, usize>) -> Result<u16> {
+    let candidates: Vec<&OpcodeSpec> = OPCODES
+        .iter()
+        .filter(|spec| spec.mnemonic == mnemonic)
+        .collect();
+    if candidates.is_empty() {
+        return err!("unknown mnemonic: {}", mnemonic);
+    }
+    for spec in candidates {
+        if spec.operands.len() != operands.len() {
+            continue;
+        }
+        if let Some(opcode) = encode_operands(spec, operands, labels)? {
+            return Ok(opcode);
+        }
+    }
+    err!("invalid operands for {}: {}", mnemonic, operands.join(", "))
+}
+
+/// None if the operands do not have the kinds of `spec`
+fn encode_operands(
+    spec: &OpcodeSpec,
+    operands: &[String],
+    labels: &HashMap<String, usize>,
+) -> Result<Option<u16>> {
+    let mut opcode = spec.pattern;
+    for (kind, token) in spec.operands.iter().zip(operands) {
+        match (kind, register(token)) {
+            (Operand::Vx, Some(x)) => opcode |= x << 8,
+            (Operand::Vy, Some(y)) => opcode |= y << 4,
+            (Operand::Literal(literal), _) if literal.eq_ignore_ascii_case(token) => {}
+            (Operand::Byte, None) if !is_literal(token) => opcode |= value_of(token, labels, 0xFF)?,
+            (Operand::Addr, None) if !is_literal(token) => {
+                opcode |= value_of(token, labels, 0xFFF)?
+            }
+            (Operand::Nibble, None) if !is_literal(token) => {
+                opcode |= value_of(token, labels, 0xF)?
+            }
+            _ => return Ok(None),
+        }
+    }
+    Ok(Some(opcode))
+}
+
+/// V0 to VF
+fn register(token: &str) -> Option<u16> {
+    let digit = token.strip_prefix(['V', 'v'])?;
+    if digit.len() == 1 {
+        u16::from_str_radix(digit, 16).ok()
+    } else {
+        None
+    }
+}
+
+/// fixed tokens like I or [I] that are never a label
+fn is_literal(token: &str) -> bool {
+    OPCODES.iter().any(|spec| {
+        spec.operands.iter().any(|operand| match operand {
+            Operand::Literal(literal) => literal.eq_ignore_ascii_case(token),
+            _ => false,
+        })
+    })
+}
+
+/// number or label no bigger than `max`
+fn value_of(token: &str, labels: &HashMap<String, usize>, max: u16) -> Result<u16> {
+    let lower = token.to_ascii_lowercase();
+    let parsed = if let Some(hex) = lower.strip_prefix("0x").or(lower.strip_prefix('$')) {
+        usize::from_str_radix(hex, 16).ok()
+    } else if let Some(bin) = lower.strip_prefix("0b") {
+        usize::from_str_radix(bin, 2).ok()
+    } else if lower.starts_with(|c: char| c.is_ascii_digit()) {
+        lower.parse().ok()
+    } else {
+        match labels.get(token) {
+            Some(&addr) => Some(addr),
+            None => return err!("unknown label: {}", token),
+        }
+    };
+    match parsed {
+        Some(value) if value <= max as usize => Ok(value as u16),
+        Some(_) => err!("{} does not fit in {:#X}", token, max),
+        None => err!("invalid number: {}", token),
+    }
+}
+
+#[cfg(test)]
+mod assembler_test {
+    use super::*;
+    use crate::instruction::Instruction;
+
+    #[test]
+    fn test_assemble() {
+        let source = "
+            ; draw the 0 glyph forever
+            start:  LD V0, 0
+                    LD F, V0
+            loop:   DRW V0, V0, 5   ; at (0, 0)
+                    JP loop
+            data:   DB 0xFF, $0F, 0b1010, 7
+                    DW data
+        ";
+        assert_eq!(
+            assemble(source, 0x200).unwrap(),
+            vec![
+                0x60, 0x00, 0xF0, 0x29, 0xD0, 0x05, 0x12, 0x04, 0xFF, 0x0F, 0x0A, 0x07, 0x02, 0x08
+            ]
+        );
+
+        assert!(assemble("JP nowhere", 0x200).is_err());
+        assert!(assemble("LD V0, 0x100", 0x200).is_err());
+        assert!(assemble("MOV V0, V1", 0x200).is_err());
+        assert!(assemble("a: CLS\na: CLS", 0x200).is_err());
+    }
+
+    #[test]
+    fn test_disassembly_round_trip() {
+        for spec in OPCODES {
+            let opcode = spec.pattern | (0x0FFF & !spec.mask & 0x0A5B);
+            let instr = Instruction::new((opcode >> 8) as u8, opcode as u8);
+            assert_eq!(
+                assemble(&instr.disassemble(), 0x200).unwrap(),
+                opcode.to_be_bytes(),
+                "{}",
+                instr
+            );
+        }
+    }
+}
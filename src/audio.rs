@@ -1,4 +0,0 @@
-pub trait AudioPlay {
-    fn resume(&self);
-    fn pause(&self);
-}
@@ -0,0 +1,81 @@
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use cpal::{SampleFormat, Stream};
+
+use super::{AudioConfig, AudioPlay, ToneControl, ToneGenerator, PATTERN_BYTES};
+use crate::logging::AUDIO;
+use crate::{err, Result};
+
+/// Beep on the default output device through cpal, for frontends without
+/// SDL2. The stream only runs while the sound timer is set.
+pub struct CpalAudio {
+    stream: Stream,
+    control: ToneControl,
+}
+
+impl CpalAudio {
+    pub fn new(config: AudioConfig) -> Result<Self> {
+        let host = cpal::default_host();
+        let device = match host.default_output_device() {
+            Some(device) => device,
+            None => return err!("no audio output device"),
+        };
+        let supported = device.default_output_config()?;
+        if supported.sample_format() != SampleFormat::F32 {
+            return err!(
+                "unsupported sample format {:?}, expect f32",
+                supported.sample_format()
+            );
+        }
+        let stream_config: cpal::StreamConfig = supported.into();
+        info!(target: AUDIO, "{:?}", stream_config);
+
+        let channels = stream_config.channels as usize;
+        let mut generator = ToneGenerator::new(config, stream_config.sample_rate.0);
+        let control = generator.control();
+        let stream = device.build_output_stream(
+            &stream_config,
+            move |data: &mut [f32], _: &cpal::OutputCallbackInfo| {
+                // the same mono sample on every channel of a frame
+                let mut sample = [0.0];
+                for frame in data.chunks_mut(channels) {
+                    generator.fill(&mut sample);
+                    frame.fill(sample[0]);
+                }
+            },
+            |e| error!(target: AUDIO, "audio stream error: {}", e),
+            None,
+        )?;
+        stream.pause()?;
+        Ok(CpalAudio { stream, control })
+    }
+}
+
+impl AudioPlay for CpalAudio {
+    fn resume(&self) {
+        if let Err(e) = self.stream.play() {
+            error!(target: AUDIO, "can not play the audio stream: {}", e);
+        }
+    }
+
+    fn pause(&self) {
+        if let Err(e) = self.stream.pause() {
+            error!(target: AUDIO, "can not pause the audio stream: {}", e);
+        }
+    }
+
+    fn set_volume(&self, volume: f32) {
+        self.control.set_volume(volume)
+    }
+
+    fn set_frequency(&self, frequency: f32) {
+        self.control.set_frequency(frequency)
+    }
+
+    fn queue_pattern(&self, pattern: &[u8; PATTERN_BYTES], pitch: u8) {
+        self.control.queue_pattern(pattern, pitch)
+    }
+
+    fn clear_pattern(&self) {
+        self.control.clear_pattern()
+    }
+}
@@ -0,0 +1,274 @@
+use std::error::Error;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+
+use rand::Rng;
+
+use crate::{err, Result};
+
+#[cfg(feature = "cpal-audio")]
+mod cpal;
+#[cfg(feature = "sdl2-frontend")]
+mod sdl2;
+
+#[cfg(feature = "cpal-audio")]
+pub use self::cpal::CpalAudio;
+#[cfg(feature = "sdl2-frontend")]
+pub use self::sdl2::Sdl2Audio;
+
+/// Backend chosen at runtime, e.g. `NullAudio` when there is no audio device
+pub type DynAudio = Box<dyn AudioPlay>;
+
+pub trait AudioPlay {
+    fn resume(&self);
+    fn pause(&self);
+
+    /// change the amplitude between 0.0 and 1.0 while playing, 0.0 mutes
+    fn set_volume(&self, _volume: f32) {}
+
+    /// change the pitch of the square and sine tone in Hz
+    fn set_frequency(&self, _frequency: f32) {}
+
+    /// XO-CHIP: loop the 128 one bit samples of `pattern` instead of the
+    /// tone, at `pattern_rate(pitch)` samples per second
+    fn queue_pattern(&self, _pattern: &[u8; PATTERN_BYTES], _pitch: u8) {}
+
+    /// go back to the tone after `queue_pattern`, e.g. on reset
+    fn clear_pattern(&self) {}
+}
+
+impl<T: AudioPlay + ?Sized> AudioPlay for Box<T> {
+    fn resume(&self) {
+        (**self).resume()
+    }
+
+    fn pause(&self) {
+        (**self).pause()
+    }
+
+    fn set_volume(&self, volume: f32) {
+        (**self).set_volume(volume)
+    }
+
+    fn set_frequency(&self, frequency: f32) {
+        (**self).set_frequency(frequency)
+    }
+
+    fn queue_pattern(&self, pattern: &[u8; PATTERN_BYTES], pitch: u8) {
+        (**self).queue_pattern(pattern, pitch)
+    }
+
+    fn clear_pattern(&self) {
+        (**self).clear_pattern()
+    }
+}
+
+/// Audio backend that plays nothing, for frontends without sound, `--no-audio`
+/// or when the audio device can not be opened
+#[derive(Default)]
+pub struct NullAudio;
+
+impl AudioPlay for NullAudio {
+    fn resume(&self) {}
+
+    fn pause(&self) {}
+}
+
+/// Waveform of the beep
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Tone {
+    Square,
+    Sine,
+    Noise,
+}
+
+impl Tone {
+    pub const NAMES: [&'static str; 3] = ["square", "sine", "noise"];
+}
+
+impl FromStr for Tone {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "square" => Ok(Tone::Square),
+            "sine" => Ok(Tone::Sine),
+            "noise" => Ok(Tone::Noise),
+            _ => err!("unknown tone: {}, expect one of {:?}", s, Tone::NAMES),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AudioConfig {
+    pub tone: Tone,
+    /// pitch of the square and sine tone in Hz
+    pub frequency: f32,
+    /// amplitude between 0.0 and 1.0
+    pub volume: f32,
+}
+
+impl Default for AudioConfig {
+    fn default() -> Self {
+        AudioConfig {
+            tone: Tone::Square,
+            frequency: 440.0,
+            volume: 0.1,
+        }
+    }
+}
+
+/// XO-CHIP audio patterns are 128 one bit samples
+pub const PATTERN_BYTES: usize = 16;
+/// XO-CHIP pitch register value at reset, plays patterns at 4000Hz
+pub const DEFAULT_PITCH: u8 = 64;
+
+/// XO-CHIP: samples per second a pattern is played at for `pitch`
+pub fn pattern_rate(pitch: u8) -> f32 {
+    4000.0 * 2f32.powf((pitch as f32 - 64.0) / 48.0)
+}
+
+#[derive(Debug, Clone, Copy)]
+struct ToneSettings {
+    volume: f32,
+    frequency: f32,
+    /// XO-CHIP pattern and pitch played instead of the tone
+    pattern: Option<([u8; PATTERN_BYTES], u8)>,
+}
+
+/// Settings of a `ToneGenerator` shared with the frontend, the generator
+/// runs on the audio thread and reads them once per buffer
+#[derive(Debug, Clone)]
+pub struct ToneControl(Arc<Mutex<ToneSettings>>);
+
+impl ToneControl {
+    fn new(config: AudioConfig) -> Self {
+        ToneControl(Arc::new(Mutex::new(ToneSettings {
+            volume: config.volume,
+            frequency: config.frequency,
+            pattern: None,
+        })))
+    }
+
+    fn settings(&self) -> ToneSettings {
+        *self.0.lock().unwrap()
+    }
+
+    pub fn set_volume(&self, volume: f32) {
+        self.0.lock().unwrap().volume = volume.clamp(0.0, 1.0);
+    }
+
+    pub fn set_frequency(&self, frequency: f32) {
+        self.0.lock().unwrap().frequency = frequency;
+    }
+
+    pub fn queue_pattern(&self, pattern: &[u8; PATTERN_BYTES], pitch: u8) {
+        self.0.lock().unwrap().pattern = Some((*pattern, pitch));
+    }
+
+    pub fn clear_pattern(&self) {
+        self.0.lock().unwrap().pattern = None;
+    }
+}
+
+/// Phase accumulator based tone generator shared by the audio backends
+pub struct ToneGenerator {
+    tone: Tone,
+    // position in the current period or pattern, between 0.0 and 1.0
+    phase: f32,
+    sample_rate: f32,
+    control: ToneControl,
+}
+
+impl ToneGenerator {
+    pub fn new(config: AudioConfig, sample_rate: u32) -> Self {
+        ToneGenerator {
+            tone: config.tone,
+            phase: 0.0,
+            sample_rate: sample_rate as f32,
+            control: ToneControl::new(config),
+        }
+    }
+
+    /// handle to change the sound after the generator moved to the audio thread
+    pub fn control(&self) -> ToneControl {
+        self.control.clone()
+    }
+
+    /// fill a mono buffer with the next samples
+    pub fn fill(&mut self, out: &mut [f32]) {
+        let mut rng = rand::thread_rng();
+        let settings = self.control.settings();
+        let volume = settings.volume;
+        let phase_inc = match settings.pattern {
+            Some((_, pitch)) => pattern_rate(pitch) / (PATTERN_BYTES * 8) as f32,
+            None => settings.frequency,
+        } / self.sample_rate;
+
+        for x in out.iter_mut() {
+            *x = match (settings.pattern, self.tone) {
+                (Some((pattern, _)), _) => {
+                    let bit = (self.phase * (PATTERN_BYTES * 8) as f32) as usize;
+                    if pattern[bit / 8] >> (7 - bit % 8) & 1 == 1 {
+                        volume
+                    } else {
+                        -volume
+                    }
+                }
+                (None, Tone::Square) => {
+                    if self.phase < 0.5 {
+                        volume
+                    } else {
+                        -volume
+                    }
+                }
+                (None, Tone::Sine) => (self.phase * std::f32::consts::TAU).sin() * volume,
+                (None, Tone::Noise) => (rng.gen_range(0.0..2.0) - 1.0) * volume,
+            };
+            self.phase = (self.phase + phase_inc) % 1.0;
+        }
+    }
+}
+
+#[cfg(test)]
+mod audio_test {
+    use super::*;
+
+    #[test]
+    fn test_tone_control() {
+        let config = AudioConfig {
+            frequency: 11_025.0,
+            ..AudioConfig::default()
+        };
+        let mut generator = ToneGenerator::new(config, 44_100);
+        let mut out = [0.0; 4];
+        generator.fill(&mut out);
+        assert_eq!(out, [0.1, 0.1, -0.1, -0.1]);
+
+        // the handle changes the generator it came from
+        let control = generator.control();
+        control.set_volume(2.0);
+        generator.fill(&mut out);
+        assert_eq!(out, [1.0, 1.0, -1.0, -1.0]);
+        control.set_volume(0.0);
+        generator.fill(&mut out);
+        assert!(out.iter().all(|&x| x == 0.0));
+    }
+
+    #[test]
+    fn test_pattern() {
+        assert_eq!(pattern_rate(DEFAULT_PITCH), 4000.0);
+        assert_eq!(pattern_rate(DEFAULT_PITCH + 48), 8000.0);
+
+        // one sample per pattern bit
+        let mut generator = ToneGenerator::new(AudioConfig::default(), 4000);
+        let control = generator.control();
+        control.queue_pattern(&[0xF0; PATTERN_BYTES], DEFAULT_PITCH);
+        let mut out = [0.0; 8];
+        generator.fill(&mut out);
+        assert_eq!(out, [0.1, 0.1, 0.1, 0.1, -0.1, -0.1, -0.1, -0.1]);
+        control.clear_pattern();
+        generator.fill(&mut out);
+        assert!(out.iter().all(|&x| x == 0.1 || x == -0.1));
+    }
+}
@@ -0,0 +1,78 @@
+use sdl2::audio::AudioCallback;
+use sdl2::audio::AudioDevice;
+use sdl2::audio::AudioSpecDesired;
+use sdl2::AudioSubsystem;
+
+use super::{AudioConfig, AudioPlay, ToneControl, ToneGenerator, PATTERN_BYTES};
+use crate::logging::AUDIO;
+use crate::Result;
+
+#[allow(dead_code)]
+pub struct Sdl2Audio {
+    sdl_audio: AudioSubsystem,
+    device: AudioDevice<ToneCallback>,
+    control: ToneControl,
+}
+
+impl Sdl2Audio {
+    pub fn new(audio_subsystem: AudioSubsystem, config: AudioConfig) -> Result<Self> {
+        let desired_spec = AudioSpecDesired {
+            freq: Some(44_100),
+            channels: Some(1), // mono
+            samples: None,     // default sample size
+        };
+
+        // None: use default device
+        // set by the callback builder, open_playback calls it before returning
+        let mut control = None;
+        let device = audio_subsystem.open_playback(None, &desired_spec, |spec| {
+            // Show obtained AudioSpec
+            info!(target: AUDIO, "{:?}", spec);
+            let generator = ToneGenerator::new(config, spec.freq as u32);
+            control = Some(generator.control());
+            ToneCallback(generator)
+        })?;
+
+        Ok(Self {
+            sdl_audio: audio_subsystem,
+            device,
+            control: control.unwrap(),
+        })
+    }
+}
+
+impl AudioPlay for Sdl2Audio {
+    fn resume(&self) {
+        self.device.resume()
+    }
+
+    fn pause(&self) {
+        self.device.pause()
+    }
+
+    fn set_volume(&self, volume: f32) {
+        self.control.set_volume(volume)
+    }
+
+    fn set_frequency(&self, frequency: f32) {
+        self.control.set_frequency(frequency)
+    }
+
+    fn queue_pattern(&self, pattern: &[u8; PATTERN_BYTES], pitch: u8) {
+        self.control.queue_pattern(pattern, pitch)
+    }
+
+    fn clear_pattern(&self) {
+        self.control.clear_pattern()
+    }
+}
+
+struct ToneCallback(ToneGenerator);
+
+impl AudioCallback for ToneCallback {
+    type Channel = f32;
+
+    fn callback(&mut self, out: &mut [f32]) {
+        self.0.fill(out)
+    }
+}
@@ -0,0 +1,155 @@
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use crate::audio::NullAudio;
+use crate::machine::Machine;
+use crate::profiler::ProfileEntry;
+use crate::quirks::Quirks;
+use crate::rom::ROM;
+use crate::{headless, Result, DEFAULT_CLOCK_FREQ};
+
+/// How `bench` runs a rom
+#[derive(Debug, Clone, Copy)]
+pub struct BenchOptions {
+    pub cycles: u64,
+    pub quirks: Quirks,
+    /// random seed of CXNN, the same seed runs the same instructions
+    pub seed: u64,
+    /// use the decode cache of `--fast`
+    pub decode_cache: bool,
+}
+
+/// Throughput of a run and the time spent per instruction
+pub struct BenchReport {
+    /// instructions executed, fewer than asked when the rom halted
+    pub cycles: u64,
+    pub elapsed: Duration,
+    /// from a second, profiled run, timing every instruction slows it down
+    pub opcodes: Vec<ProfileEntry>,
+}
+
+impl BenchReport {
+    pub fn per_second(&self) -> f64 {
+        self.cycles as f64 / self.elapsed.as_secs_f64().max(f64::EPSILON)
+    }
+}
+
+impl fmt::Display for BenchReport {
+    /// the throughput, then `DXYN DRW    41.0%  12345   123.4ns` lines with
+    /// the average time of an instruction
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "{} instructions in {:.3}s, {:.0} instructions/s",
+            self.cycles,
+            self.elapsed.as_secs_f64(),
+            self.per_second()
+        )?;
+        let total = self
+            .opcodes
+            .iter()
+            .map(|entry| entry.count)
+            .sum::<u64>()
+            .max(1);
+        for entry in &self.opcodes {
+            writeln!(
+                f,
+                "{} {:<6} {:>5.1}% {:>10} {:>8.1}ns",
+                entry.name,
+                entry.mnemonic,
+                entry.count as f64 * 100.0 / total as f64,
+                entry.count,
+                entry.time.as_nanos() as f64 / entry.count as f64
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Run `rom` headless for `options.cycles` instructions, the timers tick
+/// at 60Hz relative to `DEFAULT_CLOCK_FREQ`. It is run twice, for the
+/// throughput and then with the profiler for the time per instruction.
+pub fn bench(rom: &ROM, options: BenchOptions) -> Result<BenchReport> {
+    let mut machine = new_machine(rom, options)?;
+    let start = Instant::now();
+    let cycles = run(&mut machine, options.cycles)?;
+    let elapsed = start.elapsed();
+
+    let mut machine = new_machine(rom, options)?;
+    machine.enable_profiler();
+    run(&mut machine, options.cycles)?;
+    let opcodes = machine
+        .profile()
+        .map_or_else(Vec::new, |profile| profile.entries());
+    Ok(BenchReport {
+        cycles,
+        elapsed,
+        opcodes,
+    })
+}
+
+fn new_machine(rom: &ROM, options: BenchOptions) -> Result<Machine<NullAudio>> {
+    let mut machine = Machine::new()?;
+    machine.set_quirks(options.quirks);
+    machine.set_seed(options.seed);
+    if options.decode_cache {
+        machine.enable_decode_cache();
+    }
+    machine.load_font()?;
+    machine.load_rom(rom)?;
+    Ok(machine)
+}
+
+/// the number of instructions run before the machine halted
+fn run(machine: &mut Machine<NullAudio>, cycles: u64) -> Result<u64> {
+    let frame = headless::frame_cycles(DEFAULT_CLOCK_FREQ) as u64;
+    for executed in 0..cycles {
+        if machine.is_halt() {
+            return Ok(executed);
+        }
+        machine.run_cycle()?;
+        if (executed + 1) % frame == 0 {
+            machine.tick_60hz();
+        }
+    }
+    Ok(cycles)
+}
+
+#[cfg(test)]
+mod bench_test {
+    use super::*;
+
+    #[test]
+    fn test_bench() {
+        // count V0 up and draw it, then halt when it wraps to 0
+        #[rustfmt::skip]
+        let rom = ROM::from_bytes("bench.ch8", vec![
+            0x70, 0x01, // 200: ADD V0, 1
+            0xF0, 0x29, // 202: LD F, V0
+            0xD1, 0x15, // 204: DRW V1, V1, 5
+            0x30, 0x00, // 206: SE V0, 0
+            0x12, 0x00, // 208: JP 200
+            0x00, 0xFD, // 20A: EXIT
+        ]);
+        let options = BenchOptions {
+            cycles: 100,
+            quirks: Quirks::schip(),
+            seed: 0,
+            decode_cache: false,
+        };
+        let report = bench(&rom, options).unwrap();
+        assert_eq!(report.cycles, 100);
+        assert_eq!(report.opcodes[0].count, 20);
+        assert_eq!(report.opcodes.len(), 5);
+
+        let options = BenchOptions {
+            cycles: 10_000,
+            decode_cache: true,
+            ..options
+        };
+        let report = bench(&rom, options).unwrap();
+        // the last of 256 rounds skips the jump to the exit
+        assert_eq!(report.cycles, 256 * 5);
+        assert!(report.to_string().contains("DXYN DRW"));
+    }
+}
@@ -0,0 +1,237 @@
+use std::fs::{self, File};
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+
+use crate::display::DisplayConfig;
+use crate::video::Video;
+use crate::{err, Result};
+
+/// RGBA image of the display, every CHIP-8 pixel becomes a `scale` x `scale` square
+pub struct Frame {
+    pub width: u32,
+    pub height: u32,
+    pub rgba: Vec<u8>,
+}
+
+impl Frame {
+    pub fn new(video: &Video, config: &DisplayConfig) -> Self {
+        let mut pixels = vec![0; video.width() * video.height() * 4];
+        video.render_rgba(&mut pixels, &config.palette());
+        let scale = config.scale as usize;
+        let (width, height) = (video.width() * scale, video.height() * scale);
+        let mut rgba = Vec::with_capacity(width * height * 4);
+        for row in pixels.chunks_exact(video.width() * 4) {
+            let start = rgba.len();
+            for pixel in row.chunks_exact(4) {
+                for _ in 0..scale {
+                    rgba.extend_from_slice(pixel);
+                }
+            }
+            for _ in 1..scale {
+                rgba.extend_from_within(start..start + width * 4);
+            }
+        }
+        Frame {
+            width: width as u32,
+            height: height as u32,
+            rgba,
+        }
+    }
+
+    pub fn save_png<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let writer = BufWriter::new(File::create(path)?);
+        let mut encoder = png::Encoder::new(writer, self.width, self.height);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.write_header()?.write_image_data(&self.rgba)?;
+        Ok(())
+    }
+}
+
+/// Display snapshot kept by the recorder, row-major with the palette index
+/// of every pixel
+struct RecordedFrame {
+    width: usize,
+    height: usize,
+    pixels: Vec<u8>,
+}
+
+/// Collects the display at 60Hz and encodes it as an animated GIF when done
+#[derive(Default)]
+pub struct GifRecorder {
+    frames: Vec<RecordedFrame>,
+}
+
+impl GifRecorder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    /// record one 60Hz frame of the display
+    pub fn push(&mut self, video: &Video) {
+        let (width, height) = (video.width(), video.height());
+        let mut pixels = Vec::with_capacity(width * height);
+        for row in video.rows() {
+            pixels.extend(row.map(|on| on as u8));
+        }
+        self.frames.push(RecordedFrame {
+            width,
+            height,
+            pixels,
+        });
+    }
+
+    /// Encode the recording, frames are scaled up to the largest recorded
+    /// resolution and `config.scale`. GIF delays are in 1/100s and viewers
+    /// slow down delays below 2, so unchanged and too short frames are merged.
+    pub fn save_gif<P: AsRef<Path>>(&self, path: P, config: &DisplayConfig) -> Result<()> {
+        if self.frames.is_empty() {
+            return err!("nothing was recorded");
+        }
+        let columns = self.frames.iter().map(|f| f.width).max().unwrap();
+        let rows = self.frames.iter().map(|f| f.height).max().unwrap();
+        let scale = config.scale as usize;
+        let (width, height) = (columns * scale, rows * scale);
+        if width > u16::MAX as usize || height > u16::MAX as usize {
+            return err!(
+                "{}x{} is too big for a GIF, try a smaller scale",
+                width,
+                height
+            );
+        }
+
+        let palette: Vec<u8> = config
+            .palette()
+            .colors
+            .iter()
+            .flat_map(|color| [color.r, color.g, color.b])
+            .collect();
+        let writer = BufWriter::new(File::create(path)?);
+        let mut encoder = gif::Encoder::new(writer, width as u16, height as u16, &palette)?;
+        encoder.set_repeat(gif::Repeat::Infinite)?;
+
+        let mut write = |frame: &RecordedFrame, delay: usize| -> Result<()> {
+            // lores frames are doubled when the recording also has hires frames
+            let factor = scale * columns / frame.width;
+            let mut indices = Vec::with_capacity(width * height);
+            for y in 0..height {
+                let row = &frame.pixels[y / factor * frame.width..][..frame.width];
+                indices.extend((0..width).map(|x| row[x / factor]));
+            }
+            let mut gif_frame =
+                gif::Frame::from_indexed_pixels(width as u16, height as u16, indices, None);
+            gif_frame.delay = delay.min(u16::MAX as usize) as u16;
+            encoder.write_frame(&gif_frame)?;
+            Ok(())
+        };
+
+        // the frame waiting to be written and when it was shown, in 1/100s
+        let mut pending = (&self.frames[0], 0);
+        for (n, frame) in self.frames.iter().enumerate().skip(1) {
+            if frame.width == pending.0.width && frame.pixels == pending.0.pixels {
+                continue;
+            }
+            let time = n * 100 / 60;
+            if time - pending.1 < 2 {
+                pending.0 = frame;
+            } else {
+                write(pending.0, time - pending.1)?;
+                pending = (frame, time);
+            }
+        }
+        let end = self.frames.len() * 100 / 60;
+        write(pending.0, (end - pending.1).max(2))?;
+        Ok(())
+    }
+}
+
+/// `dir/<prefix>-<local time>.<extension>`, the directory is created if needed
+pub fn timestamped_path(dir: &Path, prefix: &str, extension: &str) -> Result<PathBuf> {
+    fs::create_dir_all(dir)?;
+    Ok(dir.join(format!("{}-{}.{}", prefix, timestamp(), extension)))
+}
+
+/// the local time for file names, like `20240102-150405.123`
+pub fn timestamp() -> String {
+    chrono::Local::now().format("%Y%m%d-%H%M%S%.3f").to_string()
+}
+
+#[cfg(test)]
+mod capture_test {
+    use super::*;
+    use crate::display::Color;
+
+    #[test]
+    fn frame_and_png() {
+        let mut video = Video::new(64, 32);
+        video.set_pixel(1, 0, true);
+        let config = DisplayConfig {
+            scale: 2,
+            foreground: Color::rgb(0xFF, 0xB0, 0x00),
+            ..DisplayConfig::default()
+        };
+        let frame = Frame::new(&video, &config);
+        assert_eq!((frame.width, frame.height), (128, 64));
+        assert_eq!(frame.rgba.len(), 128 * 64 * 4);
+        // pixel (1, 0) covers x 2..4 and y 0..2
+        assert_eq!(frame.rgba[..8], [0, 0, 0, 0xFF, 0, 0, 0, 0xFF]);
+        assert_eq!(frame.rgba[8..12], [0xFF, 0xB0, 0x00, 0xFF]);
+        assert_eq!(
+            frame.rgba[128 * 4 + 12..128 * 4 + 16],
+            [0xFF, 0xB0, 0x00, 0xFF]
+        );
+
+        let path = std::env::temp_dir().join("rchip8-capture-test.png");
+        frame.save_png(&path).unwrap();
+        let png = fs::read(&path).unwrap();
+        assert_eq!(png[..8], [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A]);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn record_gif() {
+        let mut recorder = GifRecorder::new();
+        let config = DisplayConfig {
+            scale: 1,
+            ..DisplayConfig::default()
+        };
+        let path = std::env::temp_dir().join("rchip8-capture-test.gif");
+        assert!(recorder.save_gif(&path, &config).is_err());
+
+        let mut video = Video::new(64, 32);
+        for n in 0..30 {
+            video.set_pixel(n, n, true);
+            recorder.push(&video);
+        }
+        // switching to hires doubles the whole recording
+        video.set_hires(true);
+        video.draw(0, 0, 1, &[0xFF], false);
+        recorder.push(&video);
+        assert_eq!(recorder.len(), 31);
+
+        recorder.save_gif(&path, &config).unwrap();
+        let mut decoder = gif::DecodeOptions::new()
+            .read_info(File::open(&path).unwrap())
+            .unwrap();
+        assert_eq!((decoder.width(), decoder.height()), (128, 64));
+        let mut frames = 0;
+        let mut total_delay = 0;
+        while let Some(frame) = decoder.read_next_frame().unwrap() {
+            assert!(frame.delay >= 2);
+            frames += 1;
+            total_delay += frame.delay as usize;
+        }
+        // 31 frames at 60Hz last about 52/100s, short frames are merged
+        assert!(frames < 31);
+        assert_eq!(total_delay, 52);
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,146 @@
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::machine::MEMORY_SIZE;
+use crate::{err, Result};
+
+/// One cheat code, `2A4:6009` writes the bytes 60 09 to 0x2A4 whenever the
+/// rom is loaded or reset, `3F0=03` keeps 0x3F0 at 3 by writing it every
+/// 60Hz frame
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Cheat {
+    Patch { addr: u16, bytes: Vec<u8> },
+    Freeze { addr: u16, value: u8 },
+}
+
+impl FromStr for Cheat {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.trim();
+        let (addr, value, patch) = match (s.split_once(':'), s.split_once('=')) {
+            (Some((addr, bytes)), None) => (addr, bytes, true),
+            (None, Some((addr, value))) => (addr, value, false),
+            _ => return err!("invalid cheat: {}, expect ADDR:BYTES or ADDR=BYTE", s),
+        };
+        let hex = |s: &str| s.trim().trim_start_matches("0x").to_string();
+        let addr = u16::from_str_radix(&hex(addr), 16)
+            .map_err(|e| format!("invalid cheat address in {}: {}", s, e))?;
+        let digits: String = hex(value).split_whitespace().collect();
+        if digits.is_empty() || !digits.len().is_multiple_of(2) {
+            return err!("invalid cheat: {}, expect bytes as pairs of hex digits", s);
+        }
+        let bytes = (0..digits.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&digits[i..i + 2], 16))
+            .collect::<std::result::Result<Vec<u8>, _>>()
+            .map_err(|e| format!("invalid cheat bytes in {}: {}", s, e))?;
+        if addr as usize + bytes.len() > MEMORY_SIZE {
+            return err!("invalid cheat: {}, it writes past the end of memory", s);
+        }
+        match (patch, bytes.as_slice()) {
+            (true, _) => Ok(Cheat::Patch { addr, bytes }),
+            (false, &[value]) => Ok(Cheat::Freeze { addr, value }),
+            (false, _) => err!("invalid cheat: {}, a frozen address takes one byte", s),
+        }
+    }
+}
+
+/// The cheats of a machine, from a cheat file and `--cheat`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Cheats {
+    codes: Vec<Cheat>,
+}
+
+impl Cheats {
+    /// Load a cheat file with one code per line, `#` starts a comment:
+    ///
+    /// ```text
+    /// # infinite lives
+    /// 3F0=03
+    /// # skip the title screen
+    /// 200:1300
+    /// ```
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let mut cheats = Cheats::default();
+        for (number, line) in fs::read_to_string(path)?.lines().enumerate() {
+            let code = line.split('#').next().unwrap_or("").trim();
+            if code.is_empty() {
+                continue;
+            }
+            let cheat = code
+                .parse()
+                .map_err(|e| format!("{}:{}: {}", path.display(), number + 1, e))?;
+            cheats.add(cheat);
+        }
+        Ok(cheats)
+    }
+
+    pub fn add(&mut self, cheat: Cheat) {
+        self.codes.push(cheat);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.codes.is_empty()
+    }
+
+    pub(crate) fn patches(&self) -> impl Iterator<Item = (usize, &[u8])> {
+        self.codes.iter().filter_map(|cheat| match cheat {
+            Cheat::Patch { addr, bytes } => Some((*addr as usize, bytes.as_slice())),
+            Cheat::Freeze { .. } => None,
+        })
+    }
+
+    pub(crate) fn freezes(&self) -> impl Iterator<Item = (usize, u8)> + '_ {
+        self.codes.iter().filter_map(|cheat| match cheat {
+            Cheat::Freeze { addr, value } => Some((*addr as usize, *value)),
+            Cheat::Patch { .. } => None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod cheats_test {
+    use super::*;
+
+    #[test]
+    fn test_parse_cheats() {
+        assert_eq!(
+            "2A4:60 09".parse::<Cheat>().unwrap(),
+            Cheat::Patch {
+                addr: 0x2A4,
+                bytes: vec![0x60, 0x09]
+            }
+        );
+        assert_eq!(
+            "0x3F0 = 03".parse::<Cheat>().unwrap(),
+            Cheat::Freeze {
+                addr: 0x3F0,
+                value: 3
+            }
+        );
+        for invalid in ["3F0", "3F0=0304", "2A4:609", "FFF:6009", "XYZ=01"] {
+            assert!(invalid.parse::<Cheat>().is_err(), "{}", invalid);
+        }
+
+        let path = std::env::temp_dir().join(format!("rchip8-cheats-{}.txt", std::process::id()));
+        fs::write(&path, "# lives\n3F0=03 # frozen\n\n200:1300\n").unwrap();
+        let cheats = Cheats::load(&path).unwrap();
+        assert_eq!(
+            cheats.patches().collect::<Vec<_>>(),
+            [(0x200, &[0x13, 0x00][..])]
+        );
+        assert_eq!(cheats.freezes().collect::<Vec<_>>(), [(0x3F0, 3)]);
+        fs::write(&path, "3F0=03\n3F0\n").unwrap();
+        let e = Cheats::load(&path).unwrap_err().to_string();
+        assert!(
+            e.ends_with(":2: invalid cheat: 3F0, expect ADDR:BYTES or ADDR=BYTE"),
+            "{}",
+            e
+        );
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,320 @@
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// `thread::sleep` can wake up late by about this much, the rest of the
+/// wait before a deadline is spun instead. Windows sleeps in 1ms steps
+/// at best, other systems are much finer.
+const SPIN_MARGIN: Duration = if cfg!(windows) {
+    Duration::from_millis(2)
+} else {
+    Duration::from_micros(250)
+};
+/// a pacer further behind than this skips ahead instead of catching up, so
+/// does the timer clock
+pub const MAX_LAG: Duration = Duration::from_millis(100);
+
+/// the ticks at `freq` in `MAX_LAG`, at least one
+pub const fn max_catch_up(freq: u64) -> u64 {
+    let ticks = (MAX_LAG.as_nanos() * freq as u128 / 1_000_000_000) as u64;
+    if ticks > 0 {
+        ticks
+    } else {
+        1
+    }
+}
+
+/// Ticks at `freq` per second. The ticks due are counted from a fixed start,
+/// so rounding and late wake ups are made up by the next ticks instead of
+/// accumulating.
+pub struct Pacer {
+    freq: u64,
+    start: Instant,
+    // ticks taken since `start`
+    taken: u64,
+}
+
+impl Pacer {
+    pub fn new(freq: u64) -> Self {
+        Pacer {
+            freq,
+            start: Instant::now(),
+            taken: 0,
+        }
+    }
+
+    /// count from now at a new frequency, nothing happens if it is the same
+    pub fn set_freq(&mut self, freq: u64) {
+        if freq != self.freq {
+            self.freq = freq;
+            self.restart(Instant::now());
+        }
+    }
+
+    fn restart(&mut self, now: Instant) {
+        self.start = now;
+        self.taken = 0;
+    }
+
+    /// Ticks due at `now` that were not taken yet and the dropped ones. A
+    /// caller that stalled, like a window loop while the window is dragged,
+    /// gets at most `max_catch_up` ticks and the rest is dropped, so it
+    /// resyncs instead of bursting through them.
+    pub fn take(&mut self, now: Instant) -> (u64, u64) {
+        let elapsed = now.saturating_duration_since(self.start);
+        let total = (elapsed.as_nanos() * self.freq as u128 / 1_000_000_000) as u64;
+        let due = total - self.taken;
+        let max_due = max_catch_up(self.freq);
+        if due > max_due {
+            self.restart(now);
+            return (max_due, due - max_due);
+        }
+        self.taken = total;
+        (due, 0)
+    }
+
+    /// when the next tick is due, `freq` must not be 0
+    pub fn next(&self) -> Instant {
+        let nanos = (self.taken + 1) as u128 * 1_000_000_000 / self.freq as u128;
+        self.start + Duration::from_nanos(nanos as u64)
+    }
+}
+
+/// Sleep until `deadline`, more precise than `thread::sleep` alone
+pub fn sleep_until(deadline: Instant) {
+    let now = Instant::now();
+    if deadline > now + SPIN_MARGIN {
+        thread::sleep(deadline - now - SPIN_MARGIN);
+    }
+    while Instant::now() < deadline {
+        thread::yield_now();
+    }
+}
+
+/// Intervals between presented frames, for reports of the frame pacing
+pub struct FrameStats {
+    // the refresh period frames are expected at
+    period: Duration,
+    last: Option<Instant>,
+    intervals: Vec<Duration>,
+}
+
+impl FrameStats {
+    /// frames expected at `refresh_rate` per second
+    pub fn new(refresh_rate: u32) -> Self {
+        FrameStats {
+            period: Duration::from_secs(1) / refresh_rate.max(1),
+            last: None,
+            intervals: vec![],
+        }
+    }
+
+    /// a frame was presented at `now`
+    pub fn present(&mut self, now: Instant) {
+        if let Some(last) = self.last {
+            self.intervals.push(now.saturating_duration_since(last));
+        }
+        self.last = Some(now);
+    }
+
+    /// refreshes that showed the same frame again, an interval of two
+    /// periods dropped one frame
+    pub fn dropped(&self) -> u64 {
+        let period = self.period.as_secs_f64();
+        self.intervals
+            .iter()
+            .map(|interval| (interval.as_secs_f64() / period).round().max(1.0) as u64 - 1)
+            .sum()
+    }
+
+    /// the `percent` percentile of the frame intervals
+    pub fn percentile(&self, percent: usize) -> Duration {
+        let mut sorted = self.intervals.clone();
+        sorted.sort();
+        match sorted.len() {
+            0 => Duration::ZERO,
+            len => sorted[(len - 1) * percent.min(100) / 100],
+        }
+    }
+
+    /// like `60 frames p50 16.7ms p95 17.0ms p99 18.2ms max 33.4ms 1 dropped`
+    pub fn summary(&self) -> String {
+        let ms = |duration: Duration| duration.as_secs_f64() * 1000.0;
+        format!(
+            "{} frames p50 {:.1}ms p95 {:.1}ms p99 {:.1}ms max {:.1}ms {} dropped",
+            self.intervals.len(),
+            ms(self.percentile(50)),
+            ms(self.percentile(95)),
+            ms(self.percentile(99)),
+            ms(self.percentile(100)),
+            self.dropped()
+        )
+    }
+
+    /// forget the intervals so far, for periodic reports
+    pub fn clear(&mut self) {
+        self.intervals.clear();
+    }
+}
+
+/// Rates over the last second for the statistics overlay: presented frames,
+/// executed instructions and timer ticks per second
+pub struct RunStats {
+    start: Instant,
+    frames: u64,
+    // the machine counters at `start`
+    cycles: u64,
+    ticks: u64,
+    pub fps: f64,
+    pub ips: f64,
+    /// timer ticks per second, 60 when the timers keep up
+    pub tps: f64,
+}
+
+impl RunStats {
+    /// start measuring at `now` from the `Machine::cycles` and
+    /// `Machine::timer_ticks` of the machine
+    pub fn new(now: Instant, cycles: u64, ticks: u64) -> Self {
+        RunStats {
+            start: now,
+            frames: 0,
+            cycles,
+            ticks,
+            fps: 0.0,
+            ips: 0.0,
+            tps: 0.0,
+        }
+    }
+
+    /// a frame was presented
+    pub fn frame(&mut self) {
+        self.frames += 1;
+    }
+
+    /// Update the rates when a second passed since the last update. Counters
+    /// that went back, after a reset or a loaded state, count from 0.
+    pub fn update(&mut self, now: Instant, cycles: u64, ticks: u64) {
+        let elapsed = now.saturating_duration_since(self.start).as_secs_f64();
+        if elapsed < 1.0 {
+            return;
+        }
+        let since = |count: u64, start: u64| count.checked_sub(start).unwrap_or(count);
+        self.fps = self.frames as f64 / elapsed;
+        self.ips = since(cycles, self.cycles) as f64 / elapsed;
+        self.tps = since(ticks, self.ticks) as f64 / elapsed;
+        self.start = now;
+        self.frames = 0;
+        self.cycles = cycles;
+        self.ticks = ticks;
+    }
+
+    /// like `60.0 FPS`, `700 IPS of 700 100%` and `60.0 ticks DT=00 ST=00`,
+    /// `target` is the instructions per second the clock runs at, if it has
+    /// a limit
+    pub fn lines(&self, target: Option<u64>, delay_timer: u8, sound_timer: u8) -> Vec<String> {
+        let ips = match target {
+            Some(target) => format!(
+                "{:.0} IPS of {} {:.0}%",
+                self.ips,
+                target,
+                self.ips * 100.0 / target.max(1) as f64
+            ),
+            None => format!("{:.0} IPS", self.ips),
+        };
+        vec![
+            format!("{:.1} FPS", self.fps),
+            ips,
+            format!(
+                "{:.1} ticks DT={:02X} ST={:02X}",
+                self.tps, delay_timer, sound_timer
+            ),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod clock_test {
+    use super::*;
+
+    #[test]
+    fn test_pacer() {
+        let mut pacer = Pacer::new(60);
+        let start = pacer.start;
+        let at = |millis| start + Duration::from_millis(millis);
+        assert_eq!(pacer.take(at(10)), (0, 0));
+        assert_eq!(pacer.next(), start + Duration::from_nanos(16_666_666));
+        // a late call gets the missed ticks and the next one is not delayed
+        assert_eq!(pacer.take(at(40)), (2, 0));
+        assert_eq!(pacer.next(), start + Duration::from_nanos(50_000_000));
+        assert_eq!(pacer.take(at(50)), (1, 0));
+        assert_eq!(pacer.take(at(100)), (3, 0));
+        assert_eq!(pacer.taken, 6);
+
+        // far behind only catches up MAX_LAG
+        let mut pacer = Pacer::new(1000);
+        let start = pacer.start;
+        assert_eq!(pacer.take(start + Duration::from_secs(5)), (100, 4900));
+        assert_eq!(pacer.take(start + Duration::from_secs(5)), (0, 0));
+        assert_eq!(max_catch_up(60), 6);
+        assert_eq!(max_catch_up(1), 1);
+    }
+
+    #[test]
+    fn test_sleep_until() {
+        let deadline = Instant::now() + Duration::from_millis(5);
+        sleep_until(deadline);
+        assert!(Instant::now() >= deadline);
+    }
+
+    #[test]
+    fn test_frame_stats() {
+        let mut stats = FrameStats::new(60);
+        assert_eq!(
+            stats.summary(),
+            "0 frames p50 0.0ms p95 0.0ms p99 0.0ms max 0.0ms 0 dropped"
+        );
+        let start = Instant::now();
+        let mut now = start;
+        for n in 0..=100 {
+            stats.present(now);
+            // every 50th frame is late by a whole refresh
+            let frames = if n % 50 == 49 { 2 } else { 1 };
+            now += Duration::from_micros(16_667) * frames;
+        }
+        assert_eq!(stats.dropped(), 2);
+        assert_eq!(stats.percentile(50), Duration::from_micros(16_667));
+        assert_eq!(stats.percentile(100), Duration::from_micros(33_334));
+        assert_eq!(
+            stats.summary(),
+            "100 frames p50 16.7ms p95 16.7ms p99 33.3ms max 33.3ms 2 dropped"
+        );
+
+        // the next report counts from the last frame
+        stats.clear();
+        stats.present(now);
+        assert!(stats.summary().starts_with("1 frames p50 16.7ms"));
+        assert_eq!(stats.dropped(), 0);
+    }
+
+    #[test]
+    fn test_run_stats() {
+        let start = Instant::now();
+        let mut stats = RunStats::new(start, 100, 10);
+        for _ in 0..30 {
+            stats.frame();
+        }
+        // nothing before a second passed
+        stats.update(start + Duration::from_millis(500), 450, 40);
+        assert_eq!(stats.fps, 0.0);
+        stats.update(start + Duration::from_secs(1), 800, 70);
+        assert_eq!((stats.fps, stats.ips, stats.tps), (30.0, 700.0, 60.0));
+        assert_eq!(
+            stats.lines(Some(1400), 0x3C, 0),
+            ["30.0 FPS", "700 IPS of 1400 50%", "60.0 ticks DT=3C ST=00"]
+        );
+        assert_eq!(stats.lines(None, 0, 0)[1], "700 IPS");
+
+        // a reset starts the instructions from 0
+        stats.update(start + Duration::from_secs(2), 350, 130);
+        assert_eq!((stats.fps, stats.ips, stats.tps), (0.0, 350.0, 60.0));
+    }
+}
@@ -0,0 +1,313 @@
+use std::fmt;
+use std::io::BufRead;
+
+use crate::audio::NullAudio;
+use crate::instruction::Instruction;
+use crate::machine::{Machine, REGISTER_COUNT};
+use crate::{err, Quirks, Result, DEFAULT_CLOCK_FREQ, ROM};
+
+/// The first cycle where two runs of a rom disagree
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Divergence {
+    /// counted from 1 like the lines of `--trace`
+    pub cycle: usize,
+    /// address and opcode of the instruction executed in that cycle
+    pub pc: u16,
+    pub opcode: u16,
+    /// what differs, like `V3: 2A != 00`
+    pub differences: Vec<String>,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let instr = Instruction::new((self.opcode >> 8) as u8, self.opcode as u8);
+        write!(
+            f,
+            "cycle {} at {:04X} {:04X} {}: {}",
+            self.cycle,
+            self.pc,
+            self.opcode,
+            instr.disassemble(),
+            self.differences.join(", ")
+        )
+    }
+}
+
+/// Quirks from a profile and `name=value` settings separated by commas,
+/// like `chip8,shift=vx`. Without a profile the settings change the default.
+pub fn parse_quirks(spec: &str) -> Result<Quirks> {
+    let mut quirks = Quirks::default();
+    for (n, part) in spec.split(',').map(str::trim).enumerate() {
+        if part.contains('=') {
+            quirks.set(part)?;
+        } else if n == 0 {
+            quirks = part.parse()?;
+        } else {
+            return err!("the profile {} has to come first in {}", part, spec);
+        }
+    }
+    Ok(quirks)
+}
+
+fn new_machine(rom: &ROM, quirks: Quirks, seed: u64) -> Result<Machine<NullAudio>> {
+    let mut machine = Machine::new()?;
+    machine.set_quirks(quirks);
+    machine.set_seed(seed);
+    machine.load_font()?;
+    machine.load_rom(rom)?;
+    Ok(machine)
+}
+
+/// A machine run like `--headless`, the timers tick at 60Hz relative to
+/// `DEFAULT_CLOCK_FREQ`
+struct Runner {
+    machine: Machine<NullAudio>,
+    executed: usize,
+}
+
+impl Runner {
+    /// run one cycle, the error message when the machine stopped
+    fn cycle(&mut self) -> Option<String> {
+        if let Err(e) = self.machine.run_cycle() {
+            return Some(e.to_string());
+        }
+        self.executed += 1;
+        let ticks = |cycles: usize| cycles as u64 * 60 / DEFAULT_CLOCK_FREQ;
+        if ticks(self.executed) > ticks(self.executed - 1) {
+            self.machine.tick_60hz();
+        }
+        None
+    }
+
+    fn next_instruction(&self) -> (u16, u16) {
+        let pc = self.machine.pc();
+        (pc, self.machine.next_opcode().unwrap_or(0))
+    }
+}
+
+/// the registers of `a` and `b` that differ, as `name: a != b`
+fn register_differences(a: &Machine<NullAudio>, b: &Machine<NullAudio>) -> Vec<String> {
+    let mut differences = vec![];
+    if a.pc() != b.pc() {
+        differences.push(format!("PC: {:04X} != {:04X}", a.pc(), b.pc()));
+    }
+    for (x, (va, vb)) in a.registers().iter().zip(b.registers()).enumerate() {
+        if va != vb {
+            differences.push(format!("V{:X}: {:02X} != {:02X}", x, va, vb));
+        }
+    }
+    if a.i() != b.i() {
+        differences.push(format!("I: {:04X} != {:04X}", a.i(), b.i()));
+    }
+    differences
+}
+
+/// Run `rom` with the quirks `a` and with `b` side by side for up to
+/// `cycles` instructions and find the first cycle after which PC, the
+/// registers or the display differ. Both get the random `seed`. None when
+/// the runs agree.
+pub fn compare_quirks(
+    rom: &ROM,
+    a: Quirks,
+    b: Quirks,
+    seed: u64,
+    cycles: usize,
+) -> Result<Option<Divergence>> {
+    let mut a = Runner {
+        machine: new_machine(rom, a, seed)?,
+        executed: 0,
+    };
+    let mut b = Runner {
+        machine: new_machine(rom, b, seed)?,
+        executed: 0,
+    };
+    for cycle in 1..=cycles {
+        let (halted_a, halted_b) = (a.machine.is_halt(), b.machine.is_halt());
+        if halted_a && halted_b {
+            break;
+        }
+        let (pc, opcode) = a.next_instruction();
+        let mut differences = vec![];
+        if halted_a != halted_b {
+            differences.push(format!("halted: {} != {}", halted_a, halted_b));
+        } else {
+            let (error_a, error_b) = (a.cycle(), b.cycle());
+            if error_a.is_some() || error_b.is_some() {
+                differences.push(format!(
+                    "stopped: {} != {}",
+                    error_a.as_deref().unwrap_or("no"),
+                    error_b.as_deref().unwrap_or("no")
+                ));
+            }
+            differences.extend(register_differences(&a.machine, &b.machine));
+            if a.machine.get_display() != b.machine.get_display() {
+                differences.push("display".to_string());
+            }
+        }
+        if !differences.is_empty() {
+            return Ok(Some(Divergence {
+                cycle,
+                pc,
+                opcode,
+                differences,
+            }));
+        }
+    }
+    Ok(None)
+}
+
+/// One line of a `--trace` file: the address and opcode of the instruction
+/// and the registers it changed
+struct TraceLine {
+    pc: u16,
+    opcode: u16,
+    registers: Vec<(usize, u8)>,
+    i: Option<u32>,
+}
+
+fn parse_trace_line(line: &str) -> Result<TraceLine> {
+    let mut fields = line.split_whitespace().skip(1);
+    let mut hex = |what: &str| match fields.next() {
+        Some(field) => Ok(u16::from_str_radix(field, 16)?),
+        None => err!("missing {}", what),
+    };
+    let (pc, opcode) = (hex("pc")?, hex("opcode")?);
+    let mut trace = TraceLine {
+        pc,
+        opcode,
+        registers: vec![],
+        i: None,
+    };
+    // the disassembly has no `=`, only the changes do
+    for change in line.split_whitespace().filter(|field| field.contains('=')) {
+        match change.split_once('=') {
+            Some(("I", value)) => trace.i = Some(u32::from_str_radix(value, 16)?),
+            Some((name, value)) if name.len() == 2 && name.starts_with('V') => {
+                let x = usize::from_str_radix(&name[1..], 16)?;
+                trace.registers.push((x, u8::from_str_radix(value, 16)?));
+            }
+            _ => return err!("invalid register change: {}", change),
+        }
+    }
+    Ok(trace)
+}
+
+/// Run `rom` with `quirks` along a trace in the format of `--trace`, from
+/// this emulator or converted from another one, and find the first cycle
+/// where PC, the opcode or the registers differ from the trace. The display
+/// is not in the trace and not compared, CXNN only agrees with a trace
+/// recorded with the same `--seed`. None when the run follows the whole
+/// trace.
+pub fn compare_trace<R: BufRead>(
+    rom: &ROM,
+    quirks: Quirks,
+    seed: u64,
+    trace: R,
+) -> Result<Option<Divergence>> {
+    let mut runner = Runner {
+        machine: new_machine(rom, quirks, seed)?,
+        executed: 0,
+    };
+    let mut registers = [0; REGISTER_COUNT];
+    let mut i = 0;
+    for (n, line) in trace.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let cycle = n + 1;
+        let expected =
+            parse_trace_line(&line).map_err(|e| format!("trace line {}: {}", cycle, e))?;
+        let (pc, opcode) = runner.next_instruction();
+        let mut differences = vec![];
+        if pc != expected.pc {
+            differences.push(format!("PC: {:04X} != {:04X}", pc, expected.pc));
+        } else if opcode != expected.opcode {
+            differences.push(format!("opcode: {:04X} != {:04X}", opcode, expected.opcode));
+        } else if runner.machine.is_halt() {
+            differences.push("halted: true != false".to_string());
+        } else {
+            if let Some(e) = runner.cycle() {
+                differences.push(format!("stopped: {} != no", e));
+            }
+            for (x, value) in expected.registers {
+                registers[x] = value;
+            }
+            i = expected.i.unwrap_or(i);
+            for (x, (v, expected)) in runner.machine.registers().iter().zip(registers).enumerate() {
+                if *v != expected {
+                    differences.push(format!("V{:X}: {:02X} != {:02X}", x, v, expected));
+                }
+            }
+            if runner.machine.i() != i {
+                differences.push(format!("I: {:04X} != {:04X}", runner.machine.i(), i));
+            }
+        }
+        if !differences.is_empty() {
+            return Ok(Some(Divergence {
+                cycle,
+                pc,
+                opcode,
+                differences,
+            }));
+        }
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod compare_test {
+    use super::*;
+    use crate::trace::Tracer;
+
+    #[test]
+    fn test_compare() {
+        assert_eq!(parse_quirks("chip8").unwrap(), Quirks::chip8());
+        let mut quirks = Quirks::chip8();
+        quirks.set("shift=vx").unwrap();
+        assert_eq!(parse_quirks("chip8, shift=vx").unwrap(), quirks);
+        assert!(parse_quirks("shift=vx,chip8").is_err());
+        assert!(parse_quirks("vip").is_err());
+
+        #[rustfmt::skip]
+        let rom = ROM::from_bytes("rom", vec![
+            0x61, 0x03, // 200: LD V1, 3
+            0x62, 0x10, // 202: LD V2, 0x10
+            0x81, 0x26, // 204: SHR V1, V2
+            0x12, 0x06, // 206: JP 206
+        ]);
+        assert_eq!(
+            compare_quirks(&rom, Quirks::chip8(), Quirks::chip8(), 0, 100).unwrap(),
+            None
+        );
+        let divergence = compare_quirks(&rom, Quirks::chip8(), Quirks::schip(), 0, 100)
+            .unwrap()
+            .unwrap();
+        assert_eq!((divergence.cycle, divergence.pc), (3, 0x204));
+        assert_eq!(divergence.differences, ["V1: 08 != 01", "VF: 00 != 01"]);
+        assert!(divergence.to_string().starts_with("cycle 3 at 0204 8126 "));
+
+        // a trace of the machine itself is followed to the end
+        let mut machine = new_machine(&rom, Quirks::chip8(), 0).unwrap();
+        let mut trace = vec![];
+        let mut tracer = Tracer::new(&mut trace);
+        for _ in 0..5 {
+            tracer.run_cycle(&mut machine).unwrap();
+        }
+        let trace = String::from_utf8(trace).unwrap();
+        assert_eq!(
+            compare_trace(&rom, Quirks::chip8(), 0, trace.as_bytes()).unwrap(),
+            None
+        );
+        let divergence = compare_trace(&rom, Quirks::schip(), 0, trace.as_bytes())
+            .unwrap()
+            .unwrap();
+        assert_eq!(divergence.cycle, 3);
+        assert_eq!(divergence.differences, ["V1: 01 != 08", "VF: 01 != 00"]);
+        let trace = trace.replace("0206 1206", "0206 1208");
+        let divergence = compare_trace(&rom, Quirks::chip8(), 0, trace.as_bytes())
+            .unwrap()
+            .unwrap();
+        assert_eq!(divergence.differences, ["opcode: 1206 != 1208"]);
+    }
+}
@@ -0,0 +1,155 @@
+use std::collections::{BTreeMap, HashMap};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+use crate::romdb::RomInfo;
+use crate::Result;
+
+/// Settings read from the config file at startup, command line options
+/// override them. Every setting is optional:
+///
+/// ```toml
+/// rom-dir = "roms"
+/// speed = 700             # or "unlimited"
+/// compat = "schip"
+/// scale = 8
+/// foreground = "#FFB000"
+/// background = "#202020"
+/// ghosting = 50           # percent, like --ghosting
+/// volume = 5             # percent
+///
+/// [quirks]
+/// shift = "vx"
+///
+/// [keymap]                # all 16 keys, like a --keymap file
+/// 1 = "1"
+/// ```
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Config {
+    pub rom_dir: Option<String>,
+    speed: Option<Speed>,
+    pub compat: Option<String>,
+    /// quirk name to value, like `--quirk NAME=VALUE`
+    pub quirks: BTreeMap<String, String>,
+    pub scale: Option<u32>,
+    pub foreground: Option<String>,
+    pub background: Option<String>,
+    /// fading of turned off pixels in percent, like `--ghosting`
+    pub ghosting: Option<u8>,
+    /// volume of the beep in percent, like `--volume`
+    pub volume: Option<u8>,
+    /// CHIP-8 key to physical key name
+    pub keymap: Option<HashMap<String, String>>,
+    /// the `RomInfo::scores` of the rom, only from the rom database
+    #[serde(skip)]
+    pub scores: BTreeMap<String, String>,
+}
+
+/// a number of instructions per second or "unlimited"
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(untagged)]
+pub(crate) enum Speed {
+    Hz(u64),
+    Name(String),
+}
+
+impl Config {
+    /// `$XDG_CONFIG_HOME/yarchip8/config.toml`, by default in `~/.config`
+    pub fn default_path() -> Option<PathBuf> {
+        Some(Self::dir()?.join("config.toml"))
+    }
+
+    /// `$XDG_CONFIG_HOME/yarchip8`, for the config and the files kept per rom
+    pub fn dir() -> Option<PathBuf> {
+        let dir = match env::var_os("XDG_CONFIG_HOME") {
+            Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+            _ => PathBuf::from(env::var_os("HOME").or_else(|| env::var_os("USERPROFILE"))?)
+                .join(".config"),
+        };
+        Some(dir.join("yarchip8"))
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        fs::read_to_string(path)
+            .map_err(Into::into)
+            .and_then(|content| Self::parse(&content))
+            .map_err(|e| format!("config {}: {}", path.display(), e).into())
+    }
+
+    fn parse(content: &str) -> Result<Self> {
+        Ok(toml::from_str(content)?)
+    }
+
+    /// the speed as it is given to `--speed`
+    pub fn speed(&self) -> Option<String> {
+        self.speed.as_ref().map(|speed| match speed {
+            Speed::Hz(hz) => hz.to_string(),
+            Speed::Name(name) => name.clone(),
+        })
+    }
+
+    /// Use the settings the rom database has for the loaded rom. They win
+    /// over the file, a compat profile of the rom also drops the quirks of
+    /// the file since those were meant for another profile.
+    pub fn apply_rom(&mut self, info: &RomInfo) {
+        if info.compat.is_some() {
+            self.compat = info.compat.clone();
+            self.quirks.clear();
+        }
+        self.quirks
+            .extend(info.quirks.iter().map(|(k, v)| (k.clone(), v.clone())));
+        if info.speed.is_some() {
+            self.speed = info.speed.clone();
+        }
+        if info.keymap.is_some() {
+            self.keymap = info.keymap.clone();
+        }
+        self.scores = info.scores.clone();
+    }
+
+    /// the quirks as `NAME=VALUE` settings for `Quirks::set`
+    pub fn quirk_settings(&self) -> impl Iterator<Item = String> + '_ {
+        self.quirks
+            .iter()
+            .map(|(name, value)| format!("{}={}", name, value))
+    }
+}
+
+#[cfg(test)]
+mod config_test {
+    use super::*;
+
+    #[test]
+    fn test_parse_config() {
+        let config = Config::parse(
+            r##"
+                rom-dir = "games"
+                speed = 700
+                scale = 8
+                foreground = "#FFB000"
+                volume = 5
+
+                [quirks]
+                shift = "vx"
+                jump = "vx"
+            "##,
+        )
+        .unwrap();
+        assert_eq!(config.rom_dir.as_deref(), Some("games"));
+        assert_eq!(config.speed().as_deref(), Some("700"));
+        assert_eq!(config.scale, Some(8));
+        assert_eq!(config.background, None);
+        let quirks: Vec<String> = config.quirk_settings().collect();
+        assert_eq!(quirks, ["jump=vx", "shift=vx"]);
+
+        let config = Config::parse("speed = \"unlimited\"").unwrap();
+        assert_eq!(config.speed().as_deref(), Some("unlimited"));
+        assert_eq!(Config::parse("").unwrap(), Config::default());
+        assert!(Config::parse("colour = \"#FFFFFF\"").is_err());
+    }
+}
@@ -0,0 +1,118 @@
+use std::collections::BTreeSet;
+
+use crate::detect;
+use crate::disassembler;
+use crate::symbols::Symbols;
+
+/// The addresses of the executed instructions, to find the code of a rom
+/// that never ran
+#[derive(Debug, Clone)]
+pub struct Coverage {
+    // one bit for every address PC can have
+    executed: Vec<u64>,
+}
+
+impl Default for Coverage {
+    fn default() -> Self {
+        Coverage {
+            executed: vec![0; (u16::MAX as usize + 1) / 64],
+        }
+    }
+}
+
+impl Coverage {
+    pub fn clear(&mut self) {
+        self.executed.fill(0);
+    }
+
+    /// the instruction at `pc` was executed
+    pub fn execute(&mut self, pc: u16) {
+        let addr = pc as usize;
+        self.executed[addr / 64] |= 1 << (addr % 64);
+    }
+
+    pub fn is_executed(&self, addr: u16) -> bool {
+        let addr = addr as usize;
+        self.executed[addr / 64] & 1 << (addr % 64) != 0
+    }
+
+    /// the executed addresses in `range`
+    fn executed_in(&self, range: std::ops::Range<usize>) -> BTreeSet<usize> {
+        range
+            .filter(|&addr| addr <= u16::MAX as usize && self.is_executed(addr as u16))
+            .collect()
+    }
+
+    /// `executed of total instructions executed (percent)` over the code
+    /// of `rom` loaded at `start` the static scan finds and what ran
+    pub fn summary(&self, rom: &[u8], start: u16) -> String {
+        let range = start as usize..start as usize + rom.len();
+        let executed = self.executed_in(range);
+        let mut code: BTreeSet<usize> = disassembler::reachable(rom, start, &detect::entries(rom))
+            .into_keys()
+            .collect();
+        code.extend(&executed);
+        format!(
+            "{} of {} instructions executed ({:.1}%)",
+            executed.len(),
+            code.len(),
+            executed.len() as f64 * 100.0 / code.len().max(1) as f64
+        )
+    }
+
+    /// The `summary` and the listing of `rom` loaded at `start` with a `*`
+    /// before the executed instructions, the ones without are branches that
+    /// never ran
+    pub fn report(&self, rom: &[u8], start: u16, symbols: &Symbols) -> Vec<String> {
+        let executed = self.executed_in(start as usize..start as usize + rom.len());
+        let mut lines = vec![format!("; {}", self.summary(rom, start))];
+        lines.extend(disassembler::disassemble_covered(
+            rom,
+            start,
+            &detect::entries(rom),
+            symbols,
+            &executed,
+        ));
+        lines
+    }
+}
+
+#[cfg(test)]
+mod coverage_test {
+    use super::*;
+    use crate::audio::NullAudio;
+    use crate::machine::Machine;
+    use crate::rom::ROM;
+
+    #[test]
+    fn test_coverage() {
+        // V0 is 0, so the skip is taken and the jump to 0x208 never runs
+        let rom = ROM::from_bytes(
+            "skip.ch8",
+            vec![
+                0x30, 0x00, 0x12, 0x08, 0x00, 0xFD, 0xFF, 0xFF, 0x00, 0xE0, 0x00, 0xFD,
+            ],
+        );
+        let mut machine: Machine<NullAudio> = Machine::new().unwrap();
+        machine.track_coverage();
+        machine.load_rom(&rom).unwrap();
+        while !machine.is_halt() {
+            machine.run_cycle().unwrap();
+        }
+        let coverage = machine.coverage().unwrap();
+        assert!(coverage.is_executed(0x204));
+        assert!(!coverage.is_executed(0x202));
+        assert_eq!(
+            coverage.report(&rom.raw(), 0x200, &Symbols::default()),
+            [
+                "; 2 of 5 instructions executed (40.0%)",
+                "* 0x200: 3000  SE V0, 0x00",
+                "  0x202: 1208  JP 0x208",
+                "* 0x204: 00FD  EXIT",
+                "  0x206: FFFF  DB 0xFF, 0xFF",
+                "  0x208: 00E0  CLS",
+                "  0x20A: 00FD  EXIT",
+            ]
+        );
+    }
+}
@@ -0,0 +1,115 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::audio::AudioPlay;
+use crate::capture::{self, Frame};
+use crate::debugger::register_view;
+use crate::disassembler::disassemble_with;
+use crate::display::DisplayConfig;
+use crate::error::MachineError;
+use crate::machine::Machine;
+use crate::symbols::Symbols;
+use crate::Result;
+
+/// the directory the frontends write crash dumps to
+pub const CRASH_DIR: &str = "crashes";
+/// instructions kept for the trace of a crash dump, see `Machine::keep_history`
+pub const HISTORY_LEN: usize = 1000;
+/// bytes of code listed before and after the failed instruction
+const LISTING_BYTES: usize = 32;
+
+/// Write what a bug report needs when `run_cycle` failed to a new
+/// `dir/crash-<local time>` directory and return it:
+///
+/// - `crash.txt`: the error, registers, stack and the code around it
+/// - `screen.png`: the display
+/// - `trace.txt`: the last instructions, when the machine kept its history
+/// - `memory.bin`: all of memory
+pub fn write_dump<T: AudioPlay>(
+    dir: &Path,
+    machine: &Machine<T>,
+    error: &MachineError,
+    symbols: &Symbols,
+) -> Result<PathBuf> {
+    let dir = dir.join(format!("crash-{}", capture::timestamp()));
+    fs::create_dir_all(&dir)?;
+
+    let mut report = vec![error.to_string(), String::new()];
+    report.extend(register_view(machine));
+    report.push(String::new());
+    report.extend(listing(machine.memory(), error.pc(), symbols));
+    fs::write(dir.join("crash.txt"), report.join("\n") + "\n")?;
+
+    let config = DisplayConfig {
+        scale: 4,
+        ..DisplayConfig::default()
+    };
+    Frame::new(machine.get_display(), &config).save_png(dir.join("screen.png"))?;
+    if let Some(history) = machine.history() {
+        let mut trace = history.lines(machine, symbols).join("\n");
+        trace.push('\n');
+        fs::write(dir.join("trace.txt"), trace)?;
+    }
+    fs::write(dir.join("memory.bin"), machine.memory())?;
+    Ok(dir)
+}
+
+/// the disassembly of `LISTING_BYTES` before and after `pc`, the line of
+/// `pc` is marked with `>`
+fn listing(memory: &[u8], pc: u16, symbols: &Symbols) -> Vec<String> {
+    let pc = pc as usize;
+    let start = pc.saturating_sub(LISTING_BYTES);
+    // keep the instructions aligned to the failed one
+    let start = (start + (pc - start) % 2).min(memory.len());
+    let end = (pc + 2 + LISTING_BYTES).min(memory.len());
+    let at = format!("{:#05X}:", pc);
+    disassemble_with(&memory[start..end], start as u16, symbols)
+        .into_iter()
+        .map(|line| {
+            let mark = if line.starts_with(&at) { ">" } else { " " };
+            format!("{} {}", mark, line)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod crashdump_test {
+    use super::*;
+    use crate::audio::NullAudio;
+    use crate::rom::ROM;
+
+    #[test]
+    fn test_write_dump() {
+        // V0 = 1, then an illegal opcode
+        let rom = ROM::from_bytes("crash.ch8", vec![0x60, 0x01, 0x00, 0xE0, 0xFF, 0xFF]);
+        let mut machine: Machine<NullAudio> = Machine::new().unwrap();
+        machine.keep_history(HISTORY_LEN);
+        machine.load_rom(&rom).unwrap();
+        let error = machine.step(10).unwrap_err();
+        assert_eq!(
+            error,
+            MachineError::IllegalOpcode {
+                pc: 0x204,
+                opcode: 0xFFFF
+            }
+        );
+
+        let root = std::env::temp_dir().join(format!("rchip8-crash-{}", std::process::id()));
+        let dir = write_dump(&root, &machine, &error, &Symbols::default()).unwrap();
+        let report = fs::read_to_string(dir.join("crash.txt")).unwrap();
+        assert!(report.starts_with("illegal opcode FFFF at 0x204\n"));
+        assert!(report.contains("V0=01"));
+        assert!(report.contains("> 0x204: FFFF"));
+        assert!(report.contains("  0x202: 00E0  CLS"));
+        let trace = fs::read_to_string(dir.join("trace.txt")).unwrap();
+        assert_eq!(trace.lines().count(), 3);
+        assert!(trace
+            .lines()
+            .last()
+            .unwrap()
+            .starts_with("00000003 0204 FFFF"));
+        assert!(dir.join("screen.png").exists());
+        assert_eq!(fs::read(dir.join("memory.bin")).unwrap(), machine.memory());
+        fs::remove_dir_all(root).unwrap();
+    }
+}
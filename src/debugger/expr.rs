@@ -0,0 +1,290 @@
+use std::convert::TryFrom;
+use std::fmt;
+
+use crate::audio::AudioPlay;
+use crate::machine::Machine;
+use crate::{err, Result};
+
+/// Condition of a breakpoint like `V3 == 0x1F && DT > 0`, parsed once and
+/// evaluated before every instruction at the breakpoint.
+///
+/// Values are numbers (decimal, `0x` hex or `0b` binary), the registers
+/// `V0`-`VF`, `I`, `PC`, `DT`, `ST`, `SP` (the call depth) and memory bytes
+/// `[addr]`. From the loosest to the tightest binding the operators are
+/// `||`, `&&`, `|`, `^`, `&`, `==` `!=`, `<` `<=` `>` `>=`, `+` `-` and the
+/// unary `!` and `-`. Comparisons and logic give 1 or 0, anything not 0 is
+/// true.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Condition {
+    source: String,
+    expr: Expr,
+}
+
+impl Condition {
+    pub fn parse(source: &str) -> Result<Self> {
+        let tokens = tokenize(source)?;
+        let mut parser = Parser { tokens, next: 0 };
+        let expr = parser.binary(0)?;
+        if let Some(token) = parser.peek() {
+            return err!("unexpected {:?} in condition", token);
+        }
+        Ok(Condition {
+            source: source.trim().to_string(),
+            expr,
+        })
+    }
+
+    pub fn is_true<T: AudioPlay>(&self, machine: &Machine<T>) -> bool {
+        self.expr.eval(machine) != 0
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.source)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Value {
+    Register(usize),
+    I,
+    Pc,
+    DelayTimer,
+    SoundTimer,
+    StackDepth,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Expr {
+    Number(i64),
+    Value(Value),
+    Memory(Box<Expr>),
+    Not(Box<Expr>),
+    Neg(Box<Expr>),
+    Binary(Box<Expr>, &'static str, Box<Expr>),
+}
+
+impl Expr {
+    fn eval<T: AudioPlay>(&self, machine: &Machine<T>) -> i64 {
+        match self {
+            Expr::Number(n) => *n,
+            Expr::Value(value) => match value {
+                Value::Register(x) => machine.registers()[*x] as i64,
+                Value::I => machine.i() as i64,
+                Value::Pc => machine.pc() as i64,
+                Value::DelayTimer => machine.delay_timer() as i64,
+                Value::SoundTimer => machine.sound_timer() as i64,
+                Value::StackDepth => machine.stack().len() as i64,
+            },
+            // out of memory reads as 0
+            Expr::Memory(addr) => usize::try_from(addr.eval(machine))
+                .ok()
+                .and_then(|addr| machine.memory().get(addr))
+                .map_or(0, |&byte| byte as i64),
+            Expr::Not(expr) => (expr.eval(machine) == 0) as i64,
+            Expr::Neg(expr) => expr.eval(machine).wrapping_neg(),
+            Expr::Binary(left, "&&", right) => {
+                (left.eval(machine) != 0 && right.eval(machine) != 0) as i64
+            }
+            Expr::Binary(left, "||", right) => {
+                (left.eval(machine) != 0 || right.eval(machine) != 0) as i64
+            }
+            Expr::Binary(left, op, right) => {
+                let (a, b) = (left.eval(machine), right.eval(machine));
+                match *op {
+                    "|" => a | b,
+                    "^" => a ^ b,
+                    "&" => a & b,
+                    "==" => (a == b) as i64,
+                    "!=" => (a != b) as i64,
+                    "<" => (a < b) as i64,
+                    "<=" => (a <= b) as i64,
+                    ">" => (a > b) as i64,
+                    ">=" => (a >= b) as i64,
+                    "+" => a.wrapping_add(b),
+                    "-" => a.wrapping_sub(b),
+                    _ => unreachable!(),
+                }
+            }
+        }
+    }
+}
+
+/// binary operators by precedence, the loosest first
+const LEVELS: &[&[&str]] = &[
+    &["||"],
+    &["&&"],
+    &["|"],
+    &["^"],
+    &["&"],
+    &["==", "!="],
+    &["<=", ">=", "<", ">"],
+    &["+", "-"],
+];
+
+/// longer operators first so `<=` is not read as `<`
+const OPERATORS: &[&str] = &[
+    "||", "&&", "==", "!=", "<=", ">=", "|", "^", "&", "<", ">", "+", "-", "!", "(", ")", "[", "]",
+];
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(i64),
+    Name(String),
+    Op(&'static str),
+}
+
+fn tokenize(source: &str) -> Result<Vec<Token>> {
+    let mut tokens = vec![];
+    let mut rest = source.trim_start();
+    while !rest.is_empty() {
+        if let Some(op) = OPERATORS.iter().find(|op| rest.starts_with(*op)) {
+            tokens.push(Token::Op(op));
+            rest = &rest[op.len()..];
+        } else {
+            let end = rest
+                .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+                .unwrap_or(rest.len());
+            if end == 0 {
+                return err!("unexpected {:?} in condition", rest.chars().next().unwrap());
+            }
+            let word = &rest[..end];
+            tokens.push(if word.starts_with(|c: char| c.is_ascii_digit()) {
+                Token::Number(parse_number(word)?)
+            } else {
+                Token::Name(word.to_ascii_uppercase())
+            });
+            rest = &rest[end..];
+        }
+        rest = rest.trim_start();
+    }
+    Ok(tokens)
+}
+
+pub(super) fn parse_number(word: &str) -> Result<i64> {
+    let lower = word.to_ascii_lowercase();
+    let parsed = if let Some(hex) = lower.strip_prefix("0x") {
+        i64::from_str_radix(hex, 16)
+    } else if let Some(bin) = lower.strip_prefix("0b") {
+        i64::from_str_radix(bin, 2)
+    } else {
+        lower.parse()
+    };
+    parsed.map_err(|_| format!("invalid number: {}", word).into())
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    next: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.next)
+    }
+
+    fn take(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.next).cloned();
+        self.next += 1;
+        token
+    }
+
+    fn expect(&mut self, op: &str) -> Result<()> {
+        match self.take() {
+            Some(Token::Op(found)) if found == op => Ok(()),
+            Some(token) => err!("expect {} in condition, got {:?}", op, token),
+            None => err!("expect {} at the end of the condition", op),
+        }
+    }
+
+    /// operators of `LEVELS[level]` and tighter, left to right
+    fn binary(&mut self, level: usize) -> Result<Expr> {
+        if level == LEVELS.len() {
+            return self.unary();
+        }
+        let mut expr = self.binary(level + 1)?;
+        while let Some(&Token::Op(op)) = self.peek() {
+            if !LEVELS[level].contains(&op) {
+                break;
+            }
+            self.next += 1;
+            let right = self.binary(level + 1)?;
+            expr = Expr::Binary(Box::new(expr), op, Box::new(right));
+        }
+        Ok(expr)
+    }
+
+    fn unary(&mut self) -> Result<Expr> {
+        match self.take() {
+            Some(Token::Number(n)) => Ok(Expr::Number(n)),
+            Some(Token::Name(name)) => value(&name).map(Expr::Value),
+            Some(Token::Op("!")) => Ok(Expr::Not(Box::new(self.unary()?))),
+            Some(Token::Op("-")) => Ok(Expr::Neg(Box::new(self.unary()?))),
+            Some(Token::Op("(")) => {
+                let expr = self.binary(0)?;
+                self.expect(")")?;
+                Ok(expr)
+            }
+            Some(Token::Op("[")) => {
+                let addr = self.binary(0)?;
+                self.expect("]")?;
+                Ok(Expr::Memory(Box::new(addr)))
+            }
+            Some(token) => err!("unexpected {:?} in condition", token),
+            None => err!("the condition ends too early"),
+        }
+    }
+}
+
+fn value(name: &str) -> Result<Value> {
+    Ok(match name {
+        "I" => Value::I,
+        "PC" => Value::Pc,
+        "DT" => Value::DelayTimer,
+        "ST" => Value::SoundTimer,
+        "SP" => Value::StackDepth,
+        _ => match name.strip_prefix('V') {
+            Some(x) if x.len() == 1 => match u8::from_str_radix(x, 16) {
+                Ok(x) => Value::Register(x as usize),
+                Err(_) => return err!("unknown register: {}", name),
+            },
+            _ => return err!("unknown value: {}, expect V0-VF, I, PC, DT, ST or SP", name),
+        },
+    })
+}
+
+#[cfg(test)]
+mod expr_test {
+    use super::*;
+    use crate::audio::NullAudio;
+    use crate::rom::ROM;
+
+    #[test]
+    fn test_condition() {
+        let mut machine: Machine<NullAudio> = Machine::new().unwrap();
+        // V3 = 0x1F, DT = V3, I = 0x202
+        let rom = ROM::from_bytes("rom", vec![0x63, 0x1F, 0xF3, 0x15, 0xA2, 0x02]);
+        machine.load_rom(&rom).unwrap();
+        machine.step(3).unwrap();
+        let check = |source: &str| Condition::parse(source).unwrap().is_true(&machine);
+
+        assert!(check("V3 == 0x1F && DT > 0"));
+        assert!(check("v3==31&&dt"));
+        assert!(!check("V3 == 0x1F && DT == 0"));
+        assert!(check("V0 == 1 || V3 + 1 == 0b100000"));
+        assert!(check("[I] == 0xF3 && [I + 1] == 0x15"));
+        assert!(check("PC == 0x206 && SP == 0 && !ST"));
+        assert!(check("-1 < 0 && (V3 & 0xF0) == 0x10"));
+        assert!(check("1 + 2 == 3 == 1"));
+        assert!(!check("[0x10000]"));
+
+        assert_eq!(
+            Condition::parse("  V3 == 0x1F ").unwrap().to_string(),
+            "V3 == 0x1F"
+        );
+        for invalid in ["", "V3 ==", "VG", "(V3", "V3 = 1", "0xZZ", "V3 V4", "R1"] {
+            assert!(Condition::parse(invalid).is_err(), "{}", invalid);
+        }
+    }
+}
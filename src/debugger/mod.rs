@@ -0,0 +1,758 @@
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::io::{self, BufRead, Write};
+use std::thread;
+
+use crossbeam_channel::{unbounded, Receiver};
+
+use crate::audio::AudioPlay;
+use crate::font::BIG_FONT_SIZE;
+use crate::instruction::Instruction;
+use crate::machine::{Machine, BIG_FONT_ADDR, FONT_ADDR};
+use crate::rewind::Rewind;
+use crate::symbols::Symbols;
+use crate::{err, headless, Result, DEFAULT_CLOCK_FREQ};
+
+mod expr;
+mod search;
+
+pub use self::expr::Condition;
+pub use self::search::{Filter, MemorySearch};
+
+const HELP: &str = "\
+commands:
+  s, step [n]          execute n instructions (default 1)
+  bs, back [n]         undo the last n instructions (default 1)
+  fr, frame [n]        run n 60Hz frames (default 1), the instructions of a
+                       frame at the current speed and one timer tick each
+  n, next              step over a subroutine call
+  f, finish            run until the current subroutine returns
+  c, continue          run until a breakpoint is hit
+  b, break <addr> [if <cond>]
+                       set a breakpoint at addr (hex or a label), with a
+                       condition like
+                       V3 == 0x1F && DT > 0 over V0-VF, I, PC, DT, ST, SP,
+                       [addr] and numbers
+  d, delete <addr>     remove the breakpoint at addr
+  l, list              list breakpoints
+  r, regs              show registers, timers and stack
+  m, mem <addr> [len]  dump len bytes of memory from addr (default 64)
+  p, profile           show the instruction histogram of --profile
+  cov, coverage        show how much of the rom --coverage saw executed
+  search <filter>      find a game variable, keep the addresses whose value
+                       is a number, changed, unchanged, up or down since the
+                       last search, reset starts over
+  smc                  toggle stopping when code that ran is overwritten
+  h, help              show this help
+  q, quit              quit the emulator";
+
+/// rows of the memory view around PC and around I, 16 bytes each
+const VIEW_ROWS: usize = 4;
+const ROW_BYTES: usize = 16;
+/// addresses `search` lists
+const SEARCH_LIST: usize = 16;
+/// instructions `back` can undo
+const HISTORY: usize = 10_000;
+
+pub struct Debugger {
+    // a breakpoint stops only when its condition is true
+    breakpoints: BTreeMap<u32, Option<Condition>>,
+    paused: bool,
+    steps: usize,
+    // frames left to step, each is `frame_cycles` steps and a timer tick
+    frames: usize,
+    frame_cycles: usize,
+    // the instruction at a breakpoint we stopped on should run when resumed
+    resumed: bool,
+    // stop after an instruction that wrote over executed code
+    break_on_smc: bool,
+    // run until the call stack is no deeper than this, for next and finish
+    until_depth: Option<usize>,
+    // the machine before each of the last executed instructions
+    history: Rewind,
+    // labels shown for addresses and accepted in place of them
+    symbols: Symbols,
+    search: MemorySearch,
+}
+
+impl Debugger {
+    /// the debugger starts paused, before the first instruction
+    pub fn new() -> Self {
+        Debugger {
+            breakpoints: BTreeMap::new(),
+            paused: true,
+            steps: 0,
+            frames: 0,
+            frame_cycles: headless::frame_cycles(DEFAULT_CLOCK_FREQ),
+            resumed: false,
+            break_on_smc: false,
+            until_depth: None,
+            history: Rewind::new(HISTORY),
+            symbols: Symbols::default(),
+            search: MemorySearch::default(),
+        }
+    }
+
+    pub fn set_symbols(&mut self, symbols: Symbols) {
+        self.symbols = symbols;
+    }
+
+    /// the speed `frame` steps at
+    pub fn set_clock_freq(&mut self, clock_freq: u64) {
+        self.frame_cycles = headless::frame_cycles(clock_freq);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused && self.steps == 0
+    }
+
+    /// spawn a thread forwarding every line of stdin as a debugger command,
+    /// the end of stdin is sent as a quit command
+    pub fn stdin_commands() -> Receiver<String> {
+        let (tx, rx) = unbounded();
+        thread::spawn(move || {
+            for line in io::stdin().lock().lines() {
+                match line {
+                    Ok(line) => {
+                        if tx.send(line).is_err() {
+                            return;
+                        }
+                    }
+                    Err(_) => break,
+                }
+            }
+            let _ = tx.send("quit".to_string());
+        });
+        prompt();
+        rx
+    }
+
+    /// called before each cycle, return false if the machine should not run it
+    pub fn before_cycle<T: AudioPlay>(&mut self, machine: &Machine<T>) -> bool {
+        if self.paused {
+            if self.steps == 0 {
+                return false;
+            }
+            self.steps -= 1;
+            self.history.push(machine.save_state());
+            return true;
+        }
+        let hit = match self.breakpoints.get(&(machine.pc() as u32)) {
+            Some(Some(condition)) => condition.is_true(machine),
+            Some(None) => true,
+            None => false,
+        };
+        if !self.resumed && hit {
+            self.paused = true;
+            self.until_depth = None;
+            println!("breakpoint at {}", self.symbols.describe(machine.pc()));
+            print_current(machine, &self.symbols);
+            prompt();
+            return false;
+        }
+        self.resumed = false;
+        self.history.push(machine.save_state());
+        true
+    }
+
+    /// called after each executed cycle, reports when a step finished and
+    /// ticks the timers at the end of a stepped frame
+    pub fn after_cycle<T: AudioPlay>(&mut self, machine: &mut Machine<T>) {
+        if self.frames > 0 && self.steps == 0 {
+            machine.tick_frame();
+            self.frames -= 1;
+            if self.frames > 0 {
+                self.steps = self.frame_cycles;
+            }
+        }
+        if let Some(depth) = self.until_depth {
+            if machine.stack().len() <= depth {
+                self.until_depth = None;
+                self.paused = true;
+                self.steps = 0;
+            }
+        }
+        if let Some(smc) = machine.self_modified().filter(|_| self.break_on_smc) {
+            println!(
+                "self modifying code: {:#05X} wrote over the executed {:#05X}",
+                smc.pc, smc.addr
+            );
+            self.paused = true;
+            self.steps = 0;
+            self.frames = 0;
+        }
+        if self.paused && self.steps == 0 {
+            print_current(machine, &self.symbols);
+            prompt();
+        }
+    }
+
+    /// execute one command line, return false if the emulator should quit
+    pub fn execute<T: AudioPlay>(&mut self, line: &str, machine: &mut Machine<T>) -> bool {
+        let mut args = line.split_whitespace();
+        let result = match args.next() {
+            None => Ok(()),
+            Some("s") | Some("step") => args
+                .next()
+                .map_or(Ok(1), |n| n.parse::<usize>().map_err(|e| e.into()))
+                .map(|n| {
+                    self.paused = true;
+                    self.resumed = true;
+                    self.steps = n;
+                    self.frames = 0;
+                    self.until_depth = None;
+                }),
+            Some("fr") | Some("frame") => args
+                .next()
+                .map_or(Ok(1), |n| n.parse::<usize>().map_err(|e| e.into()))
+                .map(|n| {
+                    self.paused = true;
+                    self.resumed = true;
+                    self.frames = n;
+                    self.steps = if n > 0 { self.frame_cycles } else { 0 };
+                    self.until_depth = None;
+                }),
+            Some("bs") | Some("back") => args
+                .next()
+                .map_or(Ok(1), |n| n.parse::<usize>().map_err(|e| e.into()))
+                .and_then(|n| self.step_back(n, machine)),
+            Some("n") | Some("next") => {
+                let depth = machine.stack().len();
+                if machine
+                    .next_opcode()
+                    .is_some_and(|op| op & 0xF000 == 0x2000)
+                {
+                    self.run_until(depth);
+                    return true;
+                }
+                self.paused = true;
+                self.resumed = true;
+                self.steps = 1;
+                self.frames = 0;
+                Ok(())
+            }
+            Some("f") | Some("finish") => match machine.stack().len() {
+                0 => err!("not in a subroutine"),
+                depth => {
+                    self.run_until(depth - 1);
+                    return true;
+                }
+            },
+            Some("c") | Some("continue") => {
+                self.paused = false;
+                self.resumed = true;
+                self.steps = 0;
+                self.frames = 0;
+                self.until_depth = None;
+                return true;
+            }
+            Some("b") | Some("break") => self.address(args.next()).and_then(|addr| {
+                let condition = match args.next() {
+                    None => None,
+                    Some("if") => Some(Condition::parse(&args.collect::<Vec<_>>().join(" "))?),
+                    Some(arg) => return err!("unexpected {}, expect if <condition>", arg),
+                };
+                let at = self.describe(addr);
+                match &condition {
+                    Some(condition) => println!("breakpoint set at {} if {}", at, condition),
+                    None => println!("breakpoint set at {}", at),
+                }
+                self.breakpoints.insert(addr, condition);
+                Ok(())
+            }),
+            Some("d") | Some("delete") => self.address(args.next()).map(|addr| {
+                let at = self.describe(addr);
+                if self.breakpoints.remove(&addr).is_some() {
+                    println!("breakpoint at {} deleted", at);
+                } else {
+                    println!("no breakpoint at {}", at);
+                }
+            }),
+            Some("l") | Some("list") => {
+                for (&addr, condition) in &self.breakpoints {
+                    let at = self.describe(addr);
+                    match condition {
+                        Some(condition) => println!("{} if {}", at, condition),
+                        None => println!("{}", at),
+                    }
+                }
+                Ok(())
+            }
+            Some("r") | Some("regs") => {
+                print_registers(machine);
+                Ok(())
+            }
+            Some("m") | Some("mem") => self.address(args.next()).and_then(|addr| {
+                let len = args.next().map_or(Ok(64), |n| n.parse::<usize>())?;
+                print_memory(machine.memory(), addr as usize, len);
+                Ok(())
+            }),
+            Some("p") | Some("profile") => match machine.profile() {
+                Some(profile) => {
+                    for line in profile.report() {
+                        println!("{}", line);
+                    }
+                    Ok(())
+                }
+                None => err!("the profiler is off, start with --profile"),
+            },
+            Some("cov") | Some("coverage") => match machine.coverage() {
+                Some(coverage) => {
+                    let start = machine.rom_range().start as u16;
+                    println!("{}", coverage.summary(machine.rom(), start));
+                    Ok(())
+                }
+                None => err!("coverage is off, start with --coverage"),
+            },
+            Some("search") => match args.next() {
+                Some("reset") => {
+                    self.search.reset();
+                    Ok(())
+                }
+                Some(filter) => filter.parse().map(|filter| {
+                    let started = self.search.is_started();
+                    let found = self.search.filter(filter, machine.memory()).to_vec();
+                    print_search(&self.search, &found, machine.memory(), started);
+                }),
+                None => {
+                    err!("missing filter, expect a number, changed, unchanged, up, down or reset")
+                }
+            },
+            Some("smc") => {
+                self.break_on_smc = !self.break_on_smc;
+                println!(
+                    "stop on self modifying code: {}",
+                    if self.break_on_smc { "on" } else { "off" }
+                );
+                Ok(())
+            }
+            Some("h") | Some("help") => {
+                println!("{}", HELP);
+                Ok(())
+            }
+            Some("q") | Some("quit") => return false,
+            Some(cmd) => err!("unknown command: {}, type help for a list of commands", cmd),
+        };
+        if let Err(e) = result {
+            println!("error: {}", e);
+        }
+        if self.is_paused() {
+            prompt();
+        }
+        true
+    }
+
+    /// restore the machine to before the last `n` instructions, or as far
+    /// back as the history goes
+    fn step_back<T: AudioPlay>(&mut self, n: usize, machine: &mut Machine<T>) -> Result<()> {
+        let mut state = None;
+        for _ in 0..n {
+            match self.history.pop() {
+                Some(older) => state = Some(older),
+                None => break,
+            }
+        }
+        let state = match state {
+            Some(state) => state,
+            None => return err!("no instruction to step back over"),
+        };
+        machine.load_state(&state)?;
+        self.paused = true;
+        self.steps = 0;
+        self.frames = 0;
+        self.until_depth = None;
+        // the breakpoint at the restored instruction was hit already
+        self.resumed = true;
+        print_current(machine, &self.symbols);
+        Ok(())
+    }
+
+    /// a label or a hex address
+    fn address(&self, arg: Option<&str>) -> Result<u32> {
+        match arg.and_then(|name| self.symbols.addr(name)) {
+            Some(addr) => Ok(addr as u32),
+            None => parse_address(arg),
+        }
+    }
+
+    /// `Symbols::describe` for an address anywhere in memory
+    fn describe(&self, addr: u32) -> String {
+        match u16::try_from(addr) {
+            Ok(addr) => self.symbols.describe(addr),
+            Err(_) => format!("{:#05X}", addr),
+        }
+    }
+
+    /// run until the call stack is `depth` deep or less, or a breakpoint
+    fn run_until(&mut self, depth: usize) {
+        self.paused = false;
+        self.resumed = true;
+        self.steps = 0;
+        self.frames = 0;
+        self.until_depth = Some(depth);
+    }
+}
+
+impl Default for Debugger {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn prompt() {
+    print!("(rchip8) ");
+    let _ = io::stdout().flush();
+}
+
+fn parse_address(arg: Option<&str>) -> Result<u32> {
+    let arg = match arg {
+        Some(arg) => arg,
+        None => return err!("missing address"),
+    };
+    let digits = arg.trim_start_matches("0x").trim_start_matches("0X");
+    Ok(u32::from_str_radix(digits, 16)?)
+}
+
+/// the addresses left after a search, with the cheat that freezes the last one
+fn print_search(search: &MemorySearch, found: &[u32], memory: &[u8], started: bool) {
+    if !started && found.len() == memory.len() {
+        println!("search started, search again after the value changed");
+        return;
+    }
+    println!("{} addresses", found.len());
+    for &addr in found.iter().take(SEARCH_LIST) {
+        println!("{:#05X} = {:02X}", addr, memory[addr as usize]);
+    }
+    if found.len() > SEARCH_LIST {
+        println!("...");
+    }
+    // cheats only patch the 4KB of CHIP-8 memory
+    if let [addr @ 0..=0xFFF] = found {
+        let value = search.previous(*addr).unwrap_or(0);
+        println!("freeze it with --cheat {:03X}={:02X}", addr, value);
+    }
+}
+
+fn print_current<T: AudioPlay>(machine: &Machine<T>, symbols: &Symbols) {
+    let pc = machine.pc() as usize;
+    let memory = machine.memory();
+    if let Some(name) = symbols.name(pc as u16) {
+        println!("{}:", name);
+    }
+    if pc + 1 < memory.len() {
+        let instr = Instruction::new(memory[pc], memory[pc + 1]);
+        let text = instr.disassemble_with(symbols);
+        println!("{:#05X}: {:04X}  {}", pc, instr.opcode, text);
+    } else {
+        println!("{:#05X}: out of memory", pc);
+    }
+}
+
+fn print_registers<T: AudioPlay>(machine: &Machine<T>) {
+    for line in register_view(machine) {
+        println!("{}", line);
+    }
+}
+
+/// registers, timers and the call stack as text lines, for the `regs`
+/// command and the register overlay
+pub fn register_view<T: AudioPlay>(machine: &Machine<T>) -> Vec<String> {
+    let mut lines: Vec<String> = machine
+        .registers()
+        .chunks(8)
+        .enumerate()
+        .map(|(n, chunk)| {
+            let line: Vec<String> = chunk
+                .iter()
+                .enumerate()
+                .map(|(i, v)| format!("V{:X}={:02X}", n * 8 + i, v))
+                .collect();
+            line.join(" ")
+        })
+        .collect();
+    lines.push(format!(
+        "PC={:03X} I={:03X} DT={:02X} ST={:02X}",
+        machine.pc(),
+        machine.i(),
+        machine.delay_timer(),
+        machine.sound_timer()
+    ));
+    let stack: Vec<String> = machine
+        .stack()
+        .iter()
+        .map(|addr| format!("{:03X}", addr))
+        .collect();
+    lines.push(format!(
+        "stack: [{}] {}/{}",
+        stack.join(", "),
+        stack.len(),
+        machine.stack_depth()
+    ));
+    lines
+}
+
+fn print_memory(memory: &[u8], addr: usize, len: usize) {
+    let end = (addr + len).min(memory.len());
+    for start in (addr..end).step_by(16) {
+        let bytes: Vec<String> = memory[start..(start + 16).min(end)]
+            .iter()
+            .map(|b| format!("{:02X}", b))
+            .collect();
+        println!("{:#05X}: {}", start, bytes.join(" "));
+    }
+}
+
+/// Hex view of the memory around PC and around I, refreshed every frame in
+/// the memory window. Rows are labeled with the region they start in and the
+/// instruction at PC and the byte at I are bracketed.
+pub fn memory_view<T: AudioPlay>(machine: &Machine<T>) -> Vec<String> {
+    let (pc, i) = (machine.pc() as usize, machine.i() as usize);
+    let mut lines = vec![format!("PC {:03X}", pc)];
+    lines.extend(hex_rows(machine, pc, 2));
+    lines.push(String::new());
+    lines.push(format!("I {:03X}", i));
+    lines.extend(hex_rows(machine, i, 1));
+    lines
+}
+
+/// `VIEW_ROWS` rows starting one row before `addr`, `len` bytes from `addr`
+/// on are bracketed
+fn hex_rows<T: AudioPlay>(machine: &Machine<T>, addr: usize, len: usize) -> Vec<String> {
+    let first = (addr / ROW_BYTES)
+        .saturating_sub(1)
+        .min(machine.memory().len() / ROW_BYTES - VIEW_ROWS)
+        * ROW_BYTES;
+    let marked = addr..addr + len;
+    (first..first + VIEW_ROWS * ROW_BYTES)
+        .step_by(ROW_BYTES)
+        .map(|start| {
+            let mut line = format!("{:03X} {:<4}", start, region(machine, start));
+            for (n, byte) in machine.memory()[start..start + ROW_BYTES]
+                .iter()
+                .enumerate()
+            {
+                let at = start + n;
+                line.push(if at == marked.start {
+                    '['
+                } else if at == marked.end {
+                    ']'
+                } else {
+                    ' '
+                });
+                line.push_str(&format!("{:02X}", byte));
+            }
+            if marked.end == start + ROW_BYTES {
+                line.push(']');
+            }
+            line
+        })
+        .collect()
+}
+
+fn region<T: AudioPlay>(machine: &Machine<T>, addr: usize) -> &'static str {
+    if (FONT_ADDR..BIG_FONT_ADDR + BIG_FONT_SIZE).contains(&addr) {
+        "FONT"
+    } else if machine.rom_range().contains(&addr) {
+        "ROM"
+    } else {
+        "RAM"
+    }
+}
+
+#[cfg(test)]
+mod debugger_test {
+    use super::*;
+    use crate::audio::NullAudio;
+    use crate::rom::ROM;
+
+    #[test]
+    fn test_memory_view() {
+        let mut machine: Machine<NullAudio> = Machine::new().unwrap();
+        machine.load_font().unwrap();
+        let rom = ROM::from_bytes("rom", vec![0x00, 0xE0, 0xA0, 0x50, 0x12, 0x04]);
+        machine.load_rom(&rom).unwrap();
+        for _ in 0..2 {
+            machine.run_cycle().unwrap();
+        }
+
+        let view = memory_view(&machine);
+        assert_eq!(view.len(), 2 * VIEW_ROWS + 3);
+        assert_eq!(view[0], "PC 204");
+        assert!(view[1].starts_with("1F0 RAM  00 00"));
+        assert!(view[2].starts_with("200 ROM  00 E0 A0 50[12 04]00"));
+        assert_eq!(view[6], "I 050");
+        assert!(view[7].starts_with("040 RAM "));
+        assert!(view[8].starts_with("050 FONT[F0]90 90"));
+    }
+
+    #[test]
+    fn test_break_on_smc() {
+        let mut machine: Machine<NullAudio> = Machine::new().unwrap();
+        machine.track_self_modifying(false);
+        // I = 0x200, store V0 over the first instruction
+        let rom = ROM::from_bytes("rom", vec![0xA2, 0x00, 0xF0, 0x55, 0x12, 0x04]);
+        machine.load_rom(&rom).unwrap();
+        let mut debugger = Debugger::new();
+        debugger.execute("smc", &mut machine);
+        debugger.execute("continue", &mut machine);
+        for _ in 0..2 {
+            assert!(!debugger.is_paused());
+            assert!(debugger.before_cycle(&machine));
+            machine.run_cycle().unwrap();
+            debugger.after_cycle(&mut machine);
+        }
+        assert!(debugger.is_paused());
+        assert_eq!(machine.pc(), 0x204);
+    }
+
+    #[test]
+    fn test_next_and_finish() {
+        let mut machine: Machine<NullAudio> = Machine::new().unwrap();
+        #[rustfmt::skip]
+        let rom = ROM::from_bytes("rom", vec![
+            0x22, 0x06, // 200: CALL 206
+            0x22, 0x06, // 202: CALL 206
+            0x12, 0x04, // 204: JP 204
+            0x22, 0x0A, // 206: CALL 20A
+            0x00, 0xEE, // 208: RET
+            0x61, 0x01, // 20A: LD V1, 1
+            0x00, 0xEE, // 20C: RET
+        ]);
+        machine.load_rom(&rom).unwrap();
+        let mut debugger = Debugger::new();
+        let mut run = |command: &str, machine: &mut Machine<NullAudio>| {
+            debugger.execute(command, machine);
+            for _ in 0..100 {
+                if !debugger.before_cycle(machine) {
+                    break;
+                }
+                machine.run_cycle().unwrap();
+                debugger.after_cycle(machine);
+            }
+            machine.pc()
+        };
+        assert_eq!(run("next", &mut machine), 0x202);
+        assert_eq!(run("step 2", &mut machine), 0x20A);
+        assert_eq!(run("next", &mut machine), 0x20C);
+        assert_eq!(run("finish", &mut machine), 0x208);
+        assert_eq!(run("finish", &mut machine), 0x204);
+        // nothing to finish at the top level
+        assert_eq!(run("finish", &mut machine), 0x204);
+    }
+
+    #[test]
+    fn test_frame_step() {
+        let mut machine: Machine<NullAudio> = Machine::new().unwrap();
+        #[rustfmt::skip]
+        let rom = ROM::from_bytes("rom", vec![
+            0x60, 0x05, // 200: LD V0, 5
+            0xF0, 0x15, // 202: LD DT, V0
+            0x70, 0x01, // 204: ADD V0, 1
+            0x12, 0x04, // 206: JP 204
+        ]);
+        machine.load_rom(&rom).unwrap();
+        machine.use_timer_clock();
+        let mut debugger = Debugger::new();
+        // two instructions a frame
+        debugger.set_clock_freq(120);
+        debugger.execute("frame 2", &mut machine);
+        while debugger.before_cycle(&machine) {
+            machine.run_cycle().unwrap();
+            debugger.after_cycle(&mut machine);
+        }
+        assert!(debugger.is_paused());
+        assert_eq!(machine.pc(), 0x204);
+        assert_eq!(machine.registers()[0], 6);
+        assert_eq!(machine.delay_timer(), 3);
+    }
+
+    #[test]
+    fn test_conditional_breakpoint() {
+        let mut machine: Machine<NullAudio> = Machine::new().unwrap();
+        // V0 += 1 forever
+        let rom = ROM::from_bytes("rom", vec![0x70, 0x01, 0x12, 0x00]);
+        machine.load_rom(&rom).unwrap();
+        let mut debugger = Debugger::new();
+        debugger.execute("break 200 if V0 == 5", &mut machine);
+        debugger.execute("break 202 if", &mut machine);
+        assert_eq!(debugger.breakpoints.len(), 1);
+        debugger.execute("continue", &mut machine);
+        for _ in 0..100 {
+            if !debugger.before_cycle(&machine) {
+                break;
+            }
+            machine.run_cycle().unwrap();
+            debugger.after_cycle(&mut machine);
+        }
+        assert_eq!(machine.pc(), 0x200);
+        assert_eq!(machine.registers()[0], 5);
+    }
+
+    #[test]
+    fn test_mega_addresses() {
+        let mut debugger = Debugger::new();
+        assert_eq!(debugger.address(Some("0x123456")).unwrap(), 0x123456);
+        assert_eq!(debugger.describe(0x123456), "0x123456");
+        let mut machine: Machine<NullAudio> = Machine::new().unwrap();
+        debugger.execute("break 10000", &mut machine);
+        assert!(debugger.breakpoints.contains_key(&0x10000));
+    }
+
+    #[test]
+    fn test_break_at_label() {
+        let mut machine: Machine<NullAudio> = Machine::new().unwrap();
+        let rom = ROM::from_bytes("rom", vec![0x70, 0x01, 0x70, 0x02, 0x12, 0x00]);
+        machine.load_rom(&rom).unwrap();
+        let mut debugger = Debugger::new();
+        let mut symbols = Symbols::default();
+        symbols.insert("main_loop", 0x202);
+        debugger.set_symbols(symbols);
+        debugger.execute("break main_loop", &mut machine);
+        debugger.execute("break nowhere", &mut machine);
+        assert_eq!(debugger.breakpoints.len(), 1);
+        assert!(debugger.breakpoints.contains_key(&0x202));
+        debugger.execute("continue", &mut machine);
+        while debugger.before_cycle(&machine) {
+            machine.run_cycle().unwrap();
+            debugger.after_cycle(&mut machine);
+        }
+        assert_eq!(machine.pc(), 0x202);
+        debugger.execute("delete main_loop", &mut machine);
+        assert!(debugger.breakpoints.is_empty());
+    }
+
+    #[test]
+    fn test_step_back() {
+        let mut machine: Machine<NullAudio> = Machine::new().unwrap();
+        // V0 += 1, I = 0x300 + V0 forever
+        let rom = ROM::from_bytes("rom", vec![0x70, 0x01, 0xA3, 0x00, 0xF0, 0x1E, 0x12, 0x00]);
+        machine.load_rom(&rom).unwrap();
+        let mut debugger = Debugger::new();
+        debugger.execute("step 10", &mut machine);
+        while debugger.before_cycle(&machine) {
+            machine.run_cycle().unwrap();
+            debugger.after_cycle(&mut machine);
+        }
+        assert_eq!((machine.pc(), machine.registers()[0]), (0x204, 3));
+        assert_eq!(machine.i(), 0x300);
+
+        debugger.execute("back", &mut machine);
+        assert_eq!((machine.pc(), machine.registers()[0]), (0x202, 3));
+        assert_eq!(machine.i(), 0x302);
+        debugger.execute("back 3", &mut machine);
+        assert_eq!((machine.pc(), machine.registers()[0]), (0x204, 2));
+        debugger.execute("back 100", &mut machine);
+        assert_eq!((machine.pc(), machine.registers()[0]), (0x200, 0));
+        debugger.execute("back", &mut machine);
+        assert_eq!(machine.pc(), 0x200);
+
+        // running again records the new history
+        debugger.execute("step 2", &mut machine);
+        while debugger.before_cycle(&machine) {
+            machine.run_cycle().unwrap();
+            debugger.after_cycle(&mut machine);
+        }
+        debugger.execute("back", &mut machine);
+        assert_eq!((machine.pc(), machine.registers()[0]), (0x202, 1));
+    }
+}
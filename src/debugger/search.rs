@@ -0,0 +1,123 @@
+use std::error::Error;
+use std::str::FromStr;
+
+use super::expr::parse_number;
+use crate::{err, Result};
+
+/// How `search` narrows down the addresses, by the value now or by how it
+/// changed since the last search
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Filter {
+    Equal(u8),
+    Changed,
+    Unchanged,
+    Increased,
+    Decreased,
+}
+
+impl Filter {
+    fn keeps(self, old: u8, new: u8) -> bool {
+        match self {
+            Filter::Equal(value) => new == value,
+            Filter::Changed => new != old,
+            Filter::Unchanged => new == old,
+            Filter::Increased => new > old,
+            Filter::Decreased => new < old,
+        }
+    }
+}
+
+impl FromStr for Filter {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "changed" => Ok(Filter::Changed),
+            "unchanged" => Ok(Filter::Unchanged),
+            "up" => Ok(Filter::Increased),
+            "down" => Ok(Filter::Decreased),
+            _ => match parse_number(s)? {
+                value @ 0..=0xFF => Ok(Filter::Equal(value as u8)),
+                value => err!("{} does not fit in a byte", value),
+            },
+        }
+    }
+}
+
+/// Cheat search for game variables like a lives counter: search for the
+/// value, play until it changes and search again until few addresses are
+/// left. Each search compares with the memory of the one before.
+#[derive(Debug, Clone, Default)]
+pub struct MemorySearch {
+    snapshot: Vec<u8>,
+    // u32, MegaChip memory is larger than 64K
+    candidates: Vec<u32>,
+}
+
+impl MemorySearch {
+    pub fn is_started(&self) -> bool {
+        !self.snapshot.is_empty()
+    }
+
+    /// keep the addresses `filter` matches, the first search starts from
+    /// all of memory and a change filter only takes the snapshot then
+    pub fn filter(&mut self, filter: Filter, memory: &[u8]) -> &[u32] {
+        if !self.is_started() {
+            self.candidates = (0..memory.len() as u32).collect();
+            if !matches!(filter, Filter::Equal(_)) {
+                self.snapshot = memory.to_vec();
+                return &self.candidates;
+            }
+        }
+        let snapshot = &self.snapshot;
+        self.candidates.retain(|&addr| {
+            let addr = addr as usize;
+            filter.keeps(snapshot.get(addr).copied().unwrap_or(0), memory[addr])
+        });
+        self.snapshot = memory.to_vec();
+        &self.candidates
+    }
+
+    /// the value an address had at the last search
+    pub fn previous(&self, addr: u32) -> Option<u8> {
+        self.snapshot.get(addr as usize).copied()
+    }
+
+    pub fn reset(&mut self) {
+        *self = MemorySearch::default();
+    }
+}
+
+#[cfg(test)]
+mod search_test {
+    use super::*;
+
+    #[test]
+    fn test_memory_search() {
+        let mut memory = vec![0; 16];
+        memory[3] = 3;
+        memory[7] = 3;
+        memory[9] = 5;
+        let mut search = MemorySearch::default();
+        assert_eq!(search.filter("3".parse().unwrap(), &memory), [3, 7]);
+        memory[3] = 2;
+        assert_eq!(search.filter("down".parse().unwrap(), &memory), [3]);
+        assert_eq!(search.previous(3), Some(2));
+
+        search.reset();
+        assert_eq!(search.filter(Filter::Changed, &memory).len(), 16);
+        memory[9] = 6;
+        memory[0] = 1;
+        assert_eq!(search.filter(Filter::Increased, &memory), [0, 9]);
+        assert_eq!(search.filter(Filter::Unchanged, &memory), [0, 9]);
+        assert_eq!(search.filter("0x06".parse().unwrap(), &memory), [9]);
+        assert!("256".parse::<Filter>().is_err());
+
+        // all of a 16MB MegaChip memory is searched
+        let mut memory = vec![0; 1 << 24];
+        memory[0x123456] = 42;
+        search.reset();
+        assert_eq!(search.filter(Filter::Equal(42), &memory), [0x123456]);
+        assert!("sideways".parse::<Filter>().is_err());
+    }
+}
@@ -0,0 +1,206 @@
+use std::fmt;
+
+use crate::disassembler;
+use crate::opcodes;
+
+/// where roms are loaded and start
+const START: usize = 0x200;
+/// the memory a CHIP-8 rom fits in, from `START` to 4KB
+const CHIP8_ROM_SIZE: usize = 0x1000 - START;
+
+/// The member of the CHIP-8 family a rom was written for, ordered so the
+/// later ones extend the earlier ones
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Variant {
+    Chip8,
+    /// the two-page 64x64 interpreter, see `is_two_page`
+    HiRes,
+    SuperChip,
+    XoChip,
+    MegaChip,
+}
+
+impl Variant {
+    /// the compatibility profile for `Quirks`, None when the instructions do
+    /// not tell which CHIP-8 interpreter a rom expects
+    pub fn profile(self) -> Option<&'static str> {
+        match self {
+            Variant::Chip8 => None,
+            Variant::HiRes => Some("chip8"),
+            Variant::SuperChip | Variant::MegaChip => Some("schip"),
+            Variant::XoChip => Some("xochip"),
+        }
+    }
+
+    /// the variant that introduced `opcode`
+    fn of(opcode: u16) -> Self {
+        let (nn, n) = (opcode & 0xFF, opcode & 0xF);
+        match opcode >> 12 {
+            0x0 if opcode == 0x0011 => Variant::MegaChip,
+            0x0 if opcode & 0xFFF0 == 0x00D0 => Variant::XoChip,
+            0x0 if opcode & 0xFFF0 == 0x00C0 || (0x00FB..=0x00FF).contains(&opcode) => {
+                Variant::SuperChip
+            }
+            0x5 if n == 2 || n == 3 => Variant::XoChip,
+            0xD if n == 0 => Variant::SuperChip,
+            0xF if opcode == 0xF000 || opcode == 0xF002 || nn == 0x01 || nn == 0x3A => {
+                Variant::XoChip
+            }
+            0xF if nn == 0x30 || nn == 0x75 || nn == 0x85 => Variant::SuperChip,
+            _ => Variant::Chip8,
+        }
+    }
+}
+
+impl fmt::Display for Variant {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Variant::Chip8 => "CHIP-8",
+            Variant::HiRes => "HiRes CHIP-8",
+            Variant::SuperChip => "SUPER-CHIP",
+            Variant::XoChip => "XO-CHIP",
+            Variant::MegaChip => "MegaChip",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// a rom for the HiRes CHIP-8 interpreter, it jumps to 0x260 first and has
+/// its code from 0x2C0 on
+pub fn is_two_page(rom: &[u8]) -> bool {
+    rom.len() > 0xC0 && rom.starts_with(&[0x12, 0x60])
+}
+
+/// Find the variant of `rom` from the instructions it can reach from the
+/// start, see `scan`
+pub fn variant(rom: &[u8]) -> Variant {
+    scan(rom).variant
+}
+
+/// What the instructions a rom can reach from the start use
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Scan {
+    pub variant: Variant,
+    /// reachable instructions
+    pub instructions: usize,
+    /// reachable opcodes of no variant, the rom may run into data
+    pub unknown: usize,
+    /// sets the sound timer or plays XO-CHIP or MegaChip audio
+    pub sound: bool,
+    /// reads the keypad
+    pub keys: bool,
+}
+
+/// where the code of `rom` starts, also 0x2C0 where the HiRes interpreter
+/// runs the code of a two-page rom
+pub fn entries(rom: &[u8]) -> Vec<u16> {
+    if is_two_page(rom) {
+        vec![START as u16, 0x2C0]
+    } else {
+        vec![START as u16]
+    }
+}
+
+/// Scan the instructions `rom` can reach from the start with
+/// `disassembler::reachable`, so sprites and other data that look like
+/// instructions do not count
+pub fn scan(rom: &[u8]) -> Scan {
+    let mut scan = Scan {
+        variant: Variant::Chip8,
+        instructions: 0,
+        unknown: 0,
+        sound: false,
+        keys: false,
+    };
+    for &addr in disassembler::reachable(rom, START as u16, &entries(rom)).keys() {
+        let at = addr - START;
+        let opcode = u16::from_be_bytes([rom[at], rom[at + 1]]);
+        let variant = Variant::of(opcode);
+        scan.variant = scan.variant.max(variant);
+        scan.instructions += 1;
+        if variant == Variant::Chip8 && opcodes::lookup(opcode).is_none() {
+            scan.unknown += 1;
+        }
+        let nn = opcode & 0xFF;
+        match opcode >> 12 {
+            0x0 if opcode == 0x0700 || opcode & 0xFFF0 == 0x0600 => scan.sound = true,
+            0xE if nn == 0x9E || nn == 0xA1 => scan.keys = true,
+            0xF if nn == 0x18 || opcode == 0xF002 => scan.sound = true,
+            0xF if nn == 0x0A => scan.keys = true,
+            _ => {}
+        }
+    }
+    if is_two_page(rom) {
+        scan.variant = Variant::HiRes;
+    }
+    scan
+}
+
+/// where `rom` is loaded and starts for `variant`, and the memory it needs
+pub fn entry(rom: &[u8], variant: Variant) -> String {
+    let mut entry = match variant {
+        Variant::HiRes => format!(
+            "loaded at {:#05X}, jumps to 0x260 for the two-page HiRes interpreter",
+            START
+        ),
+        _ => format!("loaded and started at {:#05X}", START),
+    };
+    if rom.len() > CHIP8_ROM_SIZE {
+        entry += &format!(
+            ", larger than the {} bytes of CHIP-8 memory",
+            CHIP8_ROM_SIZE
+        );
+    }
+    entry
+}
+
+#[cfg(test)]
+mod detect_test {
+    use super::*;
+
+    #[test]
+    fn test_variant() {
+        // CLS, jump over a sprite that looks like 00FF
+        assert_eq!(
+            variant(&[0x00, 0xE0, 0x12, 0x06, 0x00, 0xFF, 0x12, 0x06]),
+            Variant::Chip8
+        );
+        // DXY0 in a subroutine
+        assert_eq!(
+            variant(&[0x22, 0x04, 0x12, 0x02, 0xD0, 0x10, 0x00, 0xEE]),
+            Variant::SuperChip
+        );
+        // the skipped F000 NNNN is four bytes, 00FF after it is data
+        assert_eq!(
+            variant(&[0x30, 0x00, 0xF0, 0x00, 0x00, 0xFF, 0x12, 0x06]),
+            Variant::XoChip
+        );
+        assert_eq!(variant(&[0x00, 0x11, 0x00, 0xFF]), Variant::MegaChip);
+        let mut hires = vec![0; 0xD0];
+        hires[..2].copy_from_slice(&[0x12, 0x60]);
+        assert_eq!(variant(&hires), Variant::HiRes);
+        assert_eq!(Variant::SuperChip.profile(), Some("schip"));
+        assert_eq!(Variant::Chip8.profile(), None);
+    }
+
+    #[test]
+    fn test_scan() {
+        // wait for a key, beep and loop, 5A11 is reached through a skip
+        let scan = scan(&[0xF0, 0x0A, 0xF0, 0x18, 0x30, 0x00, 0x5A, 0x11, 0x12, 0x00]);
+        assert_eq!(
+            scan,
+            Scan {
+                variant: Variant::Chip8,
+                instructions: 5,
+                unknown: 1,
+                sound: true,
+                keys: true,
+            }
+        );
+        assert_eq!(
+            entry(&[0x12, 0x00], scan.variant),
+            "loaded and started at 0x200"
+        );
+        assert!(entry(&vec![0; 0x1000], Variant::XoChip).ends_with("of CHIP-8 memory"));
+    }
+}
@@ -0,0 +1,390 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::instruction::Instruction;
+use crate::symbols::Symbols;
+
+/// Annotated listing of a program loaded at `start`, one instruction per line
+pub fn disassemble(raw: &[u8], start: u16) -> Vec<String> {
+    disassemble_with(raw, start, &Symbols::default())
+}
+
+/// `disassemble` with a `name:` line before every labeled address and the
+/// label names used for addresses
+pub fn disassemble_with(raw: &[u8], start: u16, symbols: &Symbols) -> Vec<String> {
+    let mut lines = vec![];
+    for (n, bytes) in raw.chunks(2).enumerate() {
+        let addr = start as usize + n * 2;
+        if let Some(name) = symbols.name(addr as u16) {
+            lines.push(format!("{}:", name));
+        }
+        lines.push(match *bytes {
+            [high, low] => {
+                let instr = Instruction::new(high, low);
+                format!(
+                    "{:#05X}: {:04X}  {}",
+                    addr,
+                    instr.opcode,
+                    instr.disassemble_with(symbols)
+                )
+            }
+            [byte] => format!("{:#05X}: {:02X}    DB {:#04X}", addr, byte, byte),
+            _ => unreachable!(),
+        });
+    }
+    lines
+}
+
+/// F000 NNNN and MegaChip 01NN NNNN are four bytes long
+fn size(opcode: u16) -> usize {
+    if opcode == 0xF000 || opcode & 0xFF00 == 0x0100 {
+        4
+    } else {
+        2
+    }
+}
+
+/// How control gets from an instruction to one after it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Flow {
+    Next,
+    Jump,
+    Call,
+    Skip,
+}
+
+/// A program loaded at `start`
+struct Program<'a> {
+    raw: &'a [u8],
+    start: usize,
+}
+
+impl Program<'_> {
+    fn fetch(&self, addr: usize) -> Option<u16> {
+        let bytes = self.raw.get(addr.checked_sub(self.start)?..)?.get(..2)?;
+        Some(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    /// The instructions that can run after the one at `addr` and how
+    /// control gets there. BNNN is followed when V0 is loaded right before
+    /// it or NNN is a table of jumps.
+    fn successors(&self, addr: usize, opcode: u16) -> Vec<(usize, Flow)> {
+        let next = addr + size(opcode);
+        let nnn = (opcode & 0xFFF) as usize;
+        match opcode >> 12 {
+            0x0 if opcode == 0x00EE || opcode == 0x00FD => vec![],
+            0x1 => vec![(nnn, Flow::Jump)],
+            0x2 => vec![(nnn, Flow::Call), (next, Flow::Next)],
+            0x3 | 0x4 | 0x5 | 0x9 | 0xE => vec![
+                (next, Flow::Next),
+                (next + self.fetch(next).map_or(2, size), Flow::Skip),
+            ],
+            0xB => match addr.checked_sub(2).and_then(|addr| self.fetch(addr)) {
+                Some(ld) if ld & 0xF000 == 0x6000 => vec![(nnn + (ld & 0xFF) as usize, Flow::Jump)],
+                _ => (nnn..)
+                    .step_by(2)
+                    .take_while(|&entry| {
+                        self.fetch(entry).is_some_and(|opcode| opcode >> 12 == 0x1)
+                    })
+                    .map(|entry| (entry, Flow::Jump))
+                    .collect(),
+            },
+            _ => vec![(next, Flow::Next)],
+        }
+    }
+
+    /// the reachable instructions by address with their opcodes
+    fn code(&self, entries: &[u16]) -> BTreeMap<usize, u16> {
+        let mut code = BTreeMap::new();
+        let mut todo: Vec<usize> = entries.iter().map(|&entry| entry as usize).collect();
+        while let Some(addr) = todo.pop() {
+            if code.contains_key(&addr) {
+                continue;
+            }
+            if let Some(opcode) = self.fetch(addr) {
+                code.insert(addr, opcode);
+                todo.extend(
+                    self.successors(addr, opcode)
+                        .into_iter()
+                        .map(|(addr, _)| addr),
+                );
+            }
+        }
+        code
+    }
+}
+
+/// The instructions of a program loaded at `start` that can be reached from
+/// `entries`, their sizes by address. Jumps, calls and both ways of skips
+/// are followed, BNNN when V0 is loaded right before it or NNN is a table of
+/// jumps, so sprites and other data between the code are left out.
+pub fn reachable(raw: &[u8], start: u16, entries: &[u16]) -> BTreeMap<usize, usize> {
+    let program = Program {
+        raw,
+        start: start as usize,
+    };
+    program
+        .code(entries)
+        .into_iter()
+        .map(|(addr, opcode)| (addr, size(opcode)))
+        .collect()
+}
+
+/// The control-flow graph of the code `reachable` from `entries` in the
+/// Graphviz dot format: the basic blocks with their instructions, jumps as
+/// solid edges, calls dashed and skips labeled
+pub fn control_flow(raw: &[u8], start: u16, entries: &[u16], symbols: &Symbols) -> String {
+    let program = Program {
+        raw,
+        start: start as usize,
+    };
+    let code = program.code(entries);
+    // a block starts at an entry, where control goes other than to the next
+    // instruction, and after such an instruction
+    let mut leaders: BTreeSet<usize> = entries.iter().map(|&entry| entry as usize).collect();
+    for (&addr, &opcode) in &code {
+        let successors = program.successors(addr, opcode);
+        if successors != [(addr + size(opcode), Flow::Next)] {
+            leaders.extend(successors.iter().map(|&(addr, _)| addr));
+        }
+    }
+
+    let mut dot = String::from("digraph cfg {\n    node [shape=box, fontname=monospace];\n");
+    let mut edges = vec![];
+    let mut block = None;
+    for (&addr, &opcode) in &code {
+        let from = *block.get_or_insert_with(|| {
+            dot.push_str(&format!("    b{:X} [label=\"", addr));
+            addr
+        });
+        if let Some(name) = symbols.name(addr as u16) {
+            dot.push_str(&format!("{}:\\l", name));
+        }
+        let [high, low] = opcode.to_be_bytes();
+        let instr = Instruction::new(high, low);
+        dot.push_str(&format!(
+            "{:#05X}: {}\\l",
+            addr,
+            instr.disassemble_with(symbols)
+        ));
+        let next = addr + size(opcode);
+        let successors = program.successors(addr, opcode);
+        if successors == [(next, Flow::Next)]
+            && code.contains_key(&next)
+            && !leaders.contains(&next)
+        {
+            continue;
+        }
+        edges.extend(
+            successors
+                .into_iter()
+                .filter(|(to, _)| code.contains_key(to))
+                .map(|(to, flow)| (from, to, flow)),
+        );
+        dot.push_str("\"];\n");
+        block = None;
+    }
+    for (from, to, flow) in edges {
+        let style = match flow {
+            Flow::Next | Flow::Jump => "",
+            Flow::Call => " [style=dashed, label=\"call\"]",
+            Flow::Skip => " [label=\"skip\"]",
+        };
+        dot.push_str(&format!("    b{:X} -> b{:X}{};\n", from, to, style));
+    }
+    dot.push_str("}\n");
+    dot
+}
+
+/// `disassemble_with` the instructions `reachable` from `entries` only, the
+/// bytes between them are listed as data
+pub fn disassemble_code(raw: &[u8], start: u16, entries: &[u16], symbols: &Symbols) -> Vec<String> {
+    listing(raw, start, &reachable(raw, start, entries), symbols, None)
+}
+
+/// `disassemble_code` with the instructions at `executed` too, which the
+/// scan may have missed, and a `*` before the executed ones
+pub fn disassemble_covered(
+    raw: &[u8],
+    start: u16,
+    entries: &[u16],
+    symbols: &Symbols,
+    executed: &BTreeSet<usize>,
+) -> Vec<String> {
+    let program = Program {
+        raw,
+        start: start as usize,
+    };
+    let mut code = reachable(raw, start, entries);
+    for &addr in executed {
+        if let Some(opcode) = program.fetch(addr) {
+            code.entry(addr).or_insert_with(|| size(opcode));
+        }
+    }
+    listing(raw, start, &code, symbols, Some(executed))
+}
+
+/// the instructions of `code` and the other bytes as data, with the mark
+/// column when `executed` is given
+fn listing(
+    raw: &[u8],
+    start: u16,
+    code: &BTreeMap<usize, usize>,
+    symbols: &Symbols,
+    executed: Option<&BTreeSet<usize>>,
+) -> Vec<String> {
+    let mark = |addr: usize| match executed {
+        Some(executed) if executed.contains(&addr) => "* ",
+        Some(_) => "  ",
+        None => "",
+    };
+    let end = start as usize + raw.len();
+    let mut lines = vec![];
+    let mut addr = start as usize;
+    while addr < end {
+        if let Some(name) = symbols.name(addr as u16) {
+            lines.push(format!("{}:", name));
+        }
+        let at = addr - start as usize;
+        match code.get(&addr) {
+            Some(&size) => {
+                let instr = Instruction::new(raw[at], raw[at + 1]);
+                lines.push(format!(
+                    "{}{:#05X}: {:04X}  {}",
+                    mark(addr),
+                    addr,
+                    instr.opcode,
+                    instr.disassemble_with(symbols)
+                ));
+                if let Some(&[high, low]) = raw.get(at + 2..at + size) {
+                    let word = u16::from_be_bytes([high, low]);
+                    lines.push(format!(
+                        "{}{:#05X}: {:04X}  DW {:#06X}",
+                        mark(addr),
+                        addr + 2,
+                        word,
+                        word
+                    ));
+                }
+                addr += size;
+            }
+            None => {
+                // two bytes a line like the instructions, up to the next one
+                let len = if addr + 1 < end
+                    && !code.contains_key(&(addr + 1))
+                    && symbols.name(addr as u16 + 1).is_none()
+                {
+                    2
+                } else {
+                    1
+                };
+                let bytes = &raw[at..at + len];
+                let hex: String = bytes.iter().map(|byte| format!("{:02X}", byte)).collect();
+                let values: Vec<String> =
+                    bytes.iter().map(|byte| format!("{:#04X}", byte)).collect();
+                lines.push(format!(
+                    "{}{:#05X}: {:<4}  DB {}",
+                    mark(usize::MAX),
+                    addr,
+                    hex,
+                    values.join(", ")
+                ));
+                addr += len;
+            }
+        }
+    }
+    lines
+}
+
+#[cfg(test)]
+mod disassembler_test {
+    use super::*;
+
+    #[test]
+    fn test_disassemble() {
+        let raw = [
+            0x63, 0x2A, 0xD0, 0x15, 0xF2, 0x65, 0x12, 0x00, 0x5A, 0x11, 0xFF,
+        ];
+        assert_eq!(
+            disassemble(&raw, 0x200),
+            vec![
+                "0x200: 632A  LD V3, 0x2A",
+                "0x202: D015  DRW V0, V1, 5",
+                "0x204: F265  LD V2, [I]",
+                "0x206: 1200  JP 0x200",
+                "0x208: 5A11  DW 0x5A11",
+                "0x20A: FF    DB 0xFF",
+            ]
+        );
+
+        let mut symbols = Symbols::default();
+        symbols.insert("main", 0x200);
+        symbols.insert("data", 0x203);
+        assert_eq!(
+            disassemble_with(&[0x22, 0x00, 0xA2, 0x03], 0x200, &symbols),
+            ["main:", "0x200: 2200  CALL main", "0x202: A203  LD I, data"]
+        );
+    }
+
+    #[test]
+    fn test_disassemble_code() {
+        // a sprite of three bytes between the code, RET at an odd address
+        let raw = [0x22, 0x07, 0x12, 0x02, 0xFF, 0x81, 0xFF, 0x00, 0xEE];
+        assert_eq!(
+            disassemble_code(&raw, 0x200, &[0x200], &Symbols::default()),
+            [
+                "0x200: 2207  CALL 0x207",
+                "0x202: 1202  JP 0x202",
+                "0x204: FF81  DB 0xFF, 0x81",
+                "0x206: FF    DB 0xFF",
+                "0x207: 00EE  RET",
+            ]
+        );
+
+        // BNNN with V0 loaded before it
+        let raw = [0x60, 0x02, 0xB2, 0x04, 0xFF, 0xFF, 0x00, 0xFD];
+        let code = reachable(&raw, 0x200, &[0x200]);
+        assert_eq!(code.keys().collect::<Vec<_>>(), [&0x200, &0x202, &0x206]);
+        // BNNN into a table of jumps, the skip passes the long F000 NNNN
+        let raw = [
+            0xB2, 0x02, 0x12, 0x06, 0x12, 0x0C, 0x30, 0x00, 0xF0, 0x00, 0x12, 0x34, 0x00, 0xFD,
+        ];
+        let code = reachable(&raw, 0x200, &[0x200]);
+        assert_eq!(
+            code.into_iter().collect::<Vec<_>>(),
+            [
+                (0x200, 2),
+                (0x202, 2),
+                (0x204, 2),
+                (0x206, 2),
+                (0x208, 4),
+                (0x20C, 2)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_control_flow() {
+        // call a subroutine, skip to exit or jump back
+        let raw = [
+            0x22, 0x08, 0x30, 0x00, 0x12, 0x00, 0x00, 0xFD, 0x60, 0x01, 0x00, 0xEE,
+        ];
+        let dot = control_flow(&raw, 0x200, &[0x200], &Symbols::default());
+        assert_eq!(
+            dot,
+            r#"digraph cfg {
+    node [shape=box, fontname=monospace];
+    b200 [label="0x200: CALL 0x208\l"];
+    b202 [label="0x202: SE V0, 0x00\l"];
+    b204 [label="0x204: JP 0x200\l"];
+    b206 [label="0x206: EXIT\l"];
+    b208 [label="0x208: LD V0, 0x01\l0x20A: RET\l"];
+    b200 -> b208 [style=dashed, label="call"];
+    b200 -> b202;
+    b202 -> b204;
+    b202 -> b206 [label="skip"];
+    b204 -> b200;
+}
+"#
+        );
+    }
+}
@@ -0,0 +1,209 @@
+use std::error::Error;
+use std::ops::Range;
+use std::str::FromStr;
+
+use crate::video::Video;
+use crate::{err, Result};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Color {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+impl Color {
+    pub const BLACK: Color = Color::rgb(0, 0, 0);
+    pub const WHITE: Color = Color::rgb(255, 255, 255);
+
+    pub const fn rgb(r: u8, g: u8, b: u8) -> Self {
+        Color { r, g, b }
+    }
+}
+
+/// Colors by pixel value, 0 is the background and 1 the foreground. The
+/// display has a single plane, the values 2 and 3 are for a second plane
+/// like XO-CHIP's and default to the foreground.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Palette {
+    pub colors: [Color; 4],
+}
+
+impl Palette {
+    pub const fn new(background: Color, foreground: Color) -> Self {
+        Palette {
+            colors: [background, foreground, foreground, foreground],
+        }
+    }
+
+    /// RGBA bytes of pixel value `value`, fully opaque
+    pub fn rgba(&self, value: usize) -> [u8; 4] {
+        let Color { r, g, b } = self.colors[value & 3];
+        [r, g, b, 0xFF]
+    }
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Palette::new(Color::BLACK, Color::WHITE)
+    }
+}
+
+/// parse a hex color like "#ffb000" or "ffb000"
+impl FromStr for Color {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let hex = s.trim_start_matches('#');
+        if hex.len() != 6 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            return err!("invalid color: {}, expect a hex color like #RRGGBB", s);
+        }
+        let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16);
+        Ok(Color::rgb(channel(0)?, channel(2)?, channel(4)?))
+    }
+}
+
+/// How frontends present the CHIP-8 display
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DisplayConfig {
+    /// window pixels per CHIP-8 lores pixel
+    pub scale: u32,
+    pub foreground: Color,
+    pub background: Color,
+    /// darken every other line of the window like a CRT
+    pub scanlines: bool,
+    /// 0 to 100, how slowly pixels fade after they are turned off, 0 turns
+    /// them off at once
+    pub ghosting: u8,
+}
+
+impl Default for DisplayConfig {
+    fn default() -> Self {
+        DisplayConfig {
+            scale: 10,
+            foreground: Color::WHITE,
+            background: Color::BLACK,
+            scanlines: false,
+            ghosting: 0,
+        }
+    }
+}
+
+impl DisplayConfig {
+    pub fn palette(&self) -> Palette {
+        Palette::new(self.background, self.foreground)
+    }
+}
+
+/// Phosphor persistence against the flicker of sprites that are erased and
+/// drawn again every frame: pixels turned off fade out over a few frames
+/// instead. Frontends feed it the display once per 60Hz frame.
+pub struct Phosphor {
+    // of 256, the brightness an unlit pixel keeps per frame
+    keep: u32,
+    width: usize,
+    height: usize,
+    // brightness of every pixel, 255 when lit
+    levels: Vec<u8>,
+}
+
+impl Phosphor {
+    /// `ghosting` 0 to 100 like `DisplayConfig::ghosting`, at 100 a pixel
+    /// keeps 90% of its brightness per frame
+    pub fn new(ghosting: u8) -> Self {
+        Phosphor {
+            keep: ghosting.min(100) as u32 * 230 / 100,
+            width: 0,
+            height: 0,
+            levels: vec![],
+        }
+    }
+
+    /// take the display of the next frame, a new resolution starts dark
+    pub fn update(&mut self, video: &Video) {
+        if (video.width(), video.height()) != (self.width, self.height) {
+            self.width = video.width();
+            self.height = video.height();
+            self.levels = vec![0; self.width * self.height];
+        }
+        for (on, level) in video.rows().flatten().zip(self.levels.iter_mut()) {
+            *level = if on {
+                255
+            } else {
+                (*level as u32 * self.keep / 256) as u8
+            };
+        }
+    }
+
+    /// like `Video::render_rgba`, the pixels are blended from the background
+    /// to the foreground by their brightness
+    pub fn render_rgba(&self, buffer: &mut [u8], palette: &Palette) {
+        self.render_rgba_rows(0..self.height, buffer, palette);
+    }
+
+    /// like `Video::render_rgba_rows`
+    pub fn render_rgba_rows(&self, rows: Range<usize>, buffer: &mut [u8], palette: &Palette) {
+        let (background, foreground) = (palette.colors[0], palette.colors[1]);
+        let levels = &self.levels[rows.start * self.width..rows.end * self.width];
+        for (&level, rgba) in levels.iter().zip(buffer.chunks_exact_mut(4)) {
+            let level = level as u32;
+            let mix = |b: u8, f: u8| ((b as u32 * (255 - level) + f as u32 * level) / 255) as u8;
+            rgba.copy_from_slice(&[
+                mix(background.r, foreground.r),
+                mix(background.g, foreground.g),
+                mix(background.b, foreground.b),
+                0xFF,
+            ]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod display_test {
+    use super::*;
+
+    #[test]
+    fn test_phosphor() {
+        let palette = Palette::new(Color::rgb(0, 0, 0x40), Color::WHITE);
+        let mut video = Video::new(64, 32);
+        video.set_pixel(2, 1, true);
+        let mut buffer = vec![0; 64 * 32 * 4];
+        let pixel = |buffer: &[u8]| buffer[(64 + 2) * 4..][..4].to_vec();
+
+        // without ghosting it is the plain display
+        let mut phosphor = Phosphor::new(0);
+        phosphor.update(&video);
+        phosphor.render_rgba(&mut buffer, &palette);
+        let mut plain = vec![0; 64 * 32 * 4];
+        video.render_rgba(&mut plain, &palette);
+        assert_eq!(buffer, plain);
+        video.set_pixel(2, 1, false);
+        phosphor.update(&video);
+        phosphor.render_rgba(&mut buffer, &palette);
+        assert_eq!(pixel(&buffer), [0, 0, 0x40, 0xFF]);
+
+        // a turned off pixel fades out over some frames
+        let mut phosphor = Phosphor::new(100);
+        video.set_pixel(2, 1, true);
+        phosphor.update(&video);
+        video.set_pixel(2, 1, false);
+        let mut last = 255;
+        for _ in 0..60 {
+            phosphor.update(&video);
+            phosphor.render_rgba(&mut buffer, &palette);
+            let red = pixel(&buffer)[0];
+            assert!(red < last || red == 0);
+            last = red;
+        }
+        assert_eq!(pixel(&buffer), [0, 0, 0x40, 0xFF]);
+
+        // a row by itself matches the whole frame
+        video.set_pixel(2, 1, true);
+        phosphor.update(&video);
+        phosphor.render_rgba(&mut buffer, &palette);
+        let mut row = vec![0; 64 * 4];
+        phosphor.render_rgba_rows(1..2, &mut row, &palette);
+        assert_eq!(row, buffer[64 * 4..128 * 4]);
+        assert_eq!(pixel(&buffer), [0xFF, 0xFF, 0xFF, 0xFF]);
+    }
+}
@@ -0,0 +1,48 @@
+use std::error::Error;
+use std::fmt;
+
+/// Why `Machine::run_cycle` could not execute an instruction,
+/// `pc` is the address of the offending instruction
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MachineError {
+    /// 00EE with an empty stack
+    StackUnderflow { pc: u16 },
+    /// 2NNN with a full stack
+    StackOverflow { pc: u16 },
+    /// opcode that is not part of CHIP-8 or SUPER-CHIP
+    IllegalOpcode { pc: u16, opcode: u16 },
+    /// instruction fetch or I based access past the end of memory
+    MemoryOutOfBounds { pc: u16, addr: usize },
+}
+
+impl MachineError {
+    pub fn pc(&self) -> u16 {
+        match *self {
+            MachineError::StackUnderflow { pc }
+            | MachineError::StackOverflow { pc }
+            | MachineError::IllegalOpcode { pc, .. }
+            | MachineError::MemoryOutOfBounds { pc, .. } => pc,
+        }
+    }
+}
+
+impl fmt::Display for MachineError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            MachineError::StackUnderflow { pc } => write!(f, "stack underflow at {:#05X}", pc),
+            MachineError::StackOverflow { pc } => write!(f, "stack overflow at {:#05X}", pc),
+            MachineError::IllegalOpcode { pc, opcode } => {
+                write!(f, "illegal opcode {:04X} at {:#05X}", opcode, pc)
+            }
+            MachineError::MemoryOutOfBounds { pc, addr } => {
+                write!(
+                    f,
+                    "memory access at {:#05X} is out of bounds at {:#05X}",
+                    addr, pc
+                )
+            }
+        }
+    }
+}
+
+impl Error for MachineError {}
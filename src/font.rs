@@ -1,7 +1,151 @@
-pub const DEFAULTFONT: [u8; 80] = [
+use std::convert::TryInto;
+use std::fs;
+use std::path::Path;
+
+use crate::rom::parse_hex;
+use crate::{err, Result};
+
+/// 16 hex digit sprites of 5 rows, for FX29
+pub const SMALL_FONT_SIZE: usize = 80;
+/// SCHIP: 16 hex digit sprites of 10 rows, for FX30
+pub const BIG_FONT_SIZE: usize = 160;
+
+pub const DEFAULTFONT: [u8; SMALL_FONT_SIZE] = [
     0xF0, 0x90, 0x90, 0x90, 0xF0, 0x20, 0x60, 0x20, 0x20, 0x70, 0xF0, 0x10, 0xF0, 0x80, 0xF0, 0xF0,
     0x10, 0xF0, 0x10, 0xF0, 0x90, 0x90, 0xF0, 0x10, 0x10, 0xF0, 0x80, 0xF0, 0x10, 0xF0, 0xF0, 0x80,
     0xF0, 0x90, 0xF0, 0xF0, 0x10, 0x20, 0x40, 0x40, 0xF0, 0x90, 0xF0, 0x90, 0xF0, 0xF0, 0x90, 0xF0,
     0x10, 0xF0, 0xF0, 0x90, 0xF0, 0x90, 0x90, 0xE0, 0x90, 0xE0, 0x90, 0xE0, 0xF0, 0x80, 0x80, 0x80,
     0xF0, 0xE0, 0x90, 0x90, 0x90, 0xE0, 0xF0, 0x80, 0xF0, 0x80, 0xF0, 0xF0, 0x80, 0xF0, 0x80, 0x80,
 ];
+
+/// the font of the COSMAC VIP interpreter, with a flat topped 4 and a square B and D
+const VIP_FONT: [u8; SMALL_FONT_SIZE] = [
+    0xF0, 0x90, 0x90, 0x90, 0xF0, 0x60, 0x20, 0x20, 0x20, 0x70, 0xF0, 0x10, 0xF0, 0x80, 0xF0, 0xF0,
+    0x10, 0xF0, 0x10, 0xF0, 0xA0, 0xA0, 0xF0, 0x20, 0x20, 0xF0, 0x80, 0xF0, 0x10, 0xF0, 0xF0, 0x80,
+    0xF0, 0x90, 0xF0, 0xF0, 0x10, 0x10, 0x10, 0x10, 0xF0, 0x90, 0xF0, 0x90, 0xF0, 0xF0, 0x90, 0xF0,
+    0x10, 0xF0, 0xF0, 0x90, 0xF0, 0x90, 0x90, 0xF0, 0x50, 0x70, 0x50, 0xF0, 0xF0, 0x80, 0x80, 0x80,
+    0xF0, 0xF0, 0x50, 0x50, 0x50, 0xF0, 0xF0, 0x80, 0xF0, 0x80, 0xF0, 0xF0, 0x80, 0xF0, 0x80, 0x80,
+];
+
+/// the narrow 3 pixel wide font of the DREAM 6800
+const DREAM6800_FONT: [u8; SMALL_FONT_SIZE] = [
+    0xE0, 0xA0, 0xA0, 0xA0, 0xE0, 0x40, 0x40, 0x40, 0x40, 0x40, 0xE0, 0x20, 0xE0, 0x80, 0xE0, 0xE0,
+    0x20, 0xE0, 0x20, 0xE0, 0x80, 0xA0, 0xA0, 0xE0, 0x20, 0xE0, 0x80, 0xE0, 0x20, 0xE0, 0xE0, 0x80,
+    0xE0, 0xA0, 0xE0, 0xE0, 0x20, 0x20, 0x20, 0x20, 0xE0, 0xA0, 0xE0, 0xA0, 0xE0, 0xE0, 0xA0, 0xE0,
+    0x20, 0xE0, 0xE0, 0xA0, 0xE0, 0xA0, 0xA0, 0xC0, 0xA0, 0xE0, 0xA0, 0xC0, 0xE0, 0x80, 0x80, 0x80,
+    0xE0, 0xC0, 0xA0, 0xA0, 0xA0, 0xC0, 0xE0, 0x80, 0xE0, 0x80, 0xE0, 0xE0, 0x80, 0xC0, 0x80, 0x80,
+];
+
+/// SCHIP 8x10 digits, the original only had 0-9 and A-F are drawn in the
+/// same style
+pub const SCHIP_BIG_FONT: [u8; BIG_FONT_SIZE] = [
+    0x3C, 0x7E, 0xE7, 0xC3, 0xC3, 0xC3, 0xC3, 0xE7, 0x7E, 0x3C, // 0
+    0x18, 0x38, 0x58, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x3C, // 1
+    0x3E, 0x7F, 0xC3, 0x06, 0x0C, 0x18, 0x30, 0x60, 0xFF, 0xFF, // 2
+    0x3C, 0x7E, 0xC3, 0x03, 0x0E, 0x0E, 0x03, 0xC3, 0x7E, 0x3C, // 3
+    0x06, 0x0E, 0x1E, 0x36, 0x66, 0xC6, 0xFF, 0xFF, 0x06, 0x06, // 4
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFC, 0xFE, 0x03, 0xC3, 0x7E, 0x3C, // 5
+    0x3E, 0x7C, 0xC0, 0xC0, 0xFC, 0xFE, 0xC3, 0xC3, 0x7E, 0x3C, // 6
+    0xFF, 0xFF, 0x03, 0x06, 0x0C, 0x18, 0x30, 0x60, 0x60, 0x60, // 7
+    0x3C, 0x7E, 0xC3, 0xC3, 0x7E, 0x7E, 0xC3, 0xC3, 0x7E, 0x3C, // 8
+    0x3C, 0x7E, 0xC3, 0xC3, 0x7F, 0x3F, 0x03, 0x03, 0x3E, 0x7C, // 9
+    0x18, 0x3C, 0x66, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xC3, // A
+    0xFC, 0xFE, 0xC3, 0xC3, 0xFE, 0xFE, 0xC3, 0xC3, 0xFE, 0xFC, // B
+    0x3C, 0x7E, 0xC3, 0xC0, 0xC0, 0xC0, 0xC0, 0xC3, 0x7E, 0x3C, // C
+    0xFC, 0xFE, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFE, 0xFC, // D
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFC, 0xFC, 0xC0, 0xC0, 0xFF, 0xFF, // E
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFC, 0xFC, 0xC0, 0xC0, 0xC0, 0xC0, // F
+];
+
+/// Hex digit sprites the machine loads below the program
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Font {
+    pub small: [u8; SMALL_FONT_SIZE],
+    /// SCHIP big digits for FX30, left out of memory when None
+    pub big: Option<[u8; BIG_FONT_SIZE]>,
+}
+
+impl Default for Font {
+    fn default() -> Self {
+        Font {
+            small: DEFAULTFONT,
+            big: Some(SCHIP_BIG_FONT),
+        }
+    }
+}
+
+impl Font {
+    /// built-in font styles for `--font`
+    pub const NAMES: [&'static str; 3] = ["default", "vip", "dream6800"];
+
+    /// Replace sprites from a built-in font name or a font file. A file has
+    /// 80 bytes of small digits, 160 bytes of big digits or both in that
+    /// order, as binary or as text with a .hex or .txt extension: hex bytes
+    /// like font/default.hex, see `rom::parse_hex`.
+    pub fn apply(&mut self, name_or_path: &str) -> Result<()> {
+        self.small = match name_or_path {
+            "default" => DEFAULTFONT,
+            "vip" => VIP_FONT,
+            "dream6800" => DREAM6800_FONT,
+            path => return self.load(Path::new(path)),
+        };
+        Ok(())
+    }
+
+    fn load(&mut self, path: &Path) -> Result<()> {
+        let bytes = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("hex") | Some("txt") => parse_hex(&fs::read_to_string(path)?),
+            _ => Ok(fs::read(path)?),
+        };
+        bytes
+            .and_then(|bytes| self.set_bytes(&bytes))
+            .map_err(|e| format!("font {}: {}", path.display(), e).into())
+    }
+
+    fn set_bytes(&mut self, bytes: &[u8]) -> Result<()> {
+        match bytes.len() {
+            SMALL_FONT_SIZE => self.small.copy_from_slice(bytes),
+            BIG_FONT_SIZE => self.big = Some(bytes.try_into().unwrap()),
+            len if len == SMALL_FONT_SIZE + BIG_FONT_SIZE => {
+                let (small, big) = bytes.split_at(SMALL_FONT_SIZE);
+                self.small.copy_from_slice(small);
+                self.big = Some(big.try_into().unwrap());
+            }
+            len => {
+                return err!(
+                    "expect {}, {} or {} bytes, got {}",
+                    SMALL_FONT_SIZE,
+                    BIG_FONT_SIZE,
+                    SMALL_FONT_SIZE + BIG_FONT_SIZE,
+                    len
+                )
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod font_test {
+    use super::*;
+
+    #[test]
+    fn test_font_files() {
+        let mut font = Font::default();
+        font.apply("vip").unwrap();
+        assert_eq!(font.small, VIP_FONT);
+
+        // the text file of the default font
+        font.apply(concat!(env!("CARGO_MANIFEST_DIR"), "/font/default.hex"))
+            .unwrap();
+        assert_eq!(font, Font::default());
+
+        assert_eq!(parse_hex("0xF0,90\n 0X0a").unwrap(), [0xF0, 0x90, 0x0A]);
+        assert!(parse_hex("0xF0, 0x100").is_err());
+        font.set_bytes(&[0xFF; BIG_FONT_SIZE]).unwrap();
+        assert_eq!(font.small, DEFAULTFONT);
+        assert_eq!(font.big, Some([0xFF; BIG_FONT_SIZE]));
+        assert!(font.set_bytes(&[0; 81]).is_err());
+        assert!(font.apply("no-such-font.bin").is_err());
+    }
+}
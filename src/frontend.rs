@@ -0,0 +1,989 @@
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use crossbeam_channel::{never, Receiver};
+use serde_json::Value;
+
+use crate::audio::AudioPlay;
+use crate::clock::{self, Pacer};
+use crate::debugger::Debugger;
+use crate::headless;
+use crate::hooks::FrameHook;
+use crate::logging::{AUDIO, CPU};
+use crate::machine::Machine;
+use crate::netplay::{self, Netplay};
+use crate::remote::{self, Call, Request};
+use crate::rewind::Rewind;
+use crate::rom::{self, ROM};
+use crate::rpl::{self, FlagFile};
+use crate::state::SaveState;
+use crate::timing::{self, Timing, VipClock, TIMER_FREQ};
+use crate::trace::FileTracer;
+use crate::watch::RomWatcher;
+use crate::{Result, DEFAULT_CLOCK_FREQ, UNLIMITED};
+
+/// shown before the rom in the title
+pub const TITLE: &str = "yet-another-rchip8";
+// `InputEvent::SpeedUp` and `SpeedDown` change the clock speed by this many Hz
+const SPEED_STEP: u64 = 100;
+// `InputEvent::VolumeUp` and `VolumeDown` change the volume by this much
+const VOLUME_STEP: f32 = 0.05;
+// `InputEvent::Rewind` goes back up to this many seconds
+const REWIND_SECONDS: usize = 10;
+
+/// Where a frontend shows the display, like `AudioPlay` is where it plays
+/// the beep
+pub trait DisplayBackend<T: AudioPlay> {
+    /// show the machine display, called after every frame
+    fn present(&mut self, machine: &mut Machine<T>, frame: &FrameInfo) -> Result<()>;
+
+    /// show the rom and whether it is paused, e.g. in the window title
+    fn set_title(&mut self, _title: &str) -> Result<()> {
+        Ok(())
+    }
+
+    /// another rom was opened, e.g. to forget what the last one did
+    fn loaded(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    /// the display refresh period when `present` waits for it, the frames
+    /// are then paced by it instead of sleeping to the next timer tick
+    fn vsync(&self) -> Option<Duration> {
+        None
+    }
+}
+
+/// How the frame given to `DisplayBackend::present` went
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FrameInfo {
+    /// the machine ran, false while it is paused or rewound
+    pub ran: bool,
+    /// the instructions per second it runs at, none when unlimited or paid
+    /// by the VIP timing
+    pub target_ips: Option<u64>,
+}
+
+/// The state shown after the rom in the title
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Status {
+    pub paused: bool,
+    /// the clock speed over the speed the rom started at, none in turbo
+    pub speed: Option<f64>,
+    pub muted: bool,
+}
+
+/// `pong (paused, 1.2x, muted)`, the rom alone when it runs at its speed
+pub fn status_title(rom: &str, status: &Status) -> String {
+    let mut parts = vec![];
+    if status.paused {
+        parts.push("paused".to_string());
+    }
+    match status.speed {
+        None => parts.push("turbo".to_string()),
+        Some(speed) if (speed - 1.0).abs() > 0.005 => {
+            let speed = format!("{:.2}", speed);
+            parts.push(format!(
+                "{}x",
+                speed.trim_end_matches('0').trim_end_matches('.')
+            ));
+        }
+        Some(_) => {}
+    }
+    if status.muted {
+        parts.push("muted".to_string());
+    }
+    if parts.is_empty() {
+        rom.to_string()
+    } else {
+        format!("{} ({})", rom, parts.join(", "))
+    }
+}
+
+/// What the user did, from an `InputBackend`
+#[derive(Debug, Clone, PartialEq)]
+pub enum InputEvent {
+    KeyDown(u8),
+    KeyUp(u8),
+    /// pause or resume the emulation
+    TogglePause,
+    /// pause and run one frame
+    StepFrame,
+    SpeedUp,
+    SpeedDown,
+    /// switch between unlimited speed and the speed before
+    ToggleTurbo,
+    /// instructions per second, `UNLIMITED` runs as fast as possible
+    SetSpeed(u64),
+    ToggleMute,
+    VolumeUp,
+    VolumeDown,
+    /// from 0 to 1, also unmutes
+    SetVolume(f32),
+    /// restart the rom
+    Reset,
+    SaveState,
+    LoadState,
+    /// true while the rewind key is held down
+    Rewind(bool),
+    /// replace the rom with the rom file at this path
+    Open(String),
+    /// open the next of `Options::roms`
+    NextRom,
+    /// false when the window went to the background or was minimized, true
+    /// when it got the focus back
+    Focus(bool),
+    Quit,
+}
+
+impl InputEvent {
+    /// events that change the machine on one side only, off with netplay
+    fn desyncs(&self) -> bool {
+        matches!(
+            self,
+            InputEvent::TogglePause
+                | InputEvent::StepFrame
+                | InputEvent::SpeedUp
+                | InputEvent::SpeedDown
+                | InputEvent::ToggleTurbo
+                | InputEvent::SetSpeed(_)
+                | InputEvent::Reset
+                | InputEvent::SaveState
+                | InputEvent::LoadState
+                | InputEvent::Rewind(_)
+                | InputEvent::Open(_)
+                | InputEvent::NextRom
+        )
+    }
+}
+
+/// Where a frontend gets the keys from
+pub trait InputBackend {
+    /// add what happened since the last call to `events`, without waiting
+    fn poll(&mut self, events: &mut Vec<InputEvent>) -> Result<()>;
+}
+
+impl<T: AudioPlay, D: DisplayBackend<T> + ?Sized> DisplayBackend<T> for &mut D {
+    fn present(&mut self, machine: &mut Machine<T>, frame: &FrameInfo) -> Result<()> {
+        (**self).present(machine, frame)
+    }
+
+    fn set_title(&mut self, title: &str) -> Result<()> {
+        (**self).set_title(title)
+    }
+
+    fn loaded(&mut self) -> Result<()> {
+        (**self).loaded()
+    }
+
+    fn vsync(&self) -> Option<Duration> {
+        (**self).vsync()
+    }
+}
+
+impl<I: InputBackend + ?Sized> InputBackend for &mut I {
+    fn poll(&mut self, events: &mut Vec<InputEvent>) -> Result<()> {
+        (**self).poll(events)
+    }
+}
+
+/// Settings of an `Emulator`
+pub struct Options<T: AudioPlay> {
+    /// instructions per second, `UNLIMITED` runs as fast as possible
+    pub clock_freq: u64,
+    pub timing: Timing,
+    /// the sound volume from 0 to 1, unmuting goes back to it
+    pub volume: f32,
+    /// reload the rom when its file changes, follows the rom opened last
+    pub watcher: Option<RomWatcher>,
+    /// the rom path, `DisplayBackend::set_title` gets its file name. None
+    /// until a rom is opened, the machine does not run without one.
+    pub title: Option<String>,
+    /// called after every frame that ran, e.g. a script
+    pub on_frame: Option<FrameHook<T>>,
+    /// keeps the RPL flags of the rom when they change
+    pub flags: Option<FlagFile>,
+    /// keep the RPL flags of every rom opened in its `rpl::flags_path` file
+    pub keep_flags: bool,
+    /// `InputEvent::SaveState` saves and `LoadState` loads the machine state
+    /// from this file, it follows the rom opened
+    pub state_path: Option<PathBuf>,
+    /// `InputEvent::NextRom` opens the next of these roms, the machine starts
+    /// with the first
+    pub roms: Vec<String>,
+    /// keep the last `REWIND_SECONDS` for `InputEvent::Rewind`
+    pub rewind: bool,
+    /// pause while the window is in the background or minimized
+    pub auto_pause: bool,
+    /// takes commands from stdin and stops at breakpoints
+    pub debugger: Option<Debugger>,
+    /// write every executed instruction to a trace file
+    pub tracer: Option<FileTracer>,
+    /// run in lockstep with another player, the machine has the session's
+    /// seed and quirks and `clock_freq` is its speed
+    pub netplay: Option<Netplay>,
+    /// requests from `--remote` clients
+    pub remote: Option<Receiver<Call>>,
+}
+
+impl<T: AudioPlay> Default for Options<T> {
+    fn default() -> Self {
+        Options {
+            clock_freq: DEFAULT_CLOCK_FREQ,
+            timing: Timing::Fixed,
+            volume: 1.0,
+            watcher: None,
+            title: None,
+            on_frame: None,
+            flags: None,
+            keep_flags: false,
+            state_path: None,
+            roms: vec![],
+            rewind: false,
+            auto_pause: false,
+            debugger: None,
+            tracer: None,
+            netplay: None,
+            remote: None,
+        }
+    }
+}
+
+/// A machine with a display and an input, run by the 60Hz frame loop of
+/// `run` or one `frame` at a time by a frontend with its own loop
+pub struct Emulator<'a, T: AudioPlay, D, I> {
+    machine: &'a mut Machine<T>,
+    display: D,
+    input: I,
+    clock_freq: u64,
+    // the speed turbo goes back to
+    normal_speed: u64,
+    // the title shows the speed relative to this one
+    base_speed: u64,
+    timing: Timing,
+    vip_clock: VipClock,
+    volume: f32,
+    muted: bool,
+    watcher: Option<RomWatcher>,
+    title: Option<String>,
+    // what `set_title` was called with last
+    shown_title: String,
+    on_frame: Option<FrameHook<T>>,
+    flags: Option<FlagFile>,
+    keep_flags: bool,
+    state_path: Option<PathBuf>,
+    roms: Vec<String>,
+    rom_index: usize,
+    rewind: Option<Rewind>,
+    rewinding: bool,
+    paused: bool,
+    // paused by `auto_pause`, not by the user
+    focus_paused: bool,
+    auto_pause: bool,
+    debugger: Option<Debugger>,
+    commands: Receiver<String>,
+    tracer: Option<FileTracer>,
+    netplay: Option<Netplay>,
+    // the keys held on this side, with netplay they are sent to the other
+    // player first and set on the machine together with theirs
+    keys: u16,
+    remote: Receiver<Call>,
+}
+
+impl<'a, T, D, I> Emulator<'a, T, D, I>
+where
+    T: AudioPlay,
+    D: DisplayBackend<T>,
+    I: InputBackend,
+{
+    /// the machine plays its sound with `audio`, the timers follow the
+    /// timer clock
+    pub fn new(
+        machine: &'a mut Machine<T>,
+        audio: T,
+        display: D,
+        input: I,
+        options: Options<T>,
+    ) -> Self {
+        machine.init_sound(audio);
+        machine.set_volume(options.volume);
+        // with netplay every frame runs the same instructions on both sides
+        if options.netplay.is_none() {
+            machine.use_timer_clock();
+        }
+        let base_speed = match options.clock_freq {
+            UNLIMITED => DEFAULT_CLOCK_FREQ,
+            freq => freq,
+        };
+        let mut debugger = options.debugger;
+        if let Some(debugger) = debugger.as_mut() {
+            debugger.set_clock_freq(options.clock_freq);
+        }
+        let commands = match debugger {
+            Some(_) => Debugger::stdin_commands(),
+            None => never(),
+        };
+        Emulator {
+            machine,
+            display,
+            input,
+            clock_freq: options.clock_freq,
+            normal_speed: base_speed,
+            base_speed,
+            timing: options.timing,
+            vip_clock: VipClock::new(),
+            volume: options.volume,
+            muted: false,
+            watcher: options.watcher,
+            title: options.title.as_deref().map(file_name),
+            shown_title: String::new(),
+            on_frame: options.on_frame,
+            flags: options.flags,
+            keep_flags: options.keep_flags,
+            state_path: options.state_path,
+            roms: options.roms,
+            rom_index: 0,
+            rewind: options.rewind.then(|| Rewind::new(REWIND_SECONDS * 60)),
+            rewinding: false,
+            paused: false,
+            focus_paused: false,
+            auto_pause: options.auto_pause,
+            debugger,
+            commands,
+            tracer: options.tracer,
+            netplay: options.netplay,
+            keys: 0,
+            remote: options.remote.unwrap_or_else(never),
+        }
+    }
+
+    pub fn machine(&mut self) -> &mut Machine<T> {
+        self.machine
+    }
+
+    /// paused by the user or stopped by the debugger
+    pub fn is_paused(&self) -> bool {
+        self.paused || self.debugger.as_ref().is_some_and(Debugger::is_paused)
+    }
+
+    /// what the title shows after the rom
+    pub fn status(&self) -> Status {
+        Status {
+            paused: self.is_paused(),
+            speed: match self.clock_freq {
+                UNLIMITED => None,
+                freq => Some(freq as f64 / self.base_speed as f64),
+            },
+            muted: self.muted,
+        }
+    }
+
+    /// set the title when it changed
+    fn update_title(&mut self) -> Result<()> {
+        let title = match &self.title {
+            Some(rom) => status_title(&format!("{} - {}", TITLE, rom), &self.status()),
+            None => TITLE.to_string(),
+        };
+        if title != self.shown_title {
+            self.display.set_title(&title)?;
+            self.shown_title = title;
+        }
+        Ok(())
+    }
+
+    /// stop running instructions, the timers and the sound are held
+    pub fn pause(&mut self) -> Result<()> {
+        if !self.paused {
+            self.paused = true;
+            self.machine.stop_sound();
+            self.update_title()?;
+        }
+        Ok(())
+    }
+
+    pub fn resume(&mut self) -> Result<()> {
+        self.focus_paused = false;
+        if self.paused {
+            self.paused = false;
+            self.update_title()?;
+        }
+        Ok(())
+    }
+
+    /// pause and run the instructions of one 60Hz frame and a timer tick
+    pub fn step_frame(&mut self) -> Result<()> {
+        self.pause()?;
+        headless::step_frame(self.machine, self.clock_freq)?;
+        self.after_frame()
+    }
+
+    /// the frame hook, saving the RPL flags and keeping the frame to rewind
+    fn after_frame(&mut self) -> Result<()> {
+        if let Some(on_frame) = self.on_frame.as_mut() {
+            if let Err(e) = on_frame(self.machine) {
+                error!("{}, it is stopped", e);
+                self.on_frame = None;
+            }
+        }
+        if let Some(flags) = self.flags.as_mut() {
+            if let Err(e) = flags.save(self.machine) {
+                error!("{}, the RPL flags are not kept", e);
+                self.flags = None;
+            }
+        }
+        if let Some(rewind) = self.rewind.as_mut() {
+            rewind.push(self.machine.save_state());
+        }
+        Ok(())
+    }
+
+    /// restart the rom
+    pub fn reset(&mut self) -> Result<()> {
+        self.machine.reset()
+    }
+
+    /// replace the running rom and start it from scratch, the state file,
+    /// the RPL flags and the watcher follow the new rom
+    pub fn open(&mut self, path: &str) -> Result<()> {
+        let rom = ROM::new(path)?;
+        self.machine.load_rom(&rom)?;
+        self.machine.reset()?;
+        info!("rom loaded from {}", path);
+        if let Some(watcher) = self.watcher.as_mut() {
+            if let Err(e) = watcher.watch(path) {
+                error!("{}", e);
+            }
+        }
+        if self.state_path.is_some() {
+            self.state_path = Some(rom::state_path(path));
+        }
+        if self.keep_flags {
+            self.flags = rpl::for_rom(path, self.machine);
+        }
+        if let Some(rewind) = self.rewind.as_mut() {
+            rewind.clear();
+        }
+        self.title = Some(file_name(path));
+        self.paused = false;
+        self.focus_paused = false;
+        self.display.loaded()?;
+        self.update_title()
+    }
+
+    /// instructions per second
+    pub fn speed(&self) -> u64 {
+        self.clock_freq
+    }
+
+    pub fn set_speed(&mut self, clock_freq: u64) -> Result<()> {
+        self.clock_freq = clock_freq;
+        if clock_freq == UNLIMITED {
+            info!(target: CPU, "clock speed: unlimited");
+        } else {
+            self.normal_speed = clock_freq;
+            info!(target: CPU, "clock speed: {}Hz", clock_freq);
+        }
+        // the debugger steps frames at the current speed
+        if let (Some(debugger), Timing::Fixed) = (self.debugger.as_mut(), self.timing) {
+            debugger.set_clock_freq(clock_freq);
+        }
+        self.update_title()
+    }
+
+    pub fn is_muted(&self) -> bool {
+        self.muted
+    }
+
+    /// silence the sound, or play it again at the volume it had
+    pub fn set_muted(&mut self, muted: bool) -> Result<()> {
+        self.muted = muted;
+        self.machine
+            .set_volume(if muted { 0.0 } else { self.volume });
+        self.update_title()
+    }
+
+    /// play the sound at `volume` from 0 to 1, unmuted
+    pub fn set_volume(&mut self, volume: f32) -> Result<()> {
+        self.volume = volume;
+        self.set_muted(false)
+    }
+
+    /// the instructions per second the machine runs at, none when unlimited
+    /// or when VIP timing pays for every instruction
+    fn target_ips(&self) -> Option<u64> {
+        match (self.timing, self.clock_freq) {
+            (Timing::Vip, _) | (_, UNLIMITED) => None,
+            (Timing::Fixed, freq) => Some(freq),
+        }
+    }
+
+    /// Handle an input event, false when it quits
+    fn handle(&mut self, event: InputEvent) -> Result<bool> {
+        if self.netplay.is_some() && event.desyncs() {
+            info!("{:?} is off during netplay", event);
+            return Ok(true);
+        }
+        match event {
+            InputEvent::KeyDown(key) => {
+                self.keys |= 1 << key;
+                if self.netplay.is_none() {
+                    self.machine.key_down(key);
+                }
+            }
+            InputEvent::KeyUp(key) => {
+                self.keys &= !(1 << key);
+                if self.netplay.is_none() {
+                    self.machine.key_up(key);
+                }
+            }
+            InputEvent::TogglePause => {
+                if self.paused {
+                    self.resume()?;
+                } else {
+                    self.pause()?;
+                }
+                info!("paused: {}", self.paused);
+            }
+            InputEvent::StepFrame => self.step_frame()?,
+            InputEvent::SpeedUp | InputEvent::SpeedDown if self.timing == Timing::Vip => {
+                info!("the speed is set by the VIP timing")
+            }
+            InputEvent::SpeedUp if self.clock_freq != UNLIMITED => {
+                self.set_speed(self.normal_speed + SPEED_STEP)?
+            }
+            InputEvent::SpeedDown if self.clock_freq != UNLIMITED => {
+                self.set_speed(self.normal_speed.saturating_sub(SPEED_STEP).max(SPEED_STEP))?
+            }
+            InputEvent::SpeedUp | InputEvent::SpeedDown => {}
+            InputEvent::ToggleTurbo if self.clock_freq == UNLIMITED => {
+                self.set_speed(self.normal_speed)?
+            }
+            InputEvent::ToggleTurbo => self.set_speed(UNLIMITED)?,
+            InputEvent::SetSpeed(clock_freq) => self.set_speed(clock_freq)?,
+            InputEvent::ToggleMute => {
+                self.set_muted(!self.muted)?;
+                info!(target: AUDIO, "muted: {}", self.muted);
+            }
+            InputEvent::VolumeUp | InputEvent::VolumeDown => {
+                let step = if event == InputEvent::VolumeUp {
+                    VOLUME_STEP
+                } else {
+                    -VOLUME_STEP
+                };
+                self.set_volume((self.volume + step).clamp(0.0, 1.0))?;
+                info!(target: AUDIO, "volume: {:.0}%", self.volume * 100.0);
+            }
+            InputEvent::SetVolume(volume) => self.set_volume(volume)?,
+            InputEvent::Reset => match self.reset() {
+                Ok(()) => info!("machine reset"),
+                Err(e) => error!("can not reset the machine: {}", e),
+            },
+            InputEvent::SaveState => match &self.state_path {
+                Some(path) => save_state(self.machine, path),
+                None => info!("no state file to save to"),
+            },
+            InputEvent::LoadState => match &self.state_path {
+                Some(path) => load_state(self.machine, path),
+                None => info!("no state file to load from"),
+            },
+            InputEvent::Rewind(hold) => {
+                self.rewinding = hold && self.rewind.is_some();
+                if self.rewinding {
+                    self.machine.stop_sound();
+                }
+            }
+            InputEvent::Open(path) => {
+                if let Err(e) = self.open(&path) {
+                    error!("can not load rom from {}: {}", path, e);
+                }
+            }
+            InputEvent::NextRom if self.roms.len() > 1 => {
+                self.rom_index = (self.rom_index + 1) % self.roms.len();
+                let path = self.roms[self.rom_index].clone();
+                if let Err(e) = self.open(&path) {
+                    error!("can not load rom from {}: {}", path, e);
+                }
+            }
+            InputEvent::NextRom => info!("no other rom to switch to, give more with --rom"),
+            // the other player would wait, so netplay keeps running
+            InputEvent::Focus(_) if !self.auto_pause || self.netplay.is_some() => {}
+            InputEvent::Focus(false) if !self.paused => {
+                self.pause()?;
+                self.focus_paused = true;
+                info!("paused in the background");
+            }
+            InputEvent::Focus(true) if self.focus_paused => {
+                self.resume()?;
+                info!("paused: {}", self.paused);
+            }
+            InputEvent::Focus(_) => {}
+            InputEvent::Quit => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    /// Answer a `--remote` request, false when it quits
+    fn answer(&mut self, call: Call) -> Result<bool> {
+        let result = match &call.request {
+            Request::Pause => self.pause().map(|()| Value::Null),
+            Request::Resume => self.resume().map(|()| Value::Null),
+            Request::Quit => {
+                call.reply(Ok(Value::Null));
+                return Ok(false);
+            }
+            Request::Load { path } => self.open(path).map(|()| Value::Null),
+            request => remote::execute(request, self.machine, self.clock_freq),
+        };
+        call.reply(result);
+        Ok(true)
+    }
+
+    /// Run the instructions of a frame, or as many as fit before `deadline`
+    /// when unlimited. With VIP timing these are the ones the time since the
+    /// last frame paid for instead, turbo ignores the costs.
+    /// run `cycles` instructions, or the ones the VIP timing pays for, or as
+    /// many as fit before `deadline` when unlimited
+    fn run_cycles(&mut self, mut cycles: u64, deadline: Instant) -> Result<()> {
+        let Emulator {
+            machine,
+            clock_freq,
+            timing,
+            vip_clock,
+            debugger,
+            tracer,
+            ..
+        } = self;
+        let unlimited = *clock_freq == UNLIMITED;
+        let vip = *timing == Timing::Vip && !unlimited;
+        if vip {
+            vip_clock.refill();
+        }
+        while !machine.is_halt()
+            && (if vip {
+                vip_clock.has_budget()
+            } else if unlimited {
+                Instant::now() < deadline
+            } else {
+                cycles > 0
+            })
+            && debugger.as_mut().is_none_or(|d| d.before_cycle(machine))
+        {
+            let cost = machine.next_opcode().map_or(0, timing::vip_cost);
+            match tracer.as_mut() {
+                Some(tracer) => tracer.run_cycle(machine)?,
+                None => machine.run_cycle()?,
+            }
+            if let Some(debugger) = debugger.as_mut() {
+                debugger.after_cycle(machine);
+            }
+            if vip {
+                vip_clock.spend(cost);
+            } else {
+                cycles = cycles.saturating_sub(1);
+            }
+        }
+        Ok(())
+    }
+
+    /// Run one 60Hz frame of `cycles` instructions unless the machine is
+    /// paused, rewound or has no rom. True when it ran.
+    fn run_frame(&mut self, cycles: u64, deadline: Instant) -> Result<bool> {
+        if self.title.is_none() || self.is_paused() {
+            self.machine.hold_timers();
+            return Ok(false);
+        }
+        if self.rewinding {
+            if let Some(state) = self.rewind.as_mut().and_then(Rewind::step_back) {
+                self.machine.load_state(state)?;
+            }
+            return Ok(false);
+        }
+        match self.netplay.as_mut() {
+            Some(netplay) => {
+                let keys = netplay.exchange(self.keys, netplay::state_check(self.machine))?;
+                netplay::set_keys(self.machine, keys);
+                headless::run_frame(self.machine, self.clock_freq, deadline)?;
+            }
+            None => {
+                self.run_cycles(cycles, deadline)?;
+                self.machine.tick_60hz();
+            }
+        }
+        self.after_frame()?;
+        Ok(true)
+    }
+
+    /// Handle the input, run one 60Hz frame that ends by `deadline` and
+    /// present it. False when the input quits.
+    pub fn frame(&mut self, deadline: Instant) -> Result<bool> {
+        let cycles = headless::frame_cycles(self.clock_freq) as u64;
+        self.frames(1, cycles, deadline)
+    }
+
+    /// `frame` for the `frames` timer ticks due, which share the `cycles`
+    /// instructions due, and a single present
+    fn frames(&mut self, frames: u64, cycles: u64, deadline: Instant) -> Result<bool> {
+        let mut events = vec![];
+        self.input.poll(&mut events)?;
+        if let Some(watcher) = self.watcher.as_mut() {
+            if watcher.changed(Instant::now()) {
+                let path = watcher.path().to_string_lossy().into_owned();
+                events.push(InputEvent::Open(path));
+            }
+        }
+        for event in events {
+            if !self.handle(event)? {
+                return Ok(false);
+            }
+        }
+        let calls: Vec<Call> = self.remote.try_iter().collect();
+        for call in calls {
+            if !self.answer(call)? {
+                return Ok(false);
+            }
+        }
+        let commands: Vec<String> = self.commands.try_iter().collect();
+        for line in commands {
+            if let Some(debugger) = self.debugger.as_mut() {
+                if !debugger.execute(&line, self.machine) {
+                    return Ok(false);
+                }
+            }
+        }
+
+        let mut ran = false;
+        for frame in 0..frames {
+            let share = cycles * (frame + 1) / frames - cycles * frame / frames;
+            ran |= self.run_frame(share, deadline)?;
+        }
+        self.update_title()?;
+        let frame = FrameInfo {
+            ran,
+            target_ips: self.target_ips(),
+        };
+        self.display.present(self.machine, &frame)?;
+        Ok(true)
+    }
+
+    /// Run frames until the machine halts or the input quits
+    pub fn run(mut self) -> Result<()> {
+        self.update_title()?;
+        // frames run at the timer ticks, the instructions due since the last
+        // ones are counted at the current speed. With vsync the display is
+        // presented on every refresh and runs the ticks due by then.
+        let mut timer = Pacer::new(TIMER_FREQ);
+        let mut cpu = Pacer::new(self.clock_freq);
+        while !self.machine.is_halt() {
+            let vsync = self.display.vsync();
+            if vsync.is_none() {
+                clock::sleep_until(timer.next());
+            }
+            let now = Instant::now();
+            let (frames, dropped) = timer.take(now);
+            if dropped > 0 {
+                debug!(target: CPU, "behind the timer, {} frames dropped", dropped);
+            }
+            let cycles = match self.clock_freq {
+                // the instructions wait for the next tick
+                _ if frames == 0 => 0,
+                UNLIMITED => 0,
+                freq => {
+                    cpu.set_freq(freq);
+                    let (cycles, dropped) = cpu.take(now);
+                    if dropped > 0 {
+                        debug!(target: CPU, "behind the clock, {} instructions dropped", dropped);
+                    }
+                    cycles
+                }
+            };
+            // unlimited speed runs for half a refresh before it presents again
+            let deadline = match vsync {
+                Some(period) => timer.next().min(now + period / 2),
+                None => timer.next(),
+            };
+            if !self.frames(frames, cycles, deadline)? {
+                break;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// the file name of a rom path for the title
+fn file_name(path: &str) -> String {
+    Path::new(path).file_name().map_or_else(
+        || path.to_string(),
+        |name| name.to_string_lossy().into_owned(),
+    )
+}
+
+fn save_state<T: AudioPlay>(machine: &Machine<T>, path: &Path) {
+    match machine.save_state().save(path) {
+        Ok(()) => info!("state saved to {}", path.display()),
+        Err(e) => error!("can not save state to {}: {}", path.display(), e),
+    }
+}
+
+fn load_state<T: AudioPlay>(machine: &mut Machine<T>, path: &Path) {
+    match SaveState::load(path).and_then(|state| machine.load_state(&state)) {
+        Ok(()) => info!("state loaded from {}", path.display()),
+        Err(e) => error!("can not load state from {}: {}", path.display(), e),
+    }
+}
+
+#[cfg(test)]
+mod frontend_test {
+    use super::*;
+    use crate::audio::NullAudio;
+    use crate::rom::ROM;
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    #[derive(Default)]
+    struct Recorder {
+        presents: usize,
+        titles: Vec<String>,
+    }
+
+    impl<T: AudioPlay> DisplayBackend<T> for Recorder {
+        fn present(&mut self, _machine: &mut Machine<T>, _frame: &FrameInfo) -> Result<()> {
+            self.presents += 1;
+            Ok(())
+        }
+
+        fn set_title(&mut self, title: &str) -> Result<()> {
+            self.titles.push(title.to_string());
+            Ok(())
+        }
+    }
+
+    /// the events of one frame after another
+    struct Script(Vec<Vec<InputEvent>>);
+
+    impl InputBackend for Script {
+        fn poll(&mut self, events: &mut Vec<InputEvent>) -> Result<()> {
+            if !self.0.is_empty() {
+                events.extend(self.0.remove(0));
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_emulate() {
+        // V0 = the key pressed and released, then exit
+        let rom = ROM::from_bytes("key.ch8", vec![0xF0, 0x0A, 0x00, 0xFD]);
+        let mut machine: Machine<NullAudio> = Machine::new().unwrap();
+        machine.load_rom(&rom).unwrap();
+        let mut display = Recorder::default();
+        let mut input = Script(vec![
+            vec![InputEvent::TogglePause],
+            vec![InputEvent::KeyDown(5), InputEvent::TogglePause],
+            vec![InputEvent::KeyUp(5)],
+        ]);
+        let options = Options {
+            clock_freq: 600,
+            title: Some("key".to_string()),
+            ..Options::default()
+        };
+        Emulator::new(&mut machine, NullAudio, &mut display, &mut input, options)
+            .run()
+            .unwrap();
+        assert!(machine.is_halt());
+        assert_eq!(machine.registers()[0], 5);
+        assert_eq!(display.presents, 3);
+        assert_eq!(
+            display.titles,
+            [
+                "yet-another-rchip8 - key",
+                "yet-another-rchip8 - key (paused)",
+                "yet-another-rchip8 - key"
+            ]
+        );
+
+        let mut machine: Machine<NullAudio> = Machine::new().unwrap();
+        machine.load_rom(&rom).unwrap();
+        let mut input = Script(vec![vec![], vec![InputEvent::Quit]]);
+        let options = Options {
+            clock_freq: 600,
+            title: Some("key".to_string()),
+            ..Options::default()
+        };
+        Emulator::new(&mut machine, NullAudio, &mut display, &mut input, options)
+            .run()
+            .unwrap();
+        assert!(!machine.is_halt());
+    }
+
+    #[test]
+    fn test_status_title() {
+        let mut status = Status {
+            paused: false,
+            speed: Some(1.0),
+            muted: false,
+        };
+        assert_eq!(status_title("pong", &status), "pong");
+        status.speed = Some(1.2);
+        status.muted = true;
+        assert_eq!(status_title("pong", &status), "pong (1.2x, muted)");
+        status.paused = true;
+        status.speed = None;
+        assert_eq!(status_title("pong", &status), "pong (paused, turbo, muted)");
+        status.speed = Some(0.25);
+        status.muted = false;
+        assert_eq!(status_title("pong", &status), "pong (paused, 0.25x)");
+    }
+
+    #[test]
+    fn test_emulator_controls() {
+        // count up V0 in a loop
+        let rom = ROM::from_bytes("count.ch8", vec![0x70, 0x01, 0x12, 0x00]);
+        let frames = Rc::new(Cell::new(0));
+        let hooked = frames.clone();
+        let mut machine: Machine<NullAudio> = Machine::new().unwrap();
+        machine.load_rom(&rom).unwrap();
+        let options = Options {
+            clock_freq: 600,
+            title: Some("count".to_string()),
+            on_frame: Some(Box::new(move |_: &mut Machine<NullAudio>| {
+                hooked.set(hooked.get() + 1);
+                Ok(())
+            })),
+            ..Options::default()
+        };
+        let mut emulator = Emulator::new(
+            &mut machine,
+            NullAudio,
+            Recorder::default(),
+            Script(vec![]),
+            options,
+        );
+        assert!(emulator.frame(Instant::now()).unwrap());
+        assert_eq!(emulator.machine().registers()[0], 5);
+        emulator.set_speed(1200).unwrap();
+        assert_eq!(emulator.speed(), 1200);
+        emulator.set_muted(true).unwrap();
+        emulator.pause().unwrap();
+        assert!(emulator.is_paused());
+        assert_eq!(
+            emulator.status(),
+            Status {
+                paused: true,
+                speed: Some(2.0),
+                muted: true,
+            }
+        );
+        emulator.frame(Instant::now()).unwrap();
+        assert_eq!(emulator.machine().registers()[0], 5);
+        emulator.step_frame().unwrap();
+        assert_eq!(emulator.machine().registers()[0], 15);
+        assert!(emulator.is_paused());
+        emulator.resume().unwrap();
+        emulator.frame(Instant::now()).unwrap();
+        assert_eq!(emulator.machine().registers()[0], 25);
+        emulator.reset().unwrap();
+        assert_eq!(emulator.machine().registers()[0], 0);
+        // the hook only runs on frames that ran
+        assert_eq!(frames.get(), 3);
+    }
+}
@@ -0,0 +1,169 @@
+use std::fs;
+use std::path::Path;
+use std::time::Instant;
+
+use serde::Serialize;
+
+use crate::audio::AudioPlay;
+use crate::hooks::FrameHook;
+use crate::machine::Machine;
+use crate::trace::FileTracer;
+use crate::{Result, DEFAULT_CLOCK_FREQ, UNLIMITED};
+
+/// Register state written by `--dump-registers`
+#[derive(Serialize)]
+pub struct RegisterDump {
+    pub cycles: usize,
+    pub pc: u16,
+    pub i: u32,
+    pub registers: Vec<u8>,
+    pub stack: Vec<u16>,
+    pub delay_timer: u8,
+    pub sound_timer: u8,
+}
+
+impl RegisterDump {
+    pub fn new<T: AudioPlay>(machine: &Machine<T>, cycles: usize) -> Self {
+        RegisterDump {
+            cycles,
+            pc: machine.pc(),
+            i: machine.i(),
+            registers: machine.registers().to_vec(),
+            stack: machine.stack().to_vec(),
+            delay_timer: machine.delay_timer(),
+            sound_timer: machine.sound_timer(),
+        }
+    }
+}
+
+/// Run up to `cycles` instructions without a window, timers are ticked at
+/// 60Hz relative to `clock_freq`. Return the number of executed cycles.
+pub fn run<T: AudioPlay>(
+    machine: &mut Machine<T>,
+    cycles: usize,
+    clock_freq: u64,
+    tracer: Option<&mut FileTracer>,
+) -> Result<usize> {
+    run_with(machine, cycles, clock_freq, tracer, None)
+}
+
+/// `run` calling `on_frame` after every timer tick
+pub fn run_with<T: AudioPlay>(
+    machine: &mut Machine<T>,
+    cycles: usize,
+    clock_freq: u64,
+    mut tracer: Option<&mut FileTracer>,
+    mut on_frame: Option<FrameHook<T>>,
+) -> Result<usize> {
+    let clock_freq = if clock_freq == UNLIMITED {
+        DEFAULT_CLOCK_FREQ
+    } else {
+        clock_freq
+    };
+    let mut executed = 0;
+    let mut timer_ticks = 0;
+    while executed < cycles && !machine.is_halt() {
+        match tracer.as_deref_mut() {
+            Some(tracer) => tracer.run_cycle(machine)?,
+            None => machine.run_cycle()?,
+        }
+        executed += 1;
+        let ticks = executed as u64 * 60 / clock_freq;
+        if ticks > timer_ticks {
+            timer_ticks = ticks;
+            machine.tick_60hz();
+            if let Some(on_frame) = on_frame.as_mut() {
+                on_frame(machine)?;
+            }
+        }
+    }
+    if let Some(tracer) = tracer {
+        tracer.flush()?;
+    }
+    Ok(executed)
+}
+
+/// One 60Hz frame for frontends driven by a frame loop: clock_freq / 60
+/// instructions, or as many as fit before `deadline` when unlimited,
+/// then the timers are updated. True when the display needs a redraw.
+pub fn run_frame<T: AudioPlay>(
+    machine: &mut Machine<T>,
+    clock_freq: u64,
+    deadline: Instant,
+) -> Result<bool> {
+    if clock_freq == UNLIMITED {
+        while Instant::now() < deadline && !machine.is_halt() {
+            machine.run_cycle()?;
+        }
+    } else {
+        run_cycles(machine, frame_cycles(clock_freq))?;
+    }
+    Ok(machine.tick_60hz())
+}
+
+/// instructions in a 60Hz frame, unlimited counts as the default speed
+pub fn frame_cycles(clock_freq: u64) -> usize {
+    match clock_freq {
+        UNLIMITED => frame_cycles(DEFAULT_CLOCK_FREQ),
+        _ => (clock_freq / 60).max(1) as usize,
+    }
+}
+
+/// Advance a paused machine by one 60Hz frame: the instructions of a frame
+/// at `clock_freq` and then exactly one timer tick
+pub fn step_frame<T: AudioPlay>(machine: &mut Machine<T>, clock_freq: u64) -> Result<()> {
+    run_cycles(machine, frame_cycles(clock_freq))?;
+    machine.tick_frame();
+    Ok(())
+}
+
+fn run_cycles<T: AudioPlay>(machine: &mut Machine<T>, cycles: usize) -> Result<()> {
+    for _ in 0..cycles {
+        if machine.is_halt() {
+            break;
+        }
+        machine.run_cycle()?;
+    }
+    Ok(())
+}
+
+/// one line per row, '#' for a lit pixel and '.' for a dark one
+pub fn display_to_text<T: AudioPlay>(machine: &Machine<T>) -> String {
+    let mut text = String::with_capacity((machine.width() + 1) * machine.height());
+    for row in machine.get_display().rows() {
+        text.extend(row.map(|on| if on { '#' } else { '.' }));
+        text.push('\n');
+    }
+    text
+}
+
+/// plain PBM (P1) image of the display
+pub fn display_to_pbm<T: AudioPlay>(machine: &Machine<T>) -> String {
+    let mut pbm = format!("P1\n{} {}\n", machine.width(), machine.height());
+    for row in machine.get_display().rows() {
+        let row: Vec<&str> = row.map(|on| if on { "1" } else { "0" }).collect();
+        pbm.push_str(&row.join(" "));
+        pbm.push('\n');
+    }
+    pbm
+}
+
+/// write the display to `path`, as PBM when the extension is .pbm and as text otherwise
+pub fn dump_display<T: AudioPlay>(machine: &Machine<T>, path: &Path) -> Result<()> {
+    let content = match path.extension().and_then(|ext| ext.to_str()) {
+        Some("pbm") => display_to_pbm(machine),
+        _ => display_to_text(machine),
+    };
+    fs::write(path, content)?;
+    Ok(())
+}
+
+pub fn dump_registers<T: AudioPlay>(
+    machine: &Machine<T>,
+    cycles: usize,
+    path: &Path,
+) -> Result<()> {
+    let dump = RegisterDump::new(machine, cycles);
+    fs::write(path, serde_json::to_string_pretty(&dump)?)?;
+    Ok(())
+}
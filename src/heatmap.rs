@@ -0,0 +1,119 @@
+use std::ops::Range;
+
+use crate::display::Color;
+use crate::machine::MEMORY_SIZE;
+
+/// heat an instruction writing an address adds to it
+const WRITE_HEAT: u8 = 64;
+
+/// How often and how recently the instructions wrote every address, to spot
+/// the variables of a rom and code that is written over
+#[derive(Debug, Clone)]
+pub struct Heatmap {
+    heat: Vec<u8>,
+    executed: Vec<bool>,
+}
+
+impl Default for Heatmap {
+    fn default() -> Self {
+        Heatmap {
+            heat: vec![0; MEMORY_SIZE],
+            executed: vec![false; MEMORY_SIZE],
+        }
+    }
+}
+
+impl Heatmap {
+    /// addresses covered, at least the 4KB of CHIP-8 memory
+    pub fn len(&self) -> usize {
+        self.heat.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.heat.is_empty()
+    }
+
+    fn grow(&mut self, len: usize) {
+        if len > self.heat.len() {
+            self.heat.resize(len, 0);
+            self.executed.resize(len, false);
+        }
+    }
+
+    /// an instruction wrote `range`
+    pub fn write(&mut self, range: Range<usize>) {
+        self.grow(range.end);
+        for heat in &mut self.heat[range] {
+            *heat = heat.saturating_add(WRITE_HEAT);
+        }
+    }
+
+    /// the instruction at `pc` was executed
+    pub fn execute(&mut self, pc: u16) {
+        let pc = pc as usize;
+        self.grow(pc + 2);
+        self.executed[pc..pc + 2].fill(true);
+    }
+
+    /// fade the heat, once every 60Hz frame, so an address written once
+    /// cools down in about a second
+    pub fn cool(&mut self) {
+        for heat in &mut self.heat {
+            *heat = heat.saturating_sub(1 + *heat / 32);
+        }
+    }
+
+    pub fn heat(&self, addr: usize) -> u8 {
+        self.heat.get(addr).copied().unwrap_or(0)
+    }
+
+    /// Black for memory left alone, dark blue for code that ran, red to
+    /// yellow for recent writes and magenta for writes to code that ran
+    pub fn color(&self, addr: usize) -> Color {
+        let heat = self.heat(addr) as u16;
+        let glow = (96 + heat * 159 / 255) as u8;
+        match (heat, self.executed.get(addr).copied().unwrap_or(false)) {
+            (0, false) => Color::BLACK,
+            (0, true) => Color::rgb(0, 0, 96),
+            (heat, false) => Color::rgb(glow, heat as u8, 0),
+            (_, true) => Color::rgb(glow, 0, glow),
+        }
+    }
+}
+
+#[cfg(test)]
+mod heatmap_test {
+    use super::*;
+    use crate::audio::NullAudio;
+    use crate::machine::Machine;
+    use crate::rom::ROM;
+
+    #[test]
+    fn test_heatmap() {
+        // store V0 at 0x300 and over the jump at 0x204, then loop
+        let rom = ROM::from_bytes(
+            "store.ch8",
+            vec![0xA3, 0x00, 0xF0, 0x55, 0xA2, 0x04, 0xF0, 0x55, 0x12, 0x08],
+        );
+        let mut machine: Machine<NullAudio> = Machine::new().unwrap();
+        machine.load_rom(&rom).unwrap();
+        machine.track_writes();
+        for _ in 0..5 {
+            machine.run_cycle().unwrap();
+        }
+        let heatmap = machine.heatmap().unwrap();
+        assert_eq!(heatmap.heat(0x300), WRITE_HEAT);
+        assert_eq!(heatmap.color(0x301), Color::BLACK);
+        assert_eq!(heatmap.color(0x300), Color::rgb(135, 64, 0));
+        assert_eq!(heatmap.color(0x204), Color::rgb(135, 0, 135));
+        assert_eq!(heatmap.color(0x202), Color::rgb(0, 0, 96));
+
+        let mut heatmap = heatmap.clone();
+        for _ in 0..60 {
+            heatmap.cool();
+        }
+        assert_eq!(heatmap.heat(0x300), 0);
+        heatmap.write(0x1FFF..0x2001);
+        assert_eq!(heatmap.len(), 0x2001);
+    }
+}
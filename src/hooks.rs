@@ -0,0 +1,58 @@
+use crate::machine::Machine;
+use crate::video::Video;
+use crate::Result;
+
+pub type InstructionHook = Box<dyn FnMut(u16, u16)>;
+pub type DrawHook = Box<dyn FnMut(&Video)>;
+pub type SoundHook = Box<dyn FnMut(bool)>;
+/// Called by the frontends after every 60Hz frame with the whole machine,
+/// for `--script`
+pub type FrameHook<T> = Box<dyn FnMut(&mut Machine<T>) -> Result<()>>;
+
+/// Callbacks registered on a machine with `on_instruction`, `on_draw` and
+/// `on_sound`, for tools like profilers and visualizers
+#[derive(Default)]
+pub(crate) struct Hooks {
+    pub instruction: Vec<InstructionHook>,
+    pub draw: Vec<DrawHook>,
+    pub sound: Vec<SoundHook>,
+    // the last state given to the sound hooks, they only get changes
+    beeping: bool,
+}
+
+impl Hooks {
+    pub fn is_empty(&self) -> bool {
+        self.instruction.is_empty() && self.draw.is_empty() && self.sound.is_empty()
+    }
+
+    pub fn instruction(&mut self, pc: u16, opcode: u16) {
+        for hook in self.instruction.iter_mut() {
+            hook(pc, opcode);
+        }
+    }
+
+    pub fn draw(&mut self, video: &Video) {
+        for hook in self.draw.iter_mut() {
+            hook(video);
+        }
+    }
+
+    pub fn sound(&mut self, beeping: bool) {
+        if beeping != self.beeping {
+            self.beeping = beeping;
+            for hook in self.sound.iter_mut() {
+                hook(beeping);
+            }
+        }
+    }
+}
+
+/// instructions that change the display: 00CN, 00E0, 00FB-00FF, DXYN and
+/// the 0230 clear of HiRes CHIP-8
+pub(crate) fn draws(opcode: u16) -> bool {
+    opcode & 0xF000 == 0xD000
+        || opcode & 0xFFF0 == 0x00C0
+        || opcode == 0x00E0
+        || opcode == 0x0230
+        || (0x00FB..=0x00FF).contains(&opcode) && opcode != 0x00FD
+}
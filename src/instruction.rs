@@ -1,5 +1,8 @@
 use std::fmt;
 
+use crate::opcodes::{self, Operand};
+use crate::symbols::Symbols;
+
 pub struct Instruction {
     pub opcode: u16,
 }
@@ -45,6 +48,46 @@ impl Instruction {
             self.nnn(),
         )
     }
+
+    /// mnemonic form like "LD V3, 0x2A", unknown opcodes are shown as data
+    pub fn disassemble(&self) -> String {
+        self.disassemble_with(&Symbols::default())
+    }
+
+    /// `disassemble` with the label names of `symbols` for addresses, like
+    /// "CALL draw"
+    pub fn disassemble_with(&self, symbols: &Symbols) -> String {
+        let spec = match opcodes::lookup(self.opcode) {
+            Some(spec) => spec,
+            None => return format!("DW {:#06X}", self.opcode),
+        };
+        let operands: Vec<String> = spec
+            .operands
+            .iter()
+            .map(|operand| match operand {
+                Operand::Vx => format!("V{:X}", self.x()),
+                Operand::Vy => format!("V{:X}", self.y()),
+                Operand::Byte => format!("{:#04X}", self.nn()),
+                Operand::Addr => match symbols.name(self.nnn()) {
+                    Some(name) => name.to_string(),
+                    None => format!("{:#05X}", self.nnn()),
+                },
+                Operand::Nibble => self.n().to_string(),
+                Operand::Literal(token) => token.to_string(),
+            })
+            .collect();
+        if operands.is_empty() {
+            spec.mnemonic.to_string()
+        } else {
+            format!("{} {}", spec.mnemonic, operands.join(", "))
+        }
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.disassemble())
+    }
 }
 
 impl fmt::Debug for Instruction {
@@ -0,0 +1,91 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::Path;
+
+use crate::{err, Result};
+
+/// Load a keymap file mapping every CHIP-8 key (0-F) to the name of a
+/// physical key, as TOML or as JSON when the extension is .json:
+///
+/// ```toml
+/// 1 = "1"
+/// C = "4"
+/// A = "Z"
+/// ```
+///
+/// The names are resolved by the frontend, e.g. SDL2 scancode names.
+pub fn load<P: AsRef<Path>>(path: P) -> Result<BTreeMap<u8, String>> {
+    let path = path.as_ref();
+    read(path)
+        .and_then(|raw| parse(raw, true))
+        .map_err(|e| format!("keymap {}: {}", path.display(), e).into())
+}
+
+/// Load a keymap file in the same format as `load`, keys may be left
+/// unmapped, e.g. for a gamepad with less than 16 buttons.
+pub fn load_partial<P: AsRef<Path>>(path: P) -> Result<BTreeMap<u8, String>> {
+    let path = path.as_ref();
+    read(path)
+        .and_then(|raw| parse(raw, false))
+        .map_err(|e| format!("keymap {}: {}", path.display(), e).into())
+}
+
+/// Check a keymap given as a table, e.g. in the config file, every key
+/// has to be mapped like with `load`.
+pub fn from_table(raw: HashMap<String, String>) -> Result<BTreeMap<u8, String>> {
+    parse(raw, true).map_err(|e| format!("keymap: {}", e).into())
+}
+
+fn read(path: &Path) -> Result<HashMap<String, String>> {
+    let content = fs::read_to_string(path)?;
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("json") => Ok(serde_json::from_str(&content)?),
+        _ => Ok(toml::from_str(&content)?),
+    }
+}
+
+fn parse(raw: HashMap<String, String>, complete: bool) -> Result<BTreeMap<u8, String>> {
+    let mut layout = BTreeMap::new();
+    for (key, name) in raw {
+        let chip8_key = match u8::from_str_radix(&key, 16) {
+            Ok(k) if k < 16 && key.len() == 1 => k,
+            _ => return err!("unknown CHIP-8 key: {:?}, expect a hex digit 0-F", key),
+        };
+        layout.insert(chip8_key, name);
+    }
+    let missing: Vec<String> = (0..16u8)
+        .filter(|k| !layout.contains_key(k))
+        .map(|k| format!("{:X}", k))
+        .collect();
+    if complete && !missing.is_empty() {
+        return err!("CHIP-8 keys {} are not mapped", missing.join(", "));
+    }
+    Ok(layout)
+}
+
+#[cfg(test)]
+mod keymap_test {
+    use super::*;
+
+    #[test]
+    fn test_parse_keymap() {
+        let raw: HashMap<String, String> = toml::from_str(
+            &fs::read_to_string(concat!(env!("CARGO_MANIFEST_DIR"), "/keymaps/numpad.toml"))
+                .unwrap(),
+        )
+        .unwrap();
+        let layout = parse(raw.clone(), true).unwrap();
+        assert_eq!(layout[&0xB], "Keypad Enter");
+
+        let mut missing = raw.clone();
+        missing.remove("F");
+        missing.remove("A");
+        assert_eq!(parse(missing.clone(), false).unwrap().len(), 14);
+        let e = parse(missing, true).unwrap_err().to_string();
+        assert_eq!(e, "CHIP-8 keys A, F are not mapped");
+
+        let mut unknown = raw;
+        unknown.insert("10".to_string(), "Q".to_string());
+        assert!(parse(unknown, false).is_err());
+    }
+}
@@ -0,0 +1,107 @@
+/// The COSMAC VIP hex keypad, row by row
+pub const LAYOUT: [[u8; 4]; 4] = [
+    [0x1, 0x2, 0x3, 0xC],
+    [0x4, 0x5, 0x6, 0xD],
+    [0x7, 0x8, 0x9, 0xE],
+    [0xA, 0x0, 0xB, 0xF],
+];
+
+/// (column, row) of `key` in `LAYOUT`
+pub fn position(key: u8) -> (usize, usize) {
+    LAYOUT
+        .iter()
+        .enumerate()
+        .find_map(|(row, keys)| {
+            keys.iter()
+                .position(|&k| k == key)
+                .map(|column| (column, row))
+        })
+        .unwrap_or((0, 0))
+}
+
+/// Key of an on-screen keypad `width` x `height` big under the point
+/// (x, y) relative to its top left corner, for mouse and touch input
+pub fn key_at(x: f32, y: f32, width: f32, height: f32) -> Option<u8> {
+    if x < 0.0 || y < 0.0 || x >= width || y >= height {
+        return None;
+    }
+    let column = (x * 4.0 / width) as usize;
+    let row = (y * 4.0 / height) as usize;
+    Some(LAYOUT[row.min(3)][column.min(3)])
+}
+
+/// Which keys a rom tests, counted in frames, for the key hint overlay
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct KeyUsage {
+    frames: u32,
+    // frames key k was tested in with EX9E/EXA1
+    tested: [u32; 16],
+    // frames the rom waited for any key with FX0A
+    waited: u32,
+}
+
+impl KeyUsage {
+    /// add a frame with the keys of `Machine::take_checked_keys` and
+    /// whether `Machine::take_key_wait` saw an FX0A
+    pub fn add_frame(&mut self, checked: u16, waited: bool) {
+        self.frames += 1;
+        for (key, tested) in self.tested.iter_mut().enumerate() {
+            *tested += (checked >> key & 1) as u32;
+        }
+        self.waited += waited as u32;
+    }
+
+    /// the tested keys, the most tested first
+    pub fn used_keys(&self) -> Vec<u8> {
+        let mut keys: Vec<u8> = (0..16)
+            .filter(|&key| self.tested[key as usize] > 0)
+            .collect();
+        keys.sort_by_key(|&key| std::cmp::Reverse(self.tested[key as usize]));
+        keys
+    }
+
+    /// share of the frames `key` was tested in, between 0.0 and 1.0
+    pub fn heat(&self, key: u8) -> f32 {
+        if self.frames == 0 {
+            return 0.0;
+        }
+        self.tested[key as usize & 0xF] as f32 / self.frames as f32
+    }
+
+    /// the rom waited for a key press with FX0A, any key does
+    pub fn waits_for_any_key(&self) -> bool {
+        self.waited > 0
+    }
+}
+
+#[cfg(test)]
+mod keypad_test {
+    use super::*;
+
+    #[test]
+    fn test_key_at() {
+        assert_eq!(key_at(0.0, 0.0, 200.0, 200.0), Some(0x1));
+        assert_eq!(key_at(199.0, 199.0, 200.0, 200.0), Some(0xF));
+        assert_eq!(key_at(60.0, 160.0, 200.0, 200.0), Some(0x0));
+        assert_eq!(key_at(200.0, 10.0, 200.0, 200.0), None);
+        assert_eq!(key_at(-1.0, 10.0, 200.0, 200.0), None);
+        for key in 0..16 {
+            let (column, row) = position(key);
+            assert_eq!(LAYOUT[row][column], key);
+        }
+    }
+
+    #[test]
+    fn test_key_usage() {
+        let mut usage = KeyUsage::default();
+        assert_eq!(usage.heat(5), 0.0);
+        usage.add_frame(1 << 5 | 1 << 0xC, false);
+        usage.add_frame(1 << 0xC, false);
+        usage.add_frame(0, false);
+        usage.add_frame(1 << 0xC, true);
+        assert_eq!(usage.used_keys(), vec![0xC, 5]);
+        assert_eq!(usage.heat(0xC), 0.75);
+        assert_eq!(usage.heat(5), 0.25);
+        assert!(usage.waits_for_any_key());
+    }
+}
@@ -0,0 +1,98 @@
+pub mod assembler;
+pub mod audio;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod bench;
+pub mod capture;
+pub mod cheats;
+pub mod clock;
+pub mod compare;
+pub mod config;
+pub mod coverage;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod crashdump;
+pub mod debugger;
+pub mod detect;
+pub mod disassembler;
+pub mod display;
+pub mod error;
+pub mod font;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod frontend;
+pub mod headless;
+pub mod heatmap;
+pub mod hooks;
+pub mod instruction;
+pub mod keyboard;
+pub mod keymap;
+pub mod keypad;
+pub mod logging;
+pub mod machine;
+pub mod megachip;
+pub mod menu;
+#[cfg(feature = "bench")]
+pub mod microbench;
+pub mod movie;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod netplay;
+pub mod octo;
+pub mod opcodes;
+#[cfg(feature = "pixels-frontend")]
+pub mod pixels_frontend;
+pub mod profiler;
+pub mod quirks;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod remote;
+pub mod rewind;
+pub mod rom;
+pub mod romdb;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod rpl;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod scores;
+#[cfg(feature = "scripting")]
+pub mod script;
+#[cfg(feature = "sdl2-frontend")]
+pub mod sdl2_frontend;
+#[cfg(feature = "settings-ui")]
+pub mod settings_ui;
+pub mod smc;
+pub mod state;
+pub mod symbols;
+pub mod testsuite;
+pub mod text;
+pub mod timing;
+pub mod trace;
+#[cfg(feature = "tui-frontend")]
+pub mod tui_frontend;
+pub mod video;
+#[cfg(feature = "wasm")]
+pub mod wasm_frontend;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod watch;
+
+#[macro_use]
+extern crate log;
+
+use std::error::Error;
+use std::result;
+
+pub use audio::AudioPlay;
+pub use error::MachineError;
+pub use instruction::Instruction;
+pub use keyboard::KeyBoard;
+pub use machine::Machine;
+pub use quirks::Quirks;
+pub use rom::ROM;
+pub use video::Video;
+
+#[macro_export]
+macro_rules! err {
+    ($($tt:tt)*) => { Err(Box::<dyn std::error::Error>::from(format!($($tt)*))) };
+}
+
+pub type Result<T> = result::Result<T, Box<dyn Error>>;
+
+/// instructions per second when no speed is given
+pub const DEFAULT_CLOCK_FREQ: u64 = 500;
+/// clock speed that runs the machine as fast as possible
+pub const UNLIMITED: u64 = 0;
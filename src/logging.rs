@@ -0,0 +1,150 @@
+use std::collections::VecDeque;
+use std::io::Write;
+use std::sync::Mutex;
+
+use env_logger::filter::{self, Filter};
+use log::{Log, Metadata, Record};
+
+use crate::Result;
+
+// Log targets of the subsystems, filtered like modules with `RUST_LOG` or
+// `--log`, e.g. `--log input=debug,cpu=off`
+
+/// fetch, decode and execute of instructions, the clock and timers
+pub const CPU: &str = "cpu";
+/// drawing, display modes, screenshots and frame pacing
+pub const VIDEO: &str = "video";
+/// the beep and the audio devices
+pub const AUDIO: &str = "audio";
+/// keys, controllers and movies
+pub const INPUT: &str = "input";
+
+/// records kept for the debug overlay
+pub const RING_LEN: usize = 256;
+
+static RING: Mutex<VecDeque<String>> = Mutex::new(VecDeque::new());
+
+/// Prints the log like env_logger and keeps the recent records in a ring
+/// buffer, so an event can be found while the rom runs instead of in the
+/// scrollback
+struct Logger {
+    console: env_logger::Logger,
+    ring: Filter,
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.console.enabled(metadata) || self.ring.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        if self.console.matches(record) {
+            self.console.log(record);
+        }
+        if self.ring.matches(record) {
+            push(ring_line(record));
+        }
+    }
+
+    fn flush(&self) {
+        self.console.flush();
+    }
+}
+
+/// Install the logger. `filters` in the format of `RUST_LOG` come on top of
+/// it for what is printed, the ring buffer keeps info and above and what
+/// `filters` turn on.
+pub fn init(filters: Option<&str>) -> Result<()> {
+    let mut console = env_logger::Builder::from_default_env();
+    // like the default format with the target instead of the module, so
+    // the subsystem is shown
+    console.format(|buf, record| {
+        writeln!(
+            buf,
+            "[{} {:<5} {}] {}",
+            buf.timestamp(),
+            buf.default_styled_level(record.level()),
+            record.target(),
+            record.args()
+        )
+    });
+    let mut ring = filter::Builder::new();
+    ring.filter_level(log::LevelFilter::Info);
+    if let Some(filters) = filters {
+        console.parse_filters(filters);
+        ring.parse(filters);
+    }
+    let logger = Logger {
+        console: console.build(),
+        ring: ring.build(),
+    };
+    log::set_max_level(logger.console.filter().max(logger.ring.filter()));
+    log::set_boxed_logger(Box::new(logger))?;
+    Ok(())
+}
+
+/// `I input: key 5 down`, the level letter, the subsystem or the module
+/// and the message
+fn ring_line(record: &Record) -> String {
+    let target = record.target();
+    let target = target
+        .strip_prefix("yet_another_rchip8::")
+        .unwrap_or(target);
+    format!(
+        "{} {}: {}",
+        &record.level().as_str()[..1],
+        target,
+        record.args()
+    )
+}
+
+fn push(line: String) {
+    let mut ring = RING.lock().unwrap_or_else(|e| e.into_inner());
+    if ring.len() == RING_LEN {
+        ring.pop_front();
+    }
+    ring.push_back(line);
+}
+
+/// the last `n` records in the ring buffer, oldest first
+pub fn recent(n: usize) -> Vec<String> {
+    let ring = RING.lock().unwrap_or_else(|e| e.into_inner());
+    ring.iter()
+        .skip(ring.len().saturating_sub(n))
+        .cloned()
+        .collect()
+}
+
+#[cfg(test)]
+mod logging_test {
+    use super::*;
+
+    #[test]
+    fn test_ring() {
+        let mut ring = filter::Builder::new();
+        ring.filter_level(log::LevelFilter::Info)
+            .parse("input=debug,video=off");
+        let logger = Logger {
+            console: env_logger::Builder::new()
+                .filter_level(log::LevelFilter::Off)
+                .build(),
+            ring: ring.build(),
+        };
+        for (target, level) in [
+            (INPUT, log::Level::Debug),
+            (CPU, log::Level::Debug),
+            (VIDEO, log::Level::Warn),
+            ("yet_another_rchip8::rom", log::Level::Info),
+        ] {
+            logger.log(
+                &Record::builder()
+                    .target(target)
+                    .level(level)
+                    .args(format_args!("key 5 down"))
+                    .build(),
+            );
+        }
+        assert_eq!(recent(2), ["D input: key 5 down", "I rom: key 5 down"]);
+        assert!(recent(RING_LEN).len() <= RING_LEN);
+    }
+}
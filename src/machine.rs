@@ -1,39 +1,129 @@
-use std::error::Error;
+use std::ops::Range;
+use std::result;
+use std::time::Instant;
 
-use rand::Rng;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
 
-use crate::audio::AudioPlay;
-use crate::font::DEFAULTFONT;
+use crate::audio::{AudioPlay, DEFAULT_PITCH, PATTERN_BYTES};
+use crate::cheats::Cheats;
+use crate::coverage::Coverage;
+use crate::detect;
+use crate::error::MachineError;
+use crate::font::{Font, BIG_FONT_SIZE, SMALL_FONT_SIZE};
+use crate::heatmap::Heatmap;
+use crate::hooks::{self, Hooks};
 use crate::instruction::Instruction;
 use crate::keyboard::KeyBoard;
+use crate::logging::{AUDIO, CPU, INPUT, VIDEO};
+use crate::megachip::{Blend, MegaChip};
+use crate::movie::{Input, Movie, MovieEvent, MovieMode};
+use crate::profiler::Profile;
+use crate::quirks::Quirks;
 use crate::rom::ROM;
-use crate::video::Video;
+use crate::smc::{SelfModify, SmcTracker};
+use crate::state::SaveState;
+use crate::timing::TimerClock;
+use crate::trace::{History, TraceEntry};
+use crate::video::{Collisions, Video};
 use crate::{err, Result};
 
-const MEMORY_SIZE: usize = 4096;
+pub(crate) const MEMORY_SIZE: usize = 4096;
+/// MegaChip roms can be bigger, up to what the 24 bit I of 01NN NNNN reaches
+pub(crate) const MEGA_MEMORY_SIZE: usize = 1 << 24;
 const RESERVED_MEMORY_SIZE: usize = 512;
-const REGISTER_COUNT: usize = 16;
-const STACK_SIZE: usize = 16;
+/// where the hex digit sprites for FX29 are loaded
+pub(crate) const FONT_ADDR: usize = 0x50;
+/// where the SCHIP big digit sprites are loaded, right after the small ones
+pub(crate) const BIG_FONT_ADDR: usize = FONT_ADDR + SMALL_FONT_SIZE;
+pub(crate) const REGISTER_COUNT: usize = 16;
+pub(crate) const STACK_SIZE: usize = 16;
+/// the COSMAC VIP interpreter kept 12 return addresses
+const SHORT_STACK_SIZE: usize = 12;
+pub const RPL_FLAG_COUNT: usize = 8;
+/// HiRes CHIP-8 roms start with a jump here, the two-page interpreter
+/// starts them at `HIRES_START` with a 64x64 display instead
+const HIRES_ENTRY: u16 = 0x260;
+const HIRES_START: u16 = 0x2C0;
+
+/// an opcode and its fields, see `Instruction::decode`
+type Decoded = (u16, (u8, usize, usize, u8, u8, u16));
 
 pub struct Machine<T: AudioPlay> {
-    memory: [u8; MEMORY_SIZE],
+    // 4KB, more for bigger MegaChip roms, see `resize_memory`
+    memory: Vec<u8>,
     registers: [u8; REGISTER_COUNT],
     pc: u16,
-    // index register
-    i: u16,
+    // index register, 24 bits in MegaChip mode
+    i: u32,
     stack: [u16; STACK_SIZE],
+    // calls on the stack, the next return address goes to this slot
     stack_pointer: usize,
     delay_timer: u8,
     sound_timer: u8,
     keyboard: KeyBoard,
+    // keys EX9E/EXA1 tested since the last `take_checked_keys`, bit k for key k
+    checked_keys: u16,
+    // FX0A waited for a key since the last `take_key_wait`
+    key_wait: bool,
     video: Video,
+    // MegaChip drawing state, only in MegaChip mode
+    mega: Option<MegaChip>,
+    // the display as of the last frame, shown instead of `video` while it is
+    // drawn, see `latch_display`
+    latched: Option<Video>,
+    // pixels erased by sprites since the last `take_collisions`
+    collisions: Collisions,
     audio: Option<T>,
+    // the audio was resumed and not paused since, so it is only switched on
+    // the edges of the sound timer
+    sound_on: bool,
+    // SCHIP HP48 RPL user flags
+    rpl_flags: [u8; RPL_FLAG_COUNT],
+    // XO-CHIP audio pattern loaded by F002, the beep is played until then
+    audio_pattern: Option<[u8; PATTERN_BYTES]>,
+    // XO-CHIP FX3A pitch of the audio pattern
+    pitch: u8,
+    halted: bool,
+    quirks: Quirks,
+    // FX0A key that was pressed and is waited to be released
+    waiting_key: Option<u8>,
+    // a timer tick happened since the last DXYN, for the display wait quirk
+    vblank: bool,
+    // the loaded rom, kept for reset
+    rom: Vec<u8>,
+    font: Font,
+    // CXNN random numbers, seeded so runs can be reproduced
+    seed: u64,
+    rng: ChaCha8Rng,
+    // instructions executed since reset, the clock of movies
+    cycles: u64,
+    // timer ticks since the machine was created, for the statistics overlay
+    timer_ticks: u64,
+    movie: Option<MovieMode>,
+    // ticks the timers by the wall clock when set, see `use_timer_clock`
+    timer_clock: Option<TimerClock>,
+    hooks: Hooks,
+    profile: Option<Profile>,
+    // the last executed instructions, see `keep_history`
+    history: Option<History>,
+    coverage: Option<Coverage>,
+    heatmap: Option<Heatmap>,
+    // decoded instructions by address, see `enable_decode_cache`
+    decode_cache: Option<Vec<Option<Decoded>>>,
+    // see `track_self_modifying`
+    smc: Option<SmcTracker>,
+    // what the last instruction overwrote of the executed code
+    self_modified: Option<SelfModify>,
+    cheats: Cheats,
 }
 
 impl<T: AudioPlay> Machine<T> {
+    /// a machine with a random seed, see `set_seed`
     pub fn new() -> Result<Self> {
+        let seed = rand::random();
         Ok(Machine {
-            memory: [0; MEMORY_SIZE],
+            memory: vec![0; MEMORY_SIZE],
             registers: [0; REGISTER_COUNT],
             pc: 0x200,
             i: 0x0,
@@ -42,53 +132,484 @@ impl<T: AudioPlay> Machine<T> {
             delay_timer: 0,
             sound_timer: 0,
             keyboard: KeyBoard::default(),
+            checked_keys: 0,
+            key_wait: false,
             video: Video::new(64, 32),
+            mega: None,
+            latched: None,
+            collisions: Collisions::default(),
             audio: None,
+            sound_on: false,
+            rpl_flags: [0; RPL_FLAG_COUNT],
+            audio_pattern: None,
+            pitch: DEFAULT_PITCH,
+            halted: false,
+            quirks: Quirks::default(),
+            waiting_key: None,
+            vblank: false,
+            rom: vec![],
+            font: Font::default(),
+            seed,
+            rng: ChaCha8Rng::seed_from_u64(seed),
+            cycles: 0,
+            timer_ticks: 0,
+            movie: None,
+            timer_clock: None,
+            hooks: Hooks::default(),
+            profile: None,
+            history: None,
+            coverage: None,
+            heatmap: None,
+            decode_cache: None,
+            smc: None,
+            cheats: Cheats::default(),
+            self_modified: None,
         })
     }
 
+    /// restart the CXNN random numbers from `seed`
+    pub fn set_seed(&mut self, seed: u64) {
+        self.seed = seed;
+        self.rng = ChaCha8Rng::seed_from_u64(seed);
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// SCHIP FX75/FX85 user flags, see `rpl::FlagFile` to keep them on disk
+    pub fn rpl_flags(&self) -> [u8; RPL_FLAG_COUNT] {
+        self.rpl_flags
+    }
+
+    pub fn set_rpl_flags(&mut self, flags: [u8; RPL_FLAG_COUNT]) {
+        self.rpl_flags = flags;
+    }
+
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.quirks = quirks;
+    }
+
+    pub fn quirks(&self) -> Quirks {
+        self.quirks
+    }
+
     pub fn is_halt(&mut self) -> bool {
-        (self.pc as usize) >= MEMORY_SIZE
+        self.halted || (self.pc as usize) >= self.memory.len()
+    }
+
+    /// the font `load_font` and reset load, call `load_font` after it
+    pub fn set_font(&mut self, font: Font) {
+        self.font = font;
     }
 
     pub fn load_font(&mut self) -> Result<()> {
-        // TODO: load from file
-        self.memory[0x50..0x50 + DEFAULTFONT.len()].copy_from_slice(&DEFAULTFONT[..]);
+        self.memory[FONT_ADDR..FONT_ADDR + SMALL_FONT_SIZE].copy_from_slice(&self.font.small);
+        if let Some(big) = &self.font.big {
+            self.memory[BIG_FONT_ADDR..BIG_FONT_ADDR + BIG_FONT_SIZE].copy_from_slice(big);
+        }
+        self.invalidate(FONT_ADDR..BIG_FONT_ADDR + BIG_FONT_SIZE);
         Ok(())
     }
 
     pub fn load_rom(&mut self, rom: &ROM) -> Result<()> {
-        if rom.len() > MEMORY_SIZE - RESERVED_MEMORY_SIZE {
+        if rom.len() > MEGA_MEMORY_SIZE - RESERVED_MEMORY_SIZE {
             return err!(
                 "can not load rom({} Bytes) that big than the machine memory({} Bytes)",
                 rom.len(),
-                self.memory.len()
+                MEGA_MEMORY_SIZE
             );
         }
-        let start = self.pc as usize;
-        let end = start + rom.len();
-        self.memory[start..end].clone_from_slice(&rom.raw()[..]);
+        self.resize_memory(RESERVED_MEMORY_SIZE + rom.len());
+        self.rom = rom.raw();
+        if let Some(coverage) = self.coverage.as_mut() {
+            coverage.clear();
+        }
+        let start = RESERVED_MEMORY_SIZE;
+        let end = start + self.rom.len();
+        self.memory[start..end].clone_from_slice(&self.rom[..]);
+        self.invalidate(start..end);
+        self.apply_patches();
+        Ok(())
+    }
+
+    /// Memory for `size` bytes, 4KB like the COSMAC VIP unless a MegaChip
+    /// rom needs more, then the next power of two
+    fn resize_memory(&mut self, size: usize) {
+        let size = size.next_power_of_two().max(MEMORY_SIZE);
+        if size != self.memory.len() {
+            self.memory.resize(size, 0);
+            if let Some(cache) = self.decode_cache.as_mut() {
+                cache.resize(size, None);
+            }
+        }
+    }
+
+    /// use `cheats` from now on, their patches are applied right away and
+    /// again whenever the rom is loaded or the machine reset
+    pub fn set_cheats(&mut self, cheats: Cheats) {
+        self.cheats = cheats;
+        self.apply_patches();
+    }
+
+    fn apply_patches(&mut self) {
+        let cheats = std::mem::take(&mut self.cheats);
+        for (addr, bytes) in cheats.patches() {
+            self.memory[addr..addr + bytes.len()].copy_from_slice(bytes);
+            self.invalidate(addr..addr + bytes.len());
+        }
+        self.cheats = cheats;
+    }
+
+    /// write the frozen values, every 60Hz frame
+    fn apply_freezes(&mut self) {
+        let cheats = std::mem::take(&mut self.cheats);
+        for (addr, value) in cheats.freezes() {
+            self.memory[addr] = value;
+            self.invalidate(addr..addr + 1);
+        }
+        self.cheats = cheats;
+    }
+
+    /// restore the machine to the state right after the font and rom were loaded,
+    /// quirks and the RPL flags are kept and the random numbers start over
+    pub fn reset(&mut self) -> Result<()> {
+        self.memory.fill(0);
+        self.invalidate(0..self.memory.len());
+        self.forget_executed();
+        self.registers = [0; REGISTER_COUNT];
+        self.pc = RESERVED_MEMORY_SIZE as u16;
+        self.i = 0;
+        self.stack = [0; STACK_SIZE];
+        self.stack_pointer = 0;
+        self.delay_timer = 0;
+        self.sound_timer = 0;
+        self.keyboard = KeyBoard::default();
+        self.video = Video::new(64, 32);
+        self.mega = None;
+        self.latch_frame();
+        self.collisions = Collisions::default();
+        self.halted = false;
+        self.waiting_key = None;
+        self.vblank = false;
+        self.rng = ChaCha8Rng::seed_from_u64(self.seed);
+        self.cycles = 0;
+        match self.movie.as_mut() {
+            Some(MovieMode::Recording(movie)) => *movie = Movie::new(self.seed),
+            Some(MovieMode::Playing { next, .. }) => *next = 0,
+            None => {}
+        }
+        self.hold_timers();
+        self.audio_pattern = None;
+        self.pitch = DEFAULT_PITCH;
+        self.queue_pattern();
+        self.stop_sound();
+        self.load_font()?;
+        let end = RESERVED_MEMORY_SIZE + self.rom.len();
+        self.memory[RESERVED_MEMORY_SIZE..end].copy_from_slice(&self.rom);
+        self.apply_patches();
         Ok(())
     }
 
+    /// ignored while a movie is played back
     pub fn key_down(&mut self, key: u8) {
-        self.keyboard.key_down(key)
+        if self.record_input(Input::KeyDown { key }) {
+            self.keyboard.key_down(key)
+        }
     }
 
+    /// ignored while a movie is played back
     pub fn key_up(&mut self, key: u8) {
-        self.keyboard.key_up(key)
+        if self.record_input(Input::KeyUp { key }) {
+            self.keyboard.key_up(key)
+        }
+    }
+
+    pub fn is_key_down(&self, key: u8) -> bool {
+        self.keyboard.is_key_down(key)
+    }
+
+    /// keys the rom tested with EX9E/EXA1 since the last call, bit k for key
+    /// k, to show which keys a rom uses
+    pub fn take_checked_keys(&mut self) -> u16 {
+        std::mem::take(&mut self.checked_keys)
+    }
+
+    /// true when FX0A waited for a key since the last call
+    pub fn take_key_wait(&mut self) -> bool {
+        std::mem::take(&mut self.key_wait)
+    }
+
+    /// add an input from the frontend to the movie being recorded,
+    /// return false if it should be ignored because a movie is played
+    fn record_input(&mut self, input: Input) -> bool {
+        match self.movie.as_mut() {
+            Some(MovieMode::Recording(movie)) => {
+                movie.events.push(MovieEvent {
+                    cycle: self.cycles,
+                    input,
+                });
+                true
+            }
+            Some(MovieMode::Playing { .. }) => false,
+            None => true,
+        }
+    }
+
+    /// start recording the inputs from a reset, a later reset starts over
+    pub fn record_movie(&mut self) -> Result<()> {
+        self.movie = Some(MovieMode::Recording(Movie::new(self.seed)));
+        self.reset()
+    }
+
+    /// reset with the seed of `movie` and feed its inputs instead of the
+    /// frontend's until it ends
+    pub fn play_movie(&mut self, movie: Movie) -> Result<()> {
+        self.set_seed(movie.seed);
+        self.movie = Some(MovieMode::Playing { movie, next: 0 });
+        self.reset()
+    }
+
+    /// the movie being recorded, recording stops
+    pub fn take_movie(&mut self) -> Option<Movie> {
+        match self.movie.take() {
+            Some(MovieMode::Recording(movie)) => Some(movie),
+            other => {
+                self.movie = other;
+                None
+            }
+        }
+    }
+
+    pub fn is_playing_movie(&self) -> bool {
+        matches!(self.movie, Some(MovieMode::Playing { .. }))
+    }
+
+    /// apply the movie inputs due before the next instruction
+    fn feed_movie(&mut self) {
+        let (movie, next) = match self.movie.as_mut() {
+            Some(MovieMode::Playing { movie, next }) => (movie, next),
+            _ => return,
+        };
+        let mut timers = 0;
+        while let Some(event) = movie.events.get(*next) {
+            if event.cycle > self.cycles {
+                break;
+            }
+            match event.input {
+                Input::KeyDown { key } => self.keyboard.key_down(key),
+                Input::KeyUp { key } => self.keyboard.key_up(key),
+                Input::Timer => timers += 1,
+            }
+            *next += 1;
+        }
+        if *next == movie.events.len() {
+            info!(target: INPUT, "movie playback finished at cycle {}", self.cycles);
+            self.movie = None;
+        }
+        for _ in 0..timers {
+            self.tick_timers();
+        }
     }
 
-    pub fn get_display(&self) -> &[Vec<u8>] {
-        self.video.get_grid()
+    /// the display to present, the latched frame with `latch_display`
+    pub fn get_display(&self) -> &Video {
+        self.latched.as_ref().unwrap_or(&self.video)
+    }
+
+    /// display rows changed since the last call, bit y for row y,
+    /// frontends only need to redraw these
+    pub fn take_dirty_rows(&mut self) -> u64 {
+        match self.latched.as_mut() {
+            Some(latched) => latched.take_dirty_rows(),
+            None => self.video.take_dirty_rows(),
+        }
+    }
+
+    /// pixels erased by sprite draws since the last call, where the rom saw
+    /// a collision in VF, for debug overlays
+    pub fn take_collisions(&mut self) -> Collisions {
+        std::mem::take(&mut self.collisions)
     }
 
     pub fn width(&self) -> usize {
-        self.video.width()
+        self.get_display().width()
     }
 
     pub fn height(&self) -> usize {
-        self.video.height()
+        self.get_display().height()
+    }
+
+    /// Show the display only as it is at the end of each frame, when
+    /// `tick_60hz` is called. At high speeds a rom erases and redraws its
+    /// sprites several times a frame and a present in between flickers.
+    pub fn latch_display(&mut self, on: bool) {
+        self.latched = if on { Some(self.video.clone()) } else { None };
+    }
+
+    fn latch_frame(&mut self) {
+        if let Some(latched) = self.latched.as_mut() {
+            latched.latch(&mut self.video);
+        }
+    }
+
+    pub fn pc(&self) -> u16 {
+        self.pc
+    }
+
+    /// instructions executed since reset
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    /// 60Hz timer ticks since the machine was created, held timers do not
+    /// tick
+    pub fn timer_ticks(&self) -> u64 {
+        self.timer_ticks
+    }
+
+    /// the instruction `run_cycle` executes next, None if PC is out of memory
+    pub fn next_opcode(&self) -> Option<u16> {
+        let pc = self.pc as usize;
+        let bytes = self.memory.get(pc..pc + 2)?;
+        Some(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    pub fn i(&self) -> u32 {
+        self.i
+    }
+
+    pub fn registers(&self) -> &[u8] {
+        &self.registers
+    }
+
+    pub fn memory(&self) -> &[u8] {
+        &self.memory
+    }
+
+    /// the bytes of the loaded rom, as they were before it ran
+    pub fn rom(&self) -> &[u8] {
+        &self.rom
+    }
+
+    /// memory range the rom was loaded to
+    pub fn rom_range(&self) -> Range<usize> {
+        RESERVED_MEMORY_SIZE..RESERVED_MEMORY_SIZE + self.rom.len()
+    }
+
+    /// write `bytes` to memory at `addr` from outside the machine, for
+    /// scripts and debugging tools
+    pub fn write_memory(&mut self, addr: u16, bytes: &[u8]) -> Result<()> {
+        let start = addr as usize;
+        if start + bytes.len() > self.memory.len() {
+            return err!(
+                "can not write {} bytes at {:#05X}, past the end of memory",
+                bytes.len(),
+                addr
+            );
+        }
+        self.memory[start..start + bytes.len()].copy_from_slice(bytes);
+        self.invalidate(start..start + bytes.len());
+        Ok(())
+    }
+
+    /// set VX from outside the machine, panics for X past VF
+    pub fn set_register(&mut self, x: usize, value: u8) {
+        self.registers[x] = value;
+    }
+
+    /// the active part of the call stack, oldest return address first
+    pub fn stack(&self) -> &[u16] {
+        &self.stack[..self.stack_pointer]
+    }
+
+    /// calls the stack holds, see `Quirks::short_stack`
+    pub fn stack_depth(&self) -> usize {
+        if self.quirks.short_stack {
+            SHORT_STACK_SIZE
+        } else {
+            STACK_SIZE
+        }
+    }
+
+    pub fn delay_timer(&self) -> u8 {
+        self.delay_timer
+    }
+
+    pub fn sound_timer(&self) -> u8 {
+        self.sound_timer
+    }
+
+    pub fn save_state(&self) -> SaveState {
+        SaveState {
+            memory: self.memory.to_vec(),
+            registers: self.registers,
+            pc: self.pc,
+            i: self.i,
+            stack: self.stack().to_vec(),
+            stack_pointer: None,
+            delay_timer: self.delay_timer,
+            sound_timer: self.sound_timer,
+            keyboard: self.keyboard.clone(),
+            video: self.video.clone(),
+            mega: self.mega.clone(),
+            rpl_flags: self.rpl_flags,
+            halted: self.halted,
+            waiting_key: self.waiting_key,
+            vblank: self.vblank,
+            rng: Some(self.rng.clone()),
+            audio_pattern: self.audio_pattern,
+            pitch: self.pitch,
+        }
+    }
+
+    pub fn load_state(&mut self, state: &SaveState) -> Result<()> {
+        let size = state.memory.len();
+        if !size.is_power_of_two() || !(MEMORY_SIZE..=MEGA_MEMORY_SIZE).contains(&size) {
+            return err!(
+                "can not load state with {} Bytes memory, expect a power of two from {} to {} Bytes",
+                size,
+                MEMORY_SIZE,
+                MEGA_MEMORY_SIZE
+            );
+        }
+        let calls = match state.stack_pointer {
+            Some(pointer) => state.stack.get(1..=pointer),
+            None => Some(&state.stack[..]),
+        };
+        let calls = match calls {
+            Some(calls) if calls.len() <= STACK_SIZE => calls,
+            _ => return err!("can not load state with {:?} on the stack", state.stack),
+        };
+        self.resize_memory(size);
+        self.memory.copy_from_slice(&state.memory);
+        self.invalidate(0..size);
+        self.forget_executed();
+        self.registers = state.registers;
+        self.pc = state.pc;
+        self.i = state.i;
+        self.stack[..calls.len()].copy_from_slice(calls);
+        self.stack_pointer = calls.len();
+        self.delay_timer = state.delay_timer;
+        self.sound_timer = state.sound_timer;
+        self.keyboard = state.keyboard.clone();
+        self.video = state.video.clone();
+        self.video.mark_all_dirty();
+        self.mega = state.mega.clone();
+        self.latch_frame();
+        self.collisions = Collisions::default();
+        self.rpl_flags = state.rpl_flags;
+        self.halted = state.halted;
+        self.waiting_key = state.waiting_key;
+        self.vblank = state.vblank;
+        if let Some(rng) = &state.rng {
+            self.rng = rng.clone();
+        }
+        self.audio_pattern = state.audio_pattern;
+        self.pitch = state.pitch;
+        self.queue_pattern();
+        self.hold_timers();
+        Ok(())
     }
 
     fn decrement_delay_timer(&mut self) {
@@ -101,62 +622,390 @@ impl<T: AudioPlay> Machine<T> {
         self.audio = Some(auido_system);
     }
 
-    fn decrement_sound_timer(&mut self) {
-        if self.sound_timer > 0 {
-            if let Some(audio) = &self.audio {
+    /// silence the audio until the next timer tick, used when the emulation is paused
+    pub fn stop_sound(&mut self) {
+        if let Some(audio) = &self.audio {
+            audio.pause();
+        }
+        self.sound_on = false;
+    }
+
+    /// resume or pause the audio when the beep starts or stops, switching
+    /// it on every tick clicks on some drivers
+    fn set_sound(&mut self, on: bool) {
+        if on == self.sound_on {
+            return;
+        }
+        if let Some(audio) = &self.audio {
+            if on {
                 audio.resume();
+            } else {
+                audio.pause();
             }
+        }
+        self.sound_on = on;
+    }
+
+    /// hand the XO-CHIP audio pattern to the audio backend
+    fn queue_pattern(&self) {
+        if let Some(audio) = &self.audio {
+            match &self.audio_pattern {
+                Some(pattern) => audio.queue_pattern(pattern, self.pitch),
+                None => audio.clear_pattern(),
+            }
+        }
+    }
+
+    /// change the volume of the beep, 0.0 mutes it
+    pub fn set_volume(&self, volume: f32) {
+        if let Some(audio) = &self.audio {
+            audio.set_volume(volume);
+        }
+    }
+
+    fn decrement_sound_timer(&mut self) {
+        self.hooks.sound(self.sound_timer > 0);
+        self.set_sound(self.sound_timer > 0);
+        if self.sound_timer > 0 {
             self.sound_timer -= 1;
-        } else if let Some(audio) = &self.audio {
-            audio.pause();
+        }
+    }
+
+    /// Tick the timers from a monotonic clock inside the machine, so they
+    /// keep exactly 60Hz however the frontend paces its frames.
+    /// `tick_60hz` then runs the ticks that are due and every instruction
+    /// catches up first. Not available on wasm, which has no `Instant`.
+    pub fn use_timer_clock(&mut self) {
+        self.timer_clock = Some(TimerClock::new());
+    }
+
+    /// keep the timer clock from counting while the emulation does not run,
+    /// call it when paused so the timers do not jump on resume
+    pub fn hold_timers(&mut self) {
+        if let Some(clock) = self.timer_clock.as_mut() {
+            clock.restart();
+        }
+    }
+
+    /// 60Hz timer tick from the frontend, ignored while a movie is played back.
+    /// With the timer clock the ticks that are due are run instead.
+    /// Returns true when the display changed and needs a redraw.
+    pub fn tick_60hz(&mut self) -> bool {
+        if self.timer_clock.is_some() {
+            self.sync_timers();
+        } else if self.record_input(Input::Timer) {
+            self.tick_timers();
+        }
+        self.latch_frame();
+        self.get_display().is_dirty()
+    }
+
+    /// One timer tick whatever the timer clock says, for stepping a paused
+    /// machine frame by frame
+    pub fn tick_frame(&mut self) {
+        if self.record_input(Input::Timer) {
+            self.tick_timers();
+        }
+        self.hold_timers();
+        self.latch_frame();
+    }
+
+    /// run the timer clock ticks that are due
+    fn sync_timers(&mut self) {
+        let due = match self.timer_clock.as_mut() {
+            Some(clock) => clock.due(),
+            None => return,
         };
+        for _ in 0..due {
+            if self.record_input(Input::Timer) {
+                self.tick_timers();
+            }
+        }
     }
 
-    pub fn update_timer(&mut self) {
+    fn tick_timers(&mut self) {
+        self.timer_ticks += 1;
+        self.apply_freezes();
         self.decrement_delay_timer();
         self.decrement_sound_timer();
+        if let Some(heatmap) = self.heatmap.as_mut() {
+            heatmap.cool();
+        }
+        self.vblank = true;
     }
 
-    fn fetch(&mut self) -> Result<Instruction> {
-        let instr = Instruction::new(
-            self.memory[self.pc as usize],
-            self.memory[self.pc as usize + 1],
-        );
-        self.pc += 2;
+    /// display pixels a scroll by `n` moves, see `Quirks::half_scroll`
+    fn scroll_distance(&self, n: usize) -> usize {
+        if self.quirks.half_scroll && !self.video.is_hires() {
+            n / 2
+        } else {
+            n
+        }
+    }
+
+    fn fetch(&mut self) -> result::Result<Instruction, MachineError> {
+        let pc = self.pc as usize;
+        if pc + 1 >= self.memory.len() {
+            return Err(MachineError::MemoryOutOfBounds {
+                pc: self.pc,
+                addr: pc + 1,
+            });
+        }
+        let instr = Instruction::new(self.memory[pc], self.memory[pc + 1]);
+        self.advance()?;
         Ok(instr)
     }
 
-    pub fn run_cycle(&mut self) -> Result<()> {
-        debug!("registers: {:02?}", self.registers);
-        let instr = self.fetch()?;
-        debug!("execute: {:04X}, pc: {:04X}", instr.opcode, self.pc - 2);
-        let opcode = instr.opcode;
-        let (kind, x, y, n, nn, nnn) = instr.decode();
-        match kind {
-            0x0 => {
-                if opcode == 0x00e0 {
-                    self.video.clear();
-                } else if opcode == 0x00ee {
-                    self.ret()?;
-                }
+    /// move pc past an instruction, the 16 bit pc can not pass 0xFFFF even
+    /// when MegaChip memory goes on
+    fn advance(&mut self) -> result::Result<(), MachineError> {
+        match self.pc.checked_add(2) {
+            Some(pc) => {
+                self.pc = pc;
+                Ok(())
             }
-            0x1 => self.pc = nnn,
-            0x2 => self.call(nnn)?,
-            0x3 => {
-                if self.registers[x] == nn {
-                    self.pc += 2;
-                }
+            None => Err(MachineError::MemoryOutOfBounds {
+                pc: self.pc,
+                addr: self.pc as usize + 2,
+            }),
+        }
+    }
+
+    /// `fetch` and decode, from the decode cache when it is on
+    fn fetch_decoded(&mut self) -> result::Result<Decoded, MachineError> {
+        let pc = self.pc as usize;
+        let cached = self.decode_cache.as_ref().and_then(|cache| cache.get(pc));
+        if let Some(&Some(decoded)) = cached {
+            self.advance()?;
+            return Ok(decoded);
+        }
+        let instr = self.fetch()?;
+        let decoded = (instr.opcode, instr.decode());
+        if let Some(cache) = self.decode_cache.as_mut() {
+            cache[pc] = Some(decoded);
+        }
+        Ok(decoded)
+    }
+
+    /// `len` bytes of memory starting at I
+    fn memory_at_i(&self, len: usize) -> result::Result<Range<usize>, MachineError> {
+        let start = self.i as usize;
+        if start + len > self.memory.len() {
+            return Err(MachineError::MemoryOutOfBounds {
+                pc: self.pc - 2,
+                addr: start + len - 1,
+            });
+        }
+        Ok(start..start + len)
+    }
+
+    pub fn run_cycle(&mut self) -> result::Result<(), MachineError> {
+        self.sync_timers();
+        self.feed_movie();
+        self.self_modified = None;
+        if let Some(smc) = self.smc.as_mut() {
+            smc.execute(self.pc);
+        }
+        if let Some(coverage) = self.coverage.as_mut() {
+            coverage.execute(self.pc);
+        }
+        if let Some(heatmap) = self.heatmap.as_mut() {
+            heatmap.execute(self.pc);
+        }
+        if self.history.is_some() {
+            let entry = TraceEntry::new(self, self.cycles + 1);
+            if let Some(history) = self.history.as_mut() {
+                history.push(entry);
             }
-            0x4 => {
-                if self.registers[x] != nn {
-                    self.pc += 2;
+        }
+        match self.profile.as_ref().and(self.next_opcode()) {
+            Some(opcode) => {
+                let start = Instant::now();
+                let result = self.execute_with_hooks();
+                if let Some(profile) = self.profile.as_mut() {
+                    profile.record(opcode, start.elapsed());
                 }
+                result?;
             }
-            0x5 => {
-                if self.registers[x] == self.registers[y] {
-                    self.pc += 2;
+            None => self.execute_with_hooks()?,
+        }
+        self.cycles += 1;
+        Ok(())
+    }
+
+    /// Decode every instruction once and keep it until its memory is written,
+    /// for `--fast`. Memory written from outside the machine is not noticed.
+    pub fn enable_decode_cache(&mut self) {
+        self.decode_cache = Some(vec![None; self.memory.len()]);
+    }
+
+    fn forget_executed(&mut self) {
+        if let Some(smc) = self.smc.as_mut() {
+            smc.clear();
+        }
+        self.self_modified = None;
+    }
+
+    /// forget the decoded instructions overlapping `range` after it was written
+    fn invalidate(&mut self, range: Range<usize>) {
+        if let Some(cache) = self.decode_cache.as_mut() {
+            // the instruction starting right before the range overlaps it too
+            cache[range.start.saturating_sub(1)..range.end].fill(None);
+        }
+    }
+
+    /// an instruction wrote `range`
+    fn code_written(&mut self, range: Range<usize>) {
+        if let Some(heatmap) = self.heatmap.as_mut() {
+            heatmap.write(range.clone());
+        }
+        if let Some(smc) = self.smc.as_mut() {
+            self.self_modified = smc.write(self.pc - 2, range.clone());
+        }
+        self.invalidate(range);
+    }
+
+    /// Remember the executed addresses from now on, so an instruction that
+    /// writes over them is reported by `self_modified`. With `warn` every
+    /// overwritten address is logged once.
+    pub fn track_self_modifying(&mut self, warn: bool) {
+        self.smc = Some(SmcTracker::new(warn));
+    }
+
+    /// the executed code the last instruction wrote over
+    pub fn self_modified(&self) -> Option<SelfModify> {
+        self.self_modified
+    }
+
+    /// count the executed instructions and the time they take from now on,
+    /// not available on wasm, which has no `Instant`
+    pub fn enable_profiler(&mut self) {
+        self.profile = Some(Profile::default());
+    }
+
+    pub fn profile(&self) -> Option<&Profile> {
+        self.profile.as_ref()
+    }
+
+    /// keep the last `len` executed instructions from now on, for the crash
+    /// dump
+    pub fn keep_history(&mut self, len: usize) {
+        self.history = Some(History::new(len));
+    }
+
+    pub fn history(&self) -> Option<&History> {
+        self.history.as_ref()
+    }
+
+    /// remember the executed addresses from now on, for `--coverage`,
+    /// loading a rom starts over
+    pub fn track_coverage(&mut self) {
+        self.coverage = Some(Coverage::default());
+    }
+
+    pub fn coverage(&self) -> Option<&Coverage> {
+        self.coverage.as_ref()
+    }
+
+    /// keep a `Heatmap` of the memory the instructions write from now on,
+    /// for the debug windows
+    pub fn track_writes(&mut self) {
+        if self.heatmap.is_none() {
+            self.heatmap = Some(Heatmap::default());
+        }
+    }
+
+    pub fn heatmap(&self) -> Option<&Heatmap> {
+        self.heatmap.as_ref()
+    }
+
+    /// Run up to `cycles` instructions, fewer when the machine halts, and
+    /// return how many ran. The timers are not ticked unless the timer
+    /// clock is used, call `tick_60hz` for that.
+    pub fn step(&mut self, cycles: usize) -> result::Result<usize, MachineError> {
+        let mut executed = 0;
+        while executed < cycles && !self.is_halt() {
+            self.run_cycle()?;
+            executed += 1;
+        }
+        Ok(executed)
+    }
+
+    /// call `hook` with the address and opcode of every instruction before
+    /// it is executed
+    pub fn on_instruction<F: FnMut(u16, u16) + 'static>(&mut self, hook: F) {
+        self.hooks.instruction.push(Box::new(hook));
+    }
+
+    /// call `hook` with the display after every instruction that changed it
+    pub fn on_draw<F: FnMut(&Video) + 'static>(&mut self, hook: F) {
+        self.hooks.draw.push(Box::new(hook));
+    }
+
+    /// call `hook` with true when the beep starts and false when it stops
+    pub fn on_sound<F: FnMut(bool) + 'static>(&mut self, hook: F) {
+        self.hooks.sound.push(Box::new(hook));
+    }
+
+    pub fn clear_hooks(&mut self) {
+        self.hooks = Hooks::default();
+    }
+
+    fn execute_with_hooks(&mut self) -> result::Result<(), MachineError> {
+        if self.hooks.is_empty() {
+            return self.execute();
+        }
+        let pc = self.pc;
+        let opcode = self.next_opcode();
+        if let Some(opcode) = opcode {
+            self.hooks.instruction(pc, opcode);
+        }
+        self.execute()?;
+        // a DXYN held back by the display wait quirk runs again and drew nothing
+        if opcode.is_some_and(hooks::draws) && self.pc != pc {
+            self.hooks.draw(&self.video);
+        }
+        Ok(())
+    }
+
+    fn execute(&mut self) -> result::Result<(), MachineError> {
+        trace!(target: CPU, "registers={:02X?}", self.registers);
+        let (opcode, (kind, x, y, n, nn, nnn)) = self.fetch_decoded()?;
+        debug!(target: CPU, "execute pc={:03X} opcode={:04X}", self.pc - 2, opcode);
+        let illegal = MachineError::IllegalOpcode {
+            pc: self.pc - 2,
+            opcode,
+        };
+        match kind {
+            0x0 if self.mega.is_some() => self.execute_mega(opcode)?,
+            0x0 => match opcode {
+                0x00E0 => self.video.clear(),
+                // the clear screen of the HiRes CHIP-8 interpreter
+                0x0230 if self.video.is_two_page() => self.video.clear(),
+                0x00EE => self.ret()?,
+                0x00FB => self.video.scroll_right(self.scroll_distance(4)),
+                0x00FC => self.video.scroll_left(self.scroll_distance(4)),
+                0x00FD => self.halted = true,
+                0x00FE => self.video.set_hires(false),
+                0x00FF => self.video.set_hires(true),
+                0x0011 => self.set_mega(true),
+                // MegaChip off while it is off already
+                0x0010 => (),
+                _ if opcode & 0xFFF0 == 0x00C0 => {
+                    self.video.scroll_down(self.scroll_distance(n as usize))
                 }
+                _ => return Err(illegal),
+            },
+            0x1 if nnn == HIRES_ENTRY && self.pc == 0x202 && detect::is_two_page(&self.rom) => {
+                info!(target: VIDEO, "HiRes CHIP-8 rom, switching to 64x64");
+                self.video.set_two_page();
+                self.pc = HIRES_START;
             }
+            0x1 => self.pc = nnn,
+            0x2 => self.call(nnn)?,
+            0x3 => self.skip_if(self.registers[x] == nn)?,
+            0x4 => self.skip_if(self.registers[x] != nn)?,
+            0x5 if n == 0 => self.skip_if(self.registers[x] == self.registers[y])?,
             0x6 => {
                 self.registers[x] = nn;
             }
@@ -174,54 +1023,81 @@ impl<T: AudioPlay> Machine<T> {
                     0x5 => self.sub(x, y),  // 8xy5
                     0x7 => self.subb(x, y), // 8xy7
                     0x6 => {
-                        //ignore the y
-                        self.registers[0xf] = self.registers[x] & 1;
-                        self.registers[x] >>= 1;
+                        let val = self.shift_source(x, y);
+                        self.registers[x] = val >> 1;
+                        self.registers[0xf] = val & 1;
                     }
                     0xe => {
-                        //ignore the y
-                        self.registers[0xf] = self.registers[x] >> 7;
-                        self.registers[x] <<= 1;
+                        let val = self.shift_source(x, y);
+                        self.registers[x] = val << 1;
+                        self.registers[0xf] = val >> 7;
                     }
-                    _ => (),
-                }
-            }
-            0x9 => {
-                if self.registers[x] != self.registers[y] {
-                    self.pc += 2;
+                    _ => return Err(illegal),
                 }
             }
+            0x9 if n == 0 => self.skip_if(self.registers[x] != self.registers[y])?,
             0xA => {
-                self.i = nnn;
+                self.i = nnn as u32;
             }
             0xB => {
-                self.pc = nnn + self.registers[0] as u16;
+                let offset = if self.quirks.jump_uses_vx {
+                    self.registers[x]
+                } else {
+                    self.registers[0]
+                };
+                self.pc = nnn + offset as u16;
             }
             0xC => {
-                let mut rng = rand::thread_rng();
-                let r1: u8 = rng.gen();
+                let r1: u8 = self.rng.gen();
                 self.registers[x] = r1 & nn;
             }
+            0xD if self.quirks.display_wait && !self.vblank => {
+                // try again until the next timer tick
+                self.pc -= 2;
+            }
+            0xD if self.mega.is_some() => {
+                self.vblank = false;
+                let x = self.registers[x] as usize;
+                let y = self.registers[y] as usize;
+                let size = self.megachip().sprite_size();
+                let sprite = self.memory_at_i(size)?;
+                let collision = match self.mega.as_mut() {
+                    Some(mega) => mega.draw(x, y, &self.memory[sprite]),
+                    None => false,
+                };
+                self.registers[0xf] = collision as u8;
+            }
             0xD => {
-                let x = (self.registers[x] % 64) as usize;
-                let y = (self.registers[y] % 32) as usize;
-                debug!("draw at: ({}, {})", x, y);
-                let n = n as usize;
-                self.registers[0xf] =
+                self.vblank = false;
+                let x = self.registers[x] as usize % self.video.width();
+                let y = self.registers[y] as usize % self.video.height();
+                debug!(target: VIDEO, "draw x={} y={}", x, y);
+                let wrap = self.quirks.wrap_sprites;
+                let collisions = if n == 0 {
+                    // SCHIP DXY0: 16x16 sprite
+                    let sprite = self.memory_at_i(32)?;
+                    self.video.draw_large(x, y, &self.memory[sprite], wrap)
+                } else {
+                    let sprite = self.memory_at_i(n as usize)?;
                     self.video
-                        .draw(x, y, n, &self.memory[self.i as usize..self.i as usize + n])
+                        .draw(x, y, n as usize, &self.memory[sprite], wrap)
+                };
+                self.registers[0xf] = collisions.flag();
+                self.collisions.merge(&collisions);
             }
-            0xE => {
-                let key = self.registers[x];
+            0xE if nn == 0x9E || nn == 0xA1 => {
+                // only the low nibble of VX names a key
+                let key = self.registers[x] & 0xF;
+                self.checked_keys |= 1 << key;
                 let required_key_pressed = self.keyboard.is_key_down(key);
                 match (required_key_pressed, nn) {
                     (true, 0x9E) => {
-                        self.pc += 2;
-                        info!("instr: {:04X}, key {:X?} pressed", opcode, key)
+                        self.advance()?;
+                        debug!(target: INPUT, "opcode={:04X} key={:X} pressed", opcode, key)
                     }
                     (false, 0xA1) => {
-                        self.pc += 2;
-                        info!("instr: {:04X}, key {:X?} not pressed", opcode, key)
+                        self.advance()?;
+                        debug!(target: INPUT, "opcode={:04X} key={:X} released", opcode, key)
                     }
                     _ => (),
                 }
@@ -230,88 +1106,218 @@ impl<T: AudioPlay> Machine<T> {
                 0x7 => self.registers[x] = self.delay_timer,
                 0x15 => self.delay_timer = self.registers[x],
                 0x18 => self.sound_timer = self.registers[x],
-                0x1E => self.i += self.registers[x] as u16,
-                0x0A => {
-                    if let Some(pressed_key) = self.keyboard.first_down_key() {
-                        self.registers[x] = pressed_key;
-                        info!("key {:X} is being pressed", pressed_key);
-                        // after pressed, key should be up. https://github.com/livexia/yet-another-rchip8/issues/10#issue-1713963954
-                        self.keyboard.key_up(pressed_key);
-                    } else {
-                        self.pc -= 2;
-                    }
+                0x1E => self.i += self.registers[x] as u32,
+                0x02 if x == 0 => {
+                    // XO-CHIP: load the 16 byte audio pattern at I
+                    let range = self.memory_at_i(PATTERN_BYTES)?;
+                    let mut pattern = [0; PATTERN_BYTES];
+                    pattern.copy_from_slice(&self.memory[range]);
+                    self.audio_pattern = Some(pattern);
+                    self.queue_pattern();
+                }
+                0x3A => {
+                    // XO-CHIP: pitch of the audio pattern
+                    self.pitch = self.registers[x];
+                    self.queue_pattern();
                 }
+                0x0A => self.wait_key(x),
                 0x29 => {
                     let char = self.registers[x];
-                    self.i = 0x50 + 5 * char as u16;
-                    debug!("look char: {:X}", char);
+                    self.i = FONT_ADDR as u32 + 5 * char as u32;
+                    debug!(target: CPU, "font char={:X}", char);
+                }
+                0x30 => {
+                    // SCHIP: 8x10 digit for DXYA
+                    let digit = self.registers[x];
+                    self.i = BIG_FONT_ADDR as u32 + 10 * digit as u32;
+                    debug!(target: CPU, "big font digit={:X}", digit);
                 }
                 0x33 => {
-                    let mut x_val = self.registers[x];
-                    self.memory[self.i as usize + 2] = x_val % 10;
-                    x_val /= 10;
-                    self.memory[self.i as usize + 1] = x_val % 10;
-                    x_val /= 10;
-                    self.memory[self.i as usize] = x_val;
-                    debug!(
-                        "x: {}, BCD: {:?}",
-                        self.registers[x],
-                        &self.memory[self.i as usize..self.i as usize + 3]
-                    );
+                    let bcd = self.memory_at_i(3)?;
+                    let x_val = self.registers[x];
+                    self.memory[bcd.clone()].copy_from_slice(&[
+                        x_val / 100,
+                        x_val / 10 % 10,
+                        x_val % 10,
+                    ]);
+                    self.code_written(bcd.clone());
+                    debug!(target: CPU, "bcd value={} digits={:?}", x_val, &self.memory[bcd]);
                 }
                 0x55 => {
-                    let i = self.i as usize;
-                    self.memory[i..=i + x].copy_from_slice(&self.registers[..=x]);
+                    let range = self.memory_at_i(x + 1)?;
+                    self.memory[range.clone()].copy_from_slice(&self.registers[..=x]);
+                    self.code_written(range);
+                    if self.quirks.load_store_increment_i {
+                        self.i += x as u32 + 1;
+                    }
                 }
                 0x65 => {
-                    let i = self.i as usize;
-                    self.registers[..=x].copy_from_slice(&self.memory[i..=i + x]);
+                    let range = self.memory_at_i(x + 1)?;
+                    self.registers[..=x].copy_from_slice(&self.memory[range]);
+                    if self.quirks.load_store_increment_i {
+                        self.i += x as u32 + 1;
+                    }
+                }
+                0x75 => {
+                    let x = x.min(RPL_FLAG_COUNT - 1);
+                    self.rpl_flags[..=x].copy_from_slice(&self.registers[..=x]);
                 }
-                _ => (),
+                0x85 => {
+                    let x = x.min(RPL_FLAG_COUNT - 1);
+                    self.registers[..=x].copy_from_slice(&self.rpl_flags[..=x]);
+                }
+                _ => return Err(illegal),
             },
-            _ => (),
+            _ => return Err(illegal),
         }
         Ok(())
     }
 
-    /// 8xy4
+    /// 0011/0010: enter or leave MegaChip mode
+    fn set_mega(&mut self, on: bool) {
+        info!(target: VIDEO, "MegaChip mode {}", if on { "on" } else { "off" });
+        self.mega = if on { Some(MegaChip::default()) } else { None };
+        self.video.set_mega(on);
+    }
+
+    fn megachip(&mut self) -> &mut MegaChip {
+        self.mega.get_or_insert_with(MegaChip::default)
+    }
+
+    /// 0NNN in MegaChip mode, scrolls act on the back buffer and 00E0
+    /// presents it
+    fn execute_mega(&mut self, opcode: u16) -> result::Result<(), MachineError> {
+        let nn = opcode as u8;
+        let n = (opcode & 0xF) as usize;
+        match opcode {
+            0x0010 => self.set_mega(false),
+            0x0011 => self.set_mega(true),
+            0x00E0 => {
+                let frame = self.megachip().present();
+                self.video.present_mega(frame);
+            }
+            0x00EE => self.ret()?,
+            0x00FB => self.megachip().scroll_sideways(4, true),
+            0x00FC => self.megachip().scroll_sideways(4, false),
+            0x00FD => self.halted = true,
+            _ if opcode & 0xFFF0 == 0x00B0 => self.megachip().scroll_up(n),
+            _ if opcode & 0xFFF0 == 0x00C0 => self.megachip().scroll_down(n),
+            _ => match opcode >> 8 {
+                0x01 => {
+                    // 01NN NNNN: the low 16 bits follow
+                    let low = self.fetch()?.opcode;
+                    self.i = (nn as u32) << 16 | low as u32;
+                }
+                0x02 => {
+                    let colors = self.memory_at_i(4 * nn as usize)?;
+                    if let Some(mega) = self.mega.as_mut() {
+                        mega.load_palette(&self.memory[colors]);
+                    }
+                }
+                0x03 => self.megachip().set_sprite_width(nn),
+                0x04 => self.megachip().set_sprite_height(nn),
+                0x05 => self.megachip().set_alpha(nn),
+                0x06 | 0x07 => {
+                    debug!(target: AUDIO, "digitised sound is not supported, opcode={:04X}", opcode)
+                }
+                0x08 => match Blend::from_mode(nn) {
+                    Some(blend) => self.megachip().set_blend(blend),
+                    None => {
+                        return Err(MachineError::IllegalOpcode {
+                            pc: self.pc - 2,
+                            opcode,
+                        })
+                    }
+                },
+                0x09 => self.megachip().set_collision_index(nn),
+                _ => {
+                    return Err(MachineError::IllegalOpcode {
+                        pc: self.pc - 2,
+                        opcode,
+                    })
+                }
+            },
+        }
+        Ok(())
+    }
+
+    /// FX0A: block until a key is pressed and released again,
+    /// https://github.com/livexia/yet-another-rchip8/issues/10#issue-1713963954
+    fn wait_key(&mut self, x: usize) {
+        self.key_wait = true;
+        match self.waiting_key {
+            Some(key) if !self.keyboard.is_key_down(key) => {
+                self.registers[x] = key;
+                self.waiting_key = None;
+                debug!(target: INPUT, "wait key={:X} released", key);
+                return;
+            }
+            Some(_) => (),
+            None => {
+                self.waiting_key = self.keyboard.first_down_key();
+                if let Some(key) = self.waiting_key {
+                    debug!(target: INPUT, "wait key={:X} pressed", key);
+                }
+            }
+        }
+        self.pc -= 2;
+    }
+
+    fn skip_if(&mut self, condition: bool) -> result::Result<(), MachineError> {
+        if condition {
+            self.advance()?;
+        }
+        Ok(())
+    }
+
+    /// 8xy6/8xyE operand, VY or VX depends on the shift quirk
+    fn shift_source(&self, x: usize, y: usize) -> u8 {
+        if self.quirks.shift_uses_vy {
+            self.registers[y]
+        } else {
+            self.registers[x]
+        }
+    }
+
+    /// 8xy4
     fn add(&mut self, x: usize, y: usize) {
         let (val, flag) = self.registers[x].overflowing_add(self.registers[y]);
-        self.registers[0xf] = flag as u8;
+        // the flag is written last, it wins when X is F
         self.registers[x] = val;
+        self.registers[0xf] = flag as u8;
     }
 
     /// 8xy5
     fn sub(&mut self, x: usize, y: usize) {
         let (val, flag) = self.registers[x].overflowing_sub(self.registers[y]);
-        self.registers[0xf] = (!flag) as u8;
         self.registers[x] = val;
+        self.registers[0xf] = (!flag) as u8;
     }
 
     /// 8xy7
     fn subb(&mut self, x: usize, y: usize) {
         let (val, flag) = self.registers[y].overflowing_sub(self.registers[x]);
-        self.registers[0xf] = (!flag) as u8;
         self.registers[x] = val;
+        self.registers[0xf] = (!flag) as u8;
     }
 
     /// 00EE - ret
-    fn ret(&mut self) -> Result<()> {
+    fn ret(&mut self) -> result::Result<(), MachineError> {
         if self.stack_pointer == 0 {
-            return err!("Stack underflow!");
+            return Err(MachineError::StackUnderflow { pc: self.pc - 2 });
         }
-        self.pc = self.stack[self.stack_pointer];
         self.stack_pointer -= 1;
+        self.pc = self.stack[self.stack_pointer];
         Ok(())
     }
 
     /// 2nnn - call
-    fn call(&mut self, nnn: u16) -> Result<()> {
-        if self.stack_pointer + 1 >= STACK_SIZE {
-            return err!("Stack overflow! STACK_SIZE: {STACK_SIZE}");
+    fn call(&mut self, nnn: u16) -> result::Result<(), MachineError> {
+        if self.stack_pointer >= self.stack_depth() {
+            return Err(MachineError::StackOverflow { pc: self.pc - 2 });
         }
-        self.stack_pointer += 1;
         self.stack[self.stack_pointer] = self.pc;
+        self.stack_pointer += 1;
         self.pc = nnn;
         Ok(())
     }
@@ -320,11 +1326,12 @@ impl<T: AudioPlay> Machine<T> {
 #[cfg(test)]
 mod machine_test {
     use super::*;
-    use crate::sdl2_audio::Sdl2Audio;
+    use crate::audio::NullAudio;
+    use crate::font::SCHIP_BIG_FONT;
 
     #[test]
     fn test_call_and_ret() {
-        let mut machine: Machine<Sdl2Audio> = Machine::new().unwrap();
+        let mut machine: Machine<NullAudio> = Machine::new().unwrap();
         machine.registers[0] = 5;
         machine.registers[1] = 10;
 
@@ -352,7 +1359,7 @@ mod machine_test {
 
     #[test]
     fn test_stack_overflow() {
-        let mut machine: Machine<Sdl2Audio> = Machine::new().unwrap();
+        let mut machine: Machine<NullAudio> = Machine::new().unwrap();
         machine.registers[0] = 5;
         machine.registers[1] = 10;
 
@@ -361,15 +1368,28 @@ mod machine_test {
         mem[start] = 0x22;
         mem[start + 1] = 0x00;
 
-        for _ in 0..15 {
+        for _ in 0..16 {
             machine.run_cycle().expect("Stack should not overflow!")
         }
-        machine.run_cycle().expect_err("Testing Stack overflow!");
+        assert_eq!(
+            machine.run_cycle(),
+            Err(MachineError::StackOverflow { pc: 0x200 })
+        );
+
+        machine.reset().unwrap();
+        machine.memory[start..start + 2].copy_from_slice(&[0x22, 0x00]);
+        machine.set_quirks(Quirks::chip8());
+        assert_eq!(machine.stack_depth(), 12);
+        assert_eq!(
+            machine.step(13),
+            Err(MachineError::StackOverflow { pc: 0x200 })
+        );
+        assert_eq!(machine.stack().len(), 12);
     }
 
     #[test]
     fn test_stack_underflow() {
-        let mut machine: Machine<Sdl2Audio> = Machine::new().unwrap();
+        let mut machine: Machine<NullAudio> = Machine::new().unwrap();
         machine.registers[0] = 5;
         machine.registers[1] = 10;
 
@@ -378,6 +1398,838 @@ mod machine_test {
         mem[start] = 0x00;
         mem[start + 1] = 0xEE;
 
-        machine.run_cycle().expect_err("Testing Stack underflow!");
+        assert_eq!(
+            machine.run_cycle(),
+            Err(MachineError::StackUnderflow { pc: 0x200 })
+        );
+    }
+
+    #[test]
+    fn test_illegal_opcode() {
+        let mut machine: Machine<NullAudio> = Machine::new().unwrap();
+
+        let mem = &mut machine.memory;
+        let start = RESERVED_MEMORY_SIZE;
+        // 6000, then 8008 is not an ALU operation
+        mem[start..start + 4].copy_from_slice(&[0x60, 0x00, 0x80, 0x08]);
+
+        machine.run_cycle().unwrap();
+        assert_eq!(
+            machine.run_cycle(),
+            Err(MachineError::IllegalOpcode {
+                pc: 0x202,
+                opcode: 0x8008
+            })
+        );
+    }
+
+    #[test]
+    fn test_illegal_0nnn() {
+        let mut machine: Machine<NullAudio> = Machine::new().unwrap();
+        let start = RESERVED_MEMORY_SIZE;
+        // machine code calls of the COSMAC VIP can not run
+        machine.memory[start..start + 2].copy_from_slice(&[0x01, 0x23]);
+        assert_eq!(
+            machine.run_cycle(),
+            Err(MachineError::IllegalOpcode {
+                pc: 0x200,
+                opcode: 0x0123
+            })
+        );
+    }
+
+    #[test]
+    fn test_memory_out_of_bounds() {
+        let mut machine: Machine<NullAudio> = Machine::new().unwrap();
+
+        let mem = &mut machine.memory;
+        let start = RESERVED_MEMORY_SIZE;
+        // I = 0xFFE, F255 stores 3 bytes, 1FFF jumps to the last byte
+        mem[start..start + 6].copy_from_slice(&[0xAF, 0xFE, 0xF2, 0x55, 0x1F, 0xFF]);
+
+        machine.run_cycle().unwrap();
+        assert_eq!(
+            machine.run_cycle(),
+            Err(MachineError::MemoryOutOfBounds {
+                pc: 0x202,
+                addr: 0x1000
+            })
+        );
+        machine.pc = 0x204;
+        machine.run_cycle().unwrap();
+        assert_eq!(
+            machine.run_cycle(),
+            Err(MachineError::MemoryOutOfBounds {
+                pc: 0xFFF,
+                addr: 0x1000
+            })
+        );
+    }
+
+    #[test]
+    fn test_pc_past_0xffff() {
+        let mut machine: Machine<NullAudio> = Machine::new().unwrap();
+        machine.resize_memory(0x20000);
+        // 3000 skips over the last instruction the 16 bit pc can reach
+        machine.memory[0xFFFC..0xFFFE].copy_from_slice(&[0x30, 0x00]);
+        machine.pc = 0xFFFC;
+        assert_eq!(
+            machine.run_cycle(),
+            Err(MachineError::MemoryOutOfBounds {
+                pc: 0xFFFE,
+                addr: 0x10000
+            })
+        );
+        machine.pc = 0xFFFE;
+        assert_eq!(
+            machine.run_cycle(),
+            Err(MachineError::MemoryOutOfBounds {
+                pc: 0xFFFE,
+                addr: 0x10000
+            })
+        );
+    }
+
+    #[test]
+    fn test_schip_hires_and_exit() {
+        let mut machine: Machine<NullAudio> = Machine::new().unwrap();
+
+        let mem = &mut machine.memory;
+        let start = RESERVED_MEMORY_SIZE;
+        // 00FF hires, 00FE lores, 00FF hires, 00FD exit
+        mem[start..start + 8].copy_from_slice(&[0x00, 0xFF, 0x00, 0xFE, 0x00, 0xFF, 0x00, 0xFD]);
+
+        machine.run_cycle().unwrap();
+        assert_eq!((machine.width(), machine.height()), (128, 64));
+        machine.run_cycle().unwrap();
+        assert_eq!((machine.width(), machine.height()), (64, 32));
+        machine.run_cycle().unwrap();
+        assert!(!machine.is_halt());
+        machine.run_cycle().unwrap();
+        assert!(machine.is_halt());
+    }
+
+    #[test]
+    fn test_hires_chip8() {
+        let mut machine: Machine<NullAudio> = Machine::new().unwrap();
+        // jump to 0x260, the code at 0x2C0 draws at (0, 40), clears with 0230
+        let mut rom = vec![0; 0xD0];
+        rom[..2].copy_from_slice(&[0x12, 0x60]);
+        rom[0xC0..0xCA]
+            .copy_from_slice(&[0x61, 0x28, 0xA3, 0x00, 0xD0, 0x11, 0x02, 0x30, 0x12, 0xC8]);
+        machine
+            .load_rom(&ROM::from_bytes("hires.ch8", rom.clone()))
+            .unwrap();
+        machine.memory[0x300] = 0x80;
+
+        machine.run_cycle().unwrap();
+        assert_eq!(machine.pc, 0x2C0);
+        assert_eq!((machine.width(), machine.height()), (64, 64));
+        for _ in 0..3 {
+            machine.run_cycle().unwrap();
+        }
+        assert!(machine.get_display().pixel(0, 40));
+        machine.run_cycle().unwrap();
+        assert!(!machine.get_display().pixel(0, 40));
+        machine.reset().unwrap();
+        assert_eq!(machine.height(), 32);
+
+        // too short for code at 0x2C0, an ordinary jump
+        rom.truncate(0x80);
+        machine.load_rom(&ROM::from_bytes("jump.ch8", rom)).unwrap();
+        machine.run_cycle().unwrap();
+        assert_eq!(machine.pc, 0x260);
+        assert_eq!(machine.height(), 32);
+    }
+
+    #[test]
+    fn test_megachip() {
+        let mut machine: Machine<NullAudio> = Machine::new().unwrap();
+        // mega on, I = 0x1000, palette of 2, 2x1 sprites, I = 0x1008,
+        // collision index 1, draw twice at (V0, V0), present, mega off
+        let mut rom = vec![
+            0x00, 0x11, 0x01, 0x00, 0x10, 0x00, 0x02, 0x02, 0x03, 0x02, 0x04, 0x01, 0x01, 0x00,
+            0x10, 0x08, 0x09, 0x01, 0xD0, 0x00, 0xD0, 0x00, 0x00, 0xE0, 0x00, 0x10,
+        ];
+        // the data is past the 4KB of CHIP-8
+        rom.resize(0x1000 - RESERVED_MEMORY_SIZE, 0);
+        rom.extend([0xFF, 0xFF, 0, 0, 0xFF, 0, 0, 0xFF, 1, 2]);
+        machine.load_rom(&ROM::from_bytes("mega.mc8", rom)).unwrap();
+        assert_eq!(machine.memory().len(), 0x2000);
+
+        machine.run_cycle().unwrap();
+        assert_eq!((machine.width(), machine.height()), (256, 192));
+        for _ in 0..6 {
+            machine.run_cycle().unwrap();
+        }
+        assert_eq!(machine.i(), 0x1008);
+        machine.run_cycle().unwrap();
+        assert_eq!(machine.registers[0xf], 0);
+        machine.run_cycle().unwrap();
+        assert_eq!(machine.registers[0xf], 1);
+        // drawn to the back buffer until 00E0
+        assert!(!machine.get_display().pixel(0, 0));
+        machine.run_cycle().unwrap();
+        let display = machine.get_display();
+        assert!(display.pixel(0, 0) && display.pixel(1, 0) && !display.pixel(2, 0));
+        let mut rgba = vec![0; 256 * 192 * 4];
+        display.render_rgba(&mut rgba, &Default::default());
+        assert_eq!(rgba[..8], [0xFF, 0, 0, 0xFF, 0, 0, 0xFF, 0xFF]);
+
+        let state = machine.save_state();
+        machine.run_cycle().unwrap();
+        assert_eq!((machine.width(), machine.height()), (64, 32));
+        machine.load_state(&state).unwrap();
+        assert!(machine.get_display().pixel(1, 0));
+        machine.run_cycle().unwrap();
+        assert_eq!(machine.height(), 32);
+    }
+
+    #[test]
+    fn test_megachip_illegal_0nnn() {
+        let mut machine: Machine<NullAudio> = Machine::new().unwrap();
+        let start = RESERVED_MEMORY_SIZE;
+        // mega on, then 0A00 is no MegaChip opcode
+        machine.memory[start..start + 4].copy_from_slice(&[0x00, 0x11, 0x0A, 0x00]);
+        machine.run_cycle().unwrap();
+        assert_eq!(
+            machine.run_cycle(),
+            Err(MachineError::IllegalOpcode {
+                pc: 0x202,
+                opcode: 0x0A00
+            })
+        );
+    }
+
+    #[test]
+    fn test_schip_large_sprite_and_scroll() {
+        let mut machine: Machine<NullAudio> = Machine::new().unwrap();
+
+        let mem = &mut machine.memory;
+        let start = RESERVED_MEMORY_SIZE;
+        // hires, I = 0x300, draw 16x16 at (V0, V0), scroll down 2, scroll left 4
+        mem[start..start + 10]
+            .copy_from_slice(&[0x00, 0xFF, 0xA3, 0x00, 0xD0, 0x00, 0x00, 0xC2, 0x00, 0xFC]);
+        mem[0x300..0x320].fill(0xFF);
+
+        for _ in 0..3 {
+            machine.run_cycle().unwrap();
+        }
+        let display = machine.get_display();
+        assert!(display.pixel(15, 15));
+        assert!(!display.pixel(16, 0));
+        assert_eq!(machine.registers[0xf], 0);
+
+        machine.run_cycle().unwrap();
+        let display = machine.get_display();
+        assert!(!display.pixel(0, 0));
+        assert!(display.pixel(0, 17));
+        assert!(!display.pixel(0, 18));
+
+        machine.run_cycle().unwrap();
+        let display = machine.get_display();
+        assert!(display.pixel(11, 2));
+        assert!(!display.pixel(12, 2));
+    }
+
+    #[test]
+    fn test_half_scroll() {
+        // lores, I = 0x300, draw 8x1 at (0, 0), scroll right 4, scroll down 2
+        let program = [0x00, 0xFE, 0xA3, 0x00, 0xD0, 0x01, 0x00, 0xFB, 0x00, 0xC2];
+        let run = |quirks: Quirks| {
+            let mut machine: Machine<NullAudio> = Machine::new().unwrap();
+            machine.set_quirks(quirks);
+            let start = RESERVED_MEMORY_SIZE;
+            machine.memory[start..start + program.len()].copy_from_slice(&program);
+            machine.memory[0x300] = 0x80;
+            machine.step(5).unwrap();
+            machine.get_display().clone()
+        };
+        assert!(run(Quirks::default()).pixel(4, 2));
+        let display = run(Quirks {
+            half_scroll: true,
+            ..Quirks::default()
+        });
+        assert!(display.pixel(2, 1));
+        assert_eq!(display.rows().flatten().filter(|&on| on).count(), 1);
+    }
+
+    #[test]
+    fn test_schip_rpl_flags() {
+        let mut machine: Machine<NullAudio> = Machine::new().unwrap();
+        machine.registers[..4].copy_from_slice(&[1, 2, 3, 4]);
+
+        let mem = &mut machine.memory;
+        let start = RESERVED_MEMORY_SIZE;
+        // F375 save V0..V3, then F385 restore them
+        mem[start..start + 4].copy_from_slice(&[0xF3, 0x75, 0xF3, 0x85]);
+
+        machine.run_cycle().unwrap();
+        machine.registers[..4].fill(0);
+        machine.run_cycle().unwrap();
+        assert_eq!(machine.registers[..4], [1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn test_schip_big_font() {
+        let mut machine: Machine<NullAudio> = Machine::new().unwrap();
+        machine.load_font().unwrap();
+        let mem = &mut machine.memory;
+        let start = RESERVED_MEMORY_SIZE;
+        // V0 = 8, I = big 8, draw it 10 rows high at (8, 8)
+        mem[start..start + 6].copy_from_slice(&[0x60, 0x08, 0xF0, 0x30, 0xD0, 0x0A]);
+        for _ in 0..3 {
+            machine.run_cycle().unwrap();
+        }
+        assert_eq!(machine.i as usize, BIG_FONT_ADDR + 80);
+        let pixels = |y| -> u8 {
+            (0..8).fold(0, |row, x| {
+                row << 1 | machine.get_display().pixel(8 + x, y) as u8
+            })
+        };
+        assert_eq!(
+            (8..18).map(pixels).collect::<Vec<_>>(),
+            SCHIP_BIG_FONT[80..90]
+        );
+    }
+
+    #[test]
+    fn test_xochip_audio() {
+        let mut machine: Machine<NullAudio> = Machine::new().unwrap();
+        let mem = &mut machine.memory;
+        let start = RESERVED_MEMORY_SIZE;
+        // I = 0x300, load the pattern, V1 = 0x70 and set the pitch
+        mem[start..start + 8].copy_from_slice(&[0xA3, 0x00, 0xF0, 0x02, 0x61, 0x70, 0xF1, 0x3A]);
+        mem[0x300..0x310].copy_from_slice(&[0xAA; PATTERN_BYTES]);
+        for _ in 0..4 {
+            machine.run_cycle().unwrap();
+        }
+        assert_eq!(machine.audio_pattern, Some([0xAA; PATTERN_BYTES]));
+        assert_eq!(machine.pitch, 0x70);
+
+        let state = machine.save_state();
+        machine.reset().unwrap();
+        assert_eq!(
+            (machine.audio_pattern, machine.pitch),
+            (None, DEFAULT_PITCH)
+        );
+        machine.load_state(&state).unwrap();
+        assert_eq!(machine.pitch, 0x70);
+        assert!(machine.audio_pattern.is_some());
+    }
+
+    #[test]
+    fn test_flag_as_vx() {
+        // VF += V1, VF := 1, VF -= V1, VF := 5, VF =- V1
+        let program = [0x8F, 0x14, 0x6F, 0x01, 0x8F, 0x15, 0x6F, 0x05, 0x8F, 0x17];
+        let start = RESERVED_MEMORY_SIZE;
+        let mut machine: Machine<NullAudio> = Machine::new().unwrap();
+        machine.memory[start..start + program.len()].copy_from_slice(&program);
+        machine.registers[1] = 0x02;
+        machine.registers[0xF] = 0xFF;
+        // the flag overwrites the result
+        let mut flags = vec![];
+        for cycles in [1, 2, 2] {
+            machine.step(cycles).unwrap();
+            flags.push(machine.registers[0xF]);
+        }
+        assert_eq!(flags, [1, 0, 0]);
+    }
+
+    #[test]
+    fn test_quirks() {
+        let program = [0x81, 0x26, 0xF1, 0x55, 0xB2, 0x00];
+        let start = RESERVED_MEMORY_SIZE;
+
+        let mut machine: Machine<NullAudio> = Machine::new().unwrap();
+        machine.memory[start..start + 6].copy_from_slice(&program);
+        machine.registers[..3].copy_from_slice(&[0x10, 0x03, 0x08]);
+        machine.i = 0x300;
+        for _ in 0..3 {
+            machine.run_cycle().unwrap();
+        }
+        assert_eq!(machine.registers[1], 0x01);
+        assert_eq!(machine.i, 0x300);
+        assert_eq!(machine.pc, 0x210);
+
+        let mut machine: Machine<NullAudio> = Machine::new().unwrap();
+        machine.set_quirks(Quirks::chip8());
+        machine.memory[start..start + 6].copy_from_slice(&program);
+        machine.registers[..3].copy_from_slice(&[0x10, 0x03, 0x08]);
+        machine.i = 0x300;
+        for _ in 0..3 {
+            machine.run_cycle().unwrap();
+        }
+        assert_eq!(machine.registers[1], 0x04);
+        assert_eq!(machine.i, 0x302);
+        assert_eq!(machine.pc, 0x210);
+
+        let mut machine: Machine<NullAudio> = Machine::new().unwrap();
+        machine.set_quirks(Quirks::schip());
+        machine.memory[start..start + 6].copy_from_slice(&program);
+        machine.registers[..3].copy_from_slice(&[0x10, 0x03, 0x08]);
+        for _ in 0..3 {
+            machine.run_cycle().unwrap();
+        }
+        assert_eq!(machine.pc, 0x208);
+    }
+
+    #[test]
+    fn test_display_wait() {
+        let mut machine: Machine<NullAudio> = Machine::new().unwrap();
+        machine.set_quirks(Quirks::chip8());
+        machine.load_font().unwrap();
+        machine.i = 0x50;
+
+        let mem = &mut machine.memory;
+        let start = RESERVED_MEMORY_SIZE;
+        // draw the 0 glyph twice
+        mem[start..start + 4].copy_from_slice(&[0xD0, 0x05, 0xD0, 0x05]);
+
+        for _ in 0..3 {
+            machine.run_cycle().unwrap();
+        }
+        assert_eq!(machine.pc, 0x200);
+        machine.tick_60hz();
+        for _ in 0..3 {
+            machine.run_cycle().unwrap();
+        }
+        assert_eq!(machine.pc, 0x202);
+        assert!(machine.get_display().pixel(0, 0));
+        machine.tick_60hz();
+        machine.run_cycle().unwrap();
+        assert_eq!(machine.pc, 0x204);
+        assert!(!machine.get_display().pixel(0, 0));
+    }
+
+    #[test]
+    fn test_tick_60hz() {
+        use std::cell::RefCell;
+        use std::rc::Rc;
+
+        /// records every resume and pause
+        struct Switches(Rc<RefCell<Vec<bool>>>);
+
+        impl AudioPlay for Switches {
+            fn resume(&self) {
+                self.0.borrow_mut().push(true);
+            }
+
+            fn pause(&self) {
+                self.0.borrow_mut().push(false);
+            }
+        }
+
+        let switches = Rc::new(RefCell::new(vec![]));
+        let mut machine = Machine::new().unwrap();
+        machine.init_sound(Switches(switches.clone()));
+        assert!(machine.tick_60hz());
+        machine.take_dirty_rows();
+        assert!(!machine.tick_60hz());
+
+        // the beep only starts and stops once
+        machine.sound_timer = 3;
+        machine.delay_timer = 2;
+        for _ in 0..6 {
+            machine.tick_60hz();
+        }
+        assert_eq!(machine.sound_timer, 0);
+        assert_eq!(machine.delay_timer, 0);
+        assert_eq!(*switches.borrow(), vec![true, false]);
+
+        // a pause while beeping starts it again on the next tick
+        machine.sound_timer = 3;
+        machine.tick_60hz();
+        machine.stop_sound();
+        machine.tick_60hz();
+        assert_eq!(*switches.borrow(), vec![true, false, true, false, true]);
+
+        // CLS
+        machine.memory[RESERVED_MEMORY_SIZE..RESERVED_MEMORY_SIZE + 2]
+            .copy_from_slice(&[0x00, 0xE0]);
+        machine.run_cycle().unwrap();
+        assert!(machine.tick_60hz());
+    }
+
+    #[test]
+    fn test_latch_display() {
+        let mut machine: Machine<NullAudio> = Machine::new().unwrap();
+        machine.quirks.display_wait = false;
+        machine.latch_display(true);
+        // I = a block, draw it, erase it and draw it again
+        let start = RESERVED_MEMORY_SIZE;
+        machine.memory[start..start + 8]
+            .copy_from_slice(&[0xA3, 0x00, 0xD0, 0x05, 0xD0, 0x05, 0xD0, 0x05]);
+        machine.memory[0x300..0x305].fill(0xFF);
+        machine.tick_60hz();
+        machine.take_dirty_rows();
+        machine.run_cycle().unwrap();
+        machine.run_cycle().unwrap();
+        assert!(!machine.get_display().pixel(0, 0));
+        assert_eq!(machine.take_dirty_rows(), 0);
+        assert!(machine.tick_60hz());
+        assert!(machine.get_display().pixel(0, 0));
+        assert_eq!(machine.take_dirty_rows(), 0b11111);
+
+        // the erased frame in between is never shown
+        machine.run_cycle().unwrap();
+        assert!(machine.get_display().pixel(0, 0));
+        machine.run_cycle().unwrap();
+        machine.tick_60hz();
+        assert!(machine.get_display().pixel(0, 0));
+
+        machine.reset().unwrap();
+        assert!(!machine.get_display().pixel(0, 0));
+    }
+
+    #[test]
+    fn test_take_collisions() {
+        let mut machine: Machine<NullAudio> = Machine::new().unwrap();
+        machine.quirks.display_wait = false;
+        // I = a line, draw it at (0, 0), then at (4, 0)
+        let start = RESERVED_MEMORY_SIZE;
+        machine.memory[start..start + 10]
+            .copy_from_slice(&[0xA3, 0x00, 0x61, 0x04, 0xD0, 0x01, 0xD1, 0x01, 0xD1, 0x01]);
+        machine.memory[0x300] = 0xFF;
+        machine.step(3).unwrap();
+        assert!(machine.take_collisions().is_empty());
+        machine.step(1).unwrap();
+        assert_eq!(machine.registers[0xf], 1);
+        let collisions = machine.take_collisions();
+        assert_eq!(
+            collisions.pixels().collect::<Vec<_>>(),
+            vec![(4, 0), (5, 0), (6, 0), (7, 0)]
+        );
+        assert!(machine.take_collisions().is_empty());
+        // drawn again at (4, 0) only 8..12 are still lit
+        machine.step(1).unwrap();
+        let collisions = machine.take_collisions();
+        assert_eq!(
+            collisions.pixels().map(|(x, _)| x).collect::<Vec<_>>(),
+            vec![8, 9, 10, 11]
+        );
+    }
+
+    #[test]
+    fn test_key_checks() {
+        let mut machine: Machine<NullAudio> = Machine::new().unwrap();
+        // V0 = 5, skip if key 5 is down, V1 = 1, V0 = 0xC, skip if key C is up,
+        // V1 = 2, wait for a key
+        let program = [
+            0x60, 0x05, 0xE0, 0x9E, 0x61, 0x01, 0x60, 0x0C, 0xE0, 0xA1, 0x61, 0x02, 0xF2, 0x0A,
+        ];
+        let start = RESERVED_MEMORY_SIZE;
+        machine.memory[start..start + program.len()].copy_from_slice(&program);
+        machine.key_down(5);
+        assert!(machine.is_key_down(5));
+        machine.step(2).unwrap();
+        assert_eq!(machine.pc, 0x206);
+        assert_eq!(machine.take_checked_keys(), 1 << 5);
+        machine.step(2).unwrap();
+        assert_eq!(machine.pc, 0x20C);
+        assert_eq!(machine.take_checked_keys(), 1 << 0xC);
+        assert_eq!(machine.take_checked_keys(), 0);
+        assert!(!machine.take_key_wait());
+        machine.step(1).unwrap();
+        assert!(machine.take_key_wait());
+    }
+
+    #[test]
+    fn test_key_check_high_vx() {
+        let mut machine: Machine<NullAudio> = Machine::new().unwrap();
+        // V0 = 0x25, skip if key 5 is down, then skip if it is up
+        let program = [0x60, 0x25, 0xE0, 0x9E, 0x00, 0x00, 0xE0, 0xA1];
+        let start = RESERVED_MEMORY_SIZE;
+        machine.memory[start..start + program.len()].copy_from_slice(&program);
+        machine.key_down(5);
+        machine.step(2).unwrap();
+        assert_eq!(machine.pc, 0x206);
+        assert_eq!(machine.take_checked_keys(), 1 << 5);
+        machine.step(1).unwrap();
+        assert_eq!(machine.pc, 0x208);
+    }
+
+    #[test]
+    fn test_timer_clock() {
+        let mut machine: Machine<NullAudio> = Machine::new().unwrap();
+        machine.use_timer_clock();
+        machine.delay_timer = 10;
+        // extra frontend ticks do not speed the timers up
+        machine.tick_60hz();
+        machine.tick_60hz();
+        assert_eq!(machine.delay_timer, 10);
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        // a stalled frontend is caught up by the next instruction
+        machine.memory[RESERVED_MEMORY_SIZE..RESERVED_MEMORY_SIZE + 2]
+            .copy_from_slice(&[0x60, 0x00]);
+        machine.run_cycle().unwrap();
+        assert_eq!(machine.delay_timer, 7);
+        machine.tick_60hz();
+        assert_eq!(machine.delay_timer, 7);
+    }
+
+    #[test]
+    fn test_dirty_rows() {
+        let mut machine: Machine<NullAudio> = Machine::new().unwrap();
+        machine.load_font().unwrap();
+        assert_eq!(machine.take_dirty_rows(), u64::MAX);
+        assert_eq!(machine.take_dirty_rows(), 0);
+
+        let mem = &mut machine.memory;
+        let start = RESERVED_MEMORY_SIZE;
+        // V1 = 3, draw the 0 glyph at (0, 3), then clear the screen
+        mem[start..start + 6].copy_from_slice(&[0x61, 0x03, 0xD0, 0x15, 0x00, 0xE0]);
+        machine.i = 0x50;
+        machine.run_cycle().unwrap();
+        assert_eq!(machine.take_dirty_rows(), 0);
+        machine.run_cycle().unwrap();
+        assert_eq!(machine.take_dirty_rows(), 0b11111 << 3);
+        machine.run_cycle().unwrap();
+        assert_eq!(machine.take_dirty_rows(), u64::MAX);
+
+        let state = machine.save_state();
+        machine.load_state(&state).unwrap();
+        assert_eq!(machine.take_dirty_rows(), u64::MAX);
+    }
+
+    #[test]
+    fn test_cheats() {
+        let mut machine: Machine<NullAudio> = Machine::new().unwrap();
+        // I = 0x300, V0 = 0, [I] = V0, loop
+        let rom = vec![0xA3, 0x00, 0x60, 0x00, 0xF0, 0x55, 0x12, 0x06];
+        let mut cheats = Cheats::default();
+        cheats.add("202:6009".parse().unwrap());
+        cheats.add("300=07".parse().unwrap());
+        machine.set_cheats(cheats);
+        machine.load_rom(&ROM::from_bytes("cheats", rom)).unwrap();
+        machine.step(3).unwrap();
+        assert_eq!(machine.memory[0x300], 9);
+        machine.tick_60hz();
+        assert_eq!(machine.memory[0x300], 7);
+        machine.reset().unwrap();
+        assert_eq!(machine.memory[0x202..0x204], [0x60, 0x09]);
+    }
+
+    #[test]
+    fn test_seeded_random() {
+        let run = |seed: u64| {
+            let mut machine: Machine<NullAudio> = Machine::new().unwrap();
+            machine.set_seed(seed);
+            // V0..V3 = random
+            let start = RESERVED_MEMORY_SIZE;
+            for n in 0..4 {
+                machine.memory[start + n * 2] = 0xC0 | n as u8;
+                machine.memory[start + n * 2 + 1] = 0xFF;
+            }
+            for _ in 0..4 {
+                machine.run_cycle().unwrap();
+            }
+            machine.registers
+        };
+        assert_eq!(run(1234), run(1234));
+        assert_ne!(run(1234), run(4321));
+    }
+
+    #[test]
+    fn test_movie() {
+        let rom = ROM::from_bytes("rom", vec![0xF0, 0x0A, 0xC1, 0xFF, 0xF2, 0x07, 0x12, 0x04]);
+        let mut machine: Machine<NullAudio> = Machine::new().unwrap();
+        machine.load_rom(&rom).unwrap();
+        machine.record_movie().unwrap();
+        // LD V0, K waits for the key, then V1 = random and V2 = DT
+        for n in 0..40 {
+            match n {
+                10 => machine.key_down(5),
+                15 => machine.key_up(5),
+                20 => machine.delay_timer = 9,
+                25 => {
+                    machine.tick_60hz();
+                }
+                _ => {}
+            }
+            machine.run_cycle().unwrap();
+        }
+        let movie = machine.take_movie().unwrap();
+        assert_eq!(movie.events.len(), 3);
+        assert_eq!(
+            movie.events[0],
+            MovieEvent {
+                cycle: 10,
+                input: Input::KeyDown { key: 5 }
+            }
+        );
+
+        let mut replay: Machine<NullAudio> = Machine::new().unwrap();
+        replay.load_rom(&rom).unwrap();
+        replay.play_movie(movie).unwrap();
+        assert!(replay.is_playing_movie());
+        for n in 0..40 {
+            if n == 20 {
+                replay.delay_timer = 9;
+            }
+            // ignored during playback
+            replay.key_down(0xA);
+            replay.run_cycle().unwrap();
+        }
+        assert!(!replay.is_playing_movie());
+        assert_eq!(replay.registers[..3], machine.registers[..3]);
+        assert_eq!(replay.registers[0], 5);
+        assert_eq!(replay.registers[2], 8);
+    }
+
+    #[test]
+    fn test_save_and_load_state() {
+        let mut machine: Machine<NullAudio> = Machine::new().unwrap();
+        machine.load_font().unwrap();
+
+        let mem = &mut machine.memory;
+        let start = RESERVED_MEMORY_SIZE;
+        // V0 = 0x0A, I = sprite of V0, draw it, then jump back to the draw
+        mem[start..start + 8].copy_from_slice(&[0x60, 0x0A, 0xF0, 0x29, 0xD1, 0x15, 0x12, 0x04]);
+        for _ in 0..3 {
+            machine.run_cycle().unwrap();
+        }
+        let state = machine.save_state();
+
+        // drawing again erases the sprite
+        for _ in 0..2 {
+            machine.run_cycle().unwrap();
+        }
+        assert_eq!(machine.registers[0xf], 1);
+
+        machine.load_state(&state).unwrap();
+        assert_eq!(machine.pc, 0x206);
+        assert_eq!(machine.registers[0], 0x0A);
+        assert_eq!(machine.registers[0xf], 0);
+        assert_eq!(machine.get_display(), &state.video);
+
+        // states saved before kept the calls in the slots 1 to the pointer
+        let mut old = state.clone();
+        old.stack = vec![0, 0x202, 0x304];
+        old.stack.resize(STACK_SIZE, 0);
+        old.stack_pointer = Some(2);
+        machine.load_state(&old).unwrap();
+        assert_eq!(machine.stack(), [0x202, 0x304]);
+        old.stack_pointer = Some(STACK_SIZE);
+        assert!(machine.load_state(&old).is_err());
+    }
+
+    #[test]
+    fn test_wait_key_release() {
+        let mut machine: Machine<NullAudio> = Machine::new().unwrap();
+
+        let mem = &mut machine.memory;
+        let start = RESERVED_MEMORY_SIZE;
+        mem[start] = 0xF3;
+        mem[start + 1] = 0x0A;
+
+        machine.run_cycle().unwrap();
+        assert_eq!(machine.pc as usize, start);
+
+        machine.key_down(0xB);
+        machine.run_cycle().unwrap();
+        machine.run_cycle().unwrap();
+        assert_eq!(machine.pc as usize, start);
+
+        machine.key_up(0xB);
+        machine.run_cycle().unwrap();
+        assert_eq!(machine.pc as usize, start + 2);
+        assert_eq!(machine.registers[3], 0xB);
+    }
+
+    #[test]
+    fn test_reset() {
+        let mut machine: Machine<NullAudio> = Machine::new().unwrap();
+        machine.load_font().unwrap();
+        let rom = ROM::from_bytes("self modifying", vec![0x60, 0xFF, 0xA2, 0x00, 0xF0, 0x55]);
+        machine.load_rom(&rom).unwrap();
+        let fresh = machine.save_state();
+
+        for _ in 0..3 {
+            machine.run_cycle().unwrap();
+        }
+        assert_eq!(machine.memory[0x200], 0xFF);
+
+        machine.reset().unwrap();
+        let reset = machine.save_state();
+        assert_eq!(reset.memory, fresh.memory);
+        assert_eq!(reset.registers, fresh.registers);
+        assert_eq!(reset.pc, fresh.pc);
+        assert_eq!(reset.i, fresh.i);
+    }
+
+    #[test]
+    fn test_step_and_hooks() {
+        use std::cell::RefCell;
+        use std::rc::Rc;
+
+        let mut machine: Machine<NullAudio> = Machine::new().unwrap();
+        // V0 = 2, sound timer = V0, CLS, I = digit 0, DRW V0 V0 1, jump to itself
+        let rom = vec![
+            0x60, 0x02, 0xF0, 0x18, 0x00, 0xE0, 0xA0, 0x50, 0xD0, 0x01, 0x12, 0x0A,
+        ];
+        machine.load_font().unwrap();
+        machine.load_rom(&ROM::from_bytes("hooks", rom)).unwrap();
+        let events = Rc::new(RefCell::new(vec![]));
+        let log = Rc::clone(&events);
+        machine.on_instruction(move |pc, opcode| {
+            log.borrow_mut().push(format!("{:03X} {:04X}", pc, opcode))
+        });
+        let log = Rc::clone(&events);
+        machine.on_draw(move |video| log.borrow_mut().push(format!("draw {}", video.pixel(2, 2))));
+        let log = Rc::clone(&events);
+        machine.on_sound(move |on| log.borrow_mut().push(format!("sound {}", on)));
+
+        assert_eq!(machine.step(6).unwrap(), 6);
+        for _ in 0..3 {
+            machine.tick_60hz();
+        }
+        assert_eq!(
+            *events.borrow(),
+            [
+                "200 6002",
+                "202 F018",
+                "204 00E0",
+                "draw false",
+                "206 A050",
+                "208 D001",
+                "draw true",
+                "20A 120A",
+                "sound true",
+                "sound false",
+            ]
+        );
+
+        machine.clear_hooks();
+        machine.step(1).unwrap();
+        assert_eq!(events.borrow().len(), 10);
+        machine.halted = true;
+        assert_eq!(machine.step(10).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_self_modifying_code() {
+        // V2 += 1, then FX55 rewrites it to V2 += 5 and jumps back, the
+        // second pass has to run the new instruction
+        let rom = vec![
+            0x72, 0x01, 0xA2, 0x00, 0x60, 0x72, 0x61, 0x05, 0xF1, 0x55, 0x32, 0x06, 0x12, 0x00,
+            0x12, 0x0E,
+        ];
+        for cached in [false, true] {
+            let mut machine: Machine<NullAudio> = Machine::new().unwrap();
+            if cached {
+                machine.enable_decode_cache();
+            }
+            machine
+                .load_rom(&ROM::from_bytes("smc", rom.clone()))
+                .unwrap();
+            machine.step(13).unwrap();
+            assert_eq!(machine.pc, 0x20E);
+            assert_eq!(machine.registers[2], 6);
+
+            machine.reset().unwrap();
+            machine.step(13).unwrap();
+            assert_eq!(machine.registers[2], 6);
+        }
     }
 }
@@ -1,227 +1,1396 @@
-pub mod audio;
-pub mod font;
-pub mod instruction;
-pub mod keyboard;
-pub mod machine;
-pub mod rom;
-pub mod sdl2_audio;
-pub mod video;
-
-#[macro_use]
-extern crate log;
 extern crate clap;
-extern crate sdl2;
-
-use chrono::{DateTime, Utc};
-use crossbeam_channel::{select, unbounded, Sender};
-use sdl2::keyboard::{Keycode, Scancode};
-use sdl2::render::Canvas;
-use sdl2::video::Window;
-use sdl2::{event::Event, EventPump};
-use sdl2_audio::Sdl2Audio;
-use std::collections::HashMap;
-use std::error::Error;
-use std::result;
-use std::thread;
-use std::time::Duration;
-
-use clap::{App, Arg};
-
-use machine::Machine;
-use rom::ROM;
-
-#[macro_export]
-macro_rules! err {
-    ($($tt:tt)*) => { Err(Box::<dyn Error>::from(format!($($tt)*))) };
-}
-
-pub type Result<T> = result::Result<T, Box<dyn Error>>;
-
-pub struct Sdl2KeyMap {
-    scancodes_map: HashMap<Scancode, u8>,
-}
-
-impl Sdl2KeyMap {
-    pub fn new(layout: &HashMap<Scancode, u8>) -> Result<Self> {
-        let scancodes_map = layout.clone();
-        if layout.len() != 16 {
-            return err!("layout will not be matched, the layout length is not 16");
-        }
-        Ok(Sdl2KeyMap { scancodes_map })
-    }
-
-    pub fn scancode_to_key(&self, scancode: &Scancode) -> Option<u8> {
-        self.scancodes_map.get(scancode).copied()
-    }
-
-    fn default_keyboard_layout() -> HashMap<Scancode, u8> {
-        let mut default_layout: HashMap<Scancode, u8> = HashMap::with_capacity(16);
-        default_layout.insert(Scancode::X, 0);
-        default_layout.insert(Scancode::Num1, 1);
-        default_layout.insert(Scancode::Num2, 2);
-        default_layout.insert(Scancode::Num3, 3);
-        default_layout.insert(Scancode::Q, 4);
-        default_layout.insert(Scancode::W, 5);
-        default_layout.insert(Scancode::E, 6);
-        default_layout.insert(Scancode::A, 7);
-        default_layout.insert(Scancode::S, 8);
-        default_layout.insert(Scancode::D, 9);
-        default_layout.insert(Scancode::Z, 0xA);
-        default_layout.insert(Scancode::C, 0xB);
-        default_layout.insert(Scancode::Num4, 0xC);
-        default_layout.insert(Scancode::R, 0xD);
-        default_layout.insert(Scancode::F, 0xE);
-        default_layout.insert(Scancode::V, 0xF);
-        default_layout
-    }
-}
-
-impl Default for Sdl2KeyMap {
-    fn default() -> Self {
-        Self::new(&Self::default_keyboard_layout()).unwrap()
-    }
-}
-
-fn sdl2_key_event(
-    machine: &mut Machine<Sdl2Audio>,
-    running: &mut bool,
-    event_pump: &mut EventPump,
-    key_map: &Sdl2KeyMap,
-) {
-    for event in event_pump.poll_iter() {
-        match event {
-            Event::Quit { .. }
-            | Event::KeyDown {
-                keycode: Some(Keycode::Escape),
-                ..
-            } => {
-                *running = false;
-            }
-            Event::KeyDown {
-                scancode: Some(scancode),
-                ..
-            } => {
-                if let Some(key) = key_map.scancode_to_key(&scancode) {
-                    machine.key_down(key);
-                    debug!("KeyDown: {:?} -> {}", scancode, key);
-                }
-            }
-            Event::KeyUp {
-                scancode: Some(scancode),
-                ..
-            } => {
-                if let Some(key) = key_map.scancode_to_key(&scancode) {
-                    machine.key_up(key);
-                    debug!("KeyUp: {:?} -> {}", scancode, key);
-                }
-            }
-            _ => {}
-        }
-    }
-}
 
-fn sdl2_draw(canvas: &mut Canvas<Window>, machine: &Machine<Sdl2Audio>) -> Result<()> {
-    let grid = machine.get_display();
-    for (x, row) in grid.iter().enumerate() {
-        for (y, &item) in row.iter().enumerate() {
-            if item != 0 {
-                canvas.set_draw_color(sdl2::pixels::Color::RGBA(255, 255, 255, 255));
-            } else {
-                canvas.set_draw_color(sdl2::pixels::Color::RGBA(0, 0, 0, 255));
-            }
-            canvas.draw_point((x as i32, y as i32))?;
-        }
+use std::fs;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+
+use clap::{App, Arg, ArgMatches, SubCommand};
+
+#[cfg(any(feature = "sdl2-frontend", feature = "pixels-frontend"))]
+use yet_another_rchip8::audio::AudioConfig;
+use yet_another_rchip8::audio::{AudioPlay, NullAudio, Tone};
+use yet_another_rchip8::bench::{self, BenchOptions};
+use yet_another_rchip8::cheats::Cheats;
+use yet_another_rchip8::config::Config;
+use yet_another_rchip8::crashdump::{self, CRASH_DIR};
+#[cfg(any(
+    feature = "sdl2-frontend",
+    feature = "pixels-frontend",
+    feature = "tui-frontend"
+))]
+use yet_another_rchip8::display::DisplayConfig;
+use yet_another_rchip8::error::MachineError;
+use yet_another_rchip8::font::Font;
+use yet_another_rchip8::hooks::FrameHook;
+use yet_another_rchip8::logging;
+use yet_another_rchip8::movie::Movie;
+use yet_another_rchip8::romdb::RomDb;
+#[cfg(any(feature = "pixels-frontend", feature = "tui-frontend"))]
+use yet_another_rchip8::rpl::{self, FlagFile};
+#[cfg(any(
+    feature = "sdl2-frontend",
+    feature = "pixels-frontend",
+    feature = "tui-frontend"
+))]
+use yet_another_rchip8::scores::{self, ScoreFile};
+use yet_another_rchip8::symbols::Symbols;
+use yet_another_rchip8::testsuite::{self, Expectations, Verdict};
+use yet_another_rchip8::timing::Timing;
+use yet_another_rchip8::trace::Tracer;
+#[cfg(any(
+    feature = "sdl2-frontend",
+    feature = "pixels-frontend",
+    feature = "tui-frontend"
+))]
+use yet_another_rchip8::watch::RomWatcher;
+use yet_another_rchip8::{assembler, compare, detect, disassembler, headless, octo, remote};
+use yet_another_rchip8::{err, Machine, Quirks, Result, DEFAULT_CLOCK_FREQ, ROM, UNLIMITED};
+
+fn new_machine<T: AudioPlay>(
+    rom: Option<&ROM>,
+    matches: &ArgMatches,
+    config: &Config,
+) -> Result<Machine<T>> {
+    let mut machine = Machine::new()?;
+    let mut quirks = match matches.value_of("COMPAT").or(config.compat.as_deref()) {
+        Some(profile) => profile.parse()?,
+        None => Quirks::default(),
+    };
+    for setting in config.quirk_settings() {
+        quirks.set(&setting)?;
     }
-    canvas.present();
-    Ok(())
+    if matches.is_present("DISPLAY_WAIT") {
+        quirks.display_wait = true;
+    }
+    if matches.is_present("WRAP_SPRITES") {
+        quirks.wrap_sprites = true;
+    }
+    for setting in matches.values_of("QUIRK").into_iter().flatten() {
+        quirks.set(setting)?;
+    }
+    machine.set_quirks(quirks);
+    if let Some(seed) = matches.value_of("SEED") {
+        machine.set_seed(seed.parse()?);
+    }
+    log::info!("random seed: {}", machine.seed());
+    if matches.is_present("LATCH_DISPLAY") {
+        machine.latch_display(true);
+    }
+    if matches.is_present("PROFILE") {
+        machine.enable_profiler();
+    }
+    if matches.is_present("COVERAGE") {
+        machine.track_coverage();
+    }
+    if matches.is_present("FAST") {
+        machine.enable_decode_cache();
+    }
+    // the trace of a crash dump, it costs every instruction a record
+    if crash_dir(matches).is_some() {
+        machine.keep_history(crashdump::HISTORY_LEN);
+    }
+    if matches.is_present("WARN_SMC") || matches.is_present("DEBUG") {
+        machine.track_self_modifying(matches.is_present("WARN_SMC"));
+    }
+    let mut font = Font::default();
+    for font_file in matches.values_of("FONT").into_iter().flatten() {
+        font.apply(font_file)?;
+    }
+    machine.set_font(font);
+    machine.load_font()?;
+    let mut cheats = match matches.value_of("CHEATS") {
+        Some(path) => Cheats::load(path)?,
+        None => Cheats::default(),
+    };
+    for code in matches.values_of("CHEAT").into_iter().flatten() {
+        cheats.add(code.parse()?);
+    }
+    machine.set_cheats(cheats);
+    if let Some(rom) = rom {
+        machine.load_rom(rom)?;
+    }
+    if let Some(path) = matches.value_of("PLAYBACK") {
+        machine.play_movie(Movie::load(path)?)?;
+    } else if matches.is_present("RECORD_MOVIE") {
+        machine.record_movie()?;
+    }
+    Ok(machine)
 }
 
-fn sdl2_init(width: u32, height: u32) -> Result<(Canvas<Window>, Sdl2Audio, EventPump)> {
-    let sdl_context = sdl2::init()?;
-
-    let video = sdl_context.video()?;
-    let window = video
-        .window("yet-another-rchip8", 640, 320)
-        .position_centered()
-        .resizable()
-        .build()?;
-    let mut canvas = window.into_canvas().accelerated().build()?;
-    canvas.set_logical_size(width, height)?;
-
-    let audio = Sdl2Audio::new(sdl_context.audio()?)?;
-    Ok((canvas, audio, sdl_context.event_pump()?))
-}
-
-fn sdl2_emulate(machine: &mut Machine<Sdl2Audio>) -> Result<()> {
-    let (timer_tx, timer_rx) = unbounded();
-    let (clock_tx, clock_rx) = unbounded();
-
-    // timer 60Hz ~= 16667 micros
-    // clock 500Hz ~= 2000 micros
-    sender(timer_tx, clock_tx, 60, 500);
-
-    let (width, height) = (machine.width(), machine.height());
-    let (mut canvas, audio, mut event_pump) = sdl2_init(width as u32, height as u32)?;
-    machine.init_sound(audio);
-
-    let key_map = Sdl2KeyMap::default();
-
-    let mut running = true;
-    while running && !machine.is_halt() {
-        select! {
-            recv(timer_rx) -> msg => {
-                machine.update_timer();
-                sdl2_draw(&mut canvas, machine)?;
-                debug!("timer: {}", msg.unwrap());
-            },
-            recv(clock_rx) -> msg => {
-                sdl2_key_event(machine, &mut running, &mut event_pump, &key_map);
-                machine.run_cycle()?;
-                debug!("clock: {}", msg.unwrap());
-            },
-        };
+/// where crash dumps go, the --crash-dir or `CRASH_DIR` for the
+/// frontends, none for --headless runs without it so batch runs stay clean
+fn crash_dir(matches: &ArgMatches) -> Option<PathBuf> {
+    match matches.value_of("CRASH_DIR") {
+        Some(dir) => Some(PathBuf::from(dir)),
+        None if matches.is_present("HEADLESS") => None,
+        None => Some(PathBuf::from(CRASH_DIR)),
     }
-    Ok(())
 }
 
-fn sender(
-    timer_tx: Sender<DateTime<Utc>>,
-    clock_tx: Sender<DateTime<Utc>>,
-    timer_freq: u64,
-    clock_freq: u64,
-) {
-    let timer_dur = Duration::from_micros(1000000 / timer_freq);
-    thread::spawn(move || loop {
-        thread::sleep(timer_dur);
-        let _ = timer_tx.send(chrono::Utc::now());
-    });
-    let clock_dur = Duration::from_micros(1000000 / clock_freq);
-    thread::spawn(move || loop {
-        thread::sleep(clock_dur);
-        let _ = clock_tx.send(chrono::Utc::now());
-    });
+/// save the recorded inputs with --record-movie, print the --profile
+/// report and write the --coverage report, also when the run failed, and
+/// write a crash dump to the `crash_dir` when the machine failed
+fn finish_run<T: AudioPlay, R>(
+    machine: &mut Machine<T>,
+    matches: &ArgMatches,
+    result: Result<R>,
+) -> Result<R> {
+    let error = result
+        .as_ref()
+        .err()
+        .and_then(|e| e.downcast_ref::<MachineError>());
+    if let (Some(error), Some(dir)) = (error, crash_dir(matches)) {
+        let symbols = load_symbols(matches, None).unwrap_or_default();
+        match crashdump::write_dump(&dir, machine, error, &symbols) {
+            Ok(dir) => log::error!("crash dump written to {}", dir.display()),
+            Err(e) => log::error!("can not write the crash dump: {}", e),
+        }
+    }
+    if let (Some(path), Some(movie)) = (matches.value_of("RECORD_MOVIE"), machine.take_movie()) {
+        movie.save(path)?;
+        log::info!("{} inputs saved to {}", movie.events.len(), path);
+    }
+    if let Some(profile) = machine.profile() {
+        eprintln!("{} instructions executed", profile.total());
+        for line in profile.report() {
+            eprintln!("{}", line);
+        }
+    }
+    if let (Some(path), Some(coverage)) = (matches.value_of("COVERAGE"), machine.coverage()) {
+        let symbols = load_symbols(matches, None).unwrap_or_default();
+        let start = machine.rom_range().start as u16;
+        let mut report = coverage.report(machine.rom(), start, &symbols).join("\n");
+        report.push('\n');
+        fs::write(path, report)?;
+        log::info!(
+            "{}, written to {}",
+            coverage.summary(machine.rom(), start),
+            path
+        );
+    }
+    result
 }
 
 fn main() -> Result<()> {
-    env_logger::init();
-
     let matches = App::new("yet-another-rchip8")
         .version("0.0001")
         .author("livexia")
+        .arg(
+            Arg::with_name("CONFIG")
+                .long("config")
+                .takes_value(true)
+                .value_name("FILE")
+                .help("Loads settings from a TOML file [default: ~/.config/yarchip8/config.toml], options override them"),
+        )
         .arg(
             Arg::with_name("ROM")
                 .short("r")
                 .long("rom")
                 .takes_value(true)
-                .help("Sets the rom file to load"),
+                .multiple(true)
+                .number_of_values(1)
+                .help("Sets the rom file or, with the net feature, http(s) URL to load, without it a rom menu is shown. \
+                    Can be repeated, F3 switches to the next rom in the window"),
+        )
+        .arg(
+            Arg::with_name("ROMDB")
+                .long("romdb")
+                .takes_value(true)
+                .value_name("FILE")
+                .help("Adds a JSON rom database to the built-in one, settings of a known rom are applied over the config file"),
+        )
+        .arg(
+            Arg::with_name("ROM_DIR")
+                .long("rom-dir")
+                .takes_value(true)
+                .value_name("DIR")
+                .help("Sets the directory the rom menu lists [default: roms]"),
+        )
+        .arg(
+            Arg::with_name("COMPAT")
+                .long("compat")
+                .takes_value(true)
+                .possible_values(&Quirks::PROFILES)
+                .help("Sets the compatibility profile for interpreter quirks"),
+        )
+        .arg(
+            Arg::with_name("NO_DETECT")
+                .long("no-detect")
+                .help("Keeps the quirks when the rom uses SUPER-CHIP, XO-CHIP or MegaChip instructions, \
+                    by default the profile of the variant is used unless a profile is set"),
+        )
+        .arg(
+            Arg::with_name("DISPLAY_WAIT")
+                .long("display-wait")
+                .help("Draws at most one sprite per 60Hz frame like the COSMAC VIP"),
+        )
+        .arg(
+            Arg::with_name("LATCH_DISPLAY")
+                .long("latch-display")
+                .help("Shows the display only as it is at the end of each 60Hz frame, against flicker at high speeds"),
+        )
+        .arg(
+            Arg::with_name("WRAP_SPRITES")
+                .long("wrap-sprites")
+                .help("Wraps sprites around the screen edges instead of clipping them"),
+        )
+        .arg(
+            Arg::with_name("QUIRK")
+                .long("quirk")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .value_name("NAME=VALUE")
+                .help(
+                    "Overrides one quirk of the profile, can be repeated: shift=vy|vx, \
+                     load-store=increment|keep, jump=vx|v0, display=wait|immediate, \
+                     sprites=wrap|clip, scroll=half|full, stack=12|16",
+                ),
+        )
+        .arg(
+            Arg::with_name("FONT")
+                .long("font")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .value_name("NAME|FILE")
+                .help(
+                    "Sets the hex digit font: default, vip, dream6800, or a file of 80 bytes \
+                     small and/or 160 bytes big digits, binary or .hex text, can be repeated",
+                ),
+        )
+        .arg(
+            Arg::with_name("CHEATS")
+                .long("cheats")
+                .takes_value(true)
+                .value_name("FILE")
+                .help("Loads cheat codes from a file, one per line"),
+        )
+        .arg(
+            Arg::with_name("CHEAT")
+                .long("cheat")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .value_name("CODE")
+                .help("Adds a cheat, ADDR:BYTES patches memory when the rom is loaded, ADDR=BYTE keeps an address at a value, can be repeated"),
+        )
+        .arg(
+            Arg::with_name("FAST")
+                .long("fast")
+                .help("Caches decoded instructions until their memory is written, for high speeds"),
+        )
+        .arg(
+            Arg::with_name("WARN_SMC")
+                .long("warn-smc")
+                .help("Logs a warning when an instruction writes over code that already ran"),
+        )
+        .arg(
+            Arg::with_name("COVERAGE")
+                .long("coverage")
+                .takes_value(true)
+                .value_name("FILE")
+                .help("Writes the disassembly of the rom with the executed instructions marked to a file on exit"),
+        )
+        .arg(
+            Arg::with_name("PROFILE")
+                .long("profile")
+                .help("Counts the executed instructions and their time, printed on exit or with the profile debugger command"),
+        )
+        .arg(
+            Arg::with_name("SEED")
+                .long("seed")
+                .takes_value(true)
+                .value_name("N")
+                .help("Sets the seed of the random numbers for reproducible runs"),
+        )
+        .arg(
+            Arg::with_name("SPEED")
+                .long("speed")
+                .takes_value(true)
+                .value_name("HZ")
+                .help("Sets the instructions per second, or unlimited [default: 500]"),
+        )
+        .arg(
+            Arg::with_name("TIMING")
+                .long("timing")
+                .takes_value(true)
+                .possible_values(&Timing::NAMES)
+                .default_value("fixed")
+                .help("Sets how instructions are paced, vip gives each one its COSMAC VIP duration (SDL2 window only)"),
+        )
+        .arg(
+            Arg::with_name("SCALE")
+                .long("scale")
+                .takes_value(true)
+                .help("Sets the window pixels per CHIP-8 pixel [default: 10]"),
+        )
+        .arg(
+            Arg::with_name("FOREGROUND")
+                .long("fg")
+                .takes_value(true)
+                .value_name("COLOR")
+                .help("Sets the foreground color as hex, like #FFFFFF"),
+        )
+        .arg(
+            Arg::with_name("BACKGROUND")
+                .long("bg")
+                .takes_value(true)
+                .value_name("COLOR")
+                .help("Sets the background color as hex, like #000000"),
+        )
+        .arg(
+            Arg::with_name("SCANLINES")
+                .long("scanlines")
+                .help("Draws CRT like scanlines over the display"),
+        )
+        .arg(
+            Arg::with_name("VSYNC")
+                .long("vsync")
+                .help("Presents frames on the display refresh instead of the 60Hz timer, in the SDL2 window"),
+        )
+        .arg(
+            Arg::with_name("WATCH")
+                .long("watch")
+                .help("Reloads and restarts the rom whenever its file changes, e.g. an .8o source saved in an editor"),
+        )
+        .arg(
+            Arg::with_name("NO_AUTO_PAUSE")
+                .long("no-auto-pause")
+                .help("Keeps the SDL2 window running while it is in the background"),
+        )
+        .arg(
+            Arg::with_name("KEYPAD")
+                .long("keypad")
+                .help("Shows a keypad right of the SDL2 display that can be clicked or touched"),
+        )
+        .arg(
+            Arg::with_name("HOST")
+                .long("host")
+                .takes_value(true)
+                .value_name("ADDR")
+                .conflicts_with_all(&["JOIN", "DEBUG", "PLAYBACK", "WATCH"])
+                .help("Waits on an address like 0.0.0.0:7878 for a second player to join over the network (SDL2 window only)"),
+        )
+        .arg(
+            Arg::with_name("JOIN")
+                .long("join")
+                .takes_value(true)
+                .value_name("ADDR")
+                .conflicts_with_all(&["DEBUG", "PLAYBACK", "WATCH"])
+                .help("Joins the player hosting the same rom on ADDR, their seed, quirks and speed are used"),
+        )
+        .arg(
+            Arg::with_name("REMOTE")
+                .long("remote")
+                .takes_value(true)
+                .value_name("ADDR")
+                .conflicts_with_all(&["HOST", "JOIN"])
+                .help("Takes JSON line commands from scripts on an address like 127.0.0.1:7879, with --headless the rom only runs when told to"),
+        )
+        .arg(
+            Arg::with_name("SCRIPT")
+                .long("script")
+                .takes_value(true)
+                .value_name("FILE")
+                .help("Runs the fn frame(chip8) of a rhai script every frame, for cheats and automation"),
+        )
+        .arg(
+            Arg::with_name("INPUT_DELAY")
+                .long("input-delay")
+                .takes_value(true)
+                .value_name("FRAMES")
+                .help("Sets the frames keys take to act when hosting, more hides more network latency [default: 2]"),
+        )
+        .arg(
+            Arg::with_name("FRAME_STATS")
+                .long("frame-stats")
+                .help("Logs frame time percentiles and dropped frames every 5 seconds, in the SDL2 window"),
+        )
+        .arg(
+            Arg::with_name("GHOSTING")
+                .long("ghosting")
+                .takes_value(true)
+                .value_name("0..100")
+                .help("Fades turned off pixels out over a few frames against flicker, 0 is off and 100 the slowest fade, in the SDL2 and pixels windows"),
+        )
+        .arg(
+            Arg::with_name("TONE")
+                .long("tone")
+                .takes_value(true)
+                .possible_values(&Tone::NAMES)
+                .help("Sets the waveform of the beep [default: square]"),
+        )
+        .arg(
+            Arg::with_name("FREQUENCY")
+                .long("frequency")
+                .takes_value(true)
+                .value_name("HZ")
+                .help("Sets the pitch of the beep [default: 440]"),
+        )
+        .arg(
+            Arg::with_name("VOLUME")
+                .long("volume")
+                .takes_value(true)
+                .value_name("0..100")
+                .help("Sets the volume of the beep in percent, M mutes and ] [ change it [default: 10]"),
+        )
+        .arg(
+            Arg::with_name("NO_AUDIO")
+                .long("no-audio")
+                .help("Plays no sound, the window runs silently when there is no audio device anyway"),
+        )
+        .arg(
+            Arg::with_name("KEYMAP")
+                .long("keymap")
+                .takes_value(true)
+                .value_name("FILE")
+                .help("Loads the keyboard layout from a TOML or JSON file"),
+        )
+        .arg(
+            Arg::with_name("KEYMAP2")
+                .long("keymap2")
+                .takes_value(true)
+                .value_name("FILE")
+                .help("Adds keys for a second player from a keymap file, keys may be left unmapped"),
+        )
+        .arg(
+            Arg::with_name("PADMAP")
+                .long("padmap")
+                .takes_value(true)
+                .value_name("FILE")
+                .help("Loads the game controller buttons from a TOML or JSON file"),
+        )
+        .arg(
+            Arg::with_name("PADMAP2")
+                .long("padmap2")
+                .takes_value(true)
+                .value_name("FILE")
+                .help("Loads the buttons of the second game controller connected, for two player games"),
+        )
+        .arg(
+            Arg::with_name("DEBUG")
+                .long("debug")
+                .help("Starts paused with a debugger prompt on stdin and memory and register windows, F2 toggles them"),
+        )
+        .arg(
+            Arg::with_name("RECORD")
+                .long("record")
+                .takes_value(true)
+                .value_name("FILE")
+                .help("Records the display to a GIF until F10 is pressed or the window is closed"),
+        )
+        .arg(
+            Arg::with_name("RECORD_MOVIE")
+                .long("record-movie")
+                .takes_value(true)
+                .value_name("FILE")
+                .conflicts_with("PLAYBACK")
+                .help("Records the key presses and the random seed to a movie file on exit"),
+        )
+        .arg(
+            Arg::with_name("PLAYBACK")
+                .long("playback")
+                .takes_value(true)
+                .value_name("FILE")
+                .help("Replays the inputs of a movie file instead of the keyboard"),
+        )
+        .arg(
+            Arg::with_name("LOG")
+                .long("log")
+                .takes_value(true)
+                .value_name("FILTERS")
+                .help("Sets what is logged like RUST_LOG, by module or by subsystem: cpu, video, audio, input, e.g. input=debug,cpu=off"),
+        )
+        .arg(
+            Arg::with_name("TRACE")
+                .long("trace")
+                .takes_value(true)
+                .value_name("FILE")
+                .help("Writes every executed instruction and the registers it changed to a file"),
+        )
+        .arg(
+            Arg::with_name("CRASH_DIR")
+                .long("crash-dir")
+                .takes_value(true)
+                .value_name("DIR")
+                .help("Writes a crash dump here when the rom fails, the window and terminal frontends use crashes without it"),
+        )
+        .arg(
+            Arg::with_name("DISASSEMBLE")
+                .long("disassemble")
+                .takes_value(true)
+                .value_name("ROM")
+                .help("Prints the code reachable from the entry of the rom as assembly, the other bytes as data, and exits"),
+        )
+        .arg(
+            Arg::with_name("CFG")
+                .long("cfg")
+                .takes_value(true)
+                .value_name("FILE")
+                .requires("DISASSEMBLE")
+                .help("Writes the basic blocks and the jumps and calls between them of --disassemble to a Graphviz .dot file instead"),
+        )
+        .arg(
+            Arg::with_name("SYMBOLS")
+                .long("symbols")
+                .takes_value(true)
+                .value_name("FILE")
+                .help("Loads label names for the disassembler, debugger and trace from a TOML or JSON symbol file"),
+        )
+        .arg(
+            Arg::with_name("TUI")
+                .long("tui")
+                .help("Runs the rom in the terminal instead of a window"),
+        )
+        .arg(
+            Arg::with_name("HEADLESS")
+                .long("headless")
+                .help("Runs the rom without a window and dumps the final state"),
+        )
+        .arg(
+            Arg::with_name("CYCLES")
+                .long("cycles")
+                .takes_value(true)
+                .default_value("1000")
+                .help("Sets the number of cycles to run in headless mode"),
+        )
+        .arg(
+            Arg::with_name("DUMP_DISPLAY")
+                .long("dump-display")
+                .takes_value(true)
+                .value_name("FILE")
+                .help("Writes the headless display to a text file, or PBM with a .pbm extension"),
+        )
+        .arg(
+            Arg::with_name("DUMP_REGISTERS")
+                .long("dump-registers")
+                .takes_value(true)
+                .value_name("FILE")
+                .help("Writes the headless register state as JSON"),
+        )
+        .subcommand(
+            SubCommand::with_name("assemble")
+                .about("Builds a rom from assembly source in the disassembler's syntax, or Octo source with a .8o extension")
+                .arg(
+                    Arg::with_name("SOURCE")
+                        .required(true)
+                        .help("Sets the assembly source file"),
+                )
+                .arg(
+                    Arg::with_name("OUTPUT")
+                        .short("o")
+                        .long("output")
+                        .takes_value(true)
+                        .value_name("FILE")
+                        .help("Sets the rom file to write [default: SOURCE with a .ch8 extension]"),
+                )
+                .arg(
+                    Arg::with_name("SYMBOLS")
+                        .long("symbols")
+                        .takes_value(true)
+                        .value_name("FILE")
+                        .help("Writes the addresses of the labels to a symbol file"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("test-suite")
+                .about("Runs the test roms in a directory headless and checks their displays")
+                .arg(
+                    Arg::with_name("DIR")
+                        .required(true)
+                        .help("Sets the directory of test roms, searched recursively"),
+                )
+                .arg(
+                    Arg::with_name("EXPECT")
+                        .long("expect")
+                        .takes_value(true)
+                        .value_name("FILE")
+                        .help("Adds JSON expectations to the built-in ones"),
+                )
+                .arg(
+                    Arg::with_name("WRITE")
+                        .long("write")
+                        .takes_value(true)
+                        .value_name("FILE")
+                        .help("Writes the expectations with the displays of this run, check them first"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("compare")
+                .about("Runs a rom with two quirk configurations, or along a trace, and reports the first cycle they diverge")
+                .arg(
+                    Arg::with_name("ROM")
+                        .required(true)
+                        .help("Sets the rom to run"),
+                )
+                .arg(
+                    Arg::with_name("A")
+                        .long("a")
+                        .takes_value(true)
+                        .value_name("QUIRKS")
+                        .help("Sets the quirks of the first run, a profile and name=value settings like chip8,shift=vx [default: the default quirks]"),
+                )
+                .arg(
+                    Arg::with_name("B")
+                        .long("b")
+                        .takes_value(true)
+                        .value_name("QUIRKS")
+                        .required_unless("TRACE")
+                        .help("Sets the quirks of the second run"),
+                )
+                .arg(
+                    Arg::with_name("TRACE")
+                        .long("trace")
+                        .takes_value(true)
+                        .value_name("FILE")
+                        .conflicts_with("B")
+                        .help("Compares the first run with a trace in the format of --trace instead"),
+                )
+                .arg(
+                    Arg::with_name("SEED")
+                        .long("seed")
+                        .takes_value(true)
+                        .default_value("0")
+                        .help("Sets the random seed of the runs, a trace has to be recorded with the same --seed"),
+                )
+                .arg(
+                    Arg::with_name("CYCLES")
+                        .long("cycles")
+                        .takes_value(true)
+                        .default_value("10000")
+                        .help("Sets the number of cycles to compare two runs for"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("info")
+                .about("Prints the size, SHA-1, variant and what the code of roms uses, from a static scan")
+                .arg(
+                    Arg::with_name("ROM")
+                        .required(true)
+                        .multiple(true)
+                        .help("Sets the roms to look at"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("bench")
+                .about("Runs a rom headless to measure instructions per second and the time per instruction")
+                .arg(
+                    Arg::with_name("ROM")
+                        .required(true)
+                        .help("Sets the rom to run"),
+                )
+                .arg(
+                    Arg::with_name("MILLIONS")
+                        .long("cycles")
+                        .takes_value(true)
+                        .value_name("MILLIONS")
+                        .default_value("10")
+                        .help("Sets the millions of instructions to run"),
+                )
+                .arg(
+                    Arg::with_name("COMPAT")
+                        .long("compat")
+                        .takes_value(true)
+                        .value_name("PROFILE")
+                        .help("Sets the quirks profile [default: detected from the rom]"),
+                )
+                .arg(
+                    Arg::with_name("SEED")
+                        .long("seed")
+                        .takes_value(true)
+                        .default_value("0")
+                        .help("Sets the random seed, runs with the same seed execute the same instructions"),
+                )
+                .arg(
+                    Arg::with_name("FAST")
+                        .long("fast")
+                        .help("Caches decoded instructions like the --fast of a normal run"),
+                ),
         )
         .get_matches();
+    logging::init(matches.value_of("LOG"))?;
 
-    let rom = matches.value_of("ROM").unwrap_or("IBM_Logo.hex");
-    let rom = ROM::new(rom)?;
-    let mut machine = Machine::new()?;
-    machine.load_font()?;
-    machine.load_rom(&rom)?;
-    sdl2_emulate(&mut machine)?;
+    if let Some(matches) = matches.subcommand_matches("assemble") {
+        let source = Path::new(matches.value_of("SOURCE").unwrap());
+        let output = match matches.value_of("OUTPUT") {
+            Some(output) => PathBuf::from(output),
+            None => source.with_extension("ch8"),
+        };
+        let text = fs::read_to_string(source)?;
+        let (rom, symbols) = if source.extension().is_some_and(|ext| ext == "8o") {
+            octo::compile_with_symbols(&text)?
+        } else {
+            assembler::assemble_with_symbols(&text, 0x200)?
+        };
+        fs::write(&output, &rom)?;
+        if let Some(path) = matches.value_of("SYMBOLS") {
+            symbols.save(path)?;
+        }
+        println!("{} bytes written to {}", rom.len(), output.display());
+        return Ok(());
+    }
+
+    if let Some(matches) = matches.subcommand_matches("test-suite") {
+        return test_suite(matches);
+    }
+
+    if let Some(matches) = matches.subcommand_matches("compare") {
+        return compare_runs(matches);
+    }
+
+    if let Some(matches) = matches.subcommand_matches("bench") {
+        return bench_run(matches);
+    }
+
+    if let Some(info) = matches.subcommand_matches("info") {
+        return rom_info(info, matches.value_of("ROMDB"));
+    }
+
+    if let Some(rom) = matches.value_of("DISASSEMBLE") {
+        let rom = ROM::new(rom)?;
+        let symbols = load_symbols(&matches, Some(&rom))?;
+        let raw = rom.raw();
+        if let Some(path) = matches.value_of("CFG") {
+            let dot = disassembler::control_flow(&raw, 0x200, &detect::entries(&raw), &symbols);
+            fs::write(path, dot)?;
+            println!("control-flow graph written to {}", path);
+            return Ok(());
+        }
+        for line in disassembler::disassemble_code(&raw, 0x200, &detect::entries(&raw), &symbols) {
+            println!("{}", line);
+        }
+        return Ok(());
+    }
+
+    let mut config = match matches.value_of("CONFIG") {
+        Some(path) => Config::load(path)?,
+        None => match Config::default_path() {
+            Some(path) if path.exists() => Config::load(path)?,
+            _ => Config::default(),
+        },
+    };
+
+    let rom = matches.value_of("ROM").map(ROM::new).transpose()?;
+    if let Some(rom) = &rom {
+        let mut romdb = RomDb::builtin();
+        if let Some(file) = matches.value_of("ROMDB") {
+            romdb.extend(RomDb::load(file)?);
+        }
+        if let Some(info) = romdb.lookup(rom) {
+            log::info!("known rom: {}", info);
+            config.apply_rom(info);
+        }
+        if !matches.is_present("NO_DETECT")
+            && !matches.is_present("COMPAT")
+            && config.compat.is_none()
+        {
+            let variant = detect::variant(&rom.raw());
+            if let Some(profile) = variant.profile() {
+                log::info!(
+                    "{} instructions found, using the {} quirks, --no-detect turns this off",
+                    variant,
+                    profile
+                );
+                config.compat = Some(profile.to_string());
+            }
+        }
+    }
+
+    let clock_freq = match matches
+        .value_of("SPEED")
+        .map(String::from)
+        .or(config.speed())
+    {
+        Some(speed) => parse_speed(&speed)?,
+        None => DEFAULT_CLOCK_FREQ,
+    };
+
+    if matches.is_present("HEADLESS") {
+        let rom = match rom {
+            Some(rom) => rom,
+            None => return err!("--headless needs a rom, set it with --rom"),
+        };
+        let mut machine: Machine<NullAudio> = new_machine(Some(&rom), &matches, &config)?;
+        let cycles = matches.value_of("CYCLES").unwrap().parse()?;
+        let mut tracer = match matches.value_of("TRACE") {
+            Some(path) => Some(Tracer::create(path)?),
+            None => None,
+        };
+        if let Some(tracer) = &mut tracer {
+            tracer.set_symbols(load_symbols(&matches, Some(&rom))?);
+        }
+        if let Some(addr) = matches.value_of("REMOTE") {
+            let (addr, calls) = remote::serve(addr)?;
+            log::info!("remote control on {}", addr);
+            let result = remote::run(&mut machine, &calls, clock_freq);
+            return finish_run(&mut machine, &matches, result);
+        }
+        let result = headless::run_with(
+            &mut machine,
+            cycles,
+            clock_freq,
+            tracer.as_mut(),
+            frame_hook(&matches)?,
+        );
+        let executed = finish_run(&mut machine, &matches, result)?;
+        match matches.value_of("DUMP_DISPLAY") {
+            Some(path) => headless::dump_display(&machine, Path::new(path))?,
+            None => print!("{}", headless::display_to_text(&machine)),
+        }
+        match matches.value_of("DUMP_REGISTERS") {
+            Some(path) => headless::dump_registers(&machine, executed, Path::new(path))?,
+            None => println!(
+                "{}",
+                serde_json::to_string_pretty(&headless::RegisterDump::new(&machine, executed))?
+            ),
+        }
+        return Ok(());
+    }
+
+    if matches.is_present("TUI") {
+        return tui_run(rom, &matches, &config, clock_freq);
+    }
+
+    window_run(rom, &matches, &config, clock_freq)
+}
+
+#[cfg(any(feature = "sdl2-frontend", feature = "pixels-frontend"))]
+fn audio_config(matches: &ArgMatches, file: &Config) -> Result<Option<AudioConfig>> {
+    if matches.is_present("NO_AUDIO") {
+        return Ok(None);
+    }
+    let mut config = AudioConfig::default();
+    let volume = match matches.value_of("VOLUME") {
+        Some(volume) => Some(volume.parse::<u8>()?),
+        None => file.volume,
+    };
+    if let Some(volume) = volume {
+        if volume > 100 {
+            return err!("volume must be between 0 and 100");
+        }
+        config.volume = volume as f32 / 100.0;
+    }
+    if let Some(tone) = matches.value_of("TONE") {
+        config.tone = tone.parse()?;
+    }
+    if let Some(frequency) = matches.value_of("FREQUENCY") {
+        config.frequency = frequency.parse()?;
+        if config.frequency <= 0.0 {
+            return err!("frequency must be greater than 0");
+        }
+    }
+    Ok(Some(config))
+}
+
+#[cfg(any(
+    feature = "sdl2-frontend",
+    feature = "pixels-frontend",
+    feature = "tui-frontend"
+))]
+fn display_config(matches: &ArgMatches, file: &Config) -> Result<DisplayConfig> {
+    let mut config = DisplayConfig::default();
+    if let Some(scale) = matches.value_of("SCALE") {
+        config.scale = scale.parse()?;
+    } else if let Some(scale) = file.scale {
+        config.scale = scale;
+    }
+    if config.scale == 0 {
+        return err!("scale must be greater than 0");
+    }
+    if let Some(color) = matches
+        .value_of("FOREGROUND")
+        .or(file.foreground.as_deref())
+    {
+        config.foreground = color.parse()?;
+    }
+    if let Some(color) = matches
+        .value_of("BACKGROUND")
+        .or(file.background.as_deref())
+    {
+        config.background = color.parse()?;
+    }
+    config.scanlines = matches.is_present("SCANLINES");
+    if let Some(ghosting) = matches.value_of("GHOSTING") {
+        config.ghosting = ghosting.parse()?;
+    } else if let Some(ghosting) = file.ghosting {
+        config.ghosting = ghosting;
+    }
+    if config.ghosting > 100 {
+        return err!("ghosting must be between 0 and 100");
+    }
+    Ok(config)
+}
+
+/// with --watch, a watcher of the first rom file
+#[cfg(any(
+    feature = "sdl2-frontend",
+    feature = "pixels-frontend",
+    feature = "tui-frontend"
+))]
+fn rom_watcher(matches: &ArgMatches) -> Result<Option<RomWatcher>> {
+    if !matches.is_present("WATCH") {
+        return Ok(None);
+    }
+    match matches.value_of("ROM") {
+        Some(path) => RomWatcher::new(path).map(Some),
+        None => err!("--watch needs a rom file, set it with --rom"),
+    }
+}
+
+/// The RPL flags and scores are kept on disk unless a movie or netplay
+/// needs every run to start from the same machine
+#[cfg(any(
+    feature = "sdl2-frontend",
+    feature = "pixels-frontend",
+    feature = "tui-frontend"
+))]
+fn keeps_flags(matches: &ArgMatches) -> bool {
+    !["PLAYBACK", "RECORD_MOVIE", "HOST", "JOIN"]
+        .iter()
+        .any(|name| matches.is_present(name))
+}
+
+#[cfg(any(feature = "pixels-frontend", feature = "tui-frontend"))]
+fn flag_file<T: AudioPlay>(
+    matches: &ArgMatches,
+    rom: &ROM,
+    machine: &mut Machine<T>,
+) -> Option<FlagFile> {
+    keeps_flags(matches)
+        .then(|| rpl::for_rom(&rom.name, machine))
+        .flatten()
+}
+
+/// Give the loaded rom the scores saved by earlier runs, None when the rom
+/// database names no scores for it
+#[cfg(any(
+    feature = "sdl2-frontend",
+    feature = "pixels-frontend",
+    feature = "tui-frontend"
+))]
+fn restore_scores<T: AudioPlay>(
+    matches: &ArgMatches,
+    config: &Config,
+    rom: &ROM,
+    machine: &mut Machine<T>,
+) -> Option<ScoreFile> {
+    if config.scores.is_empty() || !keeps_flags(matches) {
+        return None;
+    }
+    let file = scores::scores_path(rom)
+        .ok_or_else(|| "no config directory".into())
+        .and_then(|path| ScoreFile::new(path, rom, &config.scores))
+        .and_then(|file| file.restore(machine).map(|_| file));
+    match file {
+        Ok(file) => Some(file),
+        Err(e) => {
+            log::warn!("the scores are not kept: {}", e);
+            None
+        }
+    }
+}
+
+/// keep the scores in memory when the emulator exits
+#[cfg(any(
+    feature = "sdl2-frontend",
+    feature = "pixels-frontend",
+    feature = "tui-frontend"
+))]
+fn save_scores<T: AudioPlay>(file: Option<&ScoreFile>, machine: &Machine<T>) {
+    if let Some(file) = file {
+        match file.save(machine) {
+            Ok(()) => log::info!("scores saved to {}", file.path().display()),
+            Err(e) => log::error!("can not save the scores: {}", e),
+        }
+    }
+}
+
+/// the labels of an assembled rom, with `--symbols` on top
+fn load_symbols(matches: &ArgMatches, rom: Option<&ROM>) -> Result<Symbols> {
+    let mut symbols = rom.map(|rom| rom.symbols().clone()).unwrap_or_default();
+    if let Some(path) = matches.value_of("SYMBOLS") {
+        symbols.merge(&Symbols::load(path)?);
+    }
+    Ok(symbols)
+}
+
+/// print a line per test rom and fail when one did not pass
+fn test_suite(matches: &ArgMatches) -> Result<()> {
+    let mut expectations = Expectations::builtin();
+    if let Some(path) = matches.value_of("EXPECT") {
+        expectations.extend(Expectations::load(path)?);
+    }
+    let outcomes = testsuite::run_suite(matches.value_of("DIR").unwrap(), &expectations)?;
+    let mut counts = [0; 4];
+    for outcome in &outcomes {
+        let index = match outcome.verdict {
+            Verdict::Pass => 0,
+            Verdict::Fail => 1,
+            Verdict::Unknown => 2,
+            Verdict::Error(_) => 3,
+        };
+        counts[index] += 1;
+        println!(
+            "{:<5} {} ({})",
+            outcome.verdict,
+            outcome.name,
+            outcome.path.display()
+        );
+    }
+    println!(
+        "{} passed, {} failed, {} unknown, {} errors",
+        counts[0], counts[1], counts[2], counts[3]
+    );
+    if let Some(path) = matches.value_of("WRITE") {
+        expectations.record(&outcomes);
+        expectations.save(path)?;
+        println!("expectations written to {}", path);
+    }
+    if counts[1] + counts[3] > 0 {
+        return err!(
+            "{} of {} test roms did not pass",
+            counts[1] + counts[3],
+            outcomes.len()
+        );
+    }
     Ok(())
 }
+
+fn compare_runs(matches: &ArgMatches) -> Result<()> {
+    let rom = ROM::new(matches.value_of("ROM").unwrap())?;
+    let a = match matches.value_of("A") {
+        Some(spec) => compare::parse_quirks(spec)?,
+        None => Quirks::default(),
+    };
+    let seed = matches.value_of("SEED").unwrap().parse()?;
+    let divergence = match (matches.value_of("TRACE"), matches.value_of("B")) {
+        (Some(path), _) => {
+            let trace = BufReader::new(fs::File::open(path)?);
+            compare::compare_trace(&rom, a, seed, trace)?
+        }
+        (None, Some(spec)) => {
+            let b = compare::parse_quirks(spec)?;
+            let cycles = matches.value_of("CYCLES").unwrap().parse()?;
+            compare::compare_quirks(&rom, a, b, seed, cycles)?
+        }
+        (None, None) => unreachable!(),
+    };
+    match divergence {
+        Some(divergence) => {
+            println!("{}", divergence);
+            err!("the runs diverged")
+        }
+        None => {
+            println!("no difference");
+            Ok(())
+        }
+    }
+}
+
+fn bench_run(matches: &ArgMatches) -> Result<()> {
+    let rom = ROM::new(matches.value_of("ROM").unwrap())?;
+    let profile = matches
+        .value_of("COMPAT")
+        .or_else(|| detect::variant(&rom.raw()).profile());
+    let quirks = match profile {
+        Some(profile) => profile.parse()?,
+        None => Quirks::default(),
+    };
+    let millions: f64 = matches.value_of("MILLIONS").unwrap().parse()?;
+    let options = BenchOptions {
+        cycles: (millions * 1e6) as u64,
+        quirks,
+        seed: matches.value_of("SEED").unwrap().parse()?,
+        decode_cache: matches.is_present("FAST"),
+    };
+    print!("{}", bench::bench(&rom, options)?);
+    Ok(())
+}
+
+/// Print what a static scan tells about every rom, a rom that can not be
+/// read is reported and skipped
+fn rom_info(matches: &ArgMatches, romdb_file: Option<&str>) -> Result<()> {
+    let mut romdb = RomDb::builtin();
+    if let Some(file) = romdb_file {
+        romdb.extend(RomDb::load(file)?);
+    }
+    for (n, path) in matches.values_of("ROM").unwrap().enumerate() {
+        if n > 0 {
+            println!();
+        }
+        let rom = match ROM::new(path) {
+            Ok(rom) => rom,
+            Err(e) => {
+                println!("{}: {}", path, e);
+                continue;
+            }
+        };
+        let raw = rom.raw();
+        let scan = detect::scan(&raw);
+        let yes_no = |yes| if yes { "yes" } else { "no" };
+        println!("{}", path);
+        if let Some(info) = romdb.lookup(&rom) {
+            println!("  known:    {}", info);
+        }
+        println!("  size:     {} bytes", raw.len());
+        println!("  sha-1:    {}", rom.sha1());
+        println!("  variant:  {}", scan.variant);
+        println!("  entry:    {}", detect::entry(&raw, scan.variant));
+        println!(
+            "  code:     {} reachable instructions, {} unknown",
+            scan.instructions, scan.unknown
+        );
+        println!("  sound:    {}", yes_no(scan.sound));
+        println!("  keys:     {}", yes_no(scan.keys));
+    }
+    Ok(())
+}
+
+/// "unlimited" or a positive number of instructions per second
+fn parse_speed(speed: &str) -> Result<u64> {
+    if speed == "unlimited" {
+        return Ok(UNLIMITED);
+    }
+    match speed.parse()? {
+        UNLIMITED => err!("speed must be greater than 0, or unlimited"),
+        freq => Ok(freq),
+    }
+}
+
+#[cfg(feature = "sdl2-frontend")]
+fn window_run(
+    rom: Option<ROM>,
+    matches: &ArgMatches,
+    config: &Config,
+    clock_freq: u64,
+) -> Result<()> {
+    use yet_another_rchip8::debugger::Debugger;
+    use yet_another_rchip8::keymap;
+    use yet_another_rchip8::menu::RomMenu;
+    use yet_another_rchip8::rom::state_path;
+    use yet_another_rchip8::sdl2_frontend::{emulate, Options, Sdl2KeyMap, Sdl2PadMap};
+
+    let symbols = load_symbols(matches, rom.as_ref())?;
+    // F5 saves and F9 loads the machine state next to the rom
+    let (mut machine, state_path, rom_menu) = match &rom {
+        Some(rom) => (
+            new_machine(Some(rom), matches, config)?,
+            state_path(&rom.name),
+            None,
+        ),
+        None => (
+            new_machine(None, matches, config)?,
+            PathBuf::new(),
+            Some(RomMenu::scan(
+                matches
+                    .value_of("ROM_DIR")
+                    .or(config.rom_dir.as_deref())
+                    .unwrap_or("roms"),
+            )?),
+        ),
+    };
+    let debugger = if matches.is_present("DEBUG") {
+        let mut debugger = Debugger::new();
+        debugger.set_symbols(symbols.clone());
+        Some(debugger)
+    } else {
+        None
+    };
+    let tracer = match matches.value_of("TRACE") {
+        Some(path) => {
+            let mut tracer = Tracer::create(path)?;
+            tracer.set_symbols(symbols);
+            Some(tracer)
+        }
+        None => None,
+    };
+    let mut key_map = match (matches.value_of("KEYMAP"), &config.keymap) {
+        (Some(path), _) => Sdl2KeyMap::from_names(&keymap::load(path)?)?,
+        (None, Some(table)) => Sdl2KeyMap::from_names(&keymap::from_table(table.clone())?)?,
+        (None, None) => Sdl2KeyMap::default(),
+    };
+    if let Some(path) = matches.value_of("KEYMAP2") {
+        key_map.add_names(&keymap::load_partial(path)?)?;
+    }
+    let pad_map = match matches.value_of("PADMAP") {
+        Some(path) => Sdl2PadMap::from_names(&keymap::load_partial(path)?)?,
+        None => Sdl2PadMap::default(),
+    };
+    let pad_map2 = match matches.value_of("PADMAP2") {
+        Some(path) => Some(Sdl2PadMap::from_names(&keymap::load_partial(path)?)?),
+        None => None,
+    };
+    let (netplay, clock_freq) = start_netplay(matches, rom.as_ref(), &mut machine, clock_freq)?;
+    let options = Options {
+        state_path,
+        clock_freq,
+        display: display_config(matches, config)?,
+        audio: audio_config(matches, config)?,
+        key_map,
+        pad_map,
+        pad_map2,
+        debugger,
+        tracer,
+        rom_menu,
+        roms: matches
+            .values_of("ROM")
+            .into_iter()
+            .flatten()
+            .map(String::from)
+            .collect(),
+        record: matches.value_of("RECORD").map(PathBuf::from),
+        timing: matches.value_of("TIMING").unwrap().parse()?,
+        vsync: matches.is_present("VSYNC"),
+        frame_stats: matches.is_present("FRAME_STATS"),
+        watcher: rom_watcher(matches)?,
+        keypad: matches.is_present("KEYPAD"),
+        netplay,
+        on_frame: frame_hook(matches)?,
+        keep_flags: keeps_flags(matches),
+        auto_pause: !matches.is_present("NO_AUTO_PAUSE"),
+        remote: match matches.value_of("REMOTE") {
+            Some(addr) => {
+                let (addr, calls) = remote::serve(addr)?;
+                log::info!("remote control on {}", addr);
+                Some(calls)
+            }
+            None => None,
+        },
+    };
+    let scores = rom
+        .as_ref()
+        .and_then(|rom| restore_scores(matches, config, rom, &mut machine));
+    let result = emulate(&mut machine, options);
+    save_scores(scores.as_ref(), &machine);
+    finish_run(&mut machine, matches, result)
+}
+
+/// the --script as a hook for the frontends
+#[cfg(feature = "scripting")]
+fn frame_hook<T: AudioPlay>(matches: &ArgMatches) -> Result<Option<FrameHook<T>>> {
+    use yet_another_rchip8::script::Script;
+
+    Ok(match matches.value_of("SCRIPT") {
+        Some(path) => {
+            let mut script = Script::load(path)?;
+            Some(Box::new(move |machine: &mut Machine<T>| {
+                script.frame(machine)
+            }))
+        }
+        None => None,
+    })
+}
+
+#[cfg(not(feature = "scripting"))]
+fn frame_hook<T: AudioPlay>(matches: &ArgMatches) -> Result<Option<FrameHook<T>>> {
+    match matches.value_of("SCRIPT") {
+        Some(_) => err!("built without the scripting feature"),
+        None => Ok(None),
+    }
+}
+
+/// host or join a netplay session with --host or --join, the machine gets
+/// the seed and quirks of the session and the speed is returned with it
+#[cfg(feature = "sdl2-frontend")]
+fn start_netplay<T: AudioPlay>(
+    matches: &ArgMatches,
+    rom: Option<&ROM>,
+    machine: &mut Machine<T>,
+    clock_freq: u64,
+) -> Result<(Option<yet_another_rchip8::netplay::Netplay>, u64)> {
+    use yet_another_rchip8::netplay::{Netplay, Session, DEFAULT_DELAY};
+
+    if !matches.is_present("HOST") && !matches.is_present("JOIN") {
+        return Ok((None, clock_freq));
+    }
+    let rom = match rom {
+        Some(rom) => rom,
+        None => return err!("netplay needs a rom, set it with --rom"),
+    };
+    let netplay = if let Some(addr) = matches.value_of("HOST") {
+        if clock_freq == UNLIMITED {
+            return err!("netplay needs a fixed --speed");
+        }
+        let delay = match matches.value_of("INPUT_DELAY") {
+            Some(delay) => delay.parse()?,
+            None => DEFAULT_DELAY,
+        };
+        let session = Session::new(
+            rom.sha1(),
+            machine.seed(),
+            clock_freq,
+            machine.quirks(),
+            delay,
+        );
+        Netplay::host(addr, session)?
+    } else {
+        Netplay::join(matches.value_of("JOIN").unwrap(), &rom.sha1())?
+    };
+    netplay.session().apply(machine)?;
+    let clock_freq = netplay.session().clock_freq;
+    Ok((Some(netplay), clock_freq))
+}
+
+#[cfg(feature = "tui-frontend")]
+fn tui_run(rom: Option<ROM>, matches: &ArgMatches, config: &Config, clock_freq: u64) -> Result<()> {
+    use yet_another_rchip8::tui_frontend::{emulate, Options};
+
+    let rom = match rom {
+        Some(rom) => rom,
+        None => return err!("--tui needs a rom, set it with --rom"),
+    };
+    let mut machine = new_machine(Some(&rom), matches, config)?;
+    let options = Options {
+        clock_freq,
+        display: display_config(matches, config)?,
+        watcher: rom_watcher(matches)?,
+        title: rom.name.clone(),
+        on_frame: frame_hook(matches)?,
+        flags: flag_file(matches, &rom, &mut machine),
+    };
+    let scores = restore_scores(matches, config, &rom, &mut machine);
+    let result = emulate(&mut machine, options);
+    save_scores(scores.as_ref(), &machine);
+    finish_run(&mut machine, matches, result)
+}
+
+#[cfg(not(feature = "tui-frontend"))]
+fn tui_run(_: Option<ROM>, _: &ArgMatches, _: &Config, _: u64) -> Result<()> {
+    err!("built without the tui-frontend feature")
+}
+
+/// the winit/pixels frontend is used when built without SDL2
+#[cfg(all(feature = "pixels-frontend", not(feature = "sdl2-frontend")))]
+fn window_run(
+    rom: Option<ROM>,
+    matches: &ArgMatches,
+    config: &Config,
+    clock_freq: u64,
+) -> Result<()> {
+    use yet_another_rchip8::pixels_frontend::{emulate, Options};
+
+    let rom = match rom {
+        Some(rom) => rom,
+        None => return err!("the pixels frontend has no rom menu, set a rom with --rom"),
+    };
+    let mut machine = new_machine(Some(&rom), matches, config)?;
+    let options = Options {
+        clock_freq,
+        display: display_config(matches, config)?,
+        audio: audio_config(matches, config)?,
+        watcher: rom_watcher(matches)?,
+        title: rom.name.clone(),
+        on_frame: frame_hook(matches)?,
+        flags: flag_file(matches, &rom, &mut machine),
+    };
+    let scores = restore_scores(matches, config, &rom, &mut machine);
+    let result = emulate(&mut machine, options);
+    save_scores(scores.as_ref(), &machine);
+    finish_run(&mut machine, matches, result)
+}
+
+#[cfg(not(any(feature = "sdl2-frontend", feature = "pixels-frontend")))]
+fn window_run(_: Option<ROM>, _: &ArgMatches, _: &Config, _: u64) -> Result<()> {
+    err!("built without a window frontend, enable sdl2-frontend or pixels-frontend, or try --headless")
+}
@@ -0,0 +1,238 @@
+use serde::{Deserialize, Serialize};
+
+pub const MEGA_WIDTH: usize = 256;
+pub const MEGA_HEIGHT: usize = 192;
+/// the palette has an ARGB color per index, index 0 is transparent
+const PALETTE_SIZE: usize = 256;
+const OPAQUE: u32 = 0xFF00_0000;
+
+/// 080N: how a sprite pixel is combined with the pixel under it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Blend {
+    Normal,
+    /// the sprite is drawn at 25%, 50% or 75% opacity
+    Alpha25,
+    Alpha50,
+    Alpha75,
+    Add,
+    Multiply,
+}
+
+impl Blend {
+    /// the mode of 080N, None for N past 5
+    pub fn from_mode(n: u8) -> Option<Self> {
+        match n {
+            0 => Some(Blend::Normal),
+            1 => Some(Blend::Alpha25),
+            2 => Some(Blend::Alpha50),
+            3 => Some(Blend::Alpha75),
+            4 => Some(Blend::Add),
+            5 => Some(Blend::Multiply),
+            _ => None,
+        }
+    }
+
+    /// combine the RGB channels of `src` drawn over `dst`
+    fn apply(self, src: u32, dst: u32) -> u32 {
+        let mix = |f: fn(u32, u32) -> u32| {
+            (0..3).fold(OPAQUE, |color, n| {
+                let shift = 8 * n;
+                color | f(src >> shift & 0xFF, dst >> shift & 0xFF) << shift
+            })
+        };
+        match self {
+            Blend::Normal => src | OPAQUE,
+            Blend::Alpha25 => mix(|s, d| (s * 64 + d * 192) / 256),
+            Blend::Alpha50 => mix(|s, d| (s + d) / 2),
+            Blend::Alpha75 => mix(|s, d| (s * 192 + d * 64) / 256),
+            Blend::Add => mix(|s, d| (s + d).min(0xFF)),
+            Blend::Multiply => mix(|s, d| s * d / 0xFF),
+        }
+    }
+}
+
+/// MegaChip8 drawing state, entered with 0011. Sprites are drawn with
+/// palette indices into a 256x192 back buffer that 00E0 presents and clears.
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MegaChip {
+    palette: Vec<u32>,
+    sprite_width: usize,
+    sprite_height: usize,
+    blend: Blend,
+    /// 09NN: drawing over a pixel of this index is a collision
+    collision_index: u8,
+    /// 05NN: opacity of the presented screen, fades it to black
+    alpha: u8,
+    /// ARGB back buffer, row-major
+    colors: Vec<u32>,
+    /// palette index of every back buffer pixel, for collisions
+    indices: Vec<u8>,
+}
+
+impl Default for MegaChip {
+    fn default() -> Self {
+        MegaChip {
+            palette: vec![0; PALETTE_SIZE],
+            sprite_width: 0,
+            sprite_height: 0,
+            blend: Blend::Normal,
+            collision_index: 0,
+            alpha: 0xFF,
+            colors: vec![0; MEGA_WIDTH * MEGA_HEIGHT],
+            indices: vec![0; MEGA_WIDTH * MEGA_HEIGHT],
+        }
+    }
+}
+
+impl MegaChip {
+    /// 02NN: ARGB colors, 4 bytes each, for the indices from 1 on
+    pub fn load_palette(&mut self, colors: &[u8]) {
+        for (n, argb) in colors.chunks_exact(4).take(PALETTE_SIZE - 1).enumerate() {
+            self.palette[n + 1] = u32::from_be_bytes([argb[0], argb[1], argb[2], argb[3]]);
+        }
+    }
+
+    /// 03NN: sprite width in pixels, 0 is 256
+    pub fn set_sprite_width(&mut self, width: u8) {
+        self.sprite_width = width as usize;
+    }
+
+    /// 04NN: sprite height in pixels, 0 is 256
+    pub fn set_sprite_height(&mut self, height: u8) {
+        self.sprite_height = height as usize;
+    }
+
+    /// bytes of a DXYN sprite, one palette index per pixel
+    pub fn sprite_size(&self) -> usize {
+        let (width, height) = self.sprite_dimensions();
+        width * height
+    }
+
+    fn sprite_dimensions(&self) -> (usize, usize) {
+        let size = |n: usize| if n == 0 { 256 } else { n };
+        (size(self.sprite_width), size(self.sprite_height))
+    }
+
+    pub fn set_alpha(&mut self, alpha: u8) {
+        self.alpha = alpha;
+    }
+
+    pub fn set_blend(&mut self, blend: Blend) {
+        self.blend = blend;
+    }
+
+    pub fn set_collision_index(&mut self, index: u8) {
+        self.collision_index = index;
+    }
+
+    /// DXYN: draw the sprite `data` at (x, y), index 0 is transparent and
+    /// pixels past the edges are clipped. Return true for a collision.
+    pub fn draw(&mut self, x: usize, y: usize, data: &[u8]) -> bool {
+        let (width, height) = self.sprite_dimensions();
+        let mut collision = false;
+        for (row, line) in data.chunks(width).take(height).enumerate() {
+            let py = y + row;
+            if py >= MEGA_HEIGHT {
+                break;
+            }
+            for (col, &index) in line.iter().enumerate() {
+                let px = x + col;
+                if index == 0 || px >= MEGA_WIDTH {
+                    continue;
+                }
+                let at = py * MEGA_WIDTH + px;
+                collision |= self.indices[at] == self.collision_index;
+                self.indices[at] = index;
+                self.colors[at] = self
+                    .blend
+                    .apply(self.palette[index as usize], self.colors[at]);
+            }
+        }
+        collision
+    }
+
+    /// 00E0: the back buffer for the screen, the back buffer is cleared
+    pub fn present(&mut self) -> Vec<u32> {
+        let alpha = self.alpha as u32;
+        let frame = self
+            .colors
+            .iter()
+            .map(|&color| Blend::Multiply.apply(alpha * 0x0001_0101, color))
+            .collect();
+        self.colors.fill(0);
+        self.indices.fill(0);
+        frame
+    }
+
+    /// 00BN: scroll the back buffer up by n pixels
+    pub fn scroll_up(&mut self, n: usize) {
+        self.scroll_rows(n, true);
+    }
+
+    /// 00CN: scroll the back buffer down by n pixels
+    pub fn scroll_down(&mut self, n: usize) {
+        self.scroll_rows(n, false);
+    }
+
+    fn scroll_rows(&mut self, n: usize, up: bool) {
+        let shift = n.min(MEGA_HEIGHT) * MEGA_WIDTH;
+        shift_slice(&mut self.indices, shift, up);
+        shift_slice(&mut self.colors, shift, up);
+    }
+
+    /// 00FB/00FC: scroll the back buffer right or left by n pixels
+    pub fn scroll_sideways(&mut self, n: usize, right: bool) {
+        let n = n.min(MEGA_WIDTH);
+        for y in 0..MEGA_HEIGHT {
+            let row = y * MEGA_WIDTH..(y + 1) * MEGA_WIDTH;
+            shift_slice(&mut self.indices[row.clone()], n, !right);
+            shift_slice(&mut self.colors[row], n, !right);
+        }
+    }
+}
+
+/// move the items of `buffer` by `shift` towards the start or the end and
+/// clear what was uncovered
+fn shift_slice<T: Copy + Default>(buffer: &mut [T], shift: usize, to_start: bool) {
+    if to_start {
+        buffer.rotate_left(shift);
+        let len = buffer.len();
+        buffer[len - shift..].fill(T::default());
+    } else {
+        buffer.rotate_right(shift);
+        buffer[..shift].fill(T::default());
+    }
+}
+
+#[cfg(test)]
+mod megachip_test {
+    use super::*;
+
+    #[test]
+    fn blend_scroll_and_present() {
+        let (src, dst) = (0xFF80_4000, 0xFF40_80FF);
+        assert_eq!(Blend::Normal.apply(src, dst), 0xFF80_4000);
+        assert_eq!(Blend::Alpha50.apply(src, dst), 0xFF60_607F);
+        assert_eq!(Blend::Alpha75.apply(src, dst), 0xFF70_503F);
+        assert_eq!(Blend::Add.apply(src, dst), 0xFFC0_C0FF);
+        assert_eq!(Blend::Multiply.apply(src, dst), 0xFF20_2000);
+        assert_eq!(Blend::from_mode(6), None);
+
+        let mut mega = MegaChip::default();
+        mega.load_palette(&[0xFF, 0xFF, 0, 0, 0xFF, 0, 0, 0xFF]);
+        mega.set_sprite_width(2);
+        mega.set_sprite_height(2);
+        mega.set_collision_index(1);
+        // clipped at the right edge, index 0 is transparent
+        assert!(!mega.draw(255, 0, &[1, 1, 0, 2]));
+        assert!(mega.draw(254, 0, &[2, 2, 0, 0]));
+        mega.scroll_down(1);
+        mega.scroll_sideways(4, false);
+        mega.set_alpha(0x80);
+        let frame = mega.present();
+        assert_eq!(frame[MEGA_WIDTH + 250] & 0xFF_FFFF, 0x80);
+        assert_eq!(frame[MEGA_WIDTH + 251] & 0xFF_FFFF, 0x80);
+        assert_eq!(frame[2 * MEGA_WIDTH + 251] & 0xFF_FFFF, 0);
+        assert!(mega.colors.iter().all(|&color| color == 0));
+    }
+}
@@ -0,0 +1,167 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::text::{self, ADVANCE, GLYPH_HEIGHT};
+use crate::video::Video;
+use crate::{err, Result};
+
+/// rom files the menu lists
+pub const ROM_EXTENSIONS: [&str; 5] = ["ch8", "sc8", "hex", "8o", "asm"];
+
+const WIDTH: usize = 128;
+const HEIGHT: usize = 64;
+const LINE_HEIGHT: usize = GLYPH_HEIGHT + 1;
+// the first line is the title
+const VISIBLE: usize = HEIGHT / LINE_HEIGHT - 1;
+
+/// Rom picker shown when no rom is given, independent of the machine,
+/// it is rendered as a 128x64 display like the SCHIP hires mode
+pub struct RomMenu {
+    dir: PathBuf,
+    roms: Vec<PathBuf>,
+    selected: usize,
+    // first visible entry
+    top: usize,
+}
+
+impl RomMenu {
+    /// list the roms in `dir` and its sub directories
+    pub fn scan<P: AsRef<Path>>(dir: P) -> Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        let mut roms = vec![];
+        find_roms(&dir, &mut roms)?;
+        if roms.is_empty() {
+            return err!(
+                "no roms with extension {} found in {}",
+                ROM_EXTENSIONS.join("/"),
+                dir.display()
+            );
+        }
+        roms.sort();
+        Ok(RomMenu {
+            dir,
+            roms,
+            selected: 0,
+            top: 0,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.roms.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.roms.is_empty()
+    }
+
+    pub fn selected(&self) -> &Path {
+        &self.roms[self.selected]
+    }
+
+    /// move the selection by `offset` entries, clamped to the list
+    pub fn move_by(&mut self, offset: isize) {
+        let last = self.roms.len() as isize - 1;
+        self.selected = (self.selected as isize + offset).clamp(0, last) as usize;
+        if self.selected < self.top {
+            self.top = self.selected;
+        } else if self.selected >= self.top + VISIBLE {
+            self.top = self.selected + 1 - VISIBLE;
+        }
+    }
+
+    pub fn up(&mut self) {
+        self.move_by(-1)
+    }
+
+    pub fn down(&mut self) {
+        self.move_by(1)
+    }
+
+    pub fn page_up(&mut self) {
+        self.move_by(-(VISIBLE as isize))
+    }
+
+    pub fn page_down(&mut self) {
+        self.move_by(VISIBLE as isize)
+    }
+
+    /// hires display with the list, the selected entry is inverted
+    pub fn render(&self) -> Video {
+        let mut video = Video::new(WIDTH, HEIGHT);
+        let columns = WIDTH / ADVANCE;
+        let title = format!("SELECT A ROM {}/{}", self.selected + 1, self.roms.len());
+        text::draw_text(&mut video, 1, 0, &title, true);
+
+        for (line, index) in (self.top..self.roms.len()).take(VISIBLE).enumerate() {
+            let y = (line + 1) * LINE_HEIGHT;
+            let name: String = self.name(index).chars().take(columns).collect();
+            if index == self.selected {
+                for row in y - 1..y + GLYPH_HEIGHT {
+                    for x in 0..WIDTH {
+                        video.set_pixel(x, row, true);
+                    }
+                }
+                text::draw_text(&mut video, 1, y, &name, false);
+            } else {
+                text::draw_text(&mut video, 1, y, &name, true);
+            }
+        }
+        video
+    }
+
+    /// path relative to the rom directory without the extension
+    fn name(&self, index: usize) -> String {
+        let path = &self.roms[index];
+        let relative = path.strip_prefix(&self.dir).unwrap_or(path);
+        relative.with_extension("").to_string_lossy().into_owned()
+    }
+}
+
+pub(crate) fn find_roms(dir: &Path, roms: &mut Vec<PathBuf>) -> Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            find_roms(&path, roms)?;
+        } else if path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| ROM_EXTENSIONS.contains(&ext.to_ascii_lowercase().as_str()))
+        {
+            roms.push(path);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod menu_test {
+    use super::*;
+
+    #[test]
+    fn scan_and_move() {
+        let mut menu = RomMenu::scan("roms").unwrap();
+        assert!(menu.len() > VISIBLE);
+        assert!(menu.selected().extension().unwrap() == "ch8");
+
+        menu.up();
+        assert_eq!(menu.selected, 0);
+        for _ in 0..VISIBLE {
+            menu.down();
+        }
+        assert_eq!((menu.selected, menu.top), (VISIBLE, 1));
+        menu.page_down();
+        menu.page_up();
+        assert_eq!((menu.selected, menu.top), (VISIBLE, VISIBLE));
+
+        let video = menu.render();
+        assert_eq!((video.width(), video.height()), (WIDTH, HEIGHT));
+        // the selected line is drawn inverted on the first visible line
+        assert!(video.pixel(0, LINE_HEIGHT));
+        assert!(!video.pixel(0, LINE_HEIGHT * 2));
+    }
+
+    #[test]
+    fn missing_roms() {
+        assert!(RomMenu::scan("src").is_err());
+    }
+}
@@ -0,0 +1,186 @@
+use std::fmt;
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use crate::audio::NullAudio;
+use crate::display::Palette;
+use crate::machine::Machine;
+use crate::rom::ROM;
+use crate::video::Video;
+
+/// Runs a function over and over, like criterion: a warm-up that also
+/// finds how many iterations fill a sample, then the samples
+pub struct Bencher {
+    pub warm_up: Duration,
+    pub sample_time: Duration,
+    pub samples: usize,
+}
+
+impl Default for Bencher {
+    fn default() -> Self {
+        Bencher {
+            warm_up: Duration::from_millis(500),
+            sample_time: Duration::from_millis(100),
+            samples: 30,
+        }
+    }
+}
+
+/// The time of one iteration of a benchmark
+pub struct Measurement {
+    pub name: &'static str,
+    /// median over the samples
+    pub median: Duration,
+    pub fastest: Duration,
+    pub slowest: Duration,
+    pub iterations: u64,
+}
+
+impl fmt::Display for Measurement {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let ns = |time: Duration| time.as_nanos() as f64;
+        write!(
+            f,
+            "{:<24} {:>10.1}ns [{:.1}ns {:.1}ns] {} iterations",
+            self.name,
+            ns(self.median),
+            ns(self.fastest),
+            ns(self.slowest),
+            self.iterations
+        )
+    }
+}
+
+impl Bencher {
+    pub fn measure<F: FnMut()>(&self, name: &'static str, mut f: F) -> Measurement {
+        let start = Instant::now();
+        let mut warm_up = 0u64;
+        while start.elapsed() < self.warm_up || warm_up == 0 {
+            f();
+            warm_up += 1;
+        }
+        let per_sample = (warm_up as u128 * self.sample_time.as_nanos()
+            / start.elapsed().as_nanos().max(1))
+        .max(1) as u32;
+        let mut samples: Vec<Duration> = (0..self.samples.max(1))
+            .map(|_| {
+                let start = Instant::now();
+                for _ in 0..per_sample {
+                    f();
+                }
+                start.elapsed() / per_sample
+            })
+            .collect();
+        samples.sort();
+        Measurement {
+            name,
+            median: samples[samples.len() / 2],
+            fastest: samples[0],
+            slowest: samples[samples.len() - 1],
+            iterations: per_sample as u64 * samples.len() as u64,
+        }
+    }
+}
+
+/// `Video::draw` of a font sprite all over the lores display
+pub fn video_draw(bencher: &Bencher) -> Measurement {
+    let mut video = Video::new(64, 32);
+    let sprite = [0xF0, 0x90, 0xF0, 0x90, 0x90];
+    let mut n = 0;
+    bencher.measure("video_draw", || {
+        n = (n + 7) % (64 * 32);
+        black_box(video.draw(n % 64, n / 64, 5, black_box(&sprite), false));
+    })
+}
+
+/// `Video::draw_large` of a 16x16 sprite on the hires display
+pub fn video_draw_large(bencher: &Bencher) -> Measurement {
+    let mut video = Video::new(64, 32);
+    video.set_hires(true);
+    let sprite = [0xA5; 32];
+    let mut n = 0;
+    bencher.measure("video_draw_large", || {
+        n = (n + 7) % (128 * 64);
+        black_box(video.draw_large(n % 128, n / 128, black_box(&sprite), true));
+    })
+}
+
+/// a loop counting V0 up and drawing its digit
+const CYCLE_ROM: [u8; 8] = [0x70, 0x01, 0xF0, 0x29, 0xD1, 0x15, 0x12, 0x00];
+
+fn cycle_machine(decode_cache: bool) -> Machine<NullAudio> {
+    let mut machine = Machine::new().unwrap();
+    machine.set_seed(0);
+    if decode_cache {
+        machine.enable_decode_cache();
+    }
+    machine.load_font().unwrap();
+    machine
+        .load_rom(&ROM::from_bytes("bench.ch8", CYCLE_ROM.to_vec()))
+        .unwrap();
+    machine
+}
+
+/// `Machine::run_cycle` of the fetch, decode and execute loop
+pub fn run_cycle(bencher: &Bencher) -> Measurement {
+    let mut machine = cycle_machine(false);
+    bencher.measure("run_cycle", || {
+        black_box(machine.run_cycle()).unwrap();
+    })
+}
+
+/// `Machine::run_cycle` with the decode cache of `--fast`
+pub fn run_cycle_cached(bencher: &Bencher) -> Measurement {
+    let mut machine = cycle_machine(true);
+    bencher.measure("run_cycle_cached", || {
+        black_box(machine.run_cycle()).unwrap();
+    })
+}
+
+/// `Video::render_rgba` of a full hires framebuffer, what a frontend does
+/// every frame
+pub fn render_rgba(bencher: &Bencher) -> Measurement {
+    let mut video = Video::new(64, 32);
+    video.set_hires(true);
+    for n in 0..64 {
+        video.draw_large(n * 2, n, &[0xA5; 32], true);
+    }
+    let palette = Palette::default();
+    let mut buffer = vec![0; 128 * 64 * 4];
+    bencher.measure("render_rgba", || {
+        video.render_rgba(black_box(&mut buffer), &palette);
+    })
+}
+
+/// every benchmark of this module
+pub fn all(bencher: &Bencher) -> Vec<Measurement> {
+    vec![
+        video_draw(bencher),
+        video_draw_large(bencher),
+        run_cycle(bencher),
+        run_cycle_cached(bencher),
+        render_rgba(bencher),
+    ]
+}
+
+#[cfg(test)]
+mod microbench_test {
+    use super::*;
+
+    #[test]
+    fn test_measure() {
+        let bencher = Bencher {
+            warm_up: Duration::from_millis(1),
+            sample_time: Duration::from_millis(1),
+            samples: 3,
+        };
+        let measurements = all(&bencher);
+        assert_eq!(measurements.len(), 5);
+        for measurement in measurements {
+            assert!(measurement.iterations >= 3);
+            assert!(measurement.fastest <= measurement.median);
+            assert!(measurement.median <= measurement.slowest);
+            assert!(measurement.to_string().starts_with(measurement.name));
+        }
+    }
+}
@@ -0,0 +1,67 @@
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::Result;
+
+/// Something the frontend did to the machine
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Input {
+    KeyDown {
+        key: u8,
+    },
+    KeyUp {
+        key: u8,
+    },
+    /// a 60Hz timer tick, recorded because frontends tick by wall clock
+    Timer,
+}
+
+/// An input that happened before instruction number `cycle` since reset
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MovieEvent {
+    pub cycle: u64,
+    #[serde(flatten)]
+    pub input: Input,
+}
+
+/// Inputs of a run from reset, played back they reproduce it exactly
+/// together with the random seed
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Movie {
+    pub seed: u64,
+    pub events: Vec<MovieEvent>,
+}
+
+impl Movie {
+    pub fn new(seed: u64) -> Self {
+        Movie {
+            seed,
+            events: vec![],
+        }
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let writer = BufWriter::new(File::create(path)?);
+        serde_json::to_writer(writer, self)?;
+        Ok(())
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let reader = BufReader::new(File::open(path)?);
+        Ok(serde_json::from_reader(reader)?)
+    }
+}
+
+/// What the machine does with its movie
+pub(crate) enum MovieMode {
+    Recording(Movie),
+    /// `next` is the index of the next event to feed
+    Playing {
+        movie: Movie,
+        next: usize,
+    },
+}
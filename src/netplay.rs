@@ -0,0 +1,257 @@
+use std::collections::VecDeque;
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::audio::AudioPlay;
+use crate::machine::Machine;
+use crate::quirks::Quirks;
+use crate::{err, Result};
+
+/// bumped when the messages change, both sides have to agree
+const VERSION: u32 = 1;
+/// frames of input delay by default, local keys take effect this many frames
+/// later so the peer's keys for a frame are usually there in time
+pub const DEFAULT_DELAY: u32 = 2;
+/// a peer that sends nothing for this long is gone
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// What the host decides for both machines, sent to the joining side when
+/// it connects so both run the same rom the same way
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Session {
+    pub version: u32,
+    /// SHA-1 of the rom, the joining side has to have the same one
+    pub rom: String,
+    pub seed: u64,
+    pub clock_freq: u64,
+    pub quirks: Quirks,
+    pub delay: u32,
+}
+
+impl Session {
+    pub fn new(rom: String, seed: u64, clock_freq: u64, quirks: Quirks, delay: u32) -> Self {
+        Session {
+            version: VERSION,
+            rom,
+            seed,
+            clock_freq,
+            quirks,
+            delay,
+        }
+    }
+
+    /// the seed and quirks of the session set on `machine`, which restarts
+    pub fn apply<T: AudioPlay>(&self, machine: &mut Machine<T>) -> Result<()> {
+        machine.set_seed(self.seed);
+        machine.set_quirks(self.quirks);
+        machine.reset()
+    }
+}
+
+/// Two machines in lockstep over TCP. Every frame both sides send their
+/// keys and wait for the other's, the keys of both players are merged and
+/// set on the machines before they run the frame. With the same rom,
+/// seed, quirks and speed both then run exactly the same.
+pub struct Netplay {
+    stream: TcpStream,
+    session: Session,
+    frame: u32,
+    /// keys and checks sent for the frames the peer's keys did not come for yet
+    sent: VecDeque<(u16, u32)>,
+    desynced: bool,
+}
+
+impl Netplay {
+    /// wait on `addr` for the other player to join
+    pub fn host<A: ToSocketAddrs>(addr: A, session: Session) -> Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        info!("netplay: waiting on {}", listener.local_addr()?);
+        Netplay::accept(&listener, session)
+    }
+
+    /// the first player to connect to `listener`
+    pub fn accept(listener: &TcpListener, session: Session) -> Result<Self> {
+        let (mut stream, peer) = listener.accept()?;
+        info!("netplay: {} joined", peer);
+        // the session goes first with its length, the frames follow
+        let json = serde_json::to_vec(&session)?;
+        stream.write_all(&(json.len() as u32).to_le_bytes())?;
+        stream.write_all(&json)?;
+        Netplay::start(stream, session)
+    }
+
+    /// join the player hosting on `addr` with the rom of SHA-1 `rom`
+    pub fn join<A: ToSocketAddrs>(addr: A, rom: &str) -> Result<Self> {
+        let mut stream = TcpStream::connect(addr)?;
+        stream.set_read_timeout(Some(TIMEOUT))?;
+        let mut len = [0; 4];
+        stream.read_exact(&mut len)?;
+        let mut json = vec![0; u32::from_le_bytes(len).min(4096) as usize];
+        stream.read_exact(&mut json)?;
+        let session: Session =
+            serde_json::from_slice(&json).map_err(|e| format!("netplay: bad session: {}", e))?;
+        if session.version != VERSION {
+            return err!(
+                "netplay: the host speaks version {}, this is version {}",
+                session.version,
+                VERSION
+            );
+        }
+        if session.rom != rom {
+            return err!("netplay: the host runs another rom, SHA-1 {}", session.rom);
+        }
+        info!("netplay: joined {}", stream.peer_addr()?);
+        Netplay::start(stream, session)
+    }
+
+    /// both sides start by sending nothing pressed for the delay frames
+    fn start(stream: TcpStream, session: Session) -> Result<Self> {
+        stream.set_nodelay(true)?;
+        stream.set_read_timeout(Some(TIMEOUT))?;
+        let mut netplay = Netplay {
+            stream,
+            session,
+            frame: 0,
+            sent: VecDeque::new(),
+            desynced: false,
+        };
+        for frame in 0..netplay.session.delay {
+            netplay.send(frame, 0, 0)?;
+        }
+        Ok(netplay)
+    }
+
+    pub fn session(&self) -> &Session {
+        &self.session
+    }
+
+    /// Send the local `keys` and `check` of the machine state and return the
+    /// keys of both players for the next frame, waits for the peer
+    pub fn exchange(&mut self, keys: u16, check: u32) -> Result<u16> {
+        let frame = self.frame;
+        self.send(frame + self.session.delay, keys, check)?;
+        let mut message = [0; 10];
+        self.stream
+            .read_exact(&mut message)
+            .map_err(|e| format!("netplay: lost the other player: {}", e))?;
+        let peer_frame = u32::from_le_bytes([message[0], message[1], message[2], message[3]]);
+        let peer_keys = u16::from_le_bytes([message[4], message[5]]);
+        let peer_check = u32::from_le_bytes([message[6], message[7], message[8], message[9]]);
+        if peer_frame != frame {
+            return err!("netplay: expected frame {}, got {}", frame, peer_frame);
+        }
+        let (keys, check) = self.sent.pop_front().unwrap_or_default();
+        if check != peer_check && !self.desynced {
+            warn!(
+                "netplay: the machines went out of sync before frame {}",
+                frame
+            );
+            self.desynced = true;
+        }
+        self.frame += 1;
+        Ok(keys | peer_keys)
+    }
+
+    fn send(&mut self, frame: u32, keys: u16, check: u32) -> Result<()> {
+        let mut message = [0; 10];
+        message[..4].copy_from_slice(&frame.to_le_bytes());
+        message[4..6].copy_from_slice(&keys.to_le_bytes());
+        message[6..].copy_from_slice(&check.to_le_bytes());
+        self.stream.write_all(&message)?;
+        self.sent.push_back((keys, check));
+        Ok(())
+    }
+}
+
+/// press and release keys on `machine` so exactly `keys` are held
+pub fn set_keys<T: AudioPlay>(machine: &mut Machine<T>, keys: u16) {
+    for key in 0..16 {
+        let down = keys & (1 << key) != 0;
+        if down != machine.is_key_down(key) {
+            if down {
+                machine.key_down(key);
+            } else {
+                machine.key_up(key);
+            }
+        }
+    }
+}
+
+/// FNV-1a of the registers, to notice when the two machines diverge
+pub fn state_check<T: AudioPlay>(machine: &Machine<T>) -> u32 {
+    let pc = machine.pc().to_le_bytes();
+    let i = machine.i().to_le_bytes();
+    let timers = [machine.delay_timer(), machine.sound_timer()];
+    [&pc[..], &i[..], machine.registers(), &timers[..]]
+        .iter()
+        .flat_map(|bytes| bytes.iter())
+        .fold(0x811C_9DC5, |hash, &byte| {
+            (hash ^ byte as u32).wrapping_mul(0x0100_0193)
+        })
+}
+
+#[cfg(test)]
+mod netplay_test {
+    use super::*;
+    use crate::audio::NullAudio;
+    use crate::headless;
+    use crate::rom::ROM;
+    use std::thread;
+    use std::time::Instant;
+
+    /// run a few frames holding `presses`, return the registers, the seed
+    /// and every key that was set on the machine
+    fn play(netplay: &mut Netplay, rom: &ROM, presses: u16) -> (Vec<u8>, u64, u16) {
+        let mut machine: Machine<NullAudio> = Machine::new().unwrap();
+        machine.load_font().unwrap();
+        machine.load_rom(rom).unwrap();
+        netplay.session().apply(&mut machine).unwrap();
+        let mut seen = 0;
+        for _ in 0..10 {
+            let keys = netplay.exchange(presses, state_check(&machine)).unwrap();
+            seen |= keys;
+            set_keys(&mut machine, keys);
+            headless::run_frame(&mut machine, 600, Instant::now()).unwrap();
+        }
+        assert!(!netplay.desynced);
+        (machine.registers().to_vec(), machine.seed(), seen)
+    }
+
+    #[test]
+    fn test_lockstep() {
+        // V0 random, V1 counts the frames key 5 is held, V2 those of key 9
+        let program = vec![
+            0xC0, 0xFF, 0x63, 0x05, 0xE3, 0xA1, 0x71, 0x01, 0x63, 0x09, 0xE3, 0xA1, 0x72, 0x01,
+            0x12, 0x00,
+        ];
+        let rom = ROM::from_bytes("netplay.ch8", program.clone());
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let session = Session::new(rom.sha1(), 7, 600, Quirks::default(), DEFAULT_DELAY);
+        let host = thread::spawn(move || {
+            let rom = ROM::from_bytes("netplay.ch8", program);
+            let mut netplay = Netplay::accept(&listener, session).unwrap();
+            (play(&mut netplay, &rom, 1 << 5), netplay)
+        });
+        // the connection stays open until both ran all frames
+        let mut netplay = Netplay::join(addr, &rom.sha1()).unwrap();
+        let guest = play(&mut netplay, &rom, 1 << 9);
+        let (host, _) = host.join().unwrap();
+        assert_eq!(guest.1, 7);
+        assert_eq!(host.2, 1 << 5 | 1 << 9);
+        assert_eq!(guest, host);
+        // both keys came after the same delay
+        assert_eq!(host.0[1], host.0[2]);
+        assert!(host.0[1] > 0);
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let session = Session::new(rom.sha1(), 7, 600, Quirks::default(), DEFAULT_DELAY);
+        let host = thread::spawn(move || Netplay::accept(&listener, session).is_ok());
+        assert!(Netplay::join(addr, "another rom").is_err());
+        host.join().unwrap();
+    }
+}
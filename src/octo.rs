@@ -0,0 +1,714 @@
+use std::collections::HashMap;
+
+use crate::symbols::Symbols;
+use crate::{err, Result};
+
+/// where Octo programs are loaded and start
+const START: u16 = 0x200;
+
+/// Compile a program in Octo syntax, the language of the Octo IDE, into a
+/// rom loaded at 0x200.
+///
+/// Execution starts at the `main` label, a jump to it is put in front
+/// unless `main` is at the start of the program. Supported are the CHIP-8 and
+/// SUPER-CHIP statements, `audio` and `pitch := vx` of XO-CHIP, structured
+/// `if ... then`, `if ... begin ... else ... end` and `loop ... while ...
+/// again`, and the directives `:`, `:const`, `:alias`, `:unpack`, `:next`,
+/// `:org`, `:byte` and `:call`. Macros, `:calc` and the XO-CHIP memory and
+/// plane extensions are not, this machine has neither.
+pub fn compile(source: &str) -> Result<Vec<u8>> {
+    compile_with_symbols(source).map(|(rom, _)| rom)
+}
+
+/// `compile` and the addresses of the labels
+pub fn compile_with_symbols(source: &str) -> Result<(Vec<u8>, Symbols)> {
+    let tokens = tokenize(source);
+    // the first pass finds the addresses of labels used before they are
+    // defined, statements have the same size whatever the values are
+    let mut jump = true;
+    let mut labels = first_pass(&tokens, jump)?;
+    if labels.get("main") == Some(&(START + 2)) {
+        jump = false;
+        labels = first_pass(&tokens, jump)?;
+    }
+    let mut compiler = Compiler::new(&tokens, labels, true);
+    compiler.program(jump)?;
+    let symbols = Symbols::from_labels(compiler.labels.iter().map(|(name, &addr)| (name, addr)));
+    Ok((compiler.rom, symbols))
+}
+
+fn first_pass(tokens: &[Token], jump: bool) -> Result<HashMap<String, u16>> {
+    let mut compiler = Compiler::new(tokens, HashMap::new(), false);
+    compiler.program(jump)?;
+    Ok(compiler.labels)
+}
+
+struct Token<'a> {
+    text: &'a str,
+    line: usize,
+}
+
+/// whitespace separated words, `#` comments to the end of the line
+fn tokenize(source: &str) -> Vec<Token<'_>> {
+    source
+        .lines()
+        .enumerate()
+        .flat_map(|(n, line)| {
+            let code = line.split('#').next().unwrap();
+            code.split_whitespace()
+                .map(move |text| Token { text, line: n + 1 })
+        })
+        .collect()
+}
+
+/// A test compiled to a skip, after the instructions that set VF for it
+struct Condition {
+    setup: Vec<u16>,
+    /// skips the next instruction when the condition holds
+    skip_if_true: u16,
+    /// skips the next instruction when it does not
+    skip_if_false: u16,
+}
+
+/// open `begin`, `else` and `loop` blocks, with the addresses of the jumps
+/// waiting for the end of the block
+enum Block {
+    If(u16),
+    Else(u16),
+    Loop { start: u16, exits: Vec<u16> },
+}
+
+struct Compiler<'a> {
+    tokens: &'a [Token<'a>],
+    next: usize,
+    rom: Vec<u8>,
+    addr: u16,
+    labels: HashMap<String, u16>,
+    constants: HashMap<String, u16>,
+    aliases: HashMap<String, u16>,
+    blocks: Vec<Block>,
+    /// unknown names are errors, otherwise they are labels defined later
+    final_pass: bool,
+}
+
+impl<'a> Compiler<'a> {
+    fn new(tokens: &'a [Token<'a>], labels: HashMap<String, u16>, final_pass: bool) -> Self {
+        Compiler {
+            tokens,
+            next: 0,
+            rom: vec![],
+            addr: START,
+            labels,
+            constants: HashMap::new(),
+            aliases: HashMap::new(),
+            blocks: vec![],
+            final_pass,
+        }
+    }
+
+    /// compile all tokens, after a jump to main with `jump`
+    fn program(&mut self, jump: bool) -> Result<()> {
+        if jump {
+            self.emit(0x1000);
+        }
+        while self.next < self.tokens.len() {
+            let line = self.tokens[self.next].line;
+            self.statement()
+                .map_err(|e| format!("line {}: {}", line, e))?;
+        }
+        match self.blocks.last() {
+            Some(Block::Loop { .. }) => return err!("loop without again"),
+            Some(_) => return err!("begin without end"),
+            None => {}
+        }
+        match self.labels.get("main") {
+            Some(&main) if jump => self.patch(START, 0x1000 | main),
+            None if self.final_pass => return err!("the program has no main label"),
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn take(&mut self) -> Result<&'a str> {
+        match self.tokens.get(self.next) {
+            Some(token) => {
+                self.next += 1;
+                Ok(token.text)
+            }
+            None => err!("the program ends too early"),
+        }
+    }
+
+    fn peek(&self) -> Option<&'a str> {
+        self.tokens.get(self.next).map(|token| token.text)
+    }
+
+    fn expect(&mut self, expected: &str) -> Result<()> {
+        match self.take()? {
+            found if found == expected => Ok(()),
+            found => err!("expect {}, got {}", expected, found),
+        }
+    }
+
+    fn emit_byte(&mut self, byte: u8) {
+        let offset = (self.addr - START) as usize;
+        if self.rom.len() <= offset {
+            self.rom.resize(offset + 1, 0);
+        }
+        self.rom[offset] = byte;
+        self.addr += 1;
+    }
+
+    fn emit(&mut self, opcode: u16) {
+        self.emit_byte((opcode >> 8) as u8);
+        self.emit_byte(opcode as u8);
+    }
+
+    fn patch(&mut self, addr: u16, opcode: u16) {
+        let offset = (addr - START) as usize;
+        self.rom[offset..offset + 2].copy_from_slice(&opcode.to_be_bytes());
+    }
+
+    /// a jump to be pointed at the end of a block, its address
+    fn emit_exit(&mut self) -> u16 {
+        let addr = self.addr;
+        self.emit(0x1000);
+        addr
+    }
+
+    fn define_label(&mut self, name: &str, addr: u16) -> Result<()> {
+        check_name(name)?;
+        if !self.final_pass && self.labels.insert(name.to_string(), addr).is_some() {
+            return err!("duplicate label: {}", name);
+        }
+        Ok(())
+    }
+
+    fn statement(&mut self) -> Result<()> {
+        let token = self.take()?;
+        match token {
+            ":" => {
+                let name = self.take()?;
+                self.define_label(name, self.addr)?;
+            }
+            ":const" => {
+                let name = self.take()?;
+                check_name(name)?;
+                let value = self.value(0xFFFF)?;
+                self.constants.insert(name.to_string(), value);
+            }
+            ":alias" => {
+                let name = self.take()?;
+                check_name(name)?;
+                let x = self.register()?;
+                self.aliases.insert(name.to_string(), x);
+            }
+            ":unpack" => {
+                let nibble = self.value(0xF)?;
+                let addr = self.value(0xFFF)?;
+                self.emit(0x6000 | nibble << 4 | addr >> 8);
+                self.emit(0x6100 | (addr & 0xFF));
+            }
+            ":next" => {
+                let name = self.take()?;
+                self.define_label(name, self.addr + 1)?;
+            }
+            ":org" => match self.value(0xFFF)? {
+                addr if addr < START => return err!(":org {:#X} is below {:#X}", addr, START),
+                addr => self.addr = addr,
+            },
+            ":byte" => {
+                let byte = self.byte()?;
+                self.emit_byte(byte);
+            }
+            ":call" => {
+                let addr = self.value(0xFFF)?;
+                self.emit(0x2000 | addr);
+            }
+            "clear" => self.emit(0x00E0),
+            "return" | ";" => self.emit(0x00EE),
+            "scroll-down" => {
+                let n = self.value(0xF)?;
+                self.emit(0x00C0 | n);
+            }
+            "scroll-right" => self.emit(0x00FB),
+            "scroll-left" => self.emit(0x00FC),
+            "exit" => self.emit(0x00FD),
+            "lores" => self.emit(0x00FE),
+            "hires" => self.emit(0x00FF),
+            "audio" => self.emit(0xF002),
+            "native" => {
+                let addr = self.value(0xFFF)?;
+                self.emit(addr);
+            }
+            "jump" => {
+                let addr = self.value(0xFFF)?;
+                self.emit(0x1000 | addr);
+            }
+            "jump0" => {
+                let addr = self.value(0xFFF)?;
+                self.emit(0xB000 | addr);
+            }
+            "sprite" => {
+                let (x, y) = (self.register()?, self.register()?);
+                let n = self.value(0xF)?;
+                self.emit(0xD000 | x << 8 | y << 4 | n);
+            }
+            "bcd" | "save" | "load" | "saveflags" | "loadflags" => {
+                let x = self.register()?;
+                if self.peek() == Some("-") {
+                    return err!("{} of a register range is XO-CHIP, not supported", token);
+                }
+                let low = match token {
+                    "bcd" => 0x33,
+                    "save" => 0x55,
+                    "load" => 0x65,
+                    "saveflags" => 0x75,
+                    _ => 0x85,
+                };
+                self.emit(0xF000 | x << 8 | low);
+            }
+            "delay" | "buzzer" | "pitch" => {
+                self.expect(":=")?;
+                let x = self.register()?;
+                let low = match token {
+                    "delay" => 0x15,
+                    "buzzer" => 0x18,
+                    _ => 0x3A,
+                };
+                self.emit(0xF000 | x << 8 | low);
+            }
+            "i" => self.index()?,
+            "if" => {
+                let condition = self.condition()?;
+                match self.take()? {
+                    "then" => {
+                        self.emit_setup(&condition);
+                        self.emit(condition.skip_if_false);
+                        let before = self.addr;
+                        self.statement()?;
+                        if self.addr != before + 2 {
+                            return err!(
+                                "then is followed by more than one instruction, use begin and end"
+                            );
+                        }
+                    }
+                    "begin" => {
+                        self.emit_setup(&condition);
+                        self.emit(condition.skip_if_true);
+                        let exit = self.emit_exit();
+                        self.blocks.push(Block::If(exit));
+                    }
+                    found => return err!("expect then or begin, got {}", found),
+                }
+            }
+            "else" => match self.blocks.pop() {
+                Some(Block::If(exit)) => {
+                    let end = self.emit_exit();
+                    self.patch(exit, 0x1000 | self.addr);
+                    self.blocks.push(Block::Else(end));
+                }
+                _ => return err!("else without begin"),
+            },
+            "end" => match self.blocks.pop() {
+                Some(Block::If(exit)) | Some(Block::Else(exit)) => {
+                    self.patch(exit, 0x1000 | self.addr)
+                }
+                _ => return err!("end without begin"),
+            },
+            "loop" => self.blocks.push(Block::Loop {
+                start: self.addr,
+                exits: vec![],
+            }),
+            "while" => {
+                let condition = self.condition()?;
+                self.emit_setup(&condition);
+                self.emit(condition.skip_if_true);
+                let exit = self.emit_exit();
+                let innermost = self.blocks.iter_mut().rev().find_map(|block| match block {
+                    Block::Loop { exits, .. } => Some(exits),
+                    _ => None,
+                });
+                match innermost {
+                    Some(exits) => exits.push(exit),
+                    None => return err!("while outside of a loop"),
+                }
+            }
+            "again" => match self.blocks.pop() {
+                Some(Block::Loop { start, exits }) => {
+                    self.emit(0x1000 | start);
+                    for exit in exits {
+                        self.patch(exit, 0x1000 | self.addr);
+                    }
+                }
+                _ => return err!("again without loop"),
+            },
+            _ if token.starts_with(':') => {
+                return err!("unsupported directive: {}", token);
+            }
+            _ => {
+                if let Some(x) = self.lookup_register(token) {
+                    return self.assignment(x);
+                }
+                if is_number(token) || self.constants.contains_key(token) {
+                    // data bytes
+                    self.next -= 1;
+                    let byte = self.byte()?;
+                    self.emit_byte(byte);
+                } else {
+                    // a label on its own calls it
+                    let addr = self.name_value(token, 0xFFF)?;
+                    self.emit(0x2000 | addr);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// `i := addr`, `i := hex vx`, `i := bighex vx` and `i += vx`
+    fn index(&mut self) -> Result<()> {
+        match self.take()? {
+            ":=" => match self.peek() {
+                Some("hex") | Some("bighex") => {
+                    let low = if self.take()? == "hex" { 0x29 } else { 0x30 };
+                    let x = self.register()?;
+                    self.emit(0xF000 | x << 8 | low);
+                }
+                Some("long") => return err!("i := long is XO-CHIP, not supported"),
+                _ => {
+                    let addr = self.value(0xFFF)?;
+                    self.emit(0xA000 | addr);
+                }
+            },
+            "+=" => {
+                let x = self.register()?;
+                self.emit(0xF01E | x << 8);
+            }
+            op => return err!("unknown operator for i: {}", op),
+        }
+        Ok(())
+    }
+
+    /// `vx := ...` and the other operators on a register
+    fn assignment(&mut self, x: u16) -> Result<()> {
+        let op = self.take()?;
+        let rhs = self.peek();
+        let y = rhs.and_then(|rhs| self.lookup_register(rhs));
+        if y.is_some() {
+            self.next += 1;
+        }
+        let opcode = match (op, y) {
+            (":=", Some(y)) => 0x8000 | x << 8 | y << 4,
+            ("|=", Some(y)) => 0x8001 | x << 8 | y << 4,
+            ("&=", Some(y)) => 0x8002 | x << 8 | y << 4,
+            ("^=", Some(y)) => 0x8003 | x << 8 | y << 4,
+            ("+=", Some(y)) => 0x8004 | x << 8 | y << 4,
+            ("-=", Some(y)) => 0x8005 | x << 8 | y << 4,
+            (">>=", Some(y)) => 0x8006 | x << 8 | y << 4,
+            ("=-", Some(y)) => 0x8007 | x << 8 | y << 4,
+            ("<<=", Some(y)) => 0x800E | x << 8 | y << 4,
+            (":=", None) => match rhs {
+                Some("random") => {
+                    self.next += 1;
+                    0xC000 | x << 8 | self.byte()? as u16
+                }
+                Some("key") => {
+                    self.next += 1;
+                    0xF00A | x << 8
+                }
+                Some("delay") => {
+                    self.next += 1;
+                    0xF007 | x << 8
+                }
+                _ => 0x6000 | x << 8 | self.byte()? as u16,
+            },
+            ("+=", None) => 0x7000 | x << 8 | self.byte()? as u16,
+            ("-=", None) => 0x7000 | x << 8 | (self.byte()? as u16).wrapping_neg() & 0xFF,
+            (op, _) => return err!("unknown operator: {}", op),
+        };
+        self.emit(opcode);
+        Ok(())
+    }
+
+    /// `vx == n`, `vx != vy`, `vx key`, `vx -key` or an order like `vx < n`
+    fn condition(&mut self) -> Result<Condition> {
+        let x = self.register()?;
+        let op = self.take()?;
+        let simple = |skip_if_true: u16, skip_if_false: u16| Condition {
+            setup: vec![],
+            skip_if_true,
+            skip_if_false,
+        };
+        match op {
+            "key" => return Ok(simple(0xE09E | x << 8, 0xE0A1 | x << 8)),
+            "-key" => return Ok(simple(0xE0A1 | x << 8, 0xE09E | x << 8)),
+            _ => {}
+        }
+        let rhs = self.take()?;
+        let y = self.lookup_register(rhs);
+        let n = match y {
+            Some(_) => 0,
+            None => {
+                self.next -= 1;
+                self.byte()? as u16
+            }
+        };
+        let (equal, not_equal) = match y {
+            Some(y) => (0x5000 | x << 8 | y << 4, 0x9000 | x << 8 | y << 4),
+            None => (0x3000 | x << 8 | n, 0x4000 | x << 8 | n),
+        };
+        match op {
+            "==" => return Ok(simple(equal, not_equal)),
+            "!=" => return Ok(simple(not_equal, equal)),
+            "<" | ">" | "<=" | ">=" => {}
+            _ => return err!("unknown comparison: {}", op),
+        }
+        // VF = 1 when `a >= b`, computed as `vf := b; vf =- a`. `x > n` is
+        // `x >= n + 1` and `x <= n` is `x < n + 1` as the constant can
+        // only be subtracted from.
+        let (setup, flag) = match (op, y) {
+            ("<", Some(y)) => (vec![0x8F00 | y << 4, 0x8F07 | x << 4], 0),
+            (">=", Some(y)) => (vec![0x8F00 | y << 4, 0x8F07 | x << 4], 1),
+            (">", Some(y)) => (vec![0x8F00 | x << 4, 0x8F07 | y << 4], 0),
+            ("<=", Some(y)) => (vec![0x8F00 | x << 4, 0x8F07 | y << 4], 1),
+            ("<", None) | (">=", None) => (vec![0x6F00 | n, 0x8F07 | x << 4], (op == ">=") as u16),
+            (_, None) if n == 0xFF => {
+                return err!("{} 255 is always {}", op, op == "<=");
+            }
+            // > and <= of a constant
+            _ => (vec![0x6F00 | (n + 1), 0x8F07 | x << 4], (op == ">") as u16),
+        };
+        Ok(Condition {
+            setup,
+            skip_if_true: 0x3F00 | flag,
+            skip_if_false: 0x4F00 | flag,
+        })
+    }
+
+    fn emit_setup(&mut self, condition: &Condition) {
+        for &opcode in &condition.setup {
+            self.emit(opcode);
+        }
+    }
+
+    fn lookup_register(&self, name: &str) -> Option<u16> {
+        register(name).or_else(|| self.aliases.get(name).copied())
+    }
+
+    fn register(&mut self) -> Result<u16> {
+        let token = self.take()?;
+        match self.lookup_register(token) {
+            Some(x) => Ok(x),
+            None => err!("expect a register, got {}", token),
+        }
+    }
+
+    /// a number, constant or label no bigger than `max`
+    fn value(&mut self, max: u16) -> Result<u16> {
+        let token = self.take()?;
+        match parse_number(token) {
+            Some(value) if (0..=max as i64).contains(&value) => Ok(value as u16),
+            Some(_) => err!("{} does not fit in {:#X}", token, max),
+            None => self.name_value(token, max),
+        }
+    }
+
+    /// a byte, negative numbers are taken as two's complement
+    fn byte(&mut self) -> Result<u8> {
+        let token = self.take()?;
+        match parse_number(token) {
+            Some(value) if (-128..=255).contains(&value) => Ok(value as u8),
+            Some(_) => err!("{} does not fit in a byte", token),
+            None => self.name_value(token, 0xFF).map(|value| value as u8),
+        }
+    }
+
+    fn name_value(&self, name: &str, max: u16) -> Result<u16> {
+        let value = match self.constants.get(name).or_else(|| self.labels.get(name)) {
+            Some(&value) => value,
+            None if self.final_pass || register(name).is_some() => {
+                return err!("unknown name: {}", name)
+            }
+            // a label defined further down
+            None => return check_name(name).map(|_| 0),
+        };
+        if value > max {
+            return err!("{} is {:#X}, it does not fit in {:#X}", name, value, max);
+        }
+        Ok(value)
+    }
+}
+
+/// v0 to vf
+fn register(token: &str) -> Option<u16> {
+    let digit = token.strip_prefix(['v', 'V'])?;
+    if digit.len() == 1 {
+        u16::from_str_radix(digit, 16).ok()
+    } else {
+        None
+    }
+}
+
+fn is_number(token: &str) -> bool {
+    parse_number(token).is_some()
+}
+
+/// decimal, `0x` hex or `0b` binary, with an optional `-`
+fn parse_number(token: &str) -> Option<i64> {
+    let (negative, digits) = match token.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, token),
+    };
+    let value = if let Some(hex) = digits.strip_prefix("0x") {
+        i64::from_str_radix(hex, 16).ok()?
+    } else if let Some(bin) = digits.strip_prefix("0b") {
+        i64::from_str_radix(bin, 2).ok()?
+    } else if digits.starts_with(|c: char| c.is_ascii_digit()) {
+        digits.parse().ok()?
+    } else {
+        return None;
+    };
+    Some(if negative { -value } else { value })
+}
+
+/// names of labels, constants and aliases
+fn check_name(name: &str) -> Result<()> {
+    let valid = name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+        && register(name).is_none()
+        && !KEYWORDS.contains(&name);
+    if valid {
+        Ok(())
+    } else {
+        err!("invalid name: {}", name)
+    }
+}
+
+const KEYWORDS: &[&str] = &[
+    "clear",
+    "return",
+    "scroll-down",
+    "scroll-right",
+    "scroll-left",
+    "exit",
+    "lores",
+    "hires",
+    "audio",
+    "native",
+    "jump",
+    "jump0",
+    "sprite",
+    "bcd",
+    "save",
+    "load",
+    "saveflags",
+    "loadflags",
+    "delay",
+    "buzzer",
+    "pitch",
+    "i",
+    "if",
+    "then",
+    "begin",
+    "else",
+    "end",
+    "loop",
+    "while",
+    "again",
+    "key",
+    "random",
+    "hex",
+    "bighex",
+    "long",
+];
+
+#[cfg(test)]
+mod octo_test {
+    use super::*;
+
+    fn words(rom: &[u8]) -> Vec<u16> {
+        rom.chunks(2)
+            .map(|pair| u16::from_be_bytes([pair[0], *pair.get(1).unwrap_or(&0)]))
+            .collect()
+    }
+
+    #[test]
+    fn test_compile() {
+        let source = "
+            # draw the digit in v0 and wait for a key
+            :alias digit v0
+            :const X 8
+            : main
+                clear
+                digit := 7
+                v1 := X  v2 := 4
+                i := hex digit
+                sprite v1 v2 5
+                if v1 != 0x08 then exit
+                draw-twice
+                loop
+                    v3 := key
+                    while v3 != 0xF
+                    v3 -= 1
+                again
+                jump main
+            : draw-twice
+                sprite v1 v2 5 ;
+            : data 0xFF -1 0b101
+        ";
+        assert_eq!(
+            words(&compile(source).unwrap()),
+            [
+                0x00E0, 0x6007, 0x6108, 0x6204, 0xF029, 0xD125, 0x3108, 0x00FD, 0x221E, 0xF30A,
+                0x430F, 0x121C, 0x73FF, 0x1212, 0x1200, 0xD125, 0x00EE, 0xFFFF, 0x0500,
+            ]
+        );
+
+        // a jump to main is put in front, labels can be used before they are defined
+        let source = ": data 1 2 : main i := data :unpack 0xA data";
+        assert_eq!(
+            words(&compile(source).unwrap()),
+            [0x1204, 0x0102, 0xA202, 0x60A2, 0x6102]
+        );
+    }
+
+    #[test]
+    fn test_structures() {
+        let source = ": main
+            if v1 == v2 begin v3 := 1 else v3 := 2 end
+            if v1 key then v4 += v5
+            if v1 < 5 then v4 := 0
+            if v1 > v2 then v4 := 0
+            if v1 <= 5 begin end";
+        assert_eq!(
+            words(&compile(source).unwrap()),
+            [
+                0x5120, 0x1208, 0x6301, 0x120A, 0x6302, 0xE1A1, 0x8454, 0x6F05, 0x8F17, 0x4F00,
+                0x6400, 0x8F10, 0x8F27, 0x4F00, 0x6400, 0x6F06, 0x8F17, 0x3F00, 0x1226,
+            ]
+        );
+
+        for invalid in [
+            "clear",
+            ": main jump nowhere",
+            ": main : main",
+            ": main loop",
+            ": main if v0 == 1 begin",
+            ": main again",
+            ": main while v0 == 1",
+            ": main v0 := 256",
+            ": main i := long 0x1000",
+            ": main if v0 == 1 then if v1 < 2 then clear",
+            ": main :macro",
+            ": main if v0 > 255 then clear",
+        ] {
+            assert!(compile(invalid).is_err(), "{}", invalid);
+        }
+        assert!(compile(": main\n\n  v0 += v16")
+            .unwrap_err()
+            .to_string()
+            .starts_with("line 3: "));
+    }
+}
@@ -0,0 +1,132 @@
+/// Operand of an instruction, in the order they appear in the mnemonic
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operand {
+    /// register VX, bits 8..12
+    Vx,
+    /// register VY, bits 4..8
+    Vy,
+    /// 8 bit immediate NN
+    Byte,
+    /// 12 bit address NNN
+    Addr,
+    /// 4 bit immediate N
+    Nibble,
+    /// fixed token like I, DT or [I]
+    Literal(&'static str),
+}
+
+/// One row of the instruction table: `opcode & mask == pattern`
+pub struct OpcodeSpec {
+    pub mask: u16,
+    pub pattern: u16,
+    pub mnemonic: &'static str,
+    pub operands: &'static [Operand],
+}
+
+use Operand::*;
+
+impl OpcodeSpec {
+    /// the opcode with its operand nibbles as letters, like `DXYN` or `FX1E`
+    pub fn name(&self) -> String {
+        (0..4)
+            .map(|i| {
+                let shift = 12 - 4 * i;
+                if self.mask >> shift & 0xF == 0xF {
+                    return char::from_digit((self.pattern >> shift & 0xF) as u32, 16)
+                        .unwrap()
+                        .to_ascii_uppercase();
+                }
+                match i {
+                    1 if self.operands.contains(&Vx) => 'X',
+                    2 if self.operands.contains(&Vy) => 'Y',
+                    _ => 'N',
+                }
+            })
+            .collect()
+    }
+}
+
+const fn spec(
+    mask: u16,
+    pattern: u16,
+    mnemonic: &'static str,
+    operands: &'static [Operand],
+) -> OpcodeSpec {
+    OpcodeSpec {
+        mask,
+        pattern,
+        mnemonic,
+        operands,
+    }
+}
+
+/// Cowgod style mnemonics for CHIP-8 and SUPER-CHIP, the XO-CHIP audio
+/// instructions are named after Octo and the MegaChip ones after its spec,
+/// more specific patterns come before the general ones. The four byte
+/// MegaChip 01NN NNNN is left out, it shows as SYS.
+pub const OPCODES: &[OpcodeSpec] = &[
+    spec(0xFFFF, 0x00E0, "CLS", &[]),
+    spec(0xFFFF, 0x00EE, "RET", &[]),
+    spec(0xFFF0, 0x00C0, "SCD", &[Nibble]),
+    spec(0xFFFF, 0x00FB, "SCR", &[]),
+    spec(0xFFFF, 0x00FC, "SCL", &[]),
+    spec(0xFFFF, 0x00FD, "EXIT", &[]),
+    spec(0xFFFF, 0x00FE, "LOW", &[]),
+    spec(0xFFFF, 0x00FF, "HIGH", &[]),
+    spec(0xFFFF, 0x0010, "MEGAOFF", &[]),
+    spec(0xFFFF, 0x0011, "MEGAON", &[]),
+    spec(0xFFF0, 0x00B0, "SCU", &[Nibble]),
+    spec(0xFF00, 0x0200, "LDPAL", &[Byte]),
+    spec(0xFF00, 0x0300, "SPRW", &[Byte]),
+    spec(0xFF00, 0x0400, "SPRH", &[Byte]),
+    spec(0xFF00, 0x0500, "ALPHA", &[Byte]),
+    spec(0xFFF0, 0x0600, "DIGISND", &[Nibble]),
+    spec(0xFFFF, 0x0700, "STOPSND", &[]),
+    spec(0xFFF0, 0x0800, "BMODE", &[Nibble]),
+    spec(0xFF00, 0x0900, "CCOL", &[Byte]),
+    spec(0xF000, 0x0000, "SYS", &[Addr]),
+    spec(0xF000, 0x1000, "JP", &[Addr]),
+    spec(0xF000, 0x2000, "CALL", &[Addr]),
+    spec(0xF000, 0x3000, "SE", &[Vx, Byte]),
+    spec(0xF000, 0x4000, "SNE", &[Vx, Byte]),
+    spec(0xF00F, 0x5000, "SE", &[Vx, Vy]),
+    spec(0xF000, 0x6000, "LD", &[Vx, Byte]),
+    spec(0xF000, 0x7000, "ADD", &[Vx, Byte]),
+    spec(0xF00F, 0x8000, "LD", &[Vx, Vy]),
+    spec(0xF00F, 0x8001, "OR", &[Vx, Vy]),
+    spec(0xF00F, 0x8002, "AND", &[Vx, Vy]),
+    spec(0xF00F, 0x8003, "XOR", &[Vx, Vy]),
+    spec(0xF00F, 0x8004, "ADD", &[Vx, Vy]),
+    spec(0xF00F, 0x8005, "SUB", &[Vx, Vy]),
+    spec(0xF00F, 0x8006, "SHR", &[Vx, Vy]),
+    spec(0xF00F, 0x8007, "SUBN", &[Vx, Vy]),
+    spec(0xF00F, 0x800E, "SHL", &[Vx, Vy]),
+    spec(0xF00F, 0x9000, "SNE", &[Vx, Vy]),
+    spec(0xF000, 0xA000, "LD", &[Literal("I"), Addr]),
+    spec(0xF000, 0xB000, "JP", &[Literal("V0"), Addr]),
+    spec(0xF000, 0xC000, "RND", &[Vx, Byte]),
+    spec(0xF000, 0xD000, "DRW", &[Vx, Vy, Nibble]),
+    spec(0xF0FF, 0xE09E, "SKP", &[Vx]),
+    spec(0xF0FF, 0xE0A1, "SKNP", &[Vx]),
+    spec(0xFFFF, 0xF002, "AUDIO", &[]),
+    spec(0xF0FF, 0xF007, "LD", &[Vx, Literal("DT")]),
+    spec(0xF0FF, 0xF00A, "LD", &[Vx, Literal("K")]),
+    spec(0xF0FF, 0xF015, "LD", &[Literal("DT"), Vx]),
+    spec(0xF0FF, 0xF018, "LD", &[Literal("ST"), Vx]),
+    spec(0xF0FF, 0xF01E, "ADD", &[Literal("I"), Vx]),
+    spec(0xF0FF, 0xF029, "LD", &[Literal("F"), Vx]),
+    spec(0xF0FF, 0xF030, "LD", &[Literal("HF"), Vx]),
+    spec(0xF0FF, 0xF033, "LD", &[Literal("B"), Vx]),
+    spec(0xF0FF, 0xF03A, "PITCH", &[Vx]),
+    spec(0xF0FF, 0xF055, "LD", &[Literal("[I]"), Vx]),
+    spec(0xF0FF, 0xF065, "LD", &[Vx, Literal("[I]")]),
+    spec(0xF0FF, 0xF075, "LD", &[Literal("R"), Vx]),
+    spec(0xF0FF, 0xF085, "LD", &[Vx, Literal("R")]),
+];
+
+/// find the table row matching an opcode
+pub fn lookup(opcode: u16) -> Option<&'static OpcodeSpec> {
+    OPCODES
+        .iter()
+        .find(|spec| opcode & spec.mask == spec.pattern)
+}
@@ -0,0 +1,341 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use pixels::{Pixels, SurfaceTexture};
+use winit::dpi::LogicalSize;
+use winit::event::{ElementState, Event, KeyboardInput, VirtualKeyCode, WindowEvent};
+use winit::event_loop::EventLoop;
+use winit::platform::run_return::EventLoopExtRunReturn;
+use winit::window::{Window, WindowBuilder};
+
+use crate::audio::{AudioConfig, CpalAudio, DynAudio, NullAudio};
+use crate::display::{Color, DisplayConfig, Phosphor};
+use crate::frontend::{self, DisplayBackend, Emulator, FrameInfo, InputBackend, InputEvent};
+use crate::hooks::FrameHook;
+use crate::logging::AUDIO;
+use crate::machine::Machine;
+use crate::rpl::FlagFile;
+#[cfg(feature = "settings-ui")]
+use crate::settings_ui::SettingsUi;
+use crate::watch::RomWatcher;
+use crate::{err, Quirks, Result};
+
+/// Settings of the winit/pixels frontend
+pub struct Options {
+    /// instructions per second, `UNLIMITED` runs as fast as possible
+    pub clock_freq: u64,
+    pub display: DisplayConfig,
+    /// None plays no sound, with `--no-audio`
+    pub audio: Option<AudioConfig>,
+    /// reload the rom when its file changes
+    pub watcher: Option<RomWatcher>,
+    /// the rom path, its file name is shown in the window title
+    pub title: String,
+    /// called after every frame that ran, e.g. a script
+    pub on_frame: Option<FrameHook<DynAudio>>,
+    /// keeps the RPL flags of the rom when they change
+    pub flags: Option<FlagFile>,
+}
+
+/// same physical layout as the SDL2 frontend, by key symbol, indexed by the
+/// CHIP-8 key
+const DEFAULT_KEYMAP: [VirtualKeyCode; 16] = [
+    VirtualKeyCode::X,
+    VirtualKeyCode::Key1,
+    VirtualKeyCode::Key2,
+    VirtualKeyCode::Key3,
+    VirtualKeyCode::Q,
+    VirtualKeyCode::W,
+    VirtualKeyCode::E,
+    VirtualKeyCode::A,
+    VirtualKeyCode::S,
+    VirtualKeyCode::D,
+    VirtualKeyCode::Z,
+    VirtualKeyCode::C,
+    VirtualKeyCode::Key4,
+    VirtualKeyCode::R,
+    VirtualKeyCode::F,
+    VirtualKeyCode::V,
+];
+
+/// What can be changed while the rom runs, from the settings panel with the
+/// settings-ui feature
+pub(crate) struct Settings {
+    pub clock_freq: u64,
+    pub quirks: Quirks,
+    pub foreground: Color,
+    pub background: Color,
+    pub volume: f32,
+    /// key symbol of every CHIP-8 key
+    pub keymap: [VirtualKeyCode; 16],
+    /// the CHIP-8 key bound to the next pressed key
+    pub rebinding: Option<u8>,
+}
+
+impl Settings {
+    fn key(&self, keycode: VirtualKeyCode) -> Option<u8> {
+        self.keymap
+            .iter()
+            .position(|&mapped| mapped == keycode)
+            .map(|key| key as u8)
+    }
+}
+
+/// copy the display, or its faded version with ghosting, into the RGBA
+/// frame of pixels, MegaChip frames are never faded
+fn pixels_draw(
+    frame: &mut [u8],
+    machine: &Machine<DynAudio>,
+    phosphor: Option<&Phosphor>,
+    config: &DisplayConfig,
+) {
+    match phosphor.filter(|_| !machine.get_display().is_mega()) {
+        Some(phosphor) => phosphor.render_rgba(frame, &config.palette()),
+        None => machine.get_display().render_rgba(frame, &config.palette()),
+    }
+}
+
+/// The window with its surface and settings, shared by `PixelsDisplay` and
+/// `PixelsInput` as winit hands the window events to the event loop
+struct PixelsWindow {
+    window: Window,
+    pixels: Pixels,
+    #[cfg(feature = "settings-ui")]
+    settings_ui: SettingsUi,
+    settings: Settings,
+    config: DisplayConfig,
+    phosphor: Option<Phosphor>,
+    buffer_size: (u32, u32),
+}
+
+/// The pixels surface as a display, with the settings panel over it
+pub struct PixelsDisplay(Rc<RefCell<PixelsWindow>>);
+
+impl DisplayBackend<DynAudio> for PixelsDisplay {
+    fn present(&mut self, machine: &mut Machine<DynAudio>, _frame: &FrameInfo) -> Result<()> {
+        let shared = &mut *self.0.borrow_mut();
+        if machine.quirks() != shared.settings.quirks {
+            machine.set_quirks(shared.settings.quirks);
+        }
+        shared.config.foreground = shared.settings.foreground;
+        shared.config.background = shared.settings.background;
+        if let Some(phosphor) = shared.phosphor.as_mut() {
+            phosphor.update(machine.get_display());
+        }
+        // SCHIP can switch between 64x32 and 128x64 at runtime
+        let size = (machine.width() as u32, machine.height() as u32);
+        if size != shared.buffer_size {
+            match shared.pixels.resize_buffer(size.0, size.1) {
+                Ok(()) => shared.buffer_size = size,
+                Err(e) => error!("can not resize the buffer: {}", e),
+            }
+        }
+        if size == shared.buffer_size {
+            pixels_draw(
+                shared.pixels.frame_mut(),
+                machine,
+                shared.phosphor.as_ref(),
+                &shared.config,
+            );
+        }
+        #[cfg(feature = "settings-ui")]
+        let rendered = {
+            let settings_ui = &mut shared.settings_ui;
+            settings_ui.prepare(&shared.window, &mut shared.settings);
+            shared.pixels.render_with(|encoder, target, context| {
+                context.scaling_renderer.render(encoder, target);
+                settings_ui.render(encoder, target, context);
+                Ok(())
+            })
+        };
+        #[cfg(not(feature = "settings-ui"))]
+        let rendered = shared.pixels.render();
+        match rendered {
+            Ok(()) => Ok(()),
+            Err(e) => err!("can not render: {}", e),
+        }
+    }
+
+    fn set_title(&mut self, title: &str) -> Result<()> {
+        self.0.borrow().window.set_title(title);
+        Ok(())
+    }
+}
+
+/// The winit event loop as an input: Esc quits, P pauses, M mutes and F1
+/// opens the settings with the settings-ui feature
+pub struct PixelsInput {
+    event_loop: EventLoop<()>,
+    window: Rc<RefCell<PixelsWindow>>,
+    // what the settings panel had last, a change is sent on
+    clock_freq: u64,
+    volume: f32,
+}
+
+impl PixelsInput {
+    fn window_event(&mut self, event: WindowEvent, events: &mut Vec<InputEvent>) {
+        let shared = &mut *self.window.borrow_mut();
+        #[cfg(feature = "settings-ui")]
+        if shared.settings_ui.handle_event(&event) {
+            return;
+        }
+        let settings = &mut shared.settings;
+        match event {
+            WindowEvent::CloseRequested => events.push(InputEvent::Quit),
+            WindowEvent::Resized(size) => {
+                if let Err(e) = shared.pixels.resize_surface(size.width, size.height) {
+                    error!("can not resize the surface: {}", e);
+                }
+            }
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        virtual_keycode: Some(keycode),
+                        state: ElementState::Pressed,
+                        ..
+                    },
+                ..
+            } if settings.rebinding.is_some() => {
+                // Esc cancels, a key already in use swaps with the old one
+                let key = settings.rebinding.take().unwrap() as usize;
+                if keycode != VirtualKeyCode::Escape {
+                    if let Some(other) = settings.key(keycode) {
+                        settings.keymap[other as usize] = settings.keymap[key];
+                    }
+                    settings.keymap[key] = keycode;
+                }
+            }
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        virtual_keycode: Some(keycode),
+                        state,
+                        ..
+                    },
+                ..
+            } => match (settings.key(keycode), state) {
+                (Some(key), ElementState::Pressed) => events.push(InputEvent::KeyDown(key)),
+                (Some(key), ElementState::Released) => events.push(InputEvent::KeyUp(key)),
+                (None, ElementState::Pressed) if keycode == VirtualKeyCode::Escape => {
+                    events.push(InputEvent::Quit)
+                }
+                (None, ElementState::Pressed) if keycode == VirtualKeyCode::P => {
+                    events.push(InputEvent::TogglePause)
+                }
+                (None, ElementState::Pressed) if keycode == VirtualKeyCode::M => {
+                    events.push(InputEvent::ToggleMute)
+                }
+                #[cfg(feature = "settings-ui")]
+                (None, ElementState::Pressed) if keycode == VirtualKeyCode::F1 => {
+                    shared.settings_ui.open = !shared.settings_ui.open;
+                }
+                _ => {}
+            },
+            _ => {}
+        }
+    }
+}
+
+impl InputBackend for PixelsInput {
+    fn poll(&mut self, events: &mut Vec<InputEvent>) -> Result<()> {
+        let mut window_events = vec![];
+        // run the event loop until the pending events are handled
+        self.event_loop
+            .run_return(|event, _, control_flow| match event {
+                Event::WindowEvent { event, .. } => window_events.extend(event.to_static()),
+                Event::MainEventsCleared => control_flow.set_exit(),
+                _ => {}
+            });
+        for event in window_events {
+            self.window_event(event, events);
+        }
+
+        let settings = &self.window.borrow().settings;
+        if settings.clock_freq != self.clock_freq {
+            self.clock_freq = settings.clock_freq;
+            events.push(InputEvent::SetSpeed(self.clock_freq));
+        }
+        if settings.volume != self.volume {
+            self.volume = settings.volume;
+            events.push(InputEvent::SetVolume(self.volume));
+        }
+        Ok(())
+    }
+}
+
+/// Run the machine in a winit window until it halts or the window is closed,
+/// Esc quits, P pauses, M mutes and F1 opens the settings with the
+/// settings-ui feature
+pub fn emulate(machine: &mut Machine<DynAudio>, options: Options) -> Result<()> {
+    let Options {
+        clock_freq,
+        display,
+        audio,
+        watcher,
+        title,
+        on_frame,
+        flags,
+    } = options;
+
+    let event_loop = EventLoop::new();
+    let (width, height) = (machine.width() as u32, machine.height() as u32);
+    let window = WindowBuilder::new()
+        .with_title(frontend::TITLE)
+        .with_inner_size(LogicalSize::new(
+            width * display.scale,
+            height * display.scale,
+        ))
+        .build(&event_loop)?;
+    let size = window.inner_size();
+    let pixels = Pixels::new(
+        width,
+        height,
+        SurfaceTexture::new(size.width, size.height, &window),
+    )?;
+    #[cfg(feature = "settings-ui")]
+    let settings_ui = SettingsUi::new(&event_loop, &window, &pixels);
+    let audio_device: DynAudio = match audio.map(CpalAudio::new) {
+        Some(Ok(device)) => Box::new(device),
+        Some(Err(e)) => {
+            warn!(target: AUDIO, "audio is disabled: {}", e);
+            Box::new(NullAudio)
+        }
+        None => Box::new(NullAudio),
+    };
+    let volume = audio.map_or(0.0, |audio| audio.volume);
+    let settings = Settings {
+        clock_freq,
+        quirks: machine.quirks(),
+        foreground: display.foreground,
+        background: display.background,
+        volume,
+        keymap: DEFAULT_KEYMAP,
+        rebinding: None,
+    };
+    let window = Rc::new(RefCell::new(PixelsWindow {
+        window,
+        pixels,
+        #[cfg(feature = "settings-ui")]
+        settings_ui,
+        settings,
+        phosphor: (display.ghosting > 0).then(|| Phosphor::new(display.ghosting)),
+        config: display,
+        buffer_size: (width, height),
+    }));
+    let input = PixelsInput {
+        event_loop,
+        window: Rc::clone(&window),
+        clock_freq,
+        volume,
+    };
+    let options = frontend::Options {
+        clock_freq,
+        volume,
+        watcher,
+        title: Some(title),
+        on_frame,
+        flags,
+        ..frontend::Options::default()
+    };
+    Emulator::new(machine, audio_device, PixelsDisplay(window), input, options).run()
+}
@@ -0,0 +1,127 @@
+use std::time::Duration;
+
+use crate::opcodes::{self, OPCODES};
+
+/// Executions and time spent per instruction of `opcodes::OPCODES`, turned
+/// on with `Machine::enable_profiler`
+pub struct Profile {
+    // by the index in OPCODES, the last one counts illegal opcodes
+    counts: Vec<u64>,
+    times: Vec<Duration>,
+}
+
+/// One instruction of a `Profile`, illegal opcodes are named `????`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProfileEntry {
+    /// like `DXYN`
+    pub name: String,
+    pub mnemonic: &'static str,
+    pub count: u64,
+    pub time: Duration,
+}
+
+impl Default for Profile {
+    fn default() -> Self {
+        Profile {
+            counts: vec![0; OPCODES.len() + 1],
+            times: vec![Duration::ZERO; OPCODES.len() + 1],
+        }
+    }
+}
+
+impl Profile {
+    pub fn record(&mut self, opcode: u16, elapsed: Duration) {
+        let index = OPCODES
+            .iter()
+            .position(|spec| opcode & spec.mask == spec.pattern)
+            .unwrap_or(OPCODES.len());
+        self.counts[index] += 1;
+        self.times[index] += elapsed;
+    }
+
+    pub fn total(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+
+    /// The executed instructions, the most executed first
+    pub fn entries(&self) -> Vec<ProfileEntry> {
+        let mut rows: Vec<usize> = (0..self.counts.len())
+            .filter(|&i| self.counts[i] > 0)
+            .collect();
+        rows.sort_by_key(|&i| std::cmp::Reverse(self.counts[i]));
+        rows.into_iter()
+            .map(|i| {
+                let (name, mnemonic) = match OPCODES.get(i) {
+                    Some(spec) => (spec.name(), spec.mnemonic),
+                    None => ("????".to_string(), "-"),
+                };
+                ProfileEntry {
+                    name,
+                    mnemonic,
+                    count: self.counts[i],
+                    time: self.times[i],
+                }
+            })
+            .collect()
+    }
+
+    /// Histogram lines, the most executed instruction first:
+    /// `DXYN DRW    41.0%  12345  1.234ms`
+    pub fn report(&self) -> Vec<String> {
+        let total = self.total().max(1);
+        self.entries()
+            .into_iter()
+            .map(|entry| {
+                format!(
+                    "{} {:<6} {:>5.1}% {:>10} {:>10.3}ms",
+                    entry.name,
+                    entry.mnemonic,
+                    entry.count as f64 * 100.0 / total as f64,
+                    entry.count,
+                    entry.time.as_secs_f64() * 1000.0
+                )
+            })
+            .collect()
+    }
+
+    /// count of the instruction named like `DXYN`
+    pub fn count(&self, name: &str) -> u64 {
+        OPCODES
+            .iter()
+            .position(|spec| spec.name() == name)
+            .map_or(0, |i| self.counts[i])
+    }
+}
+
+/// the `OPCODES` name of an opcode, like `DXYN` for D012
+pub fn opcode_name(opcode: u16) -> Option<String> {
+    opcodes::lookup(opcode).map(|spec| spec.name())
+}
+
+#[cfg(test)]
+mod profiler_test {
+    use super::*;
+
+    #[test]
+    fn test_profile() {
+        assert_eq!(opcode_name(0xD012).as_deref(), Some("DXYN"));
+        assert_eq!(opcode_name(0xF21E).as_deref(), Some("FX1E"));
+        assert_eq!(opcode_name(0x8124).as_deref(), Some("8XY4"));
+        assert_eq!(opcode_name(0x00C3).as_deref(), Some("00CN"));
+        assert_eq!(opcode_name(0x1234).as_deref(), Some("1NNN"));
+        assert_eq!(opcode_name(0x6A0F).as_deref(), Some("6XNN"));
+
+        let mut profile = Profile::default();
+        for _ in 0..3 {
+            profile.record(0xD012, Duration::from_micros(2));
+        }
+        profile.record(0x1200, Duration::from_micros(1));
+        profile.record(0xFFFF, Duration::ZERO);
+        assert_eq!(profile.total(), 5);
+        assert_eq!(profile.count("DXYN"), 3);
+        let report = profile.report();
+        assert_eq!(report.len(), 3);
+        assert!(report[0].starts_with("DXYN DRW     60.0%"), "{}", report[0]);
+        assert!(report[0].ends_with("0.006ms"));
+    }
+}
@@ -0,0 +1,173 @@
+use std::error::Error;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{err, Result};
+
+/// Behaviors that differ between CHIP-8 interpreters,
+/// the default keeps the behavior this emulator always had
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Quirks {
+    /// 8XY6/8XYE: shift VY and store the result in VX, instead of shifting VX in place
+    pub shift_uses_vy: bool,
+    /// FX55/FX65: I is left pointing after the last register stored or loaded
+    pub load_store_increment_i: bool,
+    /// BNNN: jump to XNN + VX instead of NNN + V0
+    pub jump_uses_vx: bool,
+    /// DXYN: wait for the next 60Hz timer tick before drawing, so at most
+    /// one sprite is drawn per frame like on the COSMAC VIP
+    pub display_wait: bool,
+    /// DXYN: sprite pixels past the right or bottom edge wrap around to the
+    /// other side instead of being clipped
+    pub wrap_sprites: bool,
+    /// 00CN/00FB/00FC: in lores scroll half as far, SUPER-CHIP 1.1 counts
+    /// the scrolled pixels in hires pixels
+    pub half_scroll: bool,
+    /// 2NNN: the call stack is 12 deep like on the COSMAC VIP instead of 16
+    #[serde(default)]
+    pub short_stack: bool,
+}
+
+impl Quirks {
+    pub const PROFILES: [&'static str; 3] = ["chip8", "schip", "xochip"];
+
+    /// `name=value` settings for single quirks, the first value turns it on
+    pub const OPTIONS: [(&'static str, &'static str, &'static str); 7] = [
+        ("shift", "vy", "vx"),
+        ("load-store", "increment", "keep"),
+        ("jump", "vx", "v0"),
+        ("display", "wait", "immediate"),
+        ("sprites", "wrap", "clip"),
+        ("scroll", "half", "full"),
+        ("stack", "12", "16"),
+    ];
+
+    /// change one quirk with a `name=value` setting from `OPTIONS`,
+    /// like `shift=vy` or `sprites=clip`
+    pub fn set(&mut self, setting: &str) -> Result<()> {
+        let (name, value) = match setting.split_once('=') {
+            Some((name, value)) => (name.trim(), value.trim().to_ascii_lowercase()),
+            None => return err!("invalid quirk setting: {}, expect name=value", setting),
+        };
+        let (_, on, off) = match Quirks::OPTIONS
+            .iter()
+            .find(|(option, _, _)| *option == name)
+        {
+            Some(option) => *option,
+            None => {
+                let names: Vec<&str> = Quirks::OPTIONS.iter().map(|option| option.0).collect();
+                return err!("unknown quirk: {}, expect one of {:?}", name, names);
+            }
+        };
+        let enabled = if value == on {
+            true
+        } else if value == off {
+            false
+        } else {
+            return err!(
+                "invalid value for quirk {}: {}, expect {} or {}",
+                name,
+                value,
+                on,
+                off
+            );
+        };
+        let flag = match name {
+            "shift" => &mut self.shift_uses_vy,
+            "load-store" => &mut self.load_store_increment_i,
+            "jump" => &mut self.jump_uses_vx,
+            "display" => &mut self.display_wait,
+            "sprites" => &mut self.wrap_sprites,
+            "scroll" => &mut self.half_scroll,
+            "stack" => &mut self.short_stack,
+            _ => unreachable!(),
+        };
+        *flag = enabled;
+        Ok(())
+    }
+
+    /// original COSMAC VIP interpreter
+    pub fn chip8() -> Self {
+        Quirks {
+            shift_uses_vy: true,
+            load_store_increment_i: true,
+            jump_uses_vx: false,
+            display_wait: true,
+            wrap_sprites: false,
+            half_scroll: false,
+            short_stack: true,
+        }
+    }
+
+    /// SUPER-CHIP 1.1 on the HP48
+    pub fn schip() -> Self {
+        Quirks {
+            shift_uses_vy: false,
+            load_store_increment_i: false,
+            jump_uses_vx: true,
+            display_wait: false,
+            wrap_sprites: false,
+            half_scroll: true,
+            short_stack: false,
+        }
+    }
+
+    /// Octo's XO-CHIP
+    pub fn xochip() -> Self {
+        Quirks {
+            shift_uses_vy: true,
+            load_store_increment_i: true,
+            jump_uses_vx: false,
+            display_wait: false,
+            wrap_sprites: true,
+            half_scroll: false,
+            short_stack: false,
+        }
+    }
+}
+
+impl FromStr for Quirks {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "chip8" | "chip-8" => Ok(Quirks::chip8()),
+            "schip" | "superchip" => Ok(Quirks::schip()),
+            "xochip" | "xo-chip" => Ok(Quirks::xochip()),
+            _ => err!(
+                "unknown compatibility profile: {}, expect one of {:?}",
+                s,
+                Quirks::PROFILES
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod quirks_test {
+    use super::*;
+
+    #[test]
+    fn set_single_quirks() {
+        let mut quirks: Quirks = "schip".parse().unwrap();
+        quirks.set("shift=vy").unwrap();
+        quirks.set("load-store=INCREMENT").unwrap();
+        quirks.set("jump=v0").unwrap();
+        quirks.set("sprites=wrap").unwrap();
+        quirks.set("scroll=full").unwrap();
+        quirks.set("stack=12").unwrap();
+        assert_eq!(
+            quirks,
+            Quirks {
+                wrap_sprites: true,
+                short_stack: true,
+                ..Quirks::xochip()
+            }
+        );
+
+        assert!(quirks.set("shift").is_err());
+        assert!(quirks.set("shift=vz").is_err());
+        assert!(quirks.set("vf-reset=on").is_err());
+    }
+}
@@ -0,0 +1,259 @@
+use std::io::{BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::thread;
+use std::time::Instant;
+
+use crossbeam_channel::{bounded, unbounded, Receiver, Sender};
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::audio::AudioPlay;
+use crate::headless::{self, RegisterDump};
+use crate::machine::Machine;
+use crate::rom::ROM;
+use crate::{err, Result, DEFAULT_CLOCK_FREQ, UNLIMITED};
+
+/// A command of the remote control protocol, one JSON object per line
+/// like `{"cmd": "read", "addr": 512, "len": 4}`
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(tag = "cmd", rename_all = "snake_case")]
+pub enum Request {
+    /// load a rom file and start it
+    Load {
+        path: String,
+    },
+    Reset,
+    Pause,
+    Resume,
+    /// run instructions, also while paused
+    Step {
+        #[serde(default = "one")]
+        cycles: usize,
+    },
+    /// run 60Hz frames at the current speed, also while paused
+    Frames {
+        #[serde(default = "one")]
+        count: usize,
+    },
+    Registers,
+    Read {
+        addr: u16,
+        len: usize,
+    },
+    Write {
+        addr: u16,
+        bytes: Vec<u8>,
+    },
+    KeyDown {
+        key: u8,
+    },
+    KeyUp {
+        key: u8,
+    },
+    /// the display as text, see `headless::display_to_text`
+    Display,
+    Quit,
+}
+
+fn one() -> usize {
+    1
+}
+
+/// A request from a client, the frontend answers it with `reply`
+pub struct Call {
+    pub request: Request,
+    reply: Sender<String>,
+}
+
+impl Call {
+    /// send `{"ok": value}` or `{"error": message}` back to the client
+    pub fn reply(self, result: Result<Value>) {
+        let reply = match result {
+            Ok(value) => json!({ "ok": value }),
+            Err(e) => json!({ "error": e.to_string() }),
+        };
+        let _ = self.reply.send(reply.to_string());
+    }
+}
+
+/// Listen for remote control clients on `addr`, for `--remote`. Every
+/// connection gets its own thread, their requests come out of the returned
+/// channel in order and are answered one at a time.
+pub fn serve<A: ToSocketAddrs>(addr: A) -> Result<(SocketAddr, Receiver<Call>)> {
+    let listener = TcpListener::bind(addr)?;
+    let local = listener.local_addr()?;
+    let (tx, rx) = unbounded();
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    let tx = tx.clone();
+                    thread::spawn(move || {
+                        if let Err(e) = client(stream, tx) {
+                            warn!("remote client: {}", e);
+                        }
+                    });
+                }
+                Err(e) => warn!("remote: {}", e),
+            }
+        }
+    });
+    Ok((local, rx))
+}
+
+fn client(stream: TcpStream, calls: Sender<Call>) -> Result<()> {
+    let peer = stream.peer_addr()?;
+    stream.set_nodelay(true)?;
+    info!("remote: {} connected", peer);
+    let mut writer = stream.try_clone()?;
+    for line in BufReader::new(stream).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let reply = match serde_json::from_str(&line) {
+            Ok(request) => {
+                let (reply, answer) = bounded(1);
+                if calls.send(Call { request, reply }).is_err() {
+                    break;
+                }
+                match answer.recv() {
+                    Ok(answer) => answer,
+                    Err(_) => break,
+                }
+            }
+            Err(e) => json!({ "error": format!("bad request: {}", e) }).to_string(),
+        };
+        writeln!(writer, "{}", reply)?;
+    }
+    info!("remote: {} disconnected", peer);
+    Ok(())
+}
+
+/// Answer the requests that only touch the machine. Load, pause, resume
+/// and quit change the frontend too and are answered by it.
+pub fn execute<T: AudioPlay>(
+    request: &Request,
+    machine: &mut Machine<T>,
+    clock_freq: u64,
+) -> Result<Value> {
+    match request {
+        Request::Reset => machine.reset()?,
+        Request::Step { cycles } => {
+            let executed = machine.step(*cycles)?;
+            return Ok(json!({ "cycles": executed, "pc": machine.pc() }));
+        }
+        Request::Frames { count } => {
+            // a frame has to end without a deadline
+            let clock_freq = if clock_freq == UNLIMITED {
+                DEFAULT_CLOCK_FREQ
+            } else {
+                clock_freq
+            };
+            for _ in 0..*count {
+                headless::run_frame(machine, clock_freq, Instant::now())?;
+            }
+            return Ok(json!({ "pc": machine.pc() }));
+        }
+        Request::Registers => return Ok(serde_json::to_value(RegisterDump::new(machine, 0))?),
+        Request::Read { addr, len } => {
+            let start = *addr as usize;
+            return match machine.memory().get(start..start + len) {
+                Some(bytes) => Ok(json!(bytes)),
+                None => err!("can not read {} bytes at {:#05X}", len, addr),
+            };
+        }
+        Request::Write { addr, bytes } => machine.write_memory(*addr, bytes)?,
+        Request::KeyDown { key } | Request::KeyUp { key } if *key > 0xF => {
+            return err!("no key {}, keys are 0 to 15", key)
+        }
+        Request::KeyDown { key } => machine.key_down(*key),
+        Request::KeyUp { key } => machine.key_up(*key),
+        Request::Display => return Ok(json!(headless::display_to_text(machine))),
+        Request::Load { path } => {
+            let rom = ROM::new(path)?;
+            machine.load_rom(&rom)?;
+            machine.reset()?;
+        }
+        Request::Pause | Request::Resume | Request::Quit => {
+            return err!("{:?} is up to the frontend", request)
+        }
+    }
+    Ok(Value::Null)
+}
+
+/// Answer requests until one says quit, for `--headless --remote`. The
+/// machine only runs on step and frames requests, pause and resume do nothing.
+pub fn run<T: AudioPlay>(
+    machine: &mut Machine<T>,
+    calls: &Receiver<Call>,
+    clock_freq: u64,
+) -> Result<()> {
+    for call in calls.iter() {
+        match call.request {
+            Request::Quit => {
+                call.reply(Ok(Value::Null));
+                break;
+            }
+            Request::Pause | Request::Resume => call.reply(Ok(Value::Null)),
+            _ => {
+                let result = execute(&call.request, machine, clock_freq);
+                call.reply(result);
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod remote_test {
+    use super::*;
+    use crate::audio::NullAudio;
+
+    #[test]
+    fn test_remote() {
+        let (addr, calls) = serve("127.0.0.1:0").unwrap();
+        let client = thread::spawn(move || {
+            let stream = TcpStream::connect(addr).unwrap();
+            stream.set_nodelay(true).unwrap();
+            let mut writer = stream.try_clone().unwrap();
+            let mut lines = BufReader::new(stream).lines();
+            let mut send = |line: &str| -> Value {
+                writeln!(writer, "{}", line).unwrap();
+                serde_json::from_str(&lines.next().unwrap().unwrap()).unwrap()
+            };
+            vec![
+                send(r#"{"cmd": "write", "addr": 512, "bytes": [96, 7, 241, 10]}"#),
+                send(r#"{"cmd": "read", "addr": 512, "len": 2}"#),
+                send(r#"{"cmd": "step", "cycles": 2}"#),
+                send(r#"{"cmd": "key_down", "key": 3}"#),
+                send(r#"{"cmd": "step"}"#),
+                send(r#"{"cmd": "key_up", "key": 3}"#),
+                send(r#"{"cmd": "step"}"#),
+                send(r#"{"cmd": "registers"}"#),
+                send(r#"{"cmd": "read", "addr": 4095, "len": 2}"#),
+                send(r#"{"cmd": "jump"}"#),
+                send(r#"{"cmd": "quit"}"#),
+            ]
+        });
+
+        let mut machine: Machine<NullAudio> = Machine::new().unwrap();
+        machine.load_font().unwrap();
+        run(&mut machine, &calls, 600).unwrap();
+        let replies = client.join().unwrap();
+        assert_eq!(replies[0], json!({ "ok": null }));
+        assert_eq!(replies[1], json!({ "ok": [96, 7] }));
+        // FX0A waits until key 3 is pressed and released
+        assert_eq!(replies[2], json!({ "ok": { "cycles": 2, "pc": 0x202 } }));
+        assert_eq!(replies[4], json!({ "ok": { "cycles": 1, "pc": 0x202 } }));
+        assert_eq!(replies[6], json!({ "ok": { "cycles": 1, "pc": 0x204 } }));
+        assert_eq!(replies[7]["ok"]["registers"][0], 7);
+        assert_eq!(replies[7]["ok"]["registers"][1], 3);
+        assert!(replies[8]["error"].is_string());
+        assert!(replies[9]["error"]
+            .as_str()
+            .unwrap()
+            .starts_with("bad request"));
+        assert_eq!(replies[10], json!({ "ok": null }));
+    }
+}
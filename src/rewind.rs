@@ -0,0 +1,150 @@
+use std::collections::VecDeque;
+
+use crate::state::SaveState;
+
+/// An older snapshot stored as the difference to the next newer one,
+/// only the memory bytes that changed are kept
+struct Delta {
+    // `memory` is left empty, it is rebuilt from the newer snapshot
+    state: SaveState,
+    // MegaChip memory goes up to 16MB, beyond u16 addresses
+    memory: Vec<(u32, u8)>,
+}
+
+/// Rolling buffer of machine snapshots for rewinding, usually pushed at 60Hz
+pub struct Rewind {
+    capacity: usize,
+    latest: Option<SaveState>,
+    // oldest first, the last one is the snapshot right before `latest`
+    history: VecDeque<Delta>,
+}
+
+impl Rewind {
+    /// keep at most `capacity` snapshots, the oldest are dropped first
+    pub fn new(capacity: usize) -> Self {
+        Rewind {
+            capacity: capacity.max(1),
+            latest: None,
+            history: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.history.len() + self.latest.is_some() as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.latest.is_none()
+    }
+
+    pub fn clear(&mut self) {
+        self.latest = None;
+        self.history.clear();
+    }
+
+    pub fn push(&mut self, state: SaveState) {
+        if let Some(mut older) = self.latest.take() {
+            let memory = older
+                .memory
+                .iter()
+                .zip(state.memory.iter())
+                .enumerate()
+                .filter(|(_, (old, new))| old != new)
+                .map(|(addr, (&old, _))| (addr as u32, old))
+                .collect();
+            older.memory = vec![];
+            self.history.push_back(Delta {
+                state: older,
+                memory,
+            });
+            if self.history.len() >= self.capacity {
+                self.history.pop_front();
+            }
+        }
+        self.latest = Some(state);
+    }
+
+    /// Drop the newest snapshot and return the one before it,
+    /// the oldest snapshot is kept and returned again once the buffer runs out
+    pub fn step_back(&mut self) -> Option<&SaveState> {
+        if let Some(Delta { mut state, memory }) = self.history.pop_back() {
+            let newer = self.latest.take()?;
+            state.memory = newer.memory;
+            for (addr, byte) in memory {
+                state.memory[addr as usize] = byte;
+            }
+            self.latest = Some(state);
+        }
+        self.latest.as_ref()
+    }
+
+    /// remove and return the newest snapshot, the one before it becomes the
+    /// newest
+    pub fn pop(&mut self) -> Option<SaveState> {
+        let newest = self.latest.take()?;
+        if let Some(Delta { mut state, memory }) = self.history.pop_back() {
+            state.memory = newest.memory.clone();
+            for (addr, byte) in memory {
+                state.memory[addr as usize] = byte;
+            }
+            self.latest = Some(state);
+        }
+        Some(newest)
+    }
+}
+
+#[cfg(test)]
+mod rewind_test {
+    use super::*;
+    use crate::audio::NullAudio;
+    use crate::machine::Machine;
+
+    #[test]
+    fn step_back() {
+        let mut machine: Machine<NullAudio> = Machine::new().unwrap();
+        let mut rewind = Rewind::new(3);
+        assert!(rewind.step_back().is_none());
+
+        let mut states = vec![];
+        for pc in 0..4u16 {
+            let mut state = machine.save_state();
+            state.pc = 0x200 + pc * 2;
+            state.memory[0x300 + pc as usize] = pc as u8 + 1;
+            machine.load_state(&state).unwrap();
+            states.push(state.clone());
+            rewind.push(state);
+        }
+        assert_eq!(rewind.len(), 3);
+
+        for expected in [&states[2], &states[1], &states[1]] {
+            let state = rewind.step_back().unwrap();
+            assert_eq!(state.pc, expected.pc);
+            assert_eq!(state.memory, expected.memory);
+        }
+        assert_eq!(rewind.len(), 1);
+
+        rewind.push(states[3].clone());
+        assert_eq!(rewind.pop().unwrap().pc, states[3].pc);
+        assert_eq!(rewind.pop().unwrap().memory, states[1].memory);
+        assert!(rewind.pop().is_none());
+
+        rewind.push(states[0].clone());
+        rewind.clear();
+        assert!(rewind.is_empty());
+    }
+
+    #[test]
+    fn step_back_mega_memory() {
+        let machine: Machine<NullAudio> = Machine::new().unwrap();
+        let mut older = machine.save_state();
+        older.memory = vec![0; 1 << 17];
+        older.memory[0x10300] = 1;
+        let mut newer = older.clone();
+        newer.memory[0x10300] = 2;
+        newer.memory[0x300] = 3;
+        let mut rewind = Rewind::new(3);
+        rewind.push(older.clone());
+        rewind.push(newer);
+        assert_eq!(rewind.step_back().unwrap().memory, older.memory);
+    }
+}
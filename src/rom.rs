@@ -1,26 +1,77 @@
-use std::fs::File;
+use std::fs;
+#[cfg(feature = "net")]
 use std::io::Read;
+use std::path::{Path, PathBuf};
+#[cfg(feature = "net")]
+use std::time::Duration;
 
-use crate::Result;
+use crate::symbols::Symbols;
+use crate::{assembler, err, octo, Result};
+
+/// where programs are loaded, `.asm` sources are assembled for it
+const START_ADDR: u16 = 0x200;
+/// largest download accepted for a rom URL, room for a hex dump of the
+/// 64KB XO-CHIP memory
+#[cfg(feature = "net")]
+const MAX_DOWNLOAD: u64 = 256 * 1024;
+#[cfg(feature = "net")]
+const DOWNLOAD_TIMEOUT: Duration = Duration::from_secs(30);
 
 #[derive(Debug)]
 pub struct ROM {
     pub name: String,
     raw: Vec<u8>,
     length: usize,
+    // labels of a rom built from source
+    symbols: Symbols,
 }
 
 impl ROM {
+    /// Load a rom file. `.ch8` and `.sc8` are binary, `.hex` is a hex text
+    /// dump (see `parse_hex`), `.8o` is Octo source compiled with
+    /// `octo::compile` and `.asm` is assembly source in the dialect of
+    /// `assembler::assemble`. Any other file that reads as a hex dump is
+    /// taken as one, otherwise it is binary. With the net feature `path` can
+    /// also be an http(s) URL.
     pub fn new(path: &str) -> Result<Self> {
-        let mut temp_f = File::open(path)?;
-        let mut raw = Vec::new();
-        temp_f.read_to_end(&mut raw)?;
+        let bytes = if is_url(path) {
+            download(path)?
+        } else {
+            fs::read(path)?
+        };
+        let extension = Path::new(path)
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(str::to_ascii_lowercase);
+        let built = match extension.as_deref() {
+            Some("ch8") | Some("sc8") => Ok((bytes, Symbols::default())),
+            Some("hex") => String::from_utf8(bytes)
+                .map_err(Into::into)
+                .and_then(|text| parse_hex(&text))
+                .map(|raw| (raw, Symbols::default())),
+            Some("8o") => String::from_utf8(bytes)
+                .map_err(Into::into)
+                .and_then(|source| octo::compile_with_symbols(&source)),
+            Some("asm") => String::from_utf8(bytes)
+                .map_err(Into::into)
+                .and_then(|source| assembler::assemble_with_symbols(&source, START_ADDR)),
+            _ => Ok((sniff_hex(&bytes).unwrap_or(bytes), Symbols::default())),
+        };
+        let (raw, symbols) = built.map_err(|e| format!("rom {}: {}", path, e))?;
+        let mut rom = Self::from_bytes(path, raw);
+        rom.symbols = symbols;
+        Ok(rom)
+    }
+
+    /// rom from raw bytes, `name` is used for display only
+    pub fn from_bytes(name: &str, raw: Vec<u8>) -> Self {
         let length = raw.len();
-        Ok(ROM {
-            name: path.to_string(),
+        ROM {
+            name: name.to_string(),
             raw,
             length,
-        })
+            symbols: Symbols::default(),
+        }
     }
 
     pub fn len(&self) -> usize {
@@ -34,4 +85,145 @@ impl ROM {
     pub fn is_empty(&self) -> bool {
         self.length == 0
     }
+
+    /// labels of a rom assembled or compiled from source, empty otherwise
+    pub fn symbols(&self) -> &Symbols {
+        &self.symbols
+    }
+
+    /// SHA-1 of the rom bytes as lowercase hex, the key of the rom database
+    pub fn sha1(&self) -> String {
+        sha1_smol::Sha1::from(&self.raw).digest().to_string()
+    }
+}
+
+/// F5 saves the state of the rom at `path` here, next to the rom, a rom
+/// from a URL keeps it in the working directory
+pub fn state_path(path: &str) -> PathBuf {
+    let name = match path.rsplit_once('/') {
+        Some((_, file)) if is_url(path) => file,
+        _ => path,
+    };
+    PathBuf::from(format!("{}.state", name))
+}
+
+/// `path` is an http or https URL
+pub fn is_url(path: &str) -> bool {
+    path.starts_with("http://") || path.starts_with("https://")
+}
+
+#[cfg(feature = "net")]
+fn download(url: &str) -> Result<Vec<u8>> {
+    let response = ureq::get(url).timeout(DOWNLOAD_TIMEOUT).call()?;
+    let mut bytes = vec![];
+    response
+        .into_reader()
+        .take(MAX_DOWNLOAD + 1)
+        .read_to_end(&mut bytes)?;
+    if bytes.len() as u64 > MAX_DOWNLOAD {
+        return err!("{} is larger than {} bytes", url, MAX_DOWNLOAD);
+    }
+    info!(
+        "downloaded {} bytes from {}, sha1 {}",
+        bytes.len(),
+        url,
+        sha1_smol::Sha1::from(&bytes).digest()
+    );
+    Ok(bytes)
+}
+
+#[cfg(not(feature = "net"))]
+fn download(url: &str) -> Result<Vec<u8>> {
+    err!("built without the net feature, can not download {}", url)
+}
+
+/// the bytes of `bytes` read as a hex dump, None when it is not one
+fn sniff_hex(bytes: &[u8]) -> Option<Vec<u8>> {
+    let text = std::str::from_utf8(bytes).ok()?;
+    parse_hex(text).ok().filter(|raw| !raw.is_empty())
+}
+
+/// Parse a hex dump like `00E0 A22A` or `0xF0, 0x90`. Tokens are separated
+/// by whitespace or commas and have an even number of digits, `#`, `;` and
+/// `//` start a comment and address labels like `0200:` are skipped.
+pub(crate) fn parse_hex(text: &str) -> Result<Vec<u8>> {
+    let mut raw = vec![];
+    for line in text.lines() {
+        let code = ["#", ";", "//"]
+            .iter()
+            .filter_map(|comment| line.find(comment))
+            .min()
+            .map_or(line, |end| &line[..end]);
+        for token in code.split(|c: char| c == ',' || c.is_whitespace()) {
+            if token.is_empty() || token.ends_with(':') {
+                continue;
+            }
+            let digits = token
+                .strip_prefix("0x")
+                .or_else(|| token.strip_prefix("0X"))
+                .unwrap_or(token);
+            if digits.is_empty()
+                || digits.len() % 2 != 0
+                || !digits.chars().all(|c| c.is_ascii_hexdigit())
+            {
+                return err!("invalid hex: {}", token);
+            }
+            for i in (0..digits.len()).step_by(2) {
+                raw.push(u8::from_str_radix(&digits[i..i + 2], 16)?);
+            }
+        }
+    }
+    Ok(raw)
+}
+
+#[cfg(test)]
+mod rom_test {
+    use super::*;
+
+    #[test]
+    fn test_parse_hex() {
+        let dump = "# IBM logo\n0200: 00E0 a22a ; clear\n  0x60,0x0C // v0\n";
+        assert_eq!(
+            parse_hex(dump).unwrap(),
+            [0x00, 0xE0, 0xA2, 0x2A, 0x60, 0x0C]
+        );
+        assert!(parse_hex("00E").is_err());
+        assert!(parse_hex("0xF0, 0x100").is_err());
+        assert!(parse_hex("CLS").is_err());
+
+        // binary roms are not mistaken for dumps
+        assert_eq!(sniff_hex(&[0x00, 0xE0, 0xA2, 0x2A]), None);
+        assert_eq!(sniff_hex(b"00E0\n"), Some(vec![0x00, 0xE0]));
+        assert_eq!(sniff_hex(b"\n"), None);
+    }
+
+    #[test]
+    fn test_rom_formats() {
+        let dir = std::env::temp_dir().join(format!("rchip8-rom-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = |name: &str| dir.join(name).to_string_lossy().into_owned();
+        fs::write(path("a.hex"), "00E0 1200").unwrap();
+        fs::write(path("a.asm"), "start: CLS\nJP start\n").unwrap();
+        fs::write(path("a.8o"), ": main clear jump main").unwrap();
+        fs::write(path("a.ch8"), "00E0 1200").unwrap();
+        assert_eq!(
+            ROM::new(&path("a.hex")).unwrap().raw(),
+            [0x00, 0xE0, 0x12, 0x00]
+        );
+        assert_eq!(
+            ROM::new(&path("a.asm")).unwrap().raw(),
+            [0x00, 0xE0, 0x12, 0x00]
+        );
+        let rom = ROM::new(&path("a.8o")).unwrap();
+        assert_eq!(rom.raw(), [0x00, 0xE0, 0x12, 0x00]);
+        assert_eq!(rom.symbols().addr("main"), Some(0x200));
+        assert_eq!(ROM::new(&path("a.ch8")).unwrap().raw(), b"00E0 1200");
+        assert!(is_url("https://example.com/a.ch8"));
+        assert!(!is_url(&path("a.ch8")));
+        assert_eq!(
+            state_path("https://example.com/roms/a.ch8"),
+            PathBuf::from("a.ch8.state")
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
 }
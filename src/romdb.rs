@@ -0,0 +1,141 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use serde::Deserialize;
+
+use crate::config::Speed;
+use crate::{Result, ROM};
+
+/// the database shipped with the emulator, see romdb.json
+const BUILTIN: &str = include_str!("../romdb.json");
+
+/// What the database knows about a rom, the settings are the same as in
+/// the config file and are applied with `Config::apply_rom`
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct RomInfo {
+    pub title: Option<String>,
+    pub author: Option<String>,
+    pub compat: Option<String>,
+    pub quirks: BTreeMap<String, String>,
+    pub(crate) speed: Option<Speed>,
+    pub keymap: Option<HashMap<String, String>>,
+    /// game variables kept across runs, like `"hiscore": "3F0:2"`, see
+    /// `scores::Score`
+    pub scores: BTreeMap<String, String>,
+}
+
+impl fmt::Display for RomInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.title.as_deref().unwrap_or("untitled"))?;
+        if let Some(author) = &self.author {
+            write!(f, " by {}", author)?;
+        }
+        Ok(())
+    }
+}
+
+/// Roms by the SHA-1 of their bytes. The file is a JSON object of
+/// lowercase hex hashes to `RomInfo`:
+///
+/// ```json
+/// {
+///   "5f518084744bf3cb8733f6e5454dfd1634320563": {
+///     "title": "Tetris",
+///     "author": "Fran Dachille",
+///     "speed": 700,
+///     "quirks": { "shift": "vx" }
+///   }
+/// }
+/// ```
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct RomDb {
+    roms: HashMap<String, RomInfo>,
+}
+
+impl RomDb {
+    pub fn builtin() -> Self {
+        Self::parse(BUILTIN).expect("invalid built-in rom database")
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        fs::read_to_string(path)
+            .map_err(Into::into)
+            .and_then(|content| Self::parse(&content))
+            .map_err(|e| format!("rom database {}: {}", path.display(), e).into())
+    }
+
+    fn parse(content: &str) -> Result<Self> {
+        let roms: HashMap<String, RomInfo> = serde_json::from_str(content)?;
+        Ok(RomDb {
+            roms: roms
+                .into_iter()
+                .map(|(hash, info)| (hash.to_ascii_lowercase(), info))
+                .collect(),
+        })
+    }
+
+    /// add the roms of `other`, replacing the ones both know
+    pub fn extend(&mut self, other: RomDb) {
+        self.roms.extend(other.roms);
+    }
+
+    pub fn lookup(&self, rom: &ROM) -> Option<&RomInfo> {
+        self.roms.get(&rom.sha1())
+    }
+
+    pub fn len(&self) -> usize {
+        self.roms.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.roms.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod romdb_test {
+    use super::*;
+    use crate::config::Config;
+
+    #[test]
+    fn test_romdb() {
+        let mut db = RomDb::builtin();
+        assert!(!db.is_empty());
+        let rom = ROM::new(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/roms/programs/IBM Logo.ch8"
+        ))
+        .unwrap();
+        assert_eq!(rom.sha1(), "1ba58656810b67fd131eb9af3e3987863bf26c90");
+        assert_eq!(db.lookup(&rom).unwrap().to_string(), "IBM Logo");
+
+        // a user database replaces the built-in entry, hashes in any case
+        db.extend(
+            RomDb::parse(
+                r#"{"1BA58656810B67FD131EB9AF3E3987863BF26C90": {
+                    "title": "Logo", "author": "IBM", "compat": "schip",
+                    "speed": "unlimited", "quirks": {"sprites": "wrap"},
+                    "scores": {"hiscore": "3F0:2"}
+                }}"#,
+            )
+            .unwrap(),
+        );
+        let info = db.lookup(&rom).unwrap();
+        assert_eq!(info.to_string(), "Logo by IBM");
+        assert!(db.lookup(&ROM::from_bytes("empty", vec![])).is_none());
+        assert!(RomDb::parse(r#"{"00": {"colour": "red"}}"#).is_err());
+
+        let mut config = Config::default();
+        config.quirks.insert("shift".into(), "vy".into());
+        config.apply_rom(info);
+        assert_eq!(config.compat.as_deref(), Some("schip"));
+        assert_eq!(config.speed().as_deref(), Some("unlimited"));
+        let quirks: Vec<String> = config.quirk_settings().collect();
+        assert_eq!(quirks, ["sprites=wrap"]);
+        assert_eq!(config.scores["hiscore"], "3F0:2");
+    }
+}
@@ -0,0 +1,117 @@
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+use crate::audio::AudioPlay;
+use crate::config::Config;
+use crate::machine::{Machine, RPL_FLAG_COUNT};
+use crate::rom::is_url;
+use crate::Result;
+
+/// `flags/<rom file name>.flags` in `Config::dir`, None without a home
+/// directory
+pub fn flags_path(rom_path: &str) -> Option<PathBuf> {
+    let name = match rom_path.rsplit_once('/') {
+        Some((_, file)) if is_url(rom_path) => file,
+        _ => Path::new(rom_path).file_name()?.to_str()?,
+    };
+    Some(Config::dir()?.join("flags").join(format!("{}.flags", name)))
+}
+
+/// `FlagFile::open` of the `flags_path` of a rom, None with a warning when
+/// its flags can not be kept
+pub fn for_rom<T: AudioPlay>(rom_path: &str, machine: &mut Machine<T>) -> Option<FlagFile> {
+    match FlagFile::open(flags_path(rom_path)?, machine) {
+        Ok(flags) => Some(flags),
+        Err(e) => {
+            warn!("the RPL flags are not kept: {}", e);
+            None
+        }
+    }
+}
+
+/// The SCHIP RPL flags of a rom kept in a file, so high scores a game saves
+/// with FX75 are there the next time it runs. The file holds the flags as
+/// raw bytes.
+pub struct FlagFile {
+    path: PathBuf,
+    saved: [u8; RPL_FLAG_COUNT],
+}
+
+impl FlagFile {
+    /// Give the machine the flags in `path`, all zero before the first save
+    pub fn open<T: AudioPlay>(path: PathBuf, machine: &mut Machine<T>) -> Result<Self> {
+        let mut saved = [0; RPL_FLAG_COUNT];
+        match fs::read(&path) {
+            Ok(bytes) => {
+                let len = bytes.len().min(RPL_FLAG_COUNT);
+                saved[..len].copy_from_slice(&bytes[..len]);
+            }
+            Err(e) if e.kind() == ErrorKind::NotFound => {}
+            Err(e) => return Err(format!("can not read {}: {}", path.display(), e).into()),
+        }
+        machine.set_rpl_flags(saved);
+        Ok(FlagFile { path, saved })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// write the flags of the machine when they changed since the last save,
+    /// cheap enough to call every frame
+    pub fn save<T: AudioPlay>(&mut self, machine: &Machine<T>) -> Result<()> {
+        let flags = machine.rpl_flags();
+        if flags == self.saved {
+            return Ok(());
+        }
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(&self.path, flags)
+            .map_err(|e| format!("can not write {}: {}", self.path.display(), e))?;
+        self.saved = flags;
+        debug!("RPL flags saved to {}", self.path.display());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod rpl_test {
+    use super::*;
+    use crate::audio::NullAudio;
+    use crate::rom::ROM;
+
+    #[test]
+    fn test_flag_file() {
+        let dir = std::env::temp_dir().join(format!("rchip8-flags-{}", std::process::id()));
+        let path = dir.join("game.ch8.flags");
+        // V0-V2 = 1, 2, 3 and save them in the flags
+        let rom = ROM::from_bytes(
+            "game.ch8",
+            vec![0x60, 0x01, 0x61, 0x02, 0x62, 0x03, 0xF2, 0x75],
+        );
+        let mut machine: Machine<NullAudio> = Machine::new().unwrap();
+        machine.load_rom(&rom).unwrap();
+        let mut flags = FlagFile::open(path.clone(), &mut machine).unwrap();
+        assert_eq!(machine.rpl_flags(), [0; RPL_FLAG_COUNT]);
+        flags.save(&machine).unwrap();
+        assert!(!path.exists());
+        for _ in 0..4 {
+            machine.run_cycle().unwrap();
+        }
+        flags.save(&machine).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), [1, 2, 3, 0, 0, 0, 0, 0]);
+
+        let mut machine: Machine<NullAudio> = Machine::new().unwrap();
+        FlagFile::open(path, &mut machine).unwrap();
+        assert_eq!(machine.rpl_flags()[..3], [1, 2, 3]);
+        fs::remove_dir_all(&dir).unwrap();
+
+        let expected = Path::new("flags").join("game.ch8.flags");
+        assert!(flags_path("roms/game.ch8").unwrap().ends_with(&expected));
+        assert!(flags_path("https://example.com/game.ch8")
+            .unwrap()
+            .ends_with(&expected));
+    }
+}
@@ -0,0 +1,205 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+use crate::audio::AudioPlay;
+use crate::config::Config;
+use crate::machine::{Machine, MEMORY_SIZE};
+use crate::rom::ROM;
+use crate::{err, Result};
+
+/// A game variable kept across runs, like the high score. `3F0:2` names the
+/// 2 bytes at 0x3F0, `3F0` the byte at 0x3F0.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Score {
+    pub name: String,
+    pub addr: u16,
+    pub len: usize,
+}
+
+impl Score {
+    pub fn parse(name: &str, spec: &str) -> Result<Self> {
+        let (addr, len) = spec.split_once(':').unwrap_or((spec, "1"));
+        let addr = u16::from_str_radix(addr.trim().trim_start_matches("0x"), 16)
+            .map_err(|e| format!("invalid score {}: {}: {}", name, spec, e))?;
+        let len: usize = len
+            .trim()
+            .parse()
+            .map_err(|e| format!("invalid score {}: {}: {}", name, spec, e))?;
+        if len == 0 || addr as usize + len > MEMORY_SIZE {
+            return err!(
+                "invalid score {}: {}, expect ADDR or ADDR:LEN in memory",
+                name,
+                spec
+            );
+        }
+        Ok(Score {
+            name: name.to_string(),
+            addr,
+            len,
+        })
+    }
+
+    fn range(&self) -> std::ops::Range<usize> {
+        self.addr as usize..self.addr as usize + self.len
+    }
+}
+
+/// `scores/<SHA-1 of the rom>.json` in `Config::dir`, None without a home
+/// directory
+pub fn scores_path(rom: &ROM) -> Option<PathBuf> {
+    Some(
+        Config::dir()?
+            .join("scores")
+            .join(format!("{}.json", rom.sha1())),
+    )
+}
+
+/// The scores of a rom and the file that keeps their bytes as hex strings
+/// by name, like `{"hiscore": "0150"}`
+pub struct ScoreFile {
+    path: PathBuf,
+    /// the rom the scores belong to
+    rom: Vec<u8>,
+    scores: Vec<Score>,
+}
+
+impl ScoreFile {
+    /// the scores named by `specs`, `RomInfo::scores` of the rom
+    pub fn new(path: PathBuf, rom: &ROM, specs: &BTreeMap<String, String>) -> Result<Self> {
+        let scores = specs
+            .iter()
+            .map(|(name, spec)| Score::parse(name, spec))
+            .collect::<Result<_>>()?;
+        Ok(ScoreFile {
+            path,
+            rom: rom.raw(),
+            scores,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// the saved bytes by name, none before the first save
+    fn read(&self) -> Result<BTreeMap<String, String>> {
+        match fs::read_to_string(&self.path) {
+            Ok(content) => serde_json::from_str(&content)
+                .map_err(|e| format!("scores {}: {}", self.path.display(), e).into()),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(BTreeMap::new()),
+            Err(e) => err!("can not read {}: {}", self.path.display(), e),
+        }
+    }
+
+    /// write the saved scores into the memory of the loaded rom, call it
+    /// after the rom is loaded
+    pub fn restore<T: AudioPlay>(&self, machine: &mut Machine<T>) -> Result<()> {
+        let saved = self.read()?;
+        for score in &self.scores {
+            let bytes = match saved.get(&score.name) {
+                Some(hex) => parse_hex(hex)
+                    .ok_or_else(|| format!("invalid saved score {}: {}", score.name, hex))?,
+                None => continue,
+            };
+            if bytes.len() == score.len {
+                machine.write_memory(score.addr, &bytes)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Keep the scores in the memory of the machine. A score that still has
+    /// the bytes the rom starts with, like after a reset, keeps its saved
+    /// value. Nothing is saved once another rom was loaded.
+    pub fn save<T: AudioPlay>(&self, machine: &Machine<T>) -> Result<()> {
+        if machine.rom() != self.rom.as_slice() {
+            return Ok(());
+        }
+        let mut saved = self.read()?;
+        let rom_start = machine.rom_range().start;
+        for score in &self.scores {
+            let bytes = &machine.memory()[score.range()];
+            let initial = score
+                .range()
+                .map(|addr| addr.checked_sub(rom_start).and_then(|at| self.rom.get(at)));
+            if !bytes
+                .iter()
+                .zip(initial)
+                .all(|(a, b)| Some(a) == b.or(Some(&0)))
+            {
+                saved.insert(score.name.clone(), to_hex(bytes));
+            }
+        }
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(&self.path, serde_json::to_string_pretty(&saved)?)
+            .map_err(|e| format!("can not write {}: {}", self.path.display(), e))?;
+        Ok(())
+    }
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02X}", byte)).collect()
+}
+
+fn parse_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod scores_test {
+    use super::*;
+    use crate::audio::NullAudio;
+
+    #[test]
+    fn test_score_file() {
+        assert_eq!(
+            Score::parse("hiscore", "3F0:2").unwrap(),
+            Score {
+                name: "hiscore".into(),
+                addr: 0x3F0,
+                len: 2
+            }
+        );
+        for invalid in ["3F0:0", "FFF:2", "XYZ", "3F0:x"] {
+            assert!(Score::parse("score", invalid).is_err(), "{}", invalid);
+        }
+
+        // the high score is the byte at 0x206, the game stores V0 there
+        let rom = ROM::from_bytes("game.ch8", vec![0xA2, 0x06, 0x60, 0x2A, 0xF0, 0x55, 0x00]);
+        let path = std::env::temp_dir().join(format!("rchip8-scores-{}.json", std::process::id()));
+        let specs = BTreeMap::from([("hiscore".to_string(), "206".to_string())]);
+        let file = ScoreFile::new(path.clone(), &rom, &specs).unwrap();
+        let mut machine: Machine<NullAudio> = Machine::new().unwrap();
+        machine.load_rom(&rom).unwrap();
+        file.restore(&mut machine).unwrap();
+        // untouched scores are not saved
+        file.save(&machine).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "{}");
+        for _ in 0..3 {
+            machine.run_cycle().unwrap();
+        }
+        file.save(&machine).unwrap();
+
+        let mut machine: Machine<NullAudio> = Machine::new().unwrap();
+        machine.load_rom(&rom).unwrap();
+        file.restore(&mut machine).unwrap();
+        assert_eq!(machine.memory()[0x206], 0x2A);
+        // after a reset the saved score stays
+        machine.reset().unwrap();
+        file.save(&machine).unwrap();
+        assert!(fs::read_to_string(&path)
+            .unwrap()
+            .contains(r#""hiscore": "2A""#));
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,269 @@
+use std::cell::RefCell;
+use std::convert::TryFrom;
+use std::path::Path;
+use std::rc::Rc;
+
+use rhai::{CallFnOptions, Dynamic, Engine, EvalAltResult, Map, Scope, AST};
+
+use crate::audio::AudioPlay;
+use crate::machine::Machine;
+use crate::Result;
+
+type ScriptResult<T> = std::result::Result<T, Box<EvalAltResult>>;
+
+/// What a script sees of the machine during one frame, its writes are
+/// applied to the machine after the frame function returned
+#[derive(Default)]
+struct View {
+    frame: i64,
+    pc: u16,
+    i: u32,
+    memory: Vec<u8>,
+    registers: Vec<u8>,
+    keys: u16,
+    pokes: Vec<(u16, u8)>,
+    register_writes: Vec<(usize, u8)>,
+    key_changes: Vec<(u8, bool)>,
+}
+
+/// The `chip8` argument of the frame function
+#[derive(Clone)]
+struct Chip8(Rc<RefCell<View>>);
+
+impl Chip8 {
+    fn new<T: AudioPlay>(machine: &Machine<T>, frame: i64) -> Self {
+        let keys = (0..16).fold(0, |keys, key| {
+            keys | (machine.is_key_down(key) as u16) << key
+        });
+        Chip8(Rc::new(RefCell::new(View {
+            frame,
+            pc: machine.pc(),
+            i: machine.i(),
+            memory: machine.memory().to_vec(),
+            registers: machine.registers().to_vec(),
+            keys,
+            ..View::default()
+        })))
+    }
+
+    fn apply<T: AudioPlay>(&self, machine: &mut Machine<T>) -> Result<()> {
+        let view = self.0.borrow();
+        for &(addr, value) in &view.pokes {
+            machine.write_memory(addr, &[value])?;
+        }
+        for &(x, value) in &view.register_writes {
+            machine.set_register(x, value);
+        }
+        for &(key, down) in &view.key_changes {
+            if down {
+                machine.key_down(key);
+            } else {
+                machine.key_up(key);
+            }
+        }
+        Ok(())
+    }
+
+    fn addr(&self, addr: i64) -> ScriptResult<usize> {
+        match usize::try_from(addr) {
+            Ok(addr) if addr < self.0.borrow().memory.len() => Ok(addr),
+            _ => Err(format!("address {:#X} is out of memory", addr).into()),
+        }
+    }
+
+    fn peek(&mut self, addr: i64) -> ScriptResult<i64> {
+        let addr = self.addr(addr)?;
+        Ok(self.0.borrow().memory[addr] as i64)
+    }
+
+    fn poke(&mut self, addr: i64, value: i64) -> ScriptResult<()> {
+        let addr = self.addr(addr)?;
+        let mut view = self.0.borrow_mut();
+        view.memory[addr] = value as u8;
+        view.pokes.push((addr as u16, value as u8));
+        Ok(())
+    }
+
+    fn v(&mut self, x: i64) -> ScriptResult<i64> {
+        let view = self.0.borrow();
+        match view.registers.get(x as usize) {
+            Some(&value) if x >= 0 => Ok(value as i64),
+            _ => Err(format!("no register V{}", x).into()),
+        }
+    }
+
+    fn set_v(&mut self, x: i64, value: i64) -> ScriptResult<()> {
+        let mut view = self.0.borrow_mut();
+        match view.registers.get_mut(x as usize) {
+            Some(register) if x >= 0 => *register = value as u8,
+            _ => return Err(format!("no register V{}", x).into()),
+        }
+        view.register_writes.push((x as usize, value as u8));
+        Ok(())
+    }
+
+    fn key(key: i64) -> ScriptResult<u8> {
+        match key {
+            0..=0xF => Ok(key as u8),
+            _ => Err(format!("no key {}, keys are 0 to 15", key).into()),
+        }
+    }
+
+    fn is_down(&mut self, key: i64) -> ScriptResult<bool> {
+        let key = Chip8::key(key)?;
+        Ok(self.0.borrow().keys & 1 << key != 0)
+    }
+
+    fn set_key(&mut self, key: i64, down: bool) -> ScriptResult<()> {
+        let key = Chip8::key(key)?;
+        let mut view = self.0.borrow_mut();
+        if down {
+            view.keys |= 1 << key;
+        } else {
+            view.keys &= !(1 << key);
+        }
+        view.key_changes.push((key, down));
+        Ok(())
+    }
+}
+
+/// A rhai script for `--script` that runs every 60Hz frame with access to
+/// memory, registers and keys, for cheats like a frozen lives counter and
+/// for automation like pressing keys on a schedule:
+///
+/// ```rhai
+/// fn frame(chip8) {
+///     chip8.poke(0x3F0, 3);
+///     if chip8.frame % 60 == 0 { chip8.press(5) } else { chip8.release(5) }
+/// }
+/// ```
+///
+/// `this` is a map that is kept between frames, like `this.n = (this.n ?? 0) + 1`.
+pub struct Script {
+    name: String,
+    engine: Engine,
+    ast: AST,
+    scope: Scope<'static>,
+    state: Dynamic,
+    frame: i64,
+}
+
+impl Script {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let name = path.display().to_string();
+        let mut engine = Engine::new();
+        engine
+            .register_type_with_name::<Chip8>("Chip8")
+            .register_get("frame", |chip8: &mut Chip8| chip8.0.borrow().frame)
+            .register_get("pc", |chip8: &mut Chip8| chip8.0.borrow().pc as i64)
+            .register_get("i", |chip8: &mut Chip8| chip8.0.borrow().i as i64)
+            .register_fn("peek", Chip8::peek)
+            .register_fn("poke", Chip8::poke)
+            .register_fn("v", Chip8::v)
+            .register_fn("set_v", Chip8::set_v)
+            .register_fn("is_down", Chip8::is_down)
+            .register_fn("press", |chip8: &mut Chip8, key: i64| {
+                chip8.set_key(key, true)
+            })
+            .register_fn("release", |chip8: &mut Chip8, key: i64| {
+                chip8.set_key(key, false)
+            });
+        let prefix = name.clone();
+        engine.on_print(move |text| info!("{}: {}", prefix, text));
+        let ast = engine
+            .compile_file(path.to_path_buf())
+            .map_err(|e| format!("script {}: {}", name, e))?;
+        if !ast
+            .iter_functions()
+            .any(|f| f.name == "frame" && f.params.len() == 1)
+        {
+            return Err(format!("script {} has no fn frame(chip8)", name).into());
+        }
+        let mut scope = Scope::new();
+        // the statements outside of functions run once
+        engine
+            .run_ast_with_scope(&mut scope, &ast)
+            .map_err(|e| format!("script {}: {}", name, e))?;
+        Ok(Script {
+            name,
+            engine,
+            ast,
+            scope,
+            state: Dynamic::from_map(Map::new()),
+            frame: 0,
+        })
+    }
+
+    /// call the frame function and apply what it changed to `machine`
+    pub fn frame<T: AudioPlay>(&mut self, machine: &mut Machine<T>) -> Result<()> {
+        let chip8 = Chip8::new(machine, self.frame);
+        let options = CallFnOptions::new()
+            .eval_ast(false)
+            .bind_this_ptr(&mut self.state);
+        // what the frame function returns does not matter
+        let _: Dynamic = self
+            .engine
+            .call_fn_with_options(
+                options,
+                &mut self.scope,
+                &self.ast,
+                "frame",
+                (chip8.clone(),),
+            )
+            .map_err(|e| format!("script {}: {}", self.name, e))?;
+        chip8.apply(machine)?;
+        self.frame += 1;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod script_test {
+    use super::*;
+    use crate::audio::NullAudio;
+    use crate::rom::ROM;
+    use std::fs;
+
+    #[test]
+    fn test_script() {
+        let dir = std::env::temp_dir().join(format!("rchip8-script-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("cheat.rhai");
+        fs::write(
+            &path,
+            r#"
+            print("loaded");
+            fn frame(chip8) {
+                this.frames = (this.frames ?? 0) + 1;
+                chip8.poke(0x300, this.frames);
+                chip8.set_v(3, chip8.peek(0x300) * 2);
+                if chip8.frame == 1 { chip8.press(0xA) }
+                if chip8.is_down(0xA) && chip8.frame == 2 { chip8.release(0xA) }
+            }
+            "#,
+        )
+        .unwrap();
+        let mut script = Script::load(&path).unwrap();
+        let mut machine: Machine<NullAudio> = Machine::new().unwrap();
+        machine
+            .load_rom(&ROM::from_bytes("loop", vec![0x12, 0x00]))
+            .unwrap();
+
+        script.frame(&mut machine).unwrap();
+        script.frame(&mut machine).unwrap();
+        assert_eq!(machine.memory()[0x300], 2);
+        assert_eq!(machine.registers()[3], 4);
+        assert!(machine.is_key_down(0xA));
+        script.frame(&mut machine).unwrap();
+        assert_eq!(machine.memory()[0x300], 3);
+        assert!(!machine.is_key_down(0xA));
+
+        fs::write(&path, "fn frame(chip8) { chip8.poke(0x1000, 1); }").unwrap();
+        let mut script = Script::load(&path).unwrap();
+        assert!(script.frame(&mut machine).is_err());
+        fs::write(&path, "let lives = 3;").unwrap();
+        assert!(Script::load(&path).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,1476 @@
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use crossbeam_channel::Receiver;
+use sdl2::controller::{Button, GameController};
+use sdl2::event::{Event, WindowEvent};
+use sdl2::keyboard::{Keycode, Scancode};
+use sdl2::mouse::MouseButton;
+use sdl2::pixels::{self, PixelFormatEnum};
+use sdl2::rect::Rect;
+use sdl2::render::{BlendMode, Canvas, Texture, TextureCreator};
+use sdl2::video::{Window, WindowContext};
+use sdl2::{EventPump, GameControllerSubsystem};
+
+use crate::audio::{AudioConfig, DynAudio, NullAudio, Sdl2Audio};
+use crate::capture::{self, Frame, GifRecorder};
+use crate::clock::{FrameStats, RunStats};
+use crate::debugger::{self, Debugger};
+use crate::display::{Color, DisplayConfig, Phosphor};
+use crate::frontend::{self, DisplayBackend, Emulator, FrameInfo, InputBackend, InputEvent};
+use crate::heatmap::Heatmap;
+use crate::hooks::FrameHook;
+use crate::keypad::{self, KeyUsage};
+use crate::logging::{self, AUDIO, INPUT, VIDEO};
+use crate::machine::Machine;
+use crate::menu::RomMenu;
+use crate::netplay::Netplay;
+use crate::remote::Call;
+use crate::rpl;
+use crate::text::{self, ADVANCE, GLYPH_HEIGHT, GLYPH_WIDTH};
+use crate::timing::Timing;
+use crate::trace::FileTracer;
+use crate::video::{Collisions, Video};
+use crate::watch::RomWatcher;
+use crate::{err, Result};
+
+// the memory write heatmap has a square of this many pixels for every
+// address, 64 addresses a row over the 4KB of CHIP-8 memory
+const HEAT_CELL: u32 = 4;
+const HEAT_COLUMNS: usize = 64;
+const HEAT_ROWS: usize = 64;
+// SDL_TOUCH_MOUSEID, the `which` of mouse events made up from touches
+const TOUCH_MOUSE_ID: u32 = u32::MAX;
+// F4 marks the pixels erased by sprites for this long
+const COLLISION_FLASH: Duration = Duration::from_millis(300);
+// the frame pacing is reported this often
+const FRAME_STATS_PERIOD: Duration = Duration::from_secs(5);
+// F12 saves screenshots here
+const SCREENSHOT_DIR: &str = "screenshots";
+// F10 saves recordings here
+const RECORDING_DIR: &str = "recordings";
+// F1 shows this many records of the log ring buffer, cut to this many
+// characters
+const OVERLAY_LOG_LINES: usize = 8;
+const OVERLAY_LOG_WIDTH: usize = 48;
+
+#[derive(Clone)]
+pub struct Sdl2KeyMap {
+    scancodes_map: HashMap<Scancode, u8>,
+}
+
+impl Sdl2KeyMap {
+    pub fn new(layout: &HashMap<Scancode, u8>) -> Result<Self> {
+        let scancodes_map = layout.clone();
+        if layout.len() != 16 {
+            return err!("layout will not be matched, the layout length is not 16");
+        }
+        Ok(Sdl2KeyMap { scancodes_map })
+    }
+
+    /// layout from a keymap file, the names are SDL2 scancode names like "Q" or "Keypad 7"
+    pub fn from_names(layout: &BTreeMap<u8, String>) -> Result<Self> {
+        let mut scancodes_map = HashMap::with_capacity(16);
+        for (&key, name) in layout {
+            let scancode = match Scancode::from_name(name) {
+                Some(scancode) => scancode,
+                None => return err!("unknown key name {:?} for CHIP-8 key {:X}", name, key),
+            };
+            if let Some(other) = scancodes_map.insert(scancode, key) {
+                return err!(
+                    "key {:?} is mapped to both CHIP-8 key {:X} and {:X}",
+                    name,
+                    other,
+                    key
+                );
+            }
+        }
+        Self::new(&scancodes_map)
+    }
+
+    /// more keys from a partial keymap file, e.g. a second player's keys on
+    /// the other side of the keyboard. SDL2 does not tell keyboards apart,
+    /// so a second keyboard works the same way.
+    pub fn add_names(&mut self, layout: &BTreeMap<u8, String>) -> Result<()> {
+        for (&key, name) in layout {
+            let scancode = match Scancode::from_name(name) {
+                Some(scancode) => scancode,
+                None => return err!("unknown key name {:?} for CHIP-8 key {:X}", name, key),
+            };
+            if let Some(other) = self.scancodes_map.insert(scancode, key) {
+                return err!(
+                    "key {:?} is mapped to both CHIP-8 key {:X} and {:X}",
+                    name,
+                    other,
+                    key
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// names of the keys mapped to CHIP-8 `key`
+    pub fn key_names(&self, key: u8) -> Vec<&'static str> {
+        let mut names: Vec<&'static str> = self
+            .scancodes_map
+            .iter()
+            .filter(|(_, &k)| k == key)
+            .map(|(scancode, _)| scancode.name())
+            .collect();
+        names.sort_unstable();
+        names
+    }
+
+    pub fn scancode_to_key(&self, scancode: &Scancode) -> Option<u8> {
+        self.scancodes_map.get(scancode).copied()
+    }
+
+    fn default_keyboard_layout() -> HashMap<Scancode, u8> {
+        let mut default_layout: HashMap<Scancode, u8> = HashMap::with_capacity(16);
+        default_layout.insert(Scancode::X, 0);
+        default_layout.insert(Scancode::Num1, 1);
+        default_layout.insert(Scancode::Num2, 2);
+        default_layout.insert(Scancode::Num3, 3);
+        default_layout.insert(Scancode::Q, 4);
+        default_layout.insert(Scancode::W, 5);
+        default_layout.insert(Scancode::E, 6);
+        default_layout.insert(Scancode::A, 7);
+        default_layout.insert(Scancode::S, 8);
+        default_layout.insert(Scancode::D, 9);
+        default_layout.insert(Scancode::Z, 0xA);
+        default_layout.insert(Scancode::C, 0xB);
+        default_layout.insert(Scancode::Num4, 0xC);
+        default_layout.insert(Scancode::R, 0xD);
+        default_layout.insert(Scancode::F, 0xE);
+        default_layout.insert(Scancode::V, 0xF);
+        default_layout
+    }
+}
+
+impl Default for Sdl2KeyMap {
+    fn default() -> Self {
+        Self::new(&Self::default_keyboard_layout()).unwrap()
+    }
+}
+
+/// Game controller buttons mapped to CHIP-8 keys, unmapped buttons are ignored
+pub struct Sdl2PadMap {
+    buttons_map: HashMap<Button, u8>,
+}
+
+impl Sdl2PadMap {
+    /// layout from a keymap file, the names are SDL2 button names like "a" or "dpup"
+    pub fn from_names(layout: &BTreeMap<u8, String>) -> Result<Self> {
+        let mut buttons_map = HashMap::with_capacity(layout.len());
+        for (&key, name) in layout {
+            let button = match Button::from_string(name) {
+                Some(button) => button,
+                None => return err!("unknown button name {:?} for CHIP-8 key {:X}", name, key),
+            };
+            if let Some(other) = buttons_map.insert(button, key) {
+                return err!(
+                    "button {:?} is mapped to both CHIP-8 key {:X} and {:X}",
+                    name,
+                    other,
+                    key
+                );
+            }
+        }
+        Ok(Sdl2PadMap { buttons_map })
+    }
+
+    pub fn button_to_key(&self, button: &Button) -> Option<u8> {
+        self.buttons_map.get(button).copied()
+    }
+}
+
+impl Default for Sdl2PadMap {
+    /// the D-pad on the 2/4/6/8 arrows most games use, A on 5, B on 0 and start on 1
+    fn default() -> Self {
+        let mut buttons_map = HashMap::with_capacity(7);
+        buttons_map.insert(Button::DPadUp, 2);
+        buttons_map.insert(Button::DPadDown, 8);
+        buttons_map.insert(Button::DPadLeft, 4);
+        buttons_map.insert(Button::DPadRight, 6);
+        buttons_map.insert(Button::A, 5);
+        buttons_map.insert(Button::B, 0);
+        buttons_map.insert(Button::Start, 1);
+        Sdl2PadMap { buttons_map }
+    }
+}
+
+/// Controllers opened as they are plugged in, keyed by joystick instance id.
+/// Each is a player, numbered in the order they were connected, and uses
+/// the pad map of its player or the first one.
+struct Gamepads {
+    subsystem: Option<GameControllerSubsystem>,
+    opened: HashMap<u32, (GameController, usize)>,
+    pad_maps: Vec<Sdl2PadMap>,
+}
+
+impl Gamepads {
+    fn new(subsystem: Option<GameControllerSubsystem>, pad_maps: Vec<Sdl2PadMap>) -> Self {
+        Gamepads {
+            subsystem,
+            opened: HashMap::new(),
+            pad_maps,
+        }
+    }
+
+    fn button_to_key(&self, instance_id: u32, button: &Button) -> Option<u8> {
+        let player = self
+            .opened
+            .get(&instance_id)
+            .map_or(0, |(_, player)| *player);
+        self.pad_maps
+            .get(player)
+            .or(self.pad_maps.first())?
+            .button_to_key(button)
+    }
+
+    // SDL also sends an added event for every controller connected at startup
+    fn added(&mut self, joystick_index: u32) {
+        let subsystem = match self.subsystem.as_ref() {
+            Some(subsystem) => subsystem,
+            None => return,
+        };
+        match subsystem.open(joystick_index) {
+            Ok(controller) => {
+                // the first player number a connected controller does not have
+                let player = (0..)
+                    .find(|&n| !self.opened.values().any(|(_, player)| *player == n))
+                    .unwrap();
+                info!(
+                    target: INPUT,
+                    "controller connected as player {}: {}",
+                    player + 1,
+                    controller.name()
+                );
+                self.opened
+                    .insert(controller.instance_id(), (controller, player));
+            }
+            Err(e) => warn!(target: INPUT, "can not open controller {}: {}", joystick_index, e),
+        }
+    }
+
+    fn removed(&mut self, instance_id: u32) {
+        if let Some((controller, _)) = self.opened.remove(&instance_id) {
+            info!(target: INPUT, "controller disconnected: {}", controller.name());
+        }
+    }
+}
+
+/// Physical key or controller button that presses a CHIP-8 key
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Input {
+    Key(Scancode),
+    /// a button of the controller with this instance id
+    Button(u32, Button),
+    /// the left mouse button on the on-screen keypad
+    Pointer,
+    /// a finger with this id on the on-screen keypad
+    Finger(i64),
+}
+
+/// Where the on-screen keypad is in the window, right of the display and as
+/// high as it, but leaving at least two thirds of the width to the display
+fn keypad_area(width: u32, height: u32) -> (Rect, Rect) {
+    let side = height.min(width / 3).max(1);
+    let display = Rect::new(0, 0, (width - side).max(1), height.max(1));
+    let keypad = Rect::new(
+        (width - side) as i32,
+        (height - side) as i32 / 2,
+        side,
+        side,
+    );
+    (display, keypad)
+}
+
+/// The on-screen keypad in window coordinates, mouse positions are in
+/// window coordinates and touch positions between 0.0 and 1.0 of the window
+#[derive(Debug, Clone, Copy)]
+struct KeypadTarget {
+    keypad: Rect,
+    window: (u32, u32),
+}
+
+impl KeypadTarget {
+    fn new(window: &Window) -> Self {
+        let (width, height) = window.size();
+        KeypadTarget {
+            keypad: keypad_area(width, height).1,
+            window: (width, height),
+        }
+    }
+
+    fn key_at(&self, x: i32, y: i32) -> Option<u8> {
+        keypad::key_at(
+            (x - self.keypad.x()) as f32,
+            (y - self.keypad.y()) as f32,
+            self.keypad.width() as f32,
+            self.keypad.height() as f32,
+        )
+    }
+
+    fn key_at_touch(&self, x: f32, y: f32) -> Option<u8> {
+        self.key_at(
+            (x * self.window.0 as f32) as i32,
+            (y * self.window.1 as f32) as i32,
+        )
+    }
+}
+
+/// The inputs holding CHIP-8 keys down. With two players both can map the
+/// same CHIP-8 key, it is released when the last of them is.
+#[derive(Default)]
+struct HeldKeys {
+    held: HashMap<Input, u8>,
+}
+
+impl HeldKeys {
+    fn press(&mut self, events: &mut Vec<InputEvent>, input: Input, key: u8) {
+        self.held.insert(input, key);
+        events.push(InputEvent::KeyDown(key));
+        debug!(target: INPUT, "{:?} down -> {:X}", input, key);
+    }
+
+    fn release(&mut self, events: &mut Vec<InputEvent>, input: Input) {
+        if let Some(key) = self.held.remove(&input) {
+            if !self.held.values().any(|&other| other == key) {
+                events.push(InputEvent::KeyUp(key));
+            }
+            debug!(target: INPUT, "{:?} up -> {:X}", input, key);
+        }
+    }
+
+    /// release what a disconnected controller held
+    fn release_controller(&mut self, events: &mut Vec<InputEvent>, instance_id: u32) {
+        let inputs: Vec<Input> = self
+            .held
+            .keys()
+            .filter(|input| matches!(input, Input::Button(id, _) if *id == instance_id))
+            .copied()
+            .collect();
+        for input in inputs {
+            self.release(events, input);
+        }
+    }
+}
+
+/// what the keys outside the CHIP-8 keypad do to the emulation
+fn bound_event(keycode: Keycode) -> Option<InputEvent> {
+    match keycode {
+        Keycode::Escape => Some(InputEvent::Quit),
+        Keycode::F5 => Some(InputEvent::SaveState),
+        Keycode::F9 => Some(InputEvent::LoadState),
+        Keycode::Equals | Keycode::Plus | Keycode::KpPlus => Some(InputEvent::SpeedUp),
+        Keycode::Minus | Keycode::KpMinus => Some(InputEvent::SpeedDown),
+        Keycode::Tab => Some(InputEvent::ToggleTurbo),
+        Keycode::P => Some(InputEvent::TogglePause),
+        Keycode::Backspace => Some(InputEvent::Reset),
+        Keycode::Backquote => Some(InputEvent::Rewind(true)),
+        Keycode::F3 => Some(InputEvent::NextRom),
+        Keycode::F7 => Some(InputEvent::StepFrame),
+        Keycode::M => Some(InputEvent::ToggleMute),
+        Keycode::RightBracket => Some(InputEvent::VolumeUp),
+        Keycode::LeftBracket => Some(InputEvent::VolumeDown),
+        _ => None,
+    }
+}
+
+/// Window actions bound to keys outside the CHIP-8 keypad, taken by
+/// `Sdl2Display`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Hotkey {
+    Screenshot,
+    Record,
+    Overlay,
+    /// show the frames, instructions and timer ticks per second
+    Stats,
+    /// mark where sprites collided
+    Collisions,
+    /// show the keys the rom uses
+    KeyHints,
+    /// open or close the memory and register windows
+    DebugWindows,
+    /// a debug window with this id was closed
+    CloseWindow(u32),
+}
+
+impl Hotkey {
+    fn from_keycode(keycode: Keycode) -> Option<Self> {
+        match keycode {
+            Keycode::F12 => Some(Hotkey::Screenshot),
+            Keycode::F10 => Some(Hotkey::Record),
+            Keycode::F1 => Some(Hotkey::Overlay),
+            Keycode::F2 => Some(Hotkey::DebugWindows),
+            Keycode::F4 => Some(Hotkey::Collisions),
+            Keycode::F6 => Some(Hotkey::KeyHints),
+            Keycode::F8 => Some(Hotkey::Stats),
+            _ => None,
+        }
+    }
+}
+
+/// What the window shows besides the machine display, changed by
+/// `Sdl2Input` and drawn by `Sdl2Display`
+#[derive(Default)]
+struct View {
+    /// shown instead of the display until a rom is picked
+    menu: Option<RomMenu>,
+    /// where the on-screen keypad is, none without it
+    keypad: Option<KeypadTarget>,
+    /// pressed since the last frame
+    hotkeys: Vec<Hotkey>,
+}
+
+/// The SDL2 events as an input: the keyboard, the controllers and the
+/// on-screen keypad, the rom menu while it is shown and the hotkeys of the
+/// window
+pub struct Sdl2Input {
+    event_pump: EventPump,
+    key_map: Sdl2KeyMap,
+    gamepads: Gamepads,
+    held: HeldKeys,
+    // the other windows are debug windows
+    main_window: u32,
+    view: Rc<RefCell<View>>,
+}
+
+impl Sdl2Input {
+    /// Closing a debug window or Esc in it closes only that window, the other
+    /// keys work in every window. Moving the focus between the windows gives
+    /// no `InputEvent::Focus`.
+    fn poll_keys(&mut self, events: &mut Vec<InputEvent>) {
+        let view = &mut *self.view.borrow_mut();
+        let held = &mut self.held;
+        let mut focus = None;
+        for event in self.event_pump.poll_iter() {
+            match event {
+                Event::Window {
+                    window_id,
+                    win_event: WindowEvent::Close,
+                    ..
+                }
+                | Event::KeyDown {
+                    window_id,
+                    keycode: Some(Keycode::Escape),
+                    ..
+                } if window_id != self.main_window => {
+                    view.hotkeys.push(Hotkey::CloseWindow(window_id))
+                }
+                // with a debug window open closing a window does not quit SDL
+                Event::Quit { .. }
+                | Event::Window {
+                    win_event: WindowEvent::Close,
+                    ..
+                } => events.push(InputEvent::Quit),
+                // the window losing the focus comes before the one getting it
+                Event::Window {
+                    win_event: WindowEvent::FocusGained,
+                    ..
+                } => focus = Some(true),
+                Event::Window {
+                    win_event: WindowEvent::FocusLost | WindowEvent::Minimized,
+                    ..
+                } => focus = Some(false),
+                Event::DropFile { filename, .. } => events.push(InputEvent::Open(filename)),
+                Event::KeyDown {
+                    keycode,
+                    scancode: Some(scancode),
+                    repeat,
+                    ..
+                } => {
+                    if let Some(key) = self.key_map.scancode_to_key(&scancode) {
+                        held.press(events, Input::Key(scancode), key);
+                    } else if !repeat {
+                        if let Some(event) = keycode.and_then(bound_event) {
+                            events.push(event);
+                        } else if let Some(hotkey) = keycode.and_then(Hotkey::from_keycode) {
+                            view.hotkeys.push(hotkey);
+                        }
+                    }
+                }
+                Event::KeyUp {
+                    keycode,
+                    scancode: Some(scancode),
+                    ..
+                } => {
+                    if self.key_map.scancode_to_key(&scancode).is_some() {
+                        held.release(events, Input::Key(scancode));
+                    } else if let Some(InputEvent::Rewind(_)) = keycode.and_then(bound_event) {
+                        events.push(InputEvent::Rewind(false));
+                    }
+                }
+                Event::ControllerButtonDown { which, button, .. } => {
+                    if let Some(key) = self.gamepads.button_to_key(which, &button) {
+                        held.press(events, Input::Button(which, button), key);
+                    }
+                }
+                Event::ControllerButtonUp { which, button, .. } => {
+                    held.release(events, Input::Button(which, button));
+                }
+                // touches also come as mouse events, they are handled as fingers
+                Event::MouseButtonDown {
+                    which,
+                    mouse_btn: MouseButton::Left,
+                    x,
+                    y,
+                    ..
+                } if which != TOUCH_MOUSE_ID => {
+                    if let Some(key) = view.keypad.and_then(|keypad| keypad.key_at(x, y)) {
+                        held.press(events, Input::Pointer, key);
+                    }
+                }
+                Event::MouseButtonUp {
+                    which,
+                    mouse_btn: MouseButton::Left,
+                    ..
+                } if which != TOUCH_MOUSE_ID => held.release(events, Input::Pointer),
+                Event::FingerDown {
+                    finger_id, x, y, ..
+                } => {
+                    if let Some(key) = view.keypad.and_then(|keypad| keypad.key_at_touch(x, y)) {
+                        held.press(events, Input::Finger(finger_id), key);
+                    }
+                }
+                Event::FingerUp { finger_id, .. } => held.release(events, Input::Finger(finger_id)),
+                Event::ControllerDeviceAdded { which, .. } => self.gamepads.added(which),
+                Event::ControllerDeviceRemoved { which, .. } => {
+                    held.release_controller(events, which);
+                    self.gamepads.removed(which);
+                }
+                _ => {}
+            }
+        }
+        events.extend(focus.map(InputEvent::Focus));
+    }
+}
+
+impl InputBackend for Sdl2Input {
+    fn poll(&mut self, events: &mut Vec<InputEvent>) -> Result<()> {
+        let mut view = self.view.borrow_mut();
+        if let Some(menu) = view.menu.as_mut() {
+            sdl2_menu_event(menu, &mut self.event_pump, events);
+            return Ok(());
+        }
+        drop(view);
+        self.poll_keys(events);
+        Ok(())
+    }
+}
+
+/// arrow keys and page up/down move the selection, enter opens the selected rom
+fn sdl2_menu_event(menu: &mut RomMenu, event_pump: &mut EventPump, events: &mut Vec<InputEvent>) {
+    for event in event_pump.poll_iter() {
+        match event {
+            Event::Quit { .. } => events.push(InputEvent::Quit),
+            Event::DropFile { filename, .. } => events.push(InputEvent::Open(filename)),
+            Event::KeyDown {
+                keycode: Some(keycode),
+                ..
+            } => match keycode {
+                Keycode::Escape => events.push(InputEvent::Quit),
+                Keycode::Up => menu.up(),
+                Keycode::Down => menu.down(),
+                Keycode::PageUp => menu.page_up(),
+                Keycode::PageDown => menu.page_down(),
+                Keycode::Return | Keycode::KpEnter => events.push(InputEvent::Open(
+                    menu.selected().to_string_lossy().into_owned(),
+                )),
+                _ => {}
+            },
+            _ => {}
+        }
+    }
+}
+
+/// Streaming texture with one texel per CHIP-8 pixel, only the rows that
+/// changed since the last frame are uploaded
+struct Screen<'a> {
+    creator: &'a TextureCreator<WindowContext>,
+    texture: Texture<'a>,
+    width: usize,
+    height: usize,
+    // with ghosting, fading pixels change every frame
+    phosphor: Option<Phosphor>,
+}
+
+impl<'a> Screen<'a> {
+    fn new(
+        creator: &'a TextureCreator<WindowContext>,
+        width: usize,
+        height: usize,
+    ) -> Result<Self> {
+        let texture = creator.create_texture_streaming(
+            PixelFormatEnum::RGBA32,
+            width as u32,
+            height as u32,
+        )?;
+        Ok(Screen {
+            creator,
+            texture,
+            width,
+            height,
+            phosphor: None,
+        })
+    }
+
+    /// upload the rows of a column-major grid whose bit is set in `dirty`
+    fn update(&mut self, video: &Video, mut dirty: u64, config: &DisplayConfig) -> Result<()> {
+        // SCHIP can switch between 64x32 and 128x64 at runtime
+        let (width, height) = (video.width(), video.height());
+        if (width, height) != (self.width, self.height) {
+            let phosphor = self.phosphor.take();
+            *self = Screen::new(self.creator, width, height)?;
+            self.phosphor = phosphor;
+            dirty = u64::MAX;
+        }
+        if config.ghosting > 0 && !video.is_mega() {
+            self.phosphor
+                .get_or_insert_with(|| Phosphor::new(config.ghosting))
+                .update(video);
+            dirty = u64::MAX;
+        }
+        if height < 64 {
+            dirty &= (1 << height) - 1;
+        }
+        if dirty == 0 {
+            return Ok(());
+        }
+
+        // locked texels are write only, so every row between the first and
+        // the last dirty one is written
+        let (first, last) = if height > 64 {
+            // MegaChip frames are always presented whole
+            (0, height - 1)
+        } else {
+            (
+                dirty.trailing_zeros() as usize,
+                63 - dirty.leading_zeros() as usize,
+            )
+        };
+        let rect = Rect::new(0, first as i32, width as u32, (last - first + 1) as u32);
+        let palette = config.palette();
+        let phosphor = self.phosphor.as_ref().filter(|_| !video.is_mega());
+        self.texture.with_lock(rect, |buffer, pitch| {
+            for (offset, line) in buffer.chunks_mut(pitch).enumerate() {
+                let y = first + offset;
+                match phosphor {
+                    Some(phosphor) => phosphor.render_rgba_rows(y..y + 1, line, &palette),
+                    None => video.render_rgba_rows(y..y + 1, line, &palette),
+                }
+            }
+        })?;
+        Ok(())
+    }
+}
+
+/// draw the display into `area` of the window, `dirty` are the rows changed
+/// since the last call, return where it was drawn
+fn sdl2_draw(
+    canvas: &mut Canvas<Window>,
+    screen: &mut Screen,
+    video: &Video,
+    dirty: u64,
+    config: &DisplayConfig,
+    area: Rect,
+) -> Result<Rect> {
+    screen.update(video, dirty, config)?;
+
+    // the biggest integer scale that fits the area is used and the display
+    // is centered
+    let (width, height) = (screen.width as u32, screen.height as u32);
+    let (output_width, output_height) = (area.width(), area.height());
+    let cell = (output_width / width).min(output_height / height).max(1);
+    let offset_x = area.x() + (output_width.saturating_sub(cell * width) / 2) as i32;
+    let offset_y = area.y() + (output_height.saturating_sub(cell * height) / 2) as i32;
+
+    let target = Rect::new(offset_x, offset_y, cell * width, cell * height);
+    canvas.set_draw_color(pixels::Color::BLACK);
+    canvas.clear();
+    canvas.copy(&screen.texture, None, target)?;
+
+    if config.scanlines && cell > 1 {
+        canvas.set_blend_mode(BlendMode::Blend);
+        canvas.set_draw_color(pixels::Color::RGBA(0, 0, 0, 96));
+        let right = offset_x + (cell * width) as i32 - 1;
+        for y in (offset_y..offset_y + (cell * height) as i32).step_by(2) {
+            canvas.draw_line((offset_x, y), (right, y))?;
+        }
+        canvas.set_blend_mode(BlendMode::None);
+    }
+    Ok(target)
+}
+
+/// Pixels erased by sprite draws in the last `COLLISION_FLASH`, marked over
+/// the display and fading out so rom developers see where sprites hit
+#[derive(Default)]
+struct CollisionFlashes {
+    recent: VecDeque<(Instant, Collisions)>,
+}
+
+impl CollisionFlashes {
+    fn push(&mut self, collisions: Collisions, now: Instant) {
+        while let Some((at, _)) = self.recent.front() {
+            if now < *at + COLLISION_FLASH {
+                break;
+            }
+            self.recent.pop_front();
+        }
+        if !collisions.is_empty() {
+            self.recent.push_back((now, collisions));
+        }
+    }
+
+    /// mark the pixels over the display drawn at `target`, `width` pixels wide
+    fn draw(&self, canvas: &mut Canvas<Window>, target: Rect, width: usize) -> Result<()> {
+        let cell = target.width() / width as u32;
+        let now = Instant::now();
+        canvas.set_blend_mode(BlendMode::Blend);
+        for (at, collisions) in &self.recent {
+            let left = COLLISION_FLASH.saturating_sub(now - *at);
+            let alpha = (192 * left.as_millis() / COLLISION_FLASH.as_millis()) as u8;
+            canvas.set_draw_color(pixels::Color::RGBA(255, 0, 0, alpha));
+            let rects: Vec<Rect> = collisions
+                .pixels()
+                .map(|(x, y)| {
+                    Rect::new(
+                        target.x() + (x as u32 * cell) as i32,
+                        target.y() + (y as u32 * cell) as i32,
+                        cell,
+                        cell,
+                    )
+                })
+                .collect();
+            canvas.fill_rects(&rects)?;
+        }
+        canvas.set_blend_mode(BlendMode::None);
+        Ok(())
+    }
+}
+
+/// What `sdl2_present` shows besides the machine display
+struct FrameContent<'a> {
+    /// shown instead of the display while a rom is picked
+    menu: Option<&'a RomMenu>,
+    /// with the register overlay on, its frame pacing and the recent log are
+    /// shown too
+    overlay: Option<&'a FrameStats>,
+    /// with F8 the rates and the instructions per second the clock runs at,
+    /// shown above the register overlay
+    stats: Option<(&'a RunStats, Option<u64>)>,
+    /// with F4 the recent sprite collisions are marked
+    collisions: Option<&'a CollisionFlashes>,
+    /// the clickable keypad right of the display
+    keypad: bool,
+    /// keys the rom tested in the last frame, outlined on the keypad
+    checked_keys: u16,
+    /// with F6 the keys the rom uses and the keys they are mapped to
+    key_hints: Option<(&'a KeyUsage, &'a Sdl2KeyMap)>,
+    config: &'a DisplayConfig,
+}
+
+/// draw the display, or the rom menu, with the overlay and present it, with
+/// vsync this waits for the display refresh
+fn sdl2_present(
+    canvas: &mut Canvas<Window>,
+    screen: &mut Screen,
+    machine: &mut Machine<DynAudio>,
+    frame: FrameContent,
+) -> Result<()> {
+    let (width, height) = canvas.output_size()?;
+    match frame.menu {
+        Some(menu) => {
+            let area = Rect::new(0, 0, width, height);
+            sdl2_draw(canvas, screen, &menu.render(), u64::MAX, frame.config, area)?;
+        }
+        None => {
+            let (area, keypad) = keypad_area(width, height);
+            let area = if frame.keypad {
+                area
+            } else {
+                Rect::new(0, 0, width, height)
+            };
+            let dirty = machine.take_dirty_rows();
+            let display = machine.get_display();
+            let target = sdl2_draw(canvas, screen, display, dirty, frame.config, area)?;
+            if frame.keypad {
+                sdl2_keypad(canvas, keypad, machine, frame.checked_keys, frame.config)?;
+            }
+            if let Some(flashes) = frame.collisions {
+                flashes.draw(canvas, target, machine.width())?;
+            }
+            let mut lines = vec![];
+            if let Some((stats, target)) = frame.stats {
+                lines.extend(stats.lines(target, machine.delay_timer(), machine.sound_timer()));
+            }
+            if let Some(stats) = frame.overlay {
+                if !lines.is_empty() {
+                    lines.push(String::new());
+                }
+                lines.extend(debugger::register_view(machine));
+                lines.push(stats.summary());
+                lines.push(String::new());
+                lines.extend(
+                    logging::recent(OVERLAY_LOG_LINES)
+                        .into_iter()
+                        .map(|line| line.chars().take(OVERLAY_LOG_WIDTH).collect()),
+                );
+            }
+            if !lines.is_empty() {
+                sdl2_overlay(canvas, &lines, false)?;
+            }
+            if let Some((usage, key_map)) = frame.key_hints {
+                sdl2_overlay(canvas, &key_hint_lines(usage, key_map), true)?;
+            }
+        }
+    }
+    canvas.present();
+    Ok(())
+}
+
+/// the keys the rom tested, how often and the keyboard keys they are on
+fn key_hint_lines(usage: &KeyUsage, key_map: &Sdl2KeyMap) -> Vec<String> {
+    let mut lines = vec!["keys used:".to_string()];
+    for key in usage.used_keys() {
+        lines.push(format!(
+            "{:X} {:<9} {:>3}%",
+            key,
+            key_map.key_names(key).join("/"),
+            (usage.heat(key) * 100.0).round()
+        ));
+    }
+    if usage.waits_for_any_key() {
+        lines.push("any key (FX0A)".to_string());
+    }
+    if lines.len() == 1 {
+        lines.push("none yet".to_string());
+    }
+    lines
+}
+
+/// the 4x4 keypad in `area`, held keys in the foreground color and the
+/// `checked` keys the rom tested outlined
+fn sdl2_keypad(
+    canvas: &mut Canvas<Window>,
+    area: Rect,
+    machine: &Machine<DynAudio>,
+    checked: u16,
+    config: &DisplayConfig,
+) -> Result<()> {
+    let rgb = |color: Color| pixels::Color::RGB(color.r, color.g, color.b);
+    let cell = area.width() / 4;
+    let gap = (cell / 12).max(1);
+    let scale = (cell / (GLYPH_HEIGHT as u32 * 3)).max(1);
+    for key in 0..16u8 {
+        let (column, row) = keypad::position(key);
+        let rect = Rect::new(
+            area.x() + (column as u32 * cell + gap) as i32,
+            area.y() + (row as u32 * cell + gap) as i32,
+            cell - 2 * gap,
+            cell - 2 * gap,
+        );
+        let down = machine.is_key_down(key);
+        let (fill, text) = if down {
+            (config.foreground, config.background)
+        } else {
+            (config.background, config.foreground)
+        };
+        canvas.set_draw_color(rgb(fill));
+        canvas.fill_rect(rect)?;
+        canvas.set_draw_color(rgb(config.foreground));
+        canvas.draw_rect(rect)?;
+        if checked >> key & 1 == 1 {
+            canvas.draw_rect(Rect::new(
+                rect.x() + gap as i32,
+                rect.y() + gap as i32,
+                rect.width().saturating_sub(2 * gap),
+                rect.height().saturating_sub(2 * gap),
+            ))?;
+        }
+        canvas.set_draw_color(rgb(text));
+        let label = format!("{:X}", key);
+        let left = rect.center().x() - (GLYPH_WIDTH as u32 * scale / 2) as i32;
+        let top = rect.center().y() - (GLYPH_HEIGHT as u32 * scale / 2) as i32;
+        sdl2_text(canvas, &[label], left, top, scale)?;
+    }
+    Ok(())
+}
+
+/// draw lines of text with the small frontend font in the current draw color,
+/// every font pixel is a `scale` x `scale` square
+fn sdl2_text(
+    canvas: &mut Canvas<Window>,
+    lines: &[String],
+    left: i32,
+    top: i32,
+    scale: u32,
+) -> Result<()> {
+    let line_height = (GLYPH_HEIGHT as u32 + 2) * scale;
+    let mut rects = vec![];
+    for (row, line) in lines.iter().enumerate() {
+        let top = top + (row as u32 * line_height) as i32;
+        for (column, c) in line.chars().enumerate() {
+            let left = left + (column as u32 * ADVANCE as u32 * scale) as i32;
+            for (y, bits) in text::glyph(c).iter().enumerate() {
+                for x in 0..GLYPH_WIDTH {
+                    if bits >> (GLYPH_WIDTH - 1 - x) & 1 == 1 {
+                        rects.push(Rect::new(
+                            left + (x as u32 * scale) as i32,
+                            top + (y as u32 * scale) as i32,
+                            scale,
+                            scale,
+                        ));
+                    }
+                }
+            }
+        }
+    }
+    canvas.fill_rects(&rects)?;
+    Ok(())
+}
+
+/// text over the top left corner of the window, or the top right one
+fn sdl2_overlay(canvas: &mut Canvas<Window>, lines: &[String], right: bool) -> Result<()> {
+    const SCALE: u32 = 2;
+    let columns = lines
+        .iter()
+        .map(|line| text::text_width(line))
+        .max()
+        .unwrap_or(0);
+    let width = (columns as u32 + 2) * SCALE;
+    let height = (lines.len() * (GLYPH_HEIGHT + 2) + 1) as u32 * SCALE;
+    canvas.set_blend_mode(BlendMode::Blend);
+    let left = if right {
+        canvas.output_size()?.0.saturating_sub(width) as i32
+    } else {
+        0
+    };
+    canvas.set_draw_color(pixels::Color::RGBA(0, 0, 0, 192));
+    canvas.fill_rect(Rect::new(left, 0, width, height))?;
+    canvas.set_blend_mode(BlendMode::None);
+    canvas.set_draw_color(pixels::Color::WHITE);
+    sdl2_text(canvas, lines, left + SCALE as i32, SCALE as i32, SCALE)
+}
+
+/// Secondary window with the text lines of a debug view in the small
+/// frontend font
+struct DebugWindow {
+    canvas: Canvas<Window>,
+}
+
+impl DebugWindow {
+    // pixels per font pixel
+    const SCALE: u32 = 3;
+    const LINE_HEIGHT: u32 = (GLYPH_HEIGHT as u32 + 2) * Self::SCALE;
+
+    /// a window fitting `lines` lines of `columns` characters with its top
+    /// left corner at (x, y)
+    fn new(
+        main: &Canvas<Window>,
+        title: &str,
+        columns: u32,
+        lines: u32,
+        x: i32,
+        y: i32,
+    ) -> Result<Self> {
+        let width = (columns * ADVANCE as u32 + 2) * Self::SCALE;
+        let height = lines * Self::LINE_HEIGHT + Self::SCALE;
+        Self::with_size(main, title, (width, height), x, y)
+    }
+
+    fn with_size(
+        main: &Canvas<Window>,
+        title: &str,
+        (width, height): (u32, u32),
+        x: i32,
+        y: i32,
+    ) -> Result<Self> {
+        let window = main
+            .window()
+            .subsystem()
+            .window(title, width, height)
+            .position(x, y)
+            .build()?;
+        let canvas = window.into_canvas().build()?;
+        Ok(DebugWindow { canvas })
+    }
+
+    fn id(&self) -> u32 {
+        self.canvas.window().id()
+    }
+
+    fn draw(&mut self, lines: &[String]) -> Result<()> {
+        let scale = Self::SCALE;
+        self.canvas.set_draw_color(pixels::Color::BLACK);
+        self.canvas.clear();
+        self.canvas.set_draw_color(pixels::Color::WHITE);
+        sdl2_text(&mut self.canvas, lines, scale as i32, scale as i32, scale)?;
+        self.canvas.present();
+        Ok(())
+    }
+
+    /// a square of `HEAT_CELL` pixels for every address of the first 4KB,
+    /// `HEAT_COLUMNS` a row
+    fn draw_heat(&mut self, heatmap: &Heatmap) -> Result<()> {
+        for addr in 0..HEAT_ROWS * HEAT_COLUMNS {
+            let (x, y) = (addr % HEAT_COLUMNS, addr / HEAT_COLUMNS);
+            let color = heatmap.color(addr);
+            self.canvas
+                .set_draw_color(pixels::Color::RGB(color.r, color.g, color.b));
+            self.canvas.fill_rect(Rect::new(
+                (x as u32 * HEAT_CELL) as i32,
+                (y as u32 * HEAT_CELL) as i32,
+                HEAT_CELL,
+                HEAT_CELL,
+            ))?;
+        }
+        self.canvas.present();
+        Ok(())
+    }
+}
+
+/// The debug views in their own windows next to the display, so no overlay
+/// covers the game: `debugger::memory_view`, below it
+/// `debugger::register_view` with the frame pacing and the `Heatmap` of
+/// the memory writes
+#[derive(Default)]
+struct DebugWindows {
+    memory: Option<DebugWindow>,
+    registers: Option<DebugWindow>,
+    writes: Option<DebugWindow>,
+}
+
+impl DebugWindows {
+    /// the machine keeps the heatmap from now on
+    fn open(main: &Canvas<Window>, machine: &mut Machine<DynAudio>) -> Result<Self> {
+        let (x, y) = main.window().position();
+        let x = x + main.window().size().0 as i32 + 8;
+        let memory = DebugWindow::new(main, "memory", 60, 12, x, y)?;
+        let below = y + memory.canvas.window().size().1 as i32 + 32;
+        let registers = DebugWindow::new(main, "registers", 60, 5, x, below)?;
+        let below = below + registers.canvas.window().size().1 as i32 + 32;
+        let size = (
+            HEAT_COLUMNS as u32 * HEAT_CELL,
+            HEAT_ROWS as u32 * HEAT_CELL,
+        );
+        let writes = DebugWindow::with_size(main, "memory writes", size, x, below)?;
+        machine.track_writes();
+        Ok(DebugWindows {
+            memory: Some(memory),
+            registers: Some(registers),
+            writes: Some(writes),
+        })
+    }
+
+    fn is_open(&self) -> bool {
+        self.memory.is_some() || self.registers.is_some() || self.writes.is_some()
+    }
+
+    fn close(&mut self, id: u32) {
+        for window in [&mut self.memory, &mut self.registers, &mut self.writes] {
+            if window.as_ref().is_some_and(|w| w.id() == id) {
+                *window = None;
+            }
+        }
+    }
+
+    fn draw(&mut self, machine: &Machine<DynAudio>, stats: &FrameStats) -> Result<()> {
+        if let Some(window) = self.memory.as_mut() {
+            window.draw(&debugger::memory_view(machine))?;
+        }
+        if let Some(window) = self.registers.as_mut() {
+            let mut lines = debugger::register_view(machine);
+            lines.push(stats.summary());
+            window.draw(&lines)?;
+        }
+        if let (Some(window), Some(heatmap)) = (self.writes.as_mut(), machine.heatmap()) {
+            window.draw_heat(heatmap)?;
+        }
+        Ok(())
+    }
+}
+
+fn sdl2_init(
+    width: u32,
+    height: u32,
+    vsync: bool,
+    audio: Option<AudioConfig>,
+) -> Result<(
+    Canvas<Window>,
+    DynAudio,
+    EventPump,
+    Option<GameControllerSubsystem>,
+)> {
+    let sdl_context = sdl2::init()?;
+
+    let video = sdl_context.video()?;
+    let window = video
+        .window(frontend::TITLE, width, height)
+        .position_centered()
+        .resizable()
+        .build()?;
+    let mut canvas = window.into_canvas().accelerated();
+    if vsync {
+        canvas = canvas.present_vsync();
+    }
+    let canvas = canvas.build()?;
+
+    let audio: DynAudio = match audio {
+        Some(config) => match sdl_context
+            .audio()
+            .map_err(Into::into)
+            .and_then(|subsystem| Sdl2Audio::new(subsystem, config))
+        {
+            Ok(audio) => Box::new(audio),
+            // a machine without an audio device can still play silently
+            Err(e) => {
+                warn!(target: AUDIO, "audio is disabled: {}", e);
+                Box::new(NullAudio)
+            }
+        },
+        None => Box::new(NullAudio),
+    };
+    // a missing controller subsystem should not stop keyboard play
+    let controller = match sdl_context.game_controller() {
+        Ok(controller) => Some(controller),
+        Err(e) => {
+            warn!(target: INPUT, "game controllers are disabled: {}", e);
+            None
+        }
+    };
+    Ok((canvas, audio, sdl_context.event_pump()?, controller))
+}
+
+/// Settings of the SDL2 frontend
+pub struct Options {
+    /// F5 saves and F9 loads the machine state from this file
+    pub state_path: PathBuf,
+    /// instructions per second, `UNLIMITED` runs as fast as possible
+    pub clock_freq: u64,
+    pub display: DisplayConfig,
+    /// None plays no sound, with `--no-audio`
+    pub audio: Option<AudioConfig>,
+    pub key_map: Sdl2KeyMap,
+    pub pad_map: Sdl2PadMap,
+    /// buttons of the second controller connected, for two player games,
+    /// otherwise every controller uses `pad_map`
+    pub pad_map2: Option<Sdl2PadMap>,
+    pub debugger: Option<Debugger>,
+    /// write every executed instruction to a trace file
+    pub tracer: Option<FileTracer>,
+    /// show a rom picker before running, the machine starts without a rom
+    pub rom_menu: Option<RomMenu>,
+    /// F3 loads the next of these roms, the machine starts with the first
+    pub roms: Vec<String>,
+    /// record a GIF from the start, saved here when F10 is pressed or on exit
+    pub record: Option<PathBuf>,
+    pub timing: Timing,
+    /// present on the display refresh instead of the 60Hz timer tick
+    pub vsync: bool,
+    /// log the frame pacing every `FRAME_STATS_PERIOD`
+    pub frame_stats: bool,
+    /// reload the rom when its file changes, follows the rom opened last
+    pub watcher: Option<RomWatcher>,
+    /// show a keypad right of the display that can be clicked or touched
+    pub keypad: bool,
+    /// run in lockstep with another player, the machine has the session's
+    /// seed and quirks and `clock_freq` is its speed
+    pub netplay: Option<Netplay>,
+    /// requests from `--remote` clients
+    pub remote: Option<Receiver<Call>>,
+    /// called after every frame the machine ran, the `--script`
+    pub on_frame: Option<FrameHook<DynAudio>>,
+    /// keep the RPL flags of every rom in its `rpl::flags_path` file
+    pub keep_flags: bool,
+    /// pause while the window is in the background or minimized
+    pub auto_pause: bool,
+}
+
+/// The window as a display, with the overlays, the rom menu and the debug
+/// windows
+pub struct Sdl2Display<'a> {
+    canvas: Canvas<Window>,
+    screen: Screen<'a>,
+    config: DisplayConfig,
+    key_map: Sdl2KeyMap,
+    view: Rc<RefCell<View>>,
+    /// the clickable keypad right of the display
+    keypad: bool,
+    /// the refresh period with vsync
+    vsync: Option<Duration>,
+    overlay: bool,
+    show_stats: bool,
+    key_hints: bool,
+    collision_flashes: Option<CollisionFlashes>,
+    key_usage: KeyUsage,
+    // keys the rom tested in the last frame that ran
+    checked_keys: u16,
+    debug_windows: DebugWindows,
+    recorder: Option<GifRecorder>,
+    record_path: Option<PathBuf>,
+    frame_stats: FrameStats,
+    run_stats: RunStats,
+    next_report: Instant,
+    log_frame_stats: bool,
+}
+
+impl<'a> Sdl2Display<'a> {
+    fn hotkey(&mut self, hotkey: Hotkey, machine: &mut Machine<DynAudio>) {
+        match hotkey {
+            Hotkey::Screenshot => screenshot(machine, &self.config),
+            Hotkey::Record => match self.recorder.take() {
+                Some(recording) => {
+                    save_recording(&recording, self.record_path.take(), &self.config)
+                }
+                None => {
+                    info!("recording started");
+                    self.recorder = Some(GifRecorder::new());
+                }
+            },
+            Hotkey::Overlay => self.overlay = !self.overlay,
+            Hotkey::Stats => self.show_stats = !self.show_stats,
+            Hotkey::KeyHints => self.key_hints = !self.key_hints,
+            Hotkey::Collisions => {
+                self.collision_flashes = match self.collision_flashes {
+                    Some(_) => None,
+                    None => Some(CollisionFlashes::default()),
+                };
+            }
+            Hotkey::DebugWindows => {
+                if self.debug_windows.is_open() {
+                    self.debug_windows = DebugWindows::default();
+                } else {
+                    match DebugWindows::open(&self.canvas, machine) {
+                        Ok(windows) => self.debug_windows = windows,
+                        Err(e) => error!("can not open the debug windows: {}", e),
+                    }
+                }
+            }
+            Hotkey::CloseWindow(id) => self.debug_windows.close(id),
+        }
+    }
+
+    /// save the recording, also after the machine stopped as it is most
+    /// useful when it shows the crash
+    fn finish(&mut self) {
+        if let Some(recording) = self.recorder.take() {
+            save_recording(&recording, self.record_path.take(), &self.config);
+        }
+    }
+}
+
+impl<'a> DisplayBackend<DynAudio> for Sdl2Display<'a> {
+    /// with vsync this waits for the display refresh
+    fn present(&mut self, machine: &mut Machine<DynAudio>, frame: &FrameInfo) -> Result<()> {
+        let hotkeys = std::mem::take(&mut self.view.borrow_mut().hotkeys);
+        for hotkey in hotkeys {
+            self.hotkey(hotkey, machine);
+        }
+        if frame.ran {
+            self.checked_keys = machine.take_checked_keys();
+            self.key_usage
+                .add_frame(self.checked_keys, machine.take_key_wait());
+            let collisions = machine.take_collisions();
+            if let Some(flashes) = self.collision_flashes.as_mut() {
+                flashes.push(collisions, Instant::now());
+            }
+            if let Some(recorder) = self.recorder.as_mut() {
+                recorder.push(machine.get_display());
+            }
+        }
+
+        let mut view = self.view.borrow_mut();
+        let content = FrameContent {
+            menu: view.menu.as_ref(),
+            overlay: self.overlay.then_some(&self.frame_stats),
+            stats: self
+                .show_stats
+                .then_some((&self.run_stats, frame.target_ips)),
+            collisions: self.collision_flashes.as_ref(),
+            keypad: self.keypad,
+            checked_keys: self.checked_keys,
+            key_hints: self.key_hints.then_some((&self.key_usage, &self.key_map)),
+            config: &self.config,
+        };
+        sdl2_present(&mut self.canvas, &mut self.screen, machine, content)?;
+        if self.keypad {
+            view.keypad = Some(KeypadTarget::new(self.canvas.window()));
+        }
+        let now = Instant::now();
+        self.frame_stats.present(now);
+        self.run_stats.frame();
+        self.debug_windows.draw(machine, &self.frame_stats)?;
+        self.run_stats
+            .update(now, machine.cycles(), machine.timer_ticks());
+        if now >= self.next_report {
+            if self.log_frame_stats {
+                info!(target: VIDEO, "frame pacing: {}", self.frame_stats.summary());
+            }
+            self.frame_stats.clear();
+            self.next_report = now + FRAME_STATS_PERIOD;
+        }
+        Ok(())
+    }
+
+    fn set_title(&mut self, title: &str) -> Result<()> {
+        self.canvas.window_mut().set_title(title)?;
+        Ok(())
+    }
+
+    fn loaded(&mut self) -> Result<()> {
+        self.view.borrow_mut().menu = None;
+        self.key_usage = KeyUsage::default();
+        Ok(())
+    }
+
+    fn vsync(&self) -> Option<Duration> {
+        self.vsync
+    }
+}
+
+/// Run the machine in an SDL2 window until it halts or the window is closed
+pub fn emulate(machine: &mut Machine<DynAudio>, options: Options) -> Result<()> {
+    let Options {
+        state_path,
+        clock_freq,
+        display,
+        audio,
+        key_map,
+        pad_map,
+        pad_map2,
+        debugger,
+        tracer,
+        rom_menu,
+        roms,
+        record,
+        timing,
+        vsync,
+        frame_stats,
+        watcher,
+        keypad,
+        netplay,
+        remote,
+        on_frame,
+        keep_flags,
+        auto_pause,
+    } = options;
+
+    let (width, height) = (machine.width() as u32, machine.height() as u32);
+    let volume = audio.map_or(0.0, |audio| audio.volume);
+    // the keypad is as high as the display
+    let window_width = width * display.scale + if keypad { height * display.scale } else { 0 };
+    let (canvas, audio, event_pump, controller) =
+        sdl2_init(window_width, height * display.scale, vsync, audio)?;
+    let texture_creator = canvas.texture_creator();
+    let screen = Screen::new(&texture_creator, width as usize, height as usize)?;
+    let debug_windows = match debugger {
+        Some(_) => DebugWindows::open(&canvas, machine)?,
+        None => DebugWindows::default(),
+    };
+    let refresh_rate = match canvas.window().display_mode() {
+        Ok(mode) if vsync && mode.refresh_rate > 0 => mode.refresh_rate as u32,
+        _ => 60,
+    };
+
+    let view = Rc::new(RefCell::new(View {
+        keypad: keypad.then(|| KeypadTarget::new(canvas.window())),
+        menu: rom_menu,
+        hotkeys: vec![],
+    }));
+    let input = Sdl2Input {
+        event_pump,
+        key_map: key_map.clone(),
+        gamepads: Gamepads::new(
+            controller,
+            std::iter::once(pad_map).chain(pad_map2).collect(),
+        ),
+        held: HeldKeys::default(),
+        main_window: canvas.window().id(),
+        view: Rc::clone(&view),
+    };
+    let mut display = Sdl2Display {
+        canvas,
+        screen,
+        config: display,
+        key_map,
+        view: Rc::clone(&view),
+        keypad,
+        vsync: vsync.then(|| Duration::from_secs(1) / refresh_rate),
+        overlay: false,
+        show_stats: false,
+        key_hints: false,
+        collision_flashes: None,
+        key_usage: KeyUsage::default(),
+        checked_keys: 0,
+        debug_windows,
+        recorder: record.as_ref().map(|_| GifRecorder::new()),
+        record_path: record,
+        frame_stats: FrameStats::new(refresh_rate),
+        run_stats: RunStats::new(Instant::now(), machine.cycles(), machine.timer_ticks()),
+        next_report: Instant::now() + FRAME_STATS_PERIOD,
+        log_frame_stats: frame_stats,
+    };
+
+    // the machine starts with the first rom unless one is picked
+    let first_rom = match view.borrow().menu {
+        Some(_) => None,
+        None => roms.first().cloned(),
+    };
+    let flags = match &first_rom {
+        Some(path) if keep_flags => rpl::for_rom(path, machine),
+        _ => None,
+    };
+    let options = frontend::Options {
+        clock_freq,
+        timing,
+        volume,
+        watcher,
+        title: first_rom,
+        on_frame,
+        flags,
+        keep_flags,
+        state_path: Some(state_path),
+        roms,
+        rewind: true,
+        auto_pause,
+        debugger,
+        tracer,
+        netplay,
+        remote,
+    };
+    let result = Emulator::new(machine, audio, &mut display, input, options).run();
+    display.finish();
+    result
+}
+
+fn screenshot(machine: &Machine<DynAudio>, display: &DisplayConfig) {
+    let frame = Frame::new(machine.get_display(), display);
+    match capture::timestamped_path(Path::new(SCREENSHOT_DIR), "screenshot", "png")
+        .and_then(|path| frame.save_png(&path).map(|_| path))
+    {
+        Ok(path) => info!(target: VIDEO, "screenshot saved to {}", path.display()),
+        Err(e) => error!(target: VIDEO, "can not save screenshot: {}", e),
+    }
+}
+
+/// save to `path`, or a new file in the recordings directory
+fn save_recording(recorder: &GifRecorder, path: Option<PathBuf>, display: &DisplayConfig) {
+    let path = match path {
+        Some(path) => Ok(path),
+        None => capture::timestamped_path(Path::new(RECORDING_DIR), "recording", "gif"),
+    };
+    match path.and_then(|path| recorder.save_gif(&path, display).map(|_| path)) {
+        Ok(path) => info!(
+            target: VIDEO,
+            "recording of {} frames saved to {}",
+            recorder.len(),
+            path.display()
+        ),
+        Err(e) => error!(target: VIDEO, "can not save recording: {}", e),
+    }
+}
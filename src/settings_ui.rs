@@ -0,0 +1,192 @@
+use egui::{ClippedPrimitive, Context, TexturesDelta};
+use egui_wgpu::renderer::{Renderer, ScreenDescriptor};
+use pixels::{wgpu, Pixels, PixelsContext};
+use winit::event::WindowEvent;
+use winit::event_loop::EventLoopWindowTarget;
+use winit::window::Window;
+
+use crate::display::Color;
+use crate::pixels_frontend::Settings;
+use crate::{Quirks, UNLIMITED};
+
+/// range of the speed slider in instructions per second
+const SPEED_RANGE: std::ops::RangeInclusive<u64> = 100..=5000;
+
+/// egui panel over the pixels window to change the settings while the rom
+/// runs, F1 shows and hides it
+pub(crate) struct SettingsUi {
+    ctx: Context,
+    state: egui_winit::State,
+    screen: ScreenDescriptor,
+    renderer: Renderer,
+    paint_jobs: Vec<ClippedPrimitive>,
+    textures: TexturesDelta,
+    pub open: bool,
+}
+
+impl SettingsUi {
+    pub fn new<T>(event_loop: &EventLoopWindowTarget<T>, window: &Window, pixels: &Pixels) -> Self {
+        let size = window.inner_size();
+        let scale_factor = window.scale_factor() as f32;
+        let mut state = egui_winit::State::new(event_loop);
+        state.set_max_texture_side(pixels.device().limits().max_texture_dimension_2d as usize);
+        state.set_pixels_per_point(scale_factor);
+        SettingsUi {
+            ctx: Context::default(),
+            state,
+            screen: ScreenDescriptor {
+                size_in_pixels: [size.width, size.height],
+                pixels_per_point: scale_factor,
+            },
+            renderer: Renderer::new(pixels.device(), pixels.render_texture_format(), None, 1),
+            paint_jobs: vec![],
+            textures: TexturesDelta::default(),
+            open: false,
+        }
+    }
+
+    /// pass a window event to egui, true when the open panel used it
+    pub fn handle_event(&mut self, event: &WindowEvent) -> bool {
+        match event {
+            WindowEvent::Resized(size) if size.width > 0 && size.height > 0 => {
+                self.screen.size_in_pixels = [size.width, size.height];
+            }
+            WindowEvent::ScaleFactorChanged { scale_factor, .. } => {
+                self.screen.pixels_per_point = *scale_factor as f32;
+            }
+            _ => {}
+        }
+        let response = self.state.on_event(&self.ctx, event);
+        self.open && response.consumed
+    }
+
+    /// lay out the panel for the next frame, `settings` is changed by it
+    pub fn prepare(&mut self, window: &Window, settings: &mut Settings) {
+        let input = self.state.take_egui_input(window);
+        let open = &mut self.open;
+        let output = self.ctx.run(input, |ctx| {
+            egui::Window::new("Settings")
+                .open(open)
+                .resizable(false)
+                .show(ctx, |ui| settings_panel(ui, settings));
+        });
+        self.textures.append(output.textures_delta);
+        self.state
+            .handle_platform_output(window, &self.ctx, output.platform_output);
+        self.paint_jobs = self.ctx.tessellate(output.shapes);
+    }
+
+    /// draw the panel over the scaled display
+    pub fn render(
+        &mut self,
+        encoder: &mut wgpu::CommandEncoder,
+        target: &wgpu::TextureView,
+        context: &PixelsContext,
+    ) {
+        for (id, delta) in &self.textures.set {
+            self.renderer
+                .update_texture(&context.device, &context.queue, *id, delta);
+        }
+        self.renderer.update_buffers(
+            &context.device,
+            &context.queue,
+            encoder,
+            &self.paint_jobs,
+            &self.screen,
+        );
+        {
+            let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("settings"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: target,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: true,
+                    },
+                })],
+                depth_stencil_attachment: None,
+            });
+            self.renderer
+                .render(&mut pass, &self.paint_jobs, &self.screen);
+        }
+        for id in &std::mem::take(&mut self.textures).free {
+            self.renderer.free_texture(id);
+        }
+    }
+}
+
+fn settings_panel(ui: &mut egui::Ui, settings: &mut Settings) {
+    ui.heading("Speed");
+    let mut unlimited = settings.clock_freq == UNLIMITED;
+    ui.horizontal(|ui| {
+        let mut freq = if unlimited {
+            *SPEED_RANGE.end()
+        } else {
+            settings.clock_freq
+        };
+        ui.add_enabled(
+            !unlimited,
+            egui::Slider::new(&mut freq, SPEED_RANGE).suffix(" Hz"),
+        );
+        ui.checkbox(&mut unlimited, "unlimited");
+        settings.clock_freq = if unlimited { UNLIMITED } else { freq };
+    });
+
+    ui.heading("Quirks");
+    ui.horizontal(|ui| {
+        for profile in Quirks::PROFILES {
+            if ui.button(profile).clicked() {
+                settings.quirks = profile.parse().unwrap();
+            }
+        }
+    });
+    let quirks = &mut settings.quirks;
+    ui.checkbox(&mut quirks.shift_uses_vy, "8XY6/8XYE shift VY");
+    ui.checkbox(&mut quirks.load_store_increment_i, "FX55/FX65 increment I");
+    ui.checkbox(&mut quirks.jump_uses_vx, "BNNN jumps to XNN + VX");
+    ui.checkbox(&mut quirks.display_wait, "DXYN waits for the display");
+    ui.checkbox(&mut quirks.wrap_sprites, "DXYN wraps sprites");
+    ui.checkbox(&mut quirks.half_scroll, "lores scrolls by half");
+    ui.checkbox(&mut quirks.short_stack, "2NNN stack 12 deep");
+
+    ui.heading("Colors");
+    ui.horizontal(|ui| {
+        color_picker(ui, &mut settings.foreground, "foreground");
+        color_picker(ui, &mut settings.background, "background");
+    });
+
+    ui.heading("Sound");
+    ui.add(egui::Slider::new(&mut settings.volume, 0.0..=1.0).text("volume"));
+
+    ui.heading("Keys");
+    ui.label("click a key and press the new one");
+    egui::Grid::new("keymap").show(ui, |ui| {
+        for (n, key) in [
+            0x1, 0x2, 0x3, 0xC, 0x4, 0x5, 0x6, 0xD, 0x7, 0x8, 0x9, 0xE, 0xA, 0x0, 0xB, 0xF,
+        ]
+        .iter()
+        .copied()
+        .enumerate()
+        {
+            let label = if settings.rebinding == Some(key) {
+                format!("{:X}: ...", key)
+            } else {
+                format!("{:X}: {:?}", key, settings.keymap[key as usize])
+            };
+            if ui.button(label).clicked() {
+                settings.rebinding = Some(key);
+            }
+            if n % 4 == 3 {
+                ui.end_row();
+            }
+        }
+    });
+}
+
+fn color_picker(ui: &mut egui::Ui, color: &mut Color, label: &str) {
+    let mut rgb = [color.r, color.g, color.b];
+    ui.color_edit_button_srgb(&mut rgb);
+    ui.label(label);
+    *color = Color::rgb(rgb[0], rgb[1], rgb[2]);
+}
@@ -0,0 +1,63 @@
+use std::ops::Range;
+
+use crate::machine::MEMORY_SIZE;
+
+/// An instruction wrote over memory that was executed before
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SelfModify {
+    /// address of the writing instruction
+    pub pc: u16,
+    /// first executed address that was overwritten
+    pub addr: u16,
+}
+
+/// Remembers the executed addresses to notice self modifying code
+pub(crate) struct SmcTracker {
+    executed: Vec<u64>,
+    // addresses already warned about, `warn` logs each one once
+    warned: Vec<u64>,
+    warn: bool,
+}
+
+impl SmcTracker {
+    pub fn new(warn: bool) -> Self {
+        SmcTracker {
+            executed: vec![0; MEMORY_SIZE / 64],
+            warned: vec![0; MEMORY_SIZE / 64],
+            warn,
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.executed.fill(0);
+        self.warned.fill(0);
+    }
+
+    /// the two bytes of the instruction at `pc` are executed
+    pub fn execute(&mut self, pc: u16) {
+        for addr in [pc as usize, pc as usize + 1] {
+            if addr < MEMORY_SIZE {
+                self.executed[addr / 64] |= 1 << (addr % 64);
+            }
+        }
+    }
+
+    /// `range` was written by the instruction at `pc`
+    pub fn write(&mut self, pc: u16, range: Range<usize>) -> Option<SelfModify> {
+        let addr = range
+            .clone()
+            .take_while(|&addr| addr < MEMORY_SIZE)
+            .find(|&addr| self.executed[addr / 64] & 1 << (addr % 64) != 0)?;
+        if self.warn && self.warned[addr / 64] & 1 << (addr % 64) == 0 {
+            self.warned[addr / 64] |= 1 << (addr % 64);
+            warn!(
+                "self modifying code: {:#05X} wrote over the executed {:#05X}",
+                pc, addr
+            );
+        }
+        Some(SelfModify {
+            pc,
+            addr: addr as u16,
+        })
+    }
+}
@@ -0,0 +1,66 @@
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::Path;
+
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::audio::{DEFAULT_PITCH, PATTERN_BYTES};
+use crate::keyboard::KeyBoard;
+use crate::machine::{REGISTER_COUNT, RPL_FLAG_COUNT};
+use crate::megachip::MegaChip;
+use crate::video::Video;
+use crate::Result;
+
+/// Snapshot of everything needed to resume a `Machine`
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SaveState {
+    pub memory: Vec<u8>,
+    pub registers: [u8; REGISTER_COUNT],
+    pub pc: u16,
+    pub i: u32,
+    /// return addresses of the active calls, oldest first
+    pub stack: Vec<u16>,
+    // states saved before the stack could be 16 deep kept all the slots and
+    // the active calls were 1 to this
+    #[serde(default, skip_serializing)]
+    pub stack_pointer: Option<usize>,
+    pub delay_timer: u8,
+    pub sound_timer: u8,
+    pub keyboard: KeyBoard,
+    pub video: Video,
+    pub rpl_flags: [u8; RPL_FLAG_COUNT],
+    pub halted: bool,
+    pub waiting_key: Option<u8>,
+    // missing in states saved before the display wait quirk
+    #[serde(default)]
+    pub vblank: bool,
+    // missing in states saved before the seeded RNG, the RNG is kept then
+    #[serde(default)]
+    pub rng: Option<ChaCha8Rng>,
+    // missing in states saved before XO-CHIP audio
+    #[serde(default)]
+    pub audio_pattern: Option<[u8; PATTERN_BYTES]>,
+    #[serde(default = "default_pitch")]
+    pub pitch: u8,
+    // missing in states saved before MegaChip mode
+    #[serde(default)]
+    pub mega: Option<MegaChip>,
+}
+
+fn default_pitch() -> u8 {
+    DEFAULT_PITCH
+}
+
+impl SaveState {
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let writer = BufWriter::new(File::create(path)?);
+        serde_json::to_writer(writer, self)?;
+        Ok(())
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let reader = BufReader::new(File::open(path)?);
+        Ok(serde_json::from_reader(reader)?)
+    }
+}
@@ -0,0 +1,136 @@
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::Path;
+
+use crate::{err, Result};
+
+/// Label names of a program, from the assembler, the Octo compiler or a
+/// symbol file, shown instead of raw addresses by the disassembler, the
+/// debugger and the trace
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Symbols {
+    by_name: BTreeMap<String, u16>,
+    // the first name given to an address
+    by_addr: BTreeMap<u16, String>,
+}
+
+impl Symbols {
+    pub fn insert(&mut self, name: &str, addr: u16) {
+        self.by_name.insert(name.to_string(), addr);
+        self.by_addr.entry(addr).or_insert_with(|| name.to_string());
+    }
+
+    /// Load a symbol file mapping names to addresses, as TOML or as JSON
+    /// when the extension is .json:
+    ///
+    /// ```toml
+    /// main = 0x200
+    /// draw-player = 0x23A
+    /// ```
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let content = fs::read_to_string(path)?;
+        let raw: BTreeMap<String, u16> = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("json") => serde_json::from_str(&content)
+                .map_err(|e| format!("symbols {}: {}", path.display(), e))?,
+            _ => toml::from_str(&content)
+                .map_err(|e| format!("symbols {}: {}", path.display(), e))?,
+        };
+        let mut symbols = Symbols::default();
+        for (name, addr) in raw {
+            if addr > 0xFFF {
+                return err!("symbols {}: {} is out of memory", path.display(), name);
+            }
+            symbols.insert(&name, addr);
+        }
+        Ok(symbols)
+    }
+
+    /// write in the TOML format of `load`, by address
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let mut entries: Vec<(&u16, &String)> = self
+            .by_name
+            .iter()
+            .map(|(name, addr)| (addr, name))
+            .collect();
+        entries.sort();
+        let mut content = String::new();
+        for (addr, name) in entries {
+            writeln!(content, "{:?} = {:#05X}", name, addr)?;
+        }
+        fs::write(path, content)?;
+        Ok(())
+    }
+
+    /// symbols of the labels of a program, where several share an address
+    /// the alphabetically first is shown
+    pub fn from_labels<'a, I: IntoIterator<Item = (&'a String, u16)>>(labels: I) -> Self {
+        let mut labels: Vec<(&String, u16)> = labels.into_iter().collect();
+        labels.sort();
+        let mut symbols = Symbols::default();
+        for (name, addr) in labels {
+            symbols.insert(name, addr);
+        }
+        symbols
+    }
+
+    /// the symbols of `other` added, its names win
+    pub fn merge(&mut self, other: &Symbols) {
+        for (name, &addr) in &other.by_name {
+            self.insert(name, addr);
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.by_name.is_empty()
+    }
+
+    /// the label at exactly `addr`
+    pub fn name(&self, addr: u16) -> Option<&str> {
+        self.by_addr.get(&addr).map(String::as_str)
+    }
+
+    pub fn addr(&self, name: &str) -> Option<u16> {
+        self.by_name.get(name).copied()
+    }
+
+    /// `0x204 (main)` with a label at `addr`, otherwise `0x204`
+    pub fn describe(&self, addr: u16) -> String {
+        match self.name(addr) {
+            Some(name) => format!("{:#05X} ({})", addr, name),
+            None => format!("{:#05X}", addr),
+        }
+    }
+}
+
+#[cfg(test)]
+mod symbols_test {
+    use super::*;
+
+    #[test]
+    fn test_symbols() {
+        let mut symbols = Symbols::default();
+        symbols.insert("main", 0x200);
+        symbols.insert("start", 0x200);
+        symbols.insert("draw-player", 0x23A);
+        assert_eq!(symbols.name(0x200), Some("main"));
+        assert_eq!(symbols.addr("start"), Some(0x200));
+        assert_eq!(symbols.describe(0x23A), "0x23A (draw-player)");
+        assert_eq!(symbols.describe(0x23C), "0x23C");
+
+        let dir = std::env::temp_dir().join(format!("rchip8-symbols-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("game.sym.toml");
+        symbols.save(&path).unwrap();
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "\"main\" = 0x200\n\"start\" = 0x200\n\"draw-player\" = 0x23A\n"
+        );
+        assert_eq!(Symbols::load(&path).unwrap().by_name, symbols.by_name);
+        let path = dir.join("game.json");
+        fs::write(&path, r#"{"main": 512, "end": 4096}"#).unwrap();
+        assert!(Symbols::load(&path).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
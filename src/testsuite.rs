@@ -0,0 +1,237 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::audio::NullAudio;
+use crate::headless;
+use crate::machine::Machine;
+use crate::menu;
+use crate::{Quirks, Result, DEFAULT_CLOCK_FREQ, ROM};
+
+/// the expectations shipped with the emulator, see testsuite.json
+const BUILTIN: &str = include_str!("../testsuite.json");
+/// instructions a rom without an expectation runs
+pub const DEFAULT_CYCLES: usize = 5000;
+/// CXNN gives the same numbers on every run
+const SEED: u64 = 0;
+
+/// How a test rom is run and the display it has to end with
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Expectation {
+    pub name: String,
+    #[serde(default = "default_cycles")]
+    pub cycles: usize,
+    /// quirks profile like `--compat`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub compat: Option<String>,
+    /// SHA-1 of the display as `display_to_text` prints it
+    pub display: String,
+}
+
+fn default_cycles() -> usize {
+    DEFAULT_CYCLES
+}
+
+/// Expectations by the SHA-1 of the rom, the file is a JSON object:
+///
+/// ```json
+/// {
+///   "1ba58656810b67fd131eb9af3e3987863bf26c90": {
+///     "name": "IBM Logo",
+///     "cycles": 5000,
+///     "display": "..."
+///   }
+/// }
+/// ```
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Expectations {
+    roms: BTreeMap<String, Expectation>,
+}
+
+impl Expectations {
+    pub fn builtin() -> Self {
+        Self::parse(BUILTIN).expect("invalid built-in test suite expectations")
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        fs::read_to_string(path)
+            .map_err(Into::into)
+            .and_then(|content| Self::parse(&content))
+            .map_err(|e| format!("expectations {}: {}", path.display(), e).into())
+    }
+
+    fn parse(content: &str) -> Result<Self> {
+        let roms: BTreeMap<String, Expectation> = serde_json::from_str(content)?;
+        Ok(Expectations {
+            roms: roms
+                .into_iter()
+                .map(|(hash, expectation)| (hash.to_ascii_lowercase(), expectation))
+                .collect(),
+        })
+    }
+
+    /// add the expectations of `other`, replacing the ones both have
+    pub fn extend(&mut self, other: Expectations) {
+        self.roms.extend(other.roms);
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        fs::write(path, serde_json::to_string_pretty(&self.roms)? + "\n")?;
+        Ok(())
+    }
+
+    /// expect what the roms of `outcomes` displayed this time, the ones that
+    /// failed to run are left out
+    pub fn record(&mut self, outcomes: &[Outcome]) {
+        for outcome in outcomes {
+            if let Some(display) = &outcome.display {
+                self.roms.insert(
+                    outcome.sha1.clone(),
+                    Expectation {
+                        name: outcome.name.clone(),
+                        cycles: outcome.cycles,
+                        compat: outcome.compat.clone(),
+                        display: display.clone(),
+                    },
+                );
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Verdict {
+    Pass,
+    Fail,
+    /// no expectation for the rom
+    Unknown,
+    /// the machine stopped with an error
+    Error(String),
+}
+
+impl fmt::Display for Verdict {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Verdict::Pass => write!(f, "PASS"),
+            Verdict::Fail => write!(f, "FAIL"),
+            Verdict::Unknown => write!(f, "????"),
+            Verdict::Error(e) => write!(f, "ERROR {}", e),
+        }
+    }
+}
+
+/// the result of one test rom
+#[derive(Debug, Clone)]
+pub struct Outcome {
+    pub path: PathBuf,
+    pub sha1: String,
+    /// of the expectation, or the file name
+    pub name: String,
+    pub cycles: usize,
+    pub compat: Option<String>,
+    /// SHA-1 of the display, None when the rom failed to run
+    pub display: Option<String>,
+    pub verdict: Verdict,
+}
+
+/// run `rom` headless and return the SHA-1 of its display
+pub fn run_rom(rom: &ROM, cycles: usize, compat: Option<&str>) -> Result<String> {
+    let mut machine: Machine<NullAudio> = Machine::new()?;
+    machine.set_quirks(match compat {
+        Some(profile) => profile.parse()?,
+        None => Quirks::default(),
+    });
+    machine.set_seed(SEED);
+    machine.load_font()?;
+    machine.load_rom(rom)?;
+    headless::run(&mut machine, cycles, DEFAULT_CLOCK_FREQ, None)?;
+    Ok(sha1_smol::Sha1::from(headless::display_to_text(&machine))
+        .digest()
+        .to_string())
+}
+
+/// Run every rom in `dir` and its sub directories and compare the displays
+/// with `expectations`
+pub fn run_suite<P: AsRef<Path>>(dir: P, expectations: &Expectations) -> Result<Vec<Outcome>> {
+    let mut paths = vec![];
+    menu::find_roms(dir.as_ref(), &mut paths)?;
+    paths.sort();
+    let mut outcomes = vec![];
+    for path in paths {
+        let rom = ROM::new(&path.to_string_lossy())?;
+        let sha1 = rom.sha1();
+        let expectation = expectations.roms.get(&sha1);
+        let name = match expectation {
+            Some(expectation) => expectation.name.clone(),
+            None => path
+                .file_stem()
+                .map_or(String::new(), |stem| stem.to_string_lossy().into_owned()),
+        };
+        let cycles = expectation.map_or(DEFAULT_CYCLES, |e| e.cycles);
+        let compat = expectation.and_then(|e| e.compat.clone());
+        let (display, verdict) = match run_rom(&rom, cycles, compat.as_deref()) {
+            Ok(display) => {
+                let verdict = match expectation {
+                    Some(e) if e.display == display => Verdict::Pass,
+                    Some(_) => Verdict::Fail,
+                    None => Verdict::Unknown,
+                };
+                (Some(display), verdict)
+            }
+            Err(e) => (None, Verdict::Error(e.to_string())),
+        };
+        outcomes.push(Outcome {
+            path,
+            sha1,
+            name,
+            cycles,
+            compat,
+            display,
+            verdict,
+        });
+    }
+    Ok(outcomes)
+}
+
+#[cfg(test)]
+mod testsuite_test {
+    use super::*;
+
+    #[test]
+    fn test_builtin_suite() {
+        let expectations = Expectations::builtin();
+        let outcomes = run_suite(
+            concat!(env!("CARGO_MANIFEST_DIR"), "/roms/programs"),
+            &expectations,
+        )
+        .unwrap();
+        let ibm = outcomes.iter().find(|o| o.name == "IBM Logo").unwrap();
+        assert_eq!(ibm.verdict, Verdict::Pass);
+        for outcome in &outcomes {
+            assert_ne!(outcome.verdict, Verdict::Fail, "{}", outcome.name);
+        }
+
+        // the display after one instruction is not the logo
+        let ibm_rom = ROM::new(&ibm.path.to_string_lossy()).unwrap();
+        assert_ne!(run_rom(&ibm_rom, 1, None).ok(), ibm.display);
+
+        // recorded outcomes pass the next run
+        let mut recorded = Expectations::default();
+        recorded.record(&outcomes);
+        let json = serde_json::to_string(&recorded.roms).unwrap();
+        let recorded = Expectations::parse(&json).unwrap();
+        let outcomes = run_suite(
+            concat!(env!("CARGO_MANIFEST_DIR"), "/roms/programs"),
+            &recorded,
+        )
+        .unwrap();
+        for outcome in &outcomes {
+            assert_eq!(outcome.verdict, Verdict::Pass, "{}", outcome.name);
+        }
+    }
+}
@@ -0,0 +1,210 @@
+use std::error::Error;
+use std::str::FromStr;
+use std::time::Instant;
+
+use crate::{clock, err, Result};
+
+/// COSMAC VIP machine cycles per second, the 1.76MHz clock takes 8 clocks a cycle
+pub const VIP_CYCLES_PER_SECOND: u64 = 220_080;
+/// of the 3668 machine cycles in a 60Hz frame the display interrupt and its
+/// DMA take about this many, the interpreter gets the rest
+const VIP_DISPLAY_CYCLES_PER_FRAME: u64 = 1_832;
+/// machine cycles the interpreter spends fetching and decoding every instruction
+const FETCH_CYCLES: u32 = 40;
+/// delay and sound timer frequency
+pub const TIMER_FREQ: u64 = 60;
+/// ticks a stalled timer clock catches up at most, a longer stall is dropped
+/// like the instructions of the clock
+const MAX_CATCH_UP: u64 = clock::max_catch_up(TIMER_FREQ);
+
+/// How the frontend paces instructions
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Timing {
+    /// every instruction takes the same time, set by the clock speed
+    #[default]
+    Fixed,
+    /// every instruction takes as long as it did on the COSMAC VIP
+    Vip,
+}
+
+impl Timing {
+    pub const NAMES: [&'static str; 2] = ["fixed", "vip"];
+}
+
+impl FromStr for Timing {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "fixed" => Ok(Timing::Fixed),
+            "vip" => Ok(Timing::Vip),
+            _ => err!("unknown timing: {}, expect one of {:?}", s, Timing::NAMES),
+        }
+    }
+}
+
+/// Approximate machine cycles the VIP interpreter takes for `opcode`,
+/// skips are counted as not taken and DXYN without waiting for the interrupt.
+/// SUPER-CHIP instructions never ran on the VIP and get a short cost.
+pub fn vip_cost(opcode: u16) -> u32 {
+    let x = (opcode >> 8 & 0xF) as u32;
+    let n = (opcode & 0xF) as u32;
+    let execute = match opcode >> 12 {
+        0x0 => match opcode {
+            // clears the 256 bytes of display memory one at a time
+            0x00E0 => 3_038,
+            _ => 10,
+        },
+        0x1 => 12,
+        0x2 => 26,
+        0x3 | 0x4 => 10,
+        0x5 | 0x9 => 14,
+        0x6 => 6,
+        0x7 => 10,
+        0x8 => 44,
+        0xA => 12,
+        0xB => 22,
+        0xC => 36,
+        // shifting and xoring every sprite row into display memory
+        0xD => 28 + 46 * n,
+        0xE => 14,
+        0xF => match opcode & 0xFF {
+            0x1E => 16,
+            0x29 => 16,
+            0x33 => 80,
+            0x55 | 0x65 => 14 + 14 * (x + 1),
+            _ => 10,
+        },
+        _ => unreachable!(),
+    };
+    FETCH_CYCLES + execute
+}
+
+/// Machine cycles the interpreter may spend, refilled from the wall clock
+pub struct VipClock {
+    last: Instant,
+    budget: i64,
+}
+
+impl VipClock {
+    pub fn new() -> Self {
+        VipClock {
+            last: Instant::now(),
+            budget: 0,
+        }
+    }
+
+    /// interpreter cycles per second, without the ones the display takes
+    fn cycles_per_second() -> u64 {
+        VIP_CYCLES_PER_SECOND - VIP_DISPLAY_CYCLES_PER_FRAME * 60
+    }
+
+    /// add the cycles elapsed since the last refill, at most one frame worth
+    /// so a stalled frontend does not run a burst of instructions
+    pub fn refill(&mut self) {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last);
+        self.last = now;
+        let per_second = Self::cycles_per_second();
+        let cycles = (elapsed.as_micros() as u64 * per_second / 1_000_000) as i64;
+        self.budget = (self.budget + cycles).min((per_second / 60) as i64);
+    }
+
+    pub fn has_budget(&self) -> bool {
+        self.budget > 0
+    }
+
+    /// pay for an instruction, the budget can go negative after an
+    /// expensive one and the next instructions wait for it
+    pub fn spend(&mut self, cycles: u32) {
+        self.budget -= cycles as i64;
+    }
+}
+
+impl Default for VipClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// 60Hz timer ticks counted from a monotonic clock. Ticks are counted since
+/// the start rather than since the last call, so late or early calls catch
+/// up instead of drifting.
+pub struct TimerClock {
+    start: Instant,
+    ticks: u64,
+}
+
+impl TimerClock {
+    pub fn new() -> Self {
+        TimerClock {
+            start: Instant::now(),
+            ticks: 0,
+        }
+    }
+
+    /// ticks that became due since the last call
+    pub fn due(&mut self) -> u64 {
+        let total = self.start.elapsed().as_micros() as u64 * TIMER_FREQ / 1_000_000;
+        let due = total - self.ticks;
+        if due > MAX_CATCH_UP {
+            self.restart();
+            return MAX_CATCH_UP;
+        }
+        self.ticks = total;
+        due
+    }
+
+    /// count from now on, e.g. when the emulation resumes after a pause
+    pub fn restart(&mut self) {
+        self.start = Instant::now();
+        self.ticks = 0;
+    }
+}
+
+impl Default for TimerClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod timing_test {
+    use super::*;
+
+    #[test]
+    fn test_vip_cost() {
+        assert_eq!(vip_cost(0x6012), 46);
+        assert!(vip_cost(0xD015) > vip_cost(0xD011));
+        assert!(vip_cost(0xFF65) > vip_cost(0xF065));
+        // a frame only has room for a few sprites
+        let frame = VipClock::cycles_per_second() / 60;
+        assert_eq!(frame as u32 / vip_cost(0xD01F), 2);
+        assert_eq!("VIP".parse::<Timing>().unwrap(), Timing::Vip);
+        assert!("cosmac".parse::<Timing>().is_err());
+    }
+
+    #[test]
+    fn test_vip_clock() {
+        let mut clock = VipClock::new();
+        assert!(!clock.has_budget());
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        clock.refill();
+        assert!(clock.has_budget());
+        // capped at one frame
+        clock.spend((VipClock::cycles_per_second() / 60) as u32);
+        assert!(!clock.has_budget());
+    }
+
+    #[test]
+    fn test_timer_clock() {
+        let mut clock = TimerClock::new();
+        assert_eq!(clock.due(), 0);
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        // 3 ticks are due after 50ms, and they are only counted once
+        assert_eq!(clock.due(), 3);
+        assert_eq!(clock.due(), 0);
+        clock.restart();
+        assert_eq!(clock.due(), 0);
+    }
+}
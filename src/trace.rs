@@ -0,0 +1,208 @@
+use std::collections::VecDeque;
+use std::fmt::Write as _;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use crate::audio::AudioPlay;
+use crate::instruction::Instruction;
+use crate::machine::{Machine, REGISTER_COUNT};
+use crate::symbols::Symbols;
+use crate::Result;
+
+/// Writes one line per executed instruction for `--trace`:
+///
+/// ```text
+/// 00000001 0200 632A LD V3, 0x2A          V3=2A
+/// 00000002 0202 A300 LD I, 0x300          I=0300
+/// ```
+///
+/// cycle count, pc, opcode, disassembly and the registers it changed.
+pub struct Tracer<W: Write> {
+    out: W,
+    cycles: u64,
+    symbols: Symbols,
+}
+
+/// tracer writing to a file, as used by the frontends
+pub type FileTracer = Tracer<BufWriter<File>>;
+
+impl FileTracer {
+    pub fn create<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Ok(Tracer::new(BufWriter::new(File::create(path)?)))
+    }
+}
+
+impl<W: Write> Tracer<W> {
+    pub fn new(out: W) -> Self {
+        Tracer {
+            out,
+            cycles: 0,
+            symbols: Symbols::default(),
+        }
+    }
+
+    /// show these label names for addresses in the disassembly
+    pub fn set_symbols(&mut self, symbols: Symbols) {
+        self.symbols = symbols;
+    }
+
+    /// run one cycle of the machine and trace it, the line is written even
+    /// if the instruction fails
+    pub fn run_cycle<T: AudioPlay>(&mut self, machine: &mut Machine<T>) -> Result<()> {
+        let before = TraceEntry::new(machine, self.cycles + 1);
+        let result = machine.run_cycle();
+        self.cycles += 1;
+        let line = before.line(machine.registers(), machine.i(), &self.symbols);
+        writeln!(self.out, "{}", line)?;
+        Ok(result?)
+    }
+
+    pub fn flush(&mut self) -> Result<()> {
+        self.out.flush()?;
+        Ok(())
+    }
+}
+
+/// The machine before an instruction, enough for its trace line
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TraceEntry {
+    pub cycle: u64,
+    pub pc: u16,
+    pub opcode: u16,
+    pub registers: [u8; REGISTER_COUNT],
+    pub i: u32,
+}
+
+impl TraceEntry {
+    /// the instruction at PC as the `cycle`th one
+    pub fn new<T: AudioPlay>(machine: &Machine<T>, cycle: u64) -> Self {
+        let mut registers = [0; REGISTER_COUNT];
+        registers.copy_from_slice(machine.registers());
+        TraceEntry {
+            cycle,
+            pc: machine.pc(),
+            opcode: machine.next_opcode().unwrap_or(0),
+            registers,
+            i: machine.i(),
+        }
+    }
+
+    /// the trace line, with the registers that differ in `registers` and `i`
+    /// after the instruction
+    pub fn line(&self, registers: &[u8], i: u32, symbols: &Symbols) -> String {
+        let instr = Instruction::new((self.opcode >> 8) as u8, self.opcode as u8);
+        let mut line = format!(
+            "{:08} {:04X} {:04X} {:<20}",
+            self.cycle,
+            self.pc,
+            self.opcode,
+            instr.disassemble_with(symbols)
+        );
+        for (x, (old, new)) in self.registers.iter().zip(registers).enumerate() {
+            if old != new {
+                let _ = write!(line, " V{:X}={:02X}", x, new);
+            }
+        }
+        if i != self.i {
+            let _ = write!(line, " I={:04X}", i);
+        }
+        line.trim_end().to_string()
+    }
+}
+
+/// The last instructions the machine executed, like a `--trace` that only
+/// keeps its tail, see `Machine::keep_history`
+#[derive(Debug, Clone)]
+pub struct History {
+    entries: VecDeque<TraceEntry>,
+    capacity: usize,
+}
+
+impl History {
+    pub fn new(capacity: usize) -> Self {
+        History {
+            entries: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    pub fn push(&mut self, entry: TraceEntry) {
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(entry);
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// the entries as trace lines, oldest first, the registers after the
+    /// last one are those of `machine`
+    pub fn lines<T: AudioPlay>(&self, machine: &Machine<T>, symbols: &Symbols) -> Vec<String> {
+        let afters = self
+            .entries
+            .iter()
+            .skip(1)
+            .map(|next| (&next.registers[..], next.i))
+            .chain([(machine.registers(), machine.i())]);
+        self.entries
+            .iter()
+            .zip(afters)
+            .map(|(entry, (registers, i))| entry.line(registers, i, symbols))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod trace_test {
+    use super::*;
+    use crate::audio::NullAudio;
+    use crate::rom::ROM;
+
+    #[test]
+    fn trace_lines() {
+        let mut machine: Machine<NullAudio> = Machine::new().unwrap();
+        let rom = ROM::from_bytes("trace", vec![0x63, 0x2A, 0xA3, 0x00, 0x00, 0xE0]);
+        machine.load_rom(&rom).unwrap();
+
+        let mut tracer = Tracer::new(vec![]);
+        let mut symbols = Symbols::default();
+        symbols.insert("sprite", 0x300);
+        tracer.set_symbols(symbols);
+        for _ in 0..3 {
+            tracer.run_cycle(&mut machine).unwrap();
+        }
+        assert_eq!(
+            String::from_utf8(tracer.out).unwrap(),
+            "00000001 0200 632A LD V3, 0x2A          V3=2A\n\
+             00000002 0202 A300 LD I, sprite         I=0300\n\
+             00000003 0204 00E0 CLS\n"
+        );
+    }
+
+    #[test]
+    fn history_lines() {
+        let mut machine: Machine<NullAudio> = Machine::new().unwrap();
+        let rom = ROM::from_bytes("trace", vec![0x63, 0x2A, 0xA3, 0x00, 0x00, 0xE0]);
+        machine.load_rom(&rom).unwrap();
+        machine.keep_history(2);
+        for _ in 0..3 {
+            machine.run_cycle().unwrap();
+        }
+        let history = machine.history().unwrap();
+        assert_eq!(history.len(), 2);
+        assert_eq!(
+            history.lines(&machine, &Symbols::default()),
+            [
+                "00000002 0202 A300 LD I, 0x300          I=0300",
+                "00000003 0204 00E0 CLS"
+            ]
+        );
+    }
+}
@@ -0,0 +1,234 @@
+use std::cell::Cell;
+use std::io::{self, Stdout, Write};
+use std::time::{Duration, Instant};
+
+use crossterm::event::{
+    self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers, KeyboardEnhancementFlags,
+    PopKeyboardEnhancementFlags, PushKeyboardEnhancementFlags,
+};
+use crossterm::style::{self, Print, SetBackgroundColor, SetForegroundColor};
+use crossterm::{cursor, execute, queue, terminal};
+
+use crate::audio::AudioPlay;
+use crate::display::{Color, DisplayConfig};
+use crate::frontend::{self, DisplayBackend, Emulator, FrameInfo, InputBackend, InputEvent};
+use crate::hooks::FrameHook;
+use crate::machine::Machine;
+use crate::rpl::FlagFile;
+use crate::watch::RomWatcher;
+use crate::Result;
+
+// most terminals only report key presses, a key is released when it was
+// not pressed or repeated for this long
+const KEY_HOLD: Duration = Duration::from_millis(200);
+
+/// Rings the terminal bell when the sound timer starts
+#[derive(Default)]
+pub struct TerminalBell {
+    playing: Cell<bool>,
+}
+
+impl AudioPlay for TerminalBell {
+    fn resume(&self) {
+        if !self.playing.replace(true) {
+            let _ = execute!(io::stdout(), Print('\x07'));
+        }
+    }
+
+    fn pause(&self) {
+        self.playing.set(false);
+    }
+}
+
+/// Settings of the terminal frontend
+pub struct Options {
+    /// instructions per second, `UNLIMITED` runs as fast as possible
+    pub clock_freq: u64,
+    pub display: DisplayConfig,
+    /// reload the rom when its file changes
+    pub watcher: Option<RomWatcher>,
+    /// the rom path, its file name is shown in the terminal title
+    pub title: String,
+    /// called after every frame that ran, e.g. a script
+    pub on_frame: Option<FrameHook<TerminalBell>>,
+    /// keeps the RPL flags of the rom when they change
+    pub flags: Option<FlagFile>,
+}
+
+/// same physical layout as the SDL2 frontend, by character
+fn char_to_key(c: char) -> Option<u8> {
+    "x123qweasdzc4rfv"
+        .find(c.to_ascii_lowercase())
+        .map(|key| key as u8)
+}
+
+/// raw mode and the alternate screen, restored on drop even after an error
+struct Terminal {
+    stdout: Stdout,
+    enhanced: bool,
+}
+
+impl Terminal {
+    fn enter() -> Result<Self> {
+        let mut stdout = io::stdout();
+        terminal::enable_raw_mode()?;
+        execute!(stdout, terminal::EnterAlternateScreen, cursor::Hide)?;
+        // terminals with the kitty keyboard protocol report key releases
+        let enhanced = terminal::supports_keyboard_enhancement().unwrap_or(false);
+        if enhanced {
+            execute!(
+                stdout,
+                PushKeyboardEnhancementFlags(KeyboardEnhancementFlags::REPORT_EVENT_TYPES)
+            )?;
+        }
+        Ok(Terminal { stdout, enhanced })
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        if self.enhanced {
+            let _ = execute!(self.stdout, PopKeyboardEnhancementFlags);
+        }
+        let _ = execute!(
+            self.stdout,
+            style::ResetColor,
+            cursor::Show,
+            terminal::LeaveAlternateScreen
+        );
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+fn term_color(color: Color) -> style::Color {
+    style::Color::Rgb {
+        r: color.r,
+        g: color.g,
+        b: color.b,
+    }
+}
+
+/// The terminal as a display: two display rows per terminal line with half
+/// block characters
+pub struct TuiDisplay {
+    terminal: Terminal,
+    config: DisplayConfig,
+}
+
+impl<T: AudioPlay> DisplayBackend<T> for TuiDisplay {
+    fn present(&mut self, machine: &mut Machine<T>, _frame: &FrameInfo) -> Result<()> {
+        let video = machine.get_display();
+        let out = &mut self.terminal.stdout;
+        queue!(
+            out,
+            cursor::MoveTo(0, 0),
+            SetForegroundColor(term_color(self.config.foreground)),
+            SetBackgroundColor(term_color(self.config.background))
+        )?;
+        for y in (0..video.height()).step_by(2) {
+            let line: String = video
+                .row_pixels(y)
+                .zip(video.row_pixels(y + 1))
+                .map(|pair| match pair {
+                    (true, true) => '█',
+                    (true, false) => '▀',
+                    (false, true) => '▄',
+                    (false, false) => ' ',
+                })
+                .collect();
+            queue!(out, Print(line), cursor::MoveToNextLine(1))?;
+        }
+        // a hires frame leaves the lores area behind when switching back
+        queue!(out, terminal::Clear(terminal::ClearType::FromCursorDown))?;
+        out.flush()?;
+        Ok(())
+    }
+
+    fn set_title(&mut self, title: &str) -> Result<()> {
+        execute!(self.terminal.stdout, terminal::SetTitle(title))?;
+        Ok(())
+    }
+}
+
+/// The terminal keyboard: Esc or Ctrl-C quits, P pauses and . steps a frame
+pub struct TuiInput {
+    /// the terminal reports key releases
+    enhanced: bool,
+    /// release deadline of every pressed key, only used without release events
+    held: [Option<Instant>; 16],
+}
+
+impl InputBackend for TuiInput {
+    fn poll(&mut self, events: &mut Vec<InputEvent>) -> Result<()> {
+        while event::poll(Duration::ZERO)? {
+            let (code, modifiers, kind) = match event::read()? {
+                Event::Key(KeyEvent {
+                    code,
+                    modifiers,
+                    kind,
+                    ..
+                }) => (code, modifiers, kind),
+                _ => continue,
+            };
+            match code {
+                KeyCode::Esc => events.push(InputEvent::Quit),
+                KeyCode::Char('c') if modifiers.contains(KeyModifiers::CONTROL) => {
+                    events.push(InputEvent::Quit)
+                }
+                KeyCode::Char(c) => match (char_to_key(c), kind) {
+                    (Some(key), KeyEventKind::Release) => events.push(InputEvent::KeyUp(key)),
+                    (Some(key), _) => {
+                        events.push(InputEvent::KeyDown(key));
+                        if !self.enhanced {
+                            self.held[key as usize] = Some(Instant::now() + KEY_HOLD);
+                        }
+                    }
+                    (None, KeyEventKind::Press) if c == 'p' => events.push(InputEvent::TogglePause),
+                    (None, KeyEventKind::Press) if c == '.' => events.push(InputEvent::StepFrame),
+                    _ => {}
+                },
+                _ => {}
+            }
+        }
+
+        let now = Instant::now();
+        for (key, deadline) in self.held.iter_mut().enumerate() {
+            if deadline.is_some_and(|deadline| deadline <= now) {
+                *deadline = None;
+                events.push(InputEvent::KeyUp(key as u8));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Run the machine in the terminal until it halts or Esc is pressed, P pauses
+/// and . steps one frame
+pub fn emulate(machine: &mut Machine<TerminalBell>, options: Options) -> Result<()> {
+    let Options {
+        clock_freq,
+        display,
+        watcher,
+        title,
+        on_frame,
+        flags,
+    } = options;
+    let terminal = Terminal::enter()?;
+    let input = TuiInput {
+        enhanced: terminal.enhanced,
+        held: [None; 16],
+    };
+    let display = TuiDisplay {
+        terminal,
+        config: display,
+    };
+    let options = frontend::Options {
+        clock_freq,
+        watcher,
+        title: Some(title),
+        on_frame,
+        flags,
+        ..frontend::Options::default()
+    };
+    Emulator::new(machine, TerminalBell::default(), display, input, options).run()
+}
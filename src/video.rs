@@ -1,50 +1,360 @@
-#[allow(dead_code)]
+use std::ops::Range;
+
+use serde::{Deserialize, Serialize};
+
+use crate::display::Palette;
+use crate::megachip::{MEGA_HEIGHT, MEGA_WIDTH};
+
+const LORES_WIDTH: usize = 64;
+const LORES_HEIGHT: usize = 32;
+const HIRES_WIDTH: usize = 128;
+const HIRES_HEIGHT: usize = 64;
+const ALL_ROWS: u64 = u64::MAX;
+const WORD_BITS: usize = 64;
+
+/// Monochrome display packed as bits, one u64 per row in lores and two in
+/// hires. Rows are handled as a u128 with the leftmost pixel in bit 127, so
+/// drawing a sprite row is a shift, an and for the collision and a xor.
+/// In MegaChip mode it is instead the 256x192 ARGB frame 00E0 presented.
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Video {
     width: usize,
     height: usize,
-    grid: Vec<Vec<u8>>,
+    hires: bool,
+    /// row-major, `width / 64` words per row
+    bits: Vec<u64>,
+    /// the MegaChip frame, row-major ARGB
+    #[serde(default)]
+    mega: Option<Vec<u32>>,
+    /// bit y is set when row y changed since the last `take_dirty_rows`,
+    /// the display is never taller than 64 rows except in MegaChip mode,
+    /// which changes all rows at once
+    #[serde(skip)]
+    dirty: u64,
+}
+
+/// Pixels a sprite draw erased, what sets VF, for tools showing where
+/// sprites hit each other. Rows are masks like the display rows, with the
+/// leftmost pixel in bit 127.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Collisions {
+    rows: Vec<(usize, u128)>,
+}
+
+impl Collisions {
+    pub fn is_empty(&self) -> bool {
+        self.rows.is_empty()
+    }
+
+    /// VF after the draw
+    pub fn flag(&self) -> u8 {
+        !self.is_empty() as u8
+    }
+
+    /// (x, y) of every erased pixel
+    pub fn pixels(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        self.rows.iter().flat_map(|&(y, mask)| {
+            (0..128)
+                .filter(move |x| mask >> (127 - x) & 1 == 1)
+                .map(move |x| (x, y))
+        })
+    }
+
+    /// add the pixels of `other`, e.g. to collect the collisions of a frame
+    pub fn merge(&mut self, other: &Collisions) {
+        for &(y, mask) in &other.rows {
+            self.add(y, mask);
+        }
+    }
+
+    fn add(&mut self, y: usize, mask: u128) {
+        match self.rows.iter_mut().find(|(row, _)| *row == y) {
+            Some((_, row_mask)) => *row_mask |= mask,
+            None => self.rows.push((y, mask)),
+        }
+    }
+}
+
+impl PartialEq for Video {
+    fn eq(&self, other: &Self) -> bool {
+        self.width == other.width
+            && self.height == other.height
+            && self.bits == other.bits
+            && self.mega == other.mega
+    }
+}
+
+impl std::fmt::Debug for Video {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Video {}x{}", self.width, self.height)?;
+        for row in self.rows() {
+            let line: String = row.map(|on| if on { '#' } else { '.' }).collect();
+            writeln!(f, "{}", line)?;
+        }
+        Ok(())
+    }
 }
 
 impl Video {
+    /// blank display, `width` is 64 or 128 and `height` at most 64
     pub fn new(width: usize, height: usize) -> Self {
-        let grid = vec![vec![0; height]; width];
+        assert!(width == LORES_WIDTH || width == HIRES_WIDTH);
+        assert!(height <= HIRES_HEIGHT);
         Self {
             width,
             height,
-            grid,
+            hires: width == HIRES_WIDTH,
+            bits: vec![0; width / WORD_BITS * height],
+            mega: None,
+            dirty: ALL_ROWS,
         }
     }
 
-    pub fn draw(&mut self, x: usize, y: usize, n: usize, data: &[u8]) -> u8 {
-        let mut flag = 0;
-        for (offset_y, bits) in data.iter().enumerate().take(n) {
-            let new_y = y + offset_y;
-            if new_y == 32 {
-                break;
+    /// draw an 8 pixel wide sprite of `n` rows at (x, y), pixels past the
+    /// edges are clipped or with `wrap` drawn on the other side
+    pub fn draw(&mut self, x: usize, y: usize, n: usize, data: &[u8], wrap: bool) -> Collisions {
+        let sprite = data.iter().take(n).map(|&bits| (bits as u128) << 120);
+        self.draw_rows(x, y, sprite, wrap)
+    }
+
+    /// SCHIP DXY0: draw a 16x16 sprite, two bytes per row
+    pub fn draw_large(&mut self, x: usize, y: usize, data: &[u8], wrap: bool) -> Collisions {
+        let sprite = data.chunks(2).take(16).map(|bits| {
+            let bits = (bits[0] as u16) << 8 | *bits.get(1).unwrap_or(&0) as u16;
+            (bits as u128) << 112
+        });
+        self.draw_rows(x, y, sprite, wrap)
+    }
+
+    /// xor left aligned sprite rows at (x, y) inside the display,
+    /// return the lit pixels that were erased
+    fn draw_rows<I>(&mut self, x: usize, y: usize, sprite: I, wrap: bool) -> Collisions
+    where
+        I: Iterator<Item = u128>,
+    {
+        let rows = if wrap { self.height } else { self.height - y };
+        let mut collisions = Collisions::default();
+        for (offset, bits) in (0..rows).zip(sprite) {
+            let new_y = (y + offset) % self.height;
+            let mut mask = bits >> x;
+            if wrap {
+                // rotate within the display width
+                mask |= bits.checked_shl((self.width - x) as u32).unwrap_or(0);
             }
-            for offset_x in 0..8 {
-                let new_x = x + offset_x;
-                if new_x < 64 {
-                    if self.grid[new_x][new_y] == 1 && (bits >> (7 - offset_x)) & 0x1 == 1 {
-                        self.grid[new_x][new_y] = 0;
-                        flag = 1;
-                    } else if self.grid[new_x][new_y] == 0 && (bits >> (7 - offset_x)) & 0x1 == 1 {
-                        self.grid[new_x][new_y] = 1;
-                    }
-                } else {
-                    break;
-                }
+            mask &= self.width_mask();
+            if mask == 0 {
+                continue;
             }
+            let row = self.row(new_y);
+            if row & mask != 0 {
+                collisions.add(new_y, row & mask);
+            }
+            self.set_row(new_y, row ^ mask);
+        }
+        collisions
+    }
+
+    /// pixels inside the display of a left aligned row
+    fn width_mask(&self) -> u128 {
+        !0 << (128 - self.width)
+    }
+
+    fn words(&self) -> usize {
+        self.width / WORD_BITS
+    }
+
+    /// row `y` left aligned, the leftmost pixel is bit 127
+    fn row(&self, y: usize) -> u128 {
+        let words = self.words();
+        self.bits[y * words..(y + 1) * words]
+            .iter()
+            .enumerate()
+            .fold(0, |row, (n, &word)| row | (word as u128) << (64 * (1 - n)))
+    }
+
+    fn set_row(&mut self, y: usize, row: u128) {
+        let words = self.words();
+        for (n, word) in self.bits[y * words..(y + 1) * words].iter_mut().enumerate() {
+            *word = (row >> (64 * (1 - n))) as u64;
+        }
+        self.dirty |= 1 << y;
+    }
+
+    /// 00CN: scroll the display down by n pixels
+    pub fn scroll_down(&mut self, n: usize) {
+        let shift = n.min(self.height) * self.words();
+        self.bits.rotate_right(shift);
+        self.bits[..shift].fill(0);
+        self.dirty = ALL_ROWS;
+    }
+
+    /// 00FB: scroll the display right by n pixels
+    pub fn scroll_right(&mut self, n: usize) {
+        for y in 0..self.height {
+            let row = self.row(y).checked_shr(n as u32).unwrap_or(0);
+            self.set_row(y, row);
+        }
+    }
+
+    /// 00FC: scroll the display left by n pixels
+    pub fn scroll_left(&mut self, n: usize) {
+        for y in 0..self.height {
+            let row = self.row(y).checked_shl(n as u32).unwrap_or(0);
+            self.set_row(y, row & self.width_mask());
+        }
+    }
+
+    /// 00FE/00FF: switch between 64x32 and 128x64, the display is cleared
+    pub fn set_hires(&mut self, hires: bool) {
+        self.hires = hires;
+        self.mega = None;
+        if hires {
+            self.width = HIRES_WIDTH;
+            self.height = HIRES_HEIGHT;
+        } else {
+            self.width = LORES_WIDTH;
+            self.height = LORES_HEIGHT;
         }
-        flag
+        self.bits = vec![0; self.words() * self.height];
+        self.dirty = ALL_ROWS;
+    }
+
+    pub fn is_hires(&self) -> bool {
+        self.hires
+    }
+
+    /// HiRes CHIP-8: 64x64 on two pages of the COSMAC VIP display memory,
+    /// lores pixels with twice the rows, the display is cleared
+    pub fn set_two_page(&mut self) {
+        self.set_hires(false);
+        self.height = HIRES_HEIGHT;
+        self.bits = vec![0; self.words() * self.height];
+    }
+
+    pub fn is_two_page(&self) -> bool {
+        !self.hires && self.height == HIRES_HEIGHT
+    }
+
+    /// 0011/0010: switch MegaChip mode on or off, the display is cleared
+    /// and in lores when it is off
+    pub fn set_mega(&mut self, on: bool) {
+        self.set_hires(on);
+        if on {
+            self.width = MEGA_WIDTH;
+            self.height = MEGA_HEIGHT;
+            self.bits.clear();
+            self.mega = Some(vec![0; MEGA_WIDTH * MEGA_HEIGHT]);
+        }
+    }
+
+    pub fn is_mega(&self) -> bool {
+        self.mega.is_some()
+    }
+
+    /// show a MegaChip frame, ARGB from `MegaChip::present`
+    pub(crate) fn present_mega(&mut self, frame: Vec<u32>) {
+        self.mega = Some(frame);
+        self.dirty = ALL_ROWS;
+    }
+
+    /// the RGB color of the pixel at (x, y) in MegaChip mode
+    fn mega_color(&self, x: usize, y: usize) -> Option<u32> {
+        let frame = self.mega.as_ref()?;
+        Some(frame[y * self.width + x] & 0xFF_FFFF)
     }
 
     pub fn clear(&mut self) {
-        self.grid = vec![vec![0; self.height]; self.width];
+        self.bits.fill(0);
+        if let Some(frame) = self.mega.as_mut() {
+            frame.fill(0);
+        }
+        self.dirty = ALL_ROWS;
+    }
+
+    /// rows changed since the last call as a bit mask, bit y for row y
+    pub fn take_dirty_rows(&mut self) -> u64 {
+        std::mem::take(&mut self.dirty)
+    }
+
+    /// some row changed since the last `take_dirty_rows`
+    pub fn is_dirty(&self) -> bool {
+        self.dirty != 0
+    }
+
+    /// copy the live display `from` for presenting, the rows it changed
+    /// since the last latch become dirty here
+    pub(crate) fn latch(&mut self, from: &mut Video) {
+        self.dirty |= from.take_dirty_rows();
+        self.width = from.width;
+        self.height = from.height;
+        self.hires = from.hires;
+        self.bits.clone_from(&from.bits);
+        self.mega.clone_from(&from.mega);
+    }
+
+    /// force a full redraw, e.g. after the display was replaced by a state
+    pub fn mark_all_dirty(&mut self) {
+        self.dirty = ALL_ROWS;
+    }
+
+    /// pixel at (x, y), false outside the display
+    pub fn pixel(&self, x: usize, y: usize) -> bool {
+        if x >= self.width || y >= self.height {
+            return false;
+        }
+        match self.mega_color(x, y) {
+            Some(color) => color != 0,
+            None => self.row(y) >> (127 - x) & 1 == 1,
+        }
+    }
+
+    /// set one pixel for frontend drawing like the rom menu, pixels outside
+    /// the display are ignored
+    pub fn set_pixel(&mut self, x: usize, y: usize, on: bool) {
+        if x < self.width && y < self.height && !self.is_mega() {
+            let bit = 1 << (127 - x);
+            let row = self.row(y);
+            self.set_row(y, if on { row | bit } else { row & !bit });
+        }
+    }
+
+    /// pixels of row `y` from left to right, MegaChip pixels are on
+    /// unless they are black
+    pub fn row_pixels(&self, y: usize) -> impl Iterator<Item = bool> + '_ {
+        let row = if self.is_mega() { 0 } else { self.row(y) };
+        (0..self.width).map(move |x| match self.mega_color(x, y) {
+            Some(color) => color != 0,
+            None => row >> (127 - x) & 1 == 1,
+        })
     }
 
-    pub fn get_grid(&self) -> &[Vec<u8>] {
-        &self.grid
+    /// rows from top to bottom, each one from left to right
+    pub fn rows(&self) -> impl Iterator<Item = impl Iterator<Item = bool> + '_> + '_ {
+        (0..self.height).map(move |y| self.row_pixels(y))
+    }
+
+    /// Fill `buffer` with the display as RGBA, row-major with 4 bytes per
+    /// pixel and no padding, ready to upload as a `width` x `height` texture.
+    /// Bytes past `width * height * 4` are left alone.
+    pub fn render_rgba(&self, buffer: &mut [u8], palette: &Palette) {
+        self.render_rgba_rows(0..self.height, buffer, palette);
+    }
+
+    /// `render_rgba` of the rows in `rows` only, the buffer starts with the
+    /// first of them. MegaChip frames have their own colors.
+    pub fn render_rgba_rows(&self, rows: Range<usize>, buffer: &mut [u8], palette: &Palette) {
+        if let Some(frame) = &self.mega {
+            let pixels = &frame[rows.start * self.width..rows.end * self.width];
+            for (&argb, rgba) in pixels.iter().zip(buffer.chunks_exact_mut(4)) {
+                let [_, r, g, b] = argb.to_be_bytes();
+                rgba.copy_from_slice(&[r, g, b, 0xFF]);
+            }
+            return;
+        }
+        let pixels = rows.flat_map(|y| self.row_pixels(y));
+        for (on, rgba) in pixels.zip(buffer.chunks_exact_mut(4)) {
+            rgba.copy_from_slice(&palette.rgba(on as usize));
+        }
     }
 
     pub fn width(&self) -> usize {
@@ -55,3 +365,118 @@ impl Video {
         self.height
     }
 }
+
+#[cfg(test)]
+mod video_test {
+    use super::*;
+
+    #[test]
+    fn draw_collide_and_clip() {
+        let mut video = Video::new(64, 32);
+        assert_eq!(video.draw(60, 31, 2, &[0xFF, 0xFF], false).flag(), 0);
+        // clipped at the right and bottom edges
+        assert!((60..64).all(|x| video.pixel(x, 31)));
+        assert!(!video.pixel(59, 31) && !video.pixel(0, 31) && !video.pixel(60, 0));
+        let collisions = video.draw(62, 31, 1, &[0xC0], false);
+        assert_eq!(collisions.flag(), 1);
+        assert_eq!(
+            collisions.pixels().collect::<Vec<_>>(),
+            vec![(62, 31), (63, 31)]
+        );
+        assert!(!video.pixel(62, 31));
+
+        // hires rows span two words
+        video.set_hires(true);
+        assert!(video.draw_large(60, 0, &[0xFF, 0xFF], false).is_empty());
+        let row: Vec<bool> = video.row_pixels(0).collect();
+        assert_eq!(row.len(), 128);
+        assert!(row[60..76].iter().all(|&on| on));
+        assert_eq!(row.iter().filter(|&&on| on).count(), 16);
+
+        video.scroll_right(4);
+        assert!(video.pixel(79, 0) && !video.pixel(63, 0));
+        video.scroll_left(4);
+        video.scroll_down(3);
+        assert!(video.pixel(60, 3) && !video.pixel(60, 0));
+        assert_eq!(video.rows().count(), 64);
+    }
+
+    #[test]
+    fn scroll_edges() {
+        for (width, height) in [(64, 32), (128, 64)] {
+            let mut video = Video::new(width, height);
+            video.set_pixel(0, 0, true);
+            video.set_pixel(width - 1, height - 1, true);
+            video.set_pixel(width - 3, 0, true);
+
+            // pixels scrolled past an edge are gone, none come in
+            video.scroll_right(4);
+            assert!(video.pixel(4, 0));
+            assert!(!video.pixel(0, 0) && !video.pixel(width - 1, 0));
+            assert!(!(0..width).any(|x| video.pixel(x, height - 1)));
+            video.scroll_left(4);
+            assert!(video.pixel(0, 0));
+            assert!((width - 4..width).all(|x| !video.pixel(x, 0)));
+            video.scroll_left(4);
+            assert!(!(0..width).any(|x| video.pixel(x, 0)));
+
+            video.set_pixel(1, 0, true);
+            video.set_pixel(1, height - 2, true);
+            video.scroll_down(1);
+            assert!(video.pixel(1, 1) && video.pixel(1, height - 1));
+            assert!(!(0..width).any(|x| video.pixel(x, 0)));
+            video.scroll_down(2);
+            assert!(video.pixel(1, 3));
+            assert_eq!(video.rows().flatten().filter(|&on| on).count(), 1);
+            video.scroll_down(15);
+            assert_eq!(height > 18, video.pixel(1, 18));
+        }
+    }
+
+    #[test]
+    fn two_page() {
+        let mut video = Video::new(64, 32);
+        video.set_two_page();
+        assert!(video.is_two_page() && !video.is_hires());
+        assert_eq!((video.width(), video.height()), (64, 64));
+        assert_eq!(video.draw(0, 60, 5, &[0x80; 5], false).flag(), 0);
+        assert!(video.pixel(0, 63) && !video.pixel(0, 0));
+        video.set_hires(false);
+        assert!(!video.is_two_page());
+        assert_eq!(video.height(), 32);
+    }
+
+    #[test]
+    fn draw_wrap() {
+        let mut video = Video::new(64, 32);
+        assert!(video.draw(60, 31, 2, &[0xFF, 0x81], true).is_empty());
+        // the right half wraps to the left edge and the second row to the top
+        assert!((60..64).chain(0..4).all(|x| video.pixel(x, 31)));
+        assert!(video.pixel(60, 0) && video.pixel(3, 0) && !video.pixel(61, 0));
+        let collisions = video.draw(0, 0, 1, &[0x10], true);
+        assert_eq!(collisions.pixels().collect::<Vec<_>>(), vec![(3, 0)]);
+
+        video.set_hires(true);
+        video.draw_large(120, 0, &[0xFF, 0xFF], true);
+        assert!((120..128).chain(0..8).all(|x| video.pixel(x, 0)));
+    }
+
+    #[test]
+    fn render_rgba() {
+        use crate::display::Color;
+
+        let mut video = Video::new(64, 32);
+        video.set_pixel(1, 0, true);
+        video.set_pixel(0, 31, true);
+        let palette = Palette::new(Color::rgb(1, 2, 3), Color::rgb(0xFF, 0xB0, 0x00));
+        let mut buffer = vec![0; 64 * 32 * 4 + 4];
+        video.render_rgba(&mut buffer, &palette);
+        assert_eq!(buffer[..8], [1, 2, 3, 0xFF, 0xFF, 0xB0, 0x00, 0xFF]);
+        assert_eq!(buffer[31 * 64 * 4..][..4], [0xFF, 0xB0, 0x00, 0xFF]);
+        assert_eq!(buffer[64 * 32 * 4..], [0, 0, 0, 0]);
+
+        let mut row = vec![0; 64 * 4];
+        video.render_rgba_rows(31..32, &mut row, &palette);
+        assert_eq!(row, buffer[31 * 64 * 4..32 * 64 * 4]);
+    }
+}
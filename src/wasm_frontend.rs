@@ -0,0 +1,290 @@
+use std::cell::{Cell, RefCell};
+
+use wasm_bindgen::prelude::*;
+use web_sys::{
+    AudioBufferSourceNode, AudioContext, CanvasRenderingContext2d, GainNode, OscillatorNode,
+    OscillatorType,
+};
+
+use crate::audio::{pattern_rate, AudioConfig, AudioPlay, Tone, PATTERN_BYTES};
+use crate::display::{Color, DisplayConfig};
+use crate::logging::AUDIO;
+use crate::machine::Machine;
+use crate::rom::ROM;
+
+/// Beep through WebAudio, the source runs all the time and the gain is
+/// switched between the volume and silence
+struct WebAudio {
+    context: AudioContext,
+    gain: GainNode,
+    /// silences the tone while an XO-CHIP pattern plays
+    tone_gain: GainNode,
+    oscillator: Option<OscillatorNode>,
+    pattern: RefCell<Option<AudioBufferSourceNode>>,
+    volume: Cell<f32>,
+    playing: Cell<bool>,
+}
+
+impl WebAudio {
+    fn new(config: AudioConfig) -> Result<Self, JsValue> {
+        let context = AudioContext::new()?;
+        let gain = context.create_gain()?;
+        gain.gain().set_value(0.0);
+        gain.connect_with_audio_node(&context.destination())?;
+        let tone_gain = context.create_gain()?;
+        tone_gain.connect_with_audio_node(&gain)?;
+
+        let mut oscillator = None;
+        match config.tone {
+            Tone::Square | Tone::Sine => {
+                let oscillator_node = context.create_oscillator()?;
+                oscillator_node.set_type(if config.tone == Tone::Square {
+                    OscillatorType::Square
+                } else {
+                    OscillatorType::Sine
+                });
+                oscillator_node.frequency().set_value(config.frequency);
+                oscillator_node.connect_with_audio_node(&tone_gain)?;
+                oscillator_node.start()?;
+                oscillator = Some(oscillator_node);
+            }
+            Tone::Noise => {
+                // one second of white noise played in a loop
+                let rate = context.sample_rate();
+                let buffer = context.create_buffer(1, rate as u32, rate)?;
+                let samples: Vec<f32> = (0..rate as u32)
+                    .map(|_| rand::random::<f32>() * 2.0 - 1.0)
+                    .collect();
+                buffer.copy_to_channel(&samples, 0)?;
+                let source = context.create_buffer_source()?;
+                source.set_buffer(Some(&buffer));
+                source.set_loop(true);
+                source.connect_with_audio_node(&tone_gain)?;
+                source.start()?;
+            }
+        }
+        Ok(WebAudio {
+            context,
+            gain,
+            tone_gain,
+            oscillator,
+            pattern: RefCell::new(None),
+            volume: Cell::new(config.volume),
+            playing: Cell::new(false),
+        })
+    }
+
+    /// loop the pattern instead of the tone, resampled to the context rate
+    /// since browsers do not take buffers at every rate
+    fn play_pattern(&self, pattern: &[u8; PATTERN_BYTES], pitch: u8) -> Result<(), JsValue> {
+        let bits = PATTERN_BYTES * 8;
+        let rate = self.context.sample_rate();
+        let len = ((bits as f32 * rate / pattern_rate(pitch)).round() as usize).max(1);
+        let samples: Vec<f32> = (0..len)
+            .map(|n| {
+                let bit = n * bits / len;
+                if pattern[bit / 8] >> (7 - bit % 8) & 1 == 1 {
+                    1.0
+                } else {
+                    -1.0
+                }
+            })
+            .collect();
+        let buffer = self.context.create_buffer(1, len as u32, rate)?;
+        buffer.copy_to_channel(&samples, 0)?;
+        let source = self.context.create_buffer_source()?;
+        source.set_buffer(Some(&buffer));
+        source.set_loop(true);
+        source.connect_with_audio_node(&self.gain)?;
+        source.start()?;
+        self.stop_pattern()?;
+        self.pattern.replace(Some(source));
+        self.tone_gain.gain().set_value(0.0);
+        Ok(())
+    }
+
+    fn stop_pattern(&self) -> Result<(), JsValue> {
+        if let Some(source) = self.pattern.take() {
+            source.disconnect()?;
+        }
+        self.tone_gain.gain().set_value(1.0);
+        Ok(())
+    }
+}
+
+impl AudioPlay for WebAudio {
+    fn resume(&self) {
+        // browsers keep the context suspended until the page got a user gesture
+        let _ = self.context.resume();
+        self.gain.gain().set_value(self.volume.get());
+        self.playing.set(true);
+    }
+
+    fn pause(&self) {
+        self.gain.gain().set_value(0.0);
+        self.playing.set(false);
+    }
+
+    fn set_volume(&self, volume: f32) {
+        self.volume.set(volume);
+        if self.playing.get() {
+            self.gain.gain().set_value(volume);
+        }
+    }
+
+    fn set_frequency(&self, frequency: f32) {
+        if let Some(oscillator) = &self.oscillator {
+            oscillator.frequency().set_value(frequency);
+        }
+    }
+
+    fn queue_pattern(&self, pattern: &[u8; PATTERN_BYTES], pitch: u8) {
+        if let Err(e) = self.play_pattern(pattern, pitch) {
+            error!(target: AUDIO, "can not play the audio pattern: {:?}", e);
+        }
+    }
+
+    fn clear_pattern(&self) {
+        if let Err(e) = self.stop_pattern() {
+            error!(target: AUDIO, "can not stop the audio pattern: {:?}", e);
+        }
+    }
+}
+
+/// CHIP-8 key for a `KeyboardEvent.code`, the same physical layout as the SDL2 frontend
+fn code_to_key(code: &str) -> Option<u8> {
+    let key = match code {
+        "Digit1" => 0x1,
+        "Digit2" => 0x2,
+        "Digit3" => 0x3,
+        "Digit4" => 0xC,
+        "KeyQ" => 0x4,
+        "KeyW" => 0x5,
+        "KeyE" => 0x6,
+        "KeyR" => 0xD,
+        "KeyA" => 0x7,
+        "KeyS" => 0x8,
+        "KeyD" => 0x9,
+        "KeyF" => 0xE,
+        "KeyZ" => 0xA,
+        "KeyX" => 0x0,
+        "KeyC" => 0xB,
+        "KeyV" => 0xF,
+        _ => return None,
+    };
+    Some(key)
+}
+
+fn css_color(color: Color) -> String {
+    format!("#{:02x}{:02x}{:02x}", color.r, color.g, color.b)
+}
+
+/// Browser frontend, the page drives it from `requestAnimationFrame`,
+/// see www/index.js
+#[wasm_bindgen]
+pub struct WasmEmulator {
+    machine: Machine<WebAudio>,
+    display: DisplayConfig,
+}
+
+#[wasm_bindgen]
+impl WasmEmulator {
+    #[wasm_bindgen(constructor)]
+    pub fn new(rom: &[u8]) -> Result<WasmEmulator, JsValue> {
+        let mut machine = Machine::new().map_err(to_js)?;
+        machine.load_font().map_err(to_js)?;
+        machine
+            .load_rom(&ROM::from_bytes("rom", rom.to_vec()))
+            .map_err(to_js)?;
+        machine.init_sound(WebAudio::new(AudioConfig::default())?);
+        Ok(WasmEmulator {
+            machine,
+            display: DisplayConfig::default(),
+        })
+    }
+
+    /// forward a `keydown` event, returns false if the key is not on the keypad
+    pub fn key_down(&mut self, code: &str) -> bool {
+        code_to_key(code)
+            .map(|key| self.machine.key_down(key))
+            .is_some()
+    }
+
+    /// forward a `keyup` event, returns false if the key is not on the keypad
+    pub fn key_up(&mut self, code: &str) -> bool {
+        code_to_key(code)
+            .map(|key| self.machine.key_up(key))
+            .is_some()
+    }
+
+    /// press a key of the on-screen keypad
+    pub fn press(&mut self, key: u8) {
+        self.machine.key_down(key & 0xF);
+    }
+
+    pub fn release(&mut self, key: u8) {
+        self.machine.key_up(key & 0xF);
+    }
+
+    /// held keys, bit k for key k
+    pub fn keys_down(&self) -> u16 {
+        (0..16)
+            .filter(|&key| self.machine.is_key_down(key))
+            .fold(0, |keys, key| keys | 1 << key)
+    }
+
+    /// keys the rom tested since the last call, bit k for key k
+    pub fn take_checked_keys(&mut self) -> u16 {
+        self.machine.take_checked_keys()
+    }
+
+    /// run `cycles` instructions and tick the timers once, call it at 60Hz,
+    /// true when the display changed and needs a `draw`
+    pub fn frame(&mut self, cycles: u32) -> Result<bool, JsValue> {
+        for _ in 0..cycles {
+            if self.machine.is_halt() {
+                break;
+            }
+            self.machine.run_cycle().map_err(to_js)?;
+        }
+        Ok(self.machine.tick_60hz())
+    }
+
+    pub fn is_halt(&mut self) -> bool {
+        self.machine.is_halt()
+    }
+
+    pub fn width(&self) -> usize {
+        self.machine.width()
+    }
+
+    pub fn height(&self) -> usize {
+        self.machine.height()
+    }
+
+    /// draw the display scaled to fill a `width` x `height` canvas
+    pub fn draw(&mut self, context: &CanvasRenderingContext2d, width: f64, height: f64) {
+        self.machine.take_dirty_rows();
+        let cell_width = width / self.machine.width() as f64;
+        let cell_height = height / self.machine.height() as f64;
+        context.set_fill_style_str(&css_color(self.display.background));
+        context.fill_rect(0.0, 0.0, width, height);
+        context.set_fill_style_str(&css_color(self.display.foreground));
+        for (y, row) in self.machine.get_display().rows().enumerate() {
+            for (x, on) in row.enumerate() {
+                if on {
+                    context.fill_rect(
+                        x as f64 * cell_width,
+                        y as f64 * cell_height,
+                        cell_width,
+                        cell_height,
+                    );
+                }
+            }
+        }
+    }
+}
+
+fn to_js<E: ToString>(e: E) -> JsValue {
+    JsValue::from_str(&e.to_string())
+}
@@ -0,0 +1,147 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use crossbeam_channel::{unbounded, Receiver};
+use notify::event::{Event, EventKind};
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+
+use crate::audio::AudioPlay;
+use crate::machine::Machine;
+use crate::rom::ROM;
+use crate::Result;
+
+/// editors save in several writes or replace the file, the rom is reloaded
+/// once it did not change for this long
+const SETTLE: Duration = Duration::from_millis(200);
+
+/// Watches a rom file for `--watch`, the frontend polls `changed` and
+/// reloads the rom when it returns true
+pub struct RomWatcher {
+    watcher: RecommendedWatcher,
+    path: PathBuf,
+    events: Receiver<notify::Result<Event>>,
+    changed_at: Option<Instant>,
+}
+
+impl RomWatcher {
+    pub fn new(path: &str) -> Result<Self> {
+        let (tx, events) = unbounded();
+        let watcher = notify::recommended_watcher(move |event| {
+            let _ = tx.send(event);
+        })?;
+        let mut watcher = RomWatcher {
+            watcher,
+            path: PathBuf::new(),
+            events,
+            changed_at: None,
+        };
+        watcher.watch(path)?;
+        Ok(watcher)
+    }
+
+    /// watch another rom file instead, e.g. after one is dropped on the window
+    pub fn watch(&mut self, path: &str) -> Result<()> {
+        // the directory is watched as editors often save by renaming a new
+        // file over the old one
+        let path = fs::canonicalize(path).map_err(|e| format!("can not watch {}: {}", path, e))?;
+        if path == self.path {
+            return Ok(());
+        }
+        let dir = path
+            .parent()
+            .unwrap_or_else(|| Path::new("/"))
+            .to_path_buf();
+        if let Some(old_dir) = self.path.parent().filter(|old| !old.as_os_str().is_empty()) {
+            if old_dir != dir {
+                self.watcher.unwatch(old_dir)?;
+            }
+        }
+        self.watcher.watch(&dir, RecursiveMode::NonRecursive)?;
+        self.path = path;
+        self.changed_at = None;
+        Ok(())
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// load the rom again and start it from scratch, the machine keeps the
+    /// old rom when the new one can not be loaded
+    pub fn reload<T: AudioPlay>(&self, machine: &mut Machine<T>) -> Result<()> {
+        let rom = ROM::new(&self.path.to_string_lossy())?;
+        machine.load_rom(&rom)?;
+        machine.reset()
+    }
+
+    /// true once after the rom was written and then left alone for `SETTLE`
+    pub fn changed(&mut self, now: Instant) -> bool {
+        for event in self.events.try_iter() {
+            match event {
+                // reading the rom to reload it is an access, not a change
+                Ok(event) if matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_)) => {
+                    if event.paths.contains(&self.path) {
+                        self.changed_at = Some(now);
+                    }
+                }
+                Ok(_) => {}
+                Err(e) => warn!("watching {}: {}", self.path.display(), e),
+            }
+        }
+        match self.changed_at {
+            Some(at) if now >= at + SETTLE && self.path.exists() => {
+                self.changed_at = None;
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod watch_test {
+    use super::*;
+    use crate::audio::NullAudio;
+    use std::thread;
+
+    /// poll like a frontend until the watcher reports a change
+    fn wait_change(watcher: &mut RomWatcher) -> bool {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while Instant::now() < deadline {
+            if watcher.changed(Instant::now()) {
+                return true;
+            }
+            thread::sleep(Duration::from_millis(20));
+        }
+        false
+    }
+
+    #[test]
+    fn test_rom_watcher() {
+        let dir = std::env::temp_dir().join(format!("rchip8-watch-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let rom = dir.join("game.ch8");
+        fs::write(&rom, [0x60, 0x01]).unwrap();
+        let mut watcher = RomWatcher::new(rom.to_str().unwrap()).unwrap();
+        let mut machine: Machine<NullAudio> = Machine::new().unwrap();
+        watcher.reload(&mut machine).unwrap();
+
+        // other files in the directory and reading the rom are no change
+        fs::write(dir.join("other.ch8"), [0x00, 0xE0]).unwrap();
+        fs::read(&rom).unwrap();
+        thread::sleep(Duration::from_millis(100));
+        assert!(!watcher.changed(Instant::now() + SETTLE));
+
+        fs::write(&rom, [0x60, 0x02]).unwrap();
+        thread::sleep(Duration::from_millis(100));
+        // not before the file settled
+        assert!(!watcher.changed(Instant::now()));
+        assert!(wait_change(&mut watcher));
+        assert!(!watcher.changed(Instant::now() + SETTLE));
+        watcher.reload(&mut machine).unwrap();
+        machine.step(1).unwrap();
+        assert_eq!(machine.registers()[0], 2);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
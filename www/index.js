@@ -0,0 +1,80 @@
+// build the package first with:
+//   wasm-pack build --target web --out-dir www/pkg -- --no-default-features --features wasm
+import init, { WasmEmulator } from "./pkg/yet_another_rchip8.js";
+
+// instructions per 60Hz frame, 500Hz like the native frontend
+const CYCLES_PER_FRAME = 8;
+
+const canvas = document.getElementById("screen");
+const context = canvas.getContext("2d");
+let emulator = null;
+
+document.addEventListener("keydown", (event) => {
+  if (emulator && emulator.key_down(event.code)) {
+    event.preventDefault();
+  }
+});
+document.addEventListener("keyup", (event) => {
+  if (emulator && emulator.key_up(event.code)) {
+    event.preventDefault();
+  }
+});
+
+// on-screen keypad for touch screens, keys the rom tests are outlined
+const keypad = [];
+for (const key of [0x1, 0x2, 0x3, 0xc, 0x4, 0x5, 0x6, 0xd, 0x7, 0x8, 0x9, 0xe, 0xa, 0x0, 0xb, 0xf]) {
+  const button = document.createElement("button");
+  button.textContent = key.toString(16).toUpperCase();
+  button.addEventListener("pointerdown", (event) => {
+    button.setPointerCapture(event.pointerId);
+    emulator?.press(key);
+  });
+  for (const type of ["pointerup", "pointercancel"]) {
+    button.addEventListener(type, () => emulator?.release(key));
+  }
+  document.getElementById("keypad").appendChild(button);
+  keypad[key] = button;
+}
+
+function drawKeypad() {
+  const down = emulator.keys_down();
+  const checked = emulator.take_checked_keys();
+  keypad.forEach((button, key) => {
+    button.classList.toggle("down", (down >> key & 1) === 1);
+    button.classList.toggle("checked", (checked >> key & 1) === 1);
+  });
+}
+
+document.getElementById("rom").addEventListener("change", async (event) => {
+  const file = event.target.files[0];
+  if (!file) {
+    return;
+  }
+  if (emulator) {
+    emulator.free();
+  }
+  emulator = new WasmEmulator(new Uint8Array(await file.arrayBuffer()));
+});
+
+function frame() {
+  let redraw = false;
+  if (emulator && !emulator.is_halt()) {
+    try {
+      redraw = emulator.frame(CYCLES_PER_FRAME);
+    } catch (e) {
+      console.error(e);
+      emulator.free();
+      emulator = null;
+    }
+  }
+  if (emulator && redraw) {
+    emulator.draw(context, canvas.width, canvas.height);
+  }
+  if (emulator) {
+    drawKeypad();
+  }
+  requestAnimationFrame(frame);
+}
+
+await init();
+requestAnimationFrame(frame);